@@ -0,0 +1,81 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gtmpl::{Context, Template, Value};
+use std::collections::HashMap;
+
+fn parsed(text: &str) -> Template {
+    let mut tmpl = Template::default();
+    tmpl.parse(text).unwrap();
+    tmpl
+}
+
+fn nested_value(depth: usize) -> Value {
+    let mut value = Value::from("leaf");
+    for _ in 0..depth {
+        let mut map = HashMap::new();
+        map.insert("child".to_owned(), value);
+        value = Value::Map(map);
+    }
+    value
+}
+
+fn deep_nesting(c: &mut Criterion) {
+    let depth = 50;
+    let text = format!(
+        "{}{{{{ . }}}}{}",
+        "{{ with .child }}{{ if . }}".repeat(depth),
+        "{{ end }}{{ end }}".repeat(depth)
+    );
+    let tmpl = parsed(&text);
+    let ctx = Context::from(nested_value(depth));
+    c.bench_function("render deep nesting", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+}
+
+fn large_range(c: &mut Criterion) {
+    let tmpl = parsed("{{ range $i, $v := . }}{{ $i }}: {{ $v }}\n{{ end }}");
+    let ctx = Context::from((0..10_000).map(Value::from).collect::<Vec<_>>());
+    c.bench_function("render large range", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+}
+
+fn large_range_rows(c: &mut Criterion) {
+    let tmpl =
+        parsed("{{ range $i, $row := .rows }}{{ $i }}: {{ $row.name }} {{ $row.tags }}\n{{ end }}");
+    let rows: Vec<Value> = (0..100_000)
+        .map(|i| {
+            let mut row = HashMap::new();
+            row.insert("name".to_owned(), Value::from(format!("row {}", i)));
+            row.insert("tags".to_owned(), Value::from(vec!["a", "b", "c"]));
+            Value::Map(row)
+        })
+        .collect();
+    let mut data = HashMap::new();
+    data.insert("rows".to_owned(), Value::from(rows));
+    let ctx = Context::from(Value::Map(data));
+    c.bench_function("render large range of rows", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+}
+
+fn printf_heavy(c: &mut Criterion) {
+    let tmpl = parsed(
+        &r#"{{ printf "%-10s|%5d|%8.3f|%x|%q" "name" 42 3.14159 255 "quoted" }}
+"#
+        .repeat(1_000),
+    );
+    let ctx = Context::empty();
+    c.bench_function("render printf heavy", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    deep_nesting,
+    large_range,
+    large_range_rows,
+    printf_heavy
+);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gtmpl::Template;
+
+fn template_set() -> Vec<(String, String)> {
+    (0..200)
+        .map(|i| {
+            let name = format!("partial_{}", i);
+            let body = format!(
+                "<div>{{{{ if .show }}}}{{{{ template \"{name}_inner\" . }}}}\
+                 {{{{ else }}}}{{{{ range $k, $v := .items }}}}{{{{ $k }}}}={{{{ $v }}}}\
+                 {{{{ end }}}}{{{{ end }}}}</div>\n",
+                name = name
+            );
+            let text = format!(
+                "{{{{ define \"{name}_inner\" }}}}{{{{ .name }}}} {{{{ printf \"%d\" .id }}}}\
+                 {{{{ end }}}}{body}",
+                name = name,
+                body = body.repeat(10)
+            );
+            (name, text)
+        })
+        .collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let set = template_set();
+    c.bench_function("parse large template set", |b| {
+        b.iter(|| {
+            let mut tmpl = Template::default();
+            for (name, text) in &set {
+                tmpl.add_template(name.as_str(), black_box(text.as_str()))
+                    .unwrap();
+            }
+            tmpl
+        })
+    });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gtmpl::{Context, Template, Value};
+use std::collections::HashMap;
+
+fn large_text_template() -> Template {
+    let mut tmpl = Template::default();
+    let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. {{ . }}\n".repeat(10_000);
+    tmpl.parse(text).unwrap();
+    tmpl
+}
+
+fn render(c: &mut Criterion) {
+    let tmpl = large_text_template();
+    let ctx = Context::from("gtmpl");
+    c.bench_function("render large text", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+    c.bench_function("render_to large text", |b| {
+        b.iter(|| {
+            let mut out = String::new();
+            tmpl.render_to(&mut out, black_box(&ctx)).unwrap();
+            out
+        })
+    });
+}
+
+fn large_context() -> Context {
+    let items: Vec<Value> = (0..1_000)
+        .map(|i| {
+            let mut item = HashMap::new();
+            item.insert("name".to_owned(), Value::from(format!("item {}", i)));
+            item.insert("id".to_owned(), Value::from(i));
+            Value::Map(item)
+        })
+        .collect();
+    let mut data = HashMap::new();
+    data.insert("items".to_owned(), Value::Array(items));
+    data.insert("title".to_owned(), Value::from("items"));
+    Context::from(Value::Map(data))
+}
+
+fn render_large_context(c: &mut Criterion) {
+    let mut tmpl = Template::default();
+    tmpl.parse(
+        "{{ range .items }}{{ $.title }} {{ .id }}: {{ .name }}\n{{ end }}\
+         {{ with .items }}{{ len . }}{{ end }}",
+    )
+    .unwrap();
+    let ctx = large_context();
+    c.bench_function("render large context", |b| {
+        b.iter(|| tmpl.render(black_box(&ctx)).unwrap())
+    });
+}
+
+criterion_group!(benches, render, render_large_context);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+use gtmpl::Value;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|format: &str| {
+    let args = [
+        Value::from(42),
+        Value::from(-1.5),
+        Value::from("→ü"),
+        Value::from(true),
+        Value::from(vec![1, 2, 3]),
+        Value::Nil,
+    ];
+    // Feed every arity so missing and extra arguments are covered too.
+    for n in 0..=args.len() {
+        gtmpl::fuzzing::sprintf(format, &args[..n]);
+    }
+});
@@ -0,0 +1,447 @@
+//! Static analysis of parsed templates.
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use gtmpl_value::Value;
+
+use crate::node::{Node, Nodes, PipeNode, PipeOrString, Span};
+use crate::template::Template;
+use crate::utils::is_true;
+
+/// Fields and functions referenced by a template.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct References {
+    /// Field chains like `.user.name` or `$.user.name`.
+    pub fields: BTreeSet<String>,
+    /// Names of called functions like `printf` or `strings.upper`.
+    pub functions: BTreeSet<String>,
+}
+
+/// Difference between two versions of a template as returned by [`diff`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TemplateDiff {
+    pub added_templates: Vec<String>,
+    pub removed_templates: Vec<String>,
+    pub added_fields: Vec<String>,
+    pub removed_fields: Vec<String>,
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+}
+
+impl TemplateDiff {
+    /// Returns `true` if neither templates nor references changed.
+    pub fn is_empty(&self) -> bool {
+        self.added_templates.is_empty()
+            && self.removed_templates.is_empty()
+            && self.added_fields.is_empty()
+            && self.removed_fields.is_empty()
+            && self.added_functions.is_empty()
+            && self.removed_functions.is_empty()
+    }
+}
+
+/// Collects the fields and functions referenced by all templates of `template`.
+pub fn references(template: &Template) -> References {
+    let mut refs = References::default();
+    for root in template.tree_set.values().filter_map(|t| t.root.as_ref()) {
+        collect(root, &mut refs);
+    }
+    refs
+}
+
+/// Compares two parsed templates without rendering them.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{analysis, Template};
+///
+/// let mut old = Template::default();
+/// old.parse(r#"{{ define "a" }}{{ .name }}{{ end }}"#).unwrap();
+/// let mut new = Template::default();
+/// new.parse(r#"{{ define "b" }}{{ .name | printf "%q" }}{{ end }}"#).unwrap();
+///
+/// let diff = analysis::diff(&old, &new);
+/// assert_eq!(diff.added_templates, vec!["b"]);
+/// assert_eq!(diff.removed_templates, vec!["a"]);
+/// assert!(diff.added_fields.is_empty());
+/// assert_eq!(diff.added_functions, vec!["printf"]);
+/// ```
+pub fn diff(old: &Template, new: &Template) -> TemplateDiff {
+    let old_names: BTreeSet<&String> = old.tree_set.keys().collect();
+    let new_names: BTreeSet<&String> = new.tree_set.keys().collect();
+    let old_refs = references(old);
+    let new_refs = references(new);
+    TemplateDiff {
+        added_templates: new_names
+            .difference(&old_names)
+            .map(|s| s.to_string())
+            .collect(),
+        removed_templates: old_names
+            .difference(&new_names)
+            .map(|s| s.to_string())
+            .collect(),
+        added_fields: new_refs
+            .fields
+            .difference(&old_refs.fields)
+            .cloned()
+            .collect(),
+        removed_fields: old_refs
+            .fields
+            .difference(&new_refs.fields)
+            .cloned()
+            .collect(),
+        added_functions: new_refs
+            .functions
+            .difference(&old_refs.functions)
+            .cloned()
+            .collect(),
+        removed_functions: old_refs
+            .functions
+            .difference(&new_refs.functions)
+            .cloned()
+            .collect(),
+    }
+}
+
+/// A problem found by [`lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Name of the template containing the problem.
+    pub template: String,
+    pub span: Span,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A variable is declared but never used.
+    UnusedVariable(String),
+    /// A defined template is never invoked via `template` or `block`.
+    UnusedTemplate(String),
+    /// A function is called which is not registered (anymore).
+    UnknownFunction(String),
+    /// The condition of an `if` or `with` is a constant, so one branch is unreachable.
+    ConstantCondition(bool),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.template)?;
+        match self.kind {
+            WarningKind::UnusedVariable(ref name) => write!(f, "variable {} is never used", name),
+            WarningKind::UnusedTemplate(ref name) => {
+                write!(f, "template {} is never invoked", name)
+            }
+            WarningKind::UnknownFunction(ref name) => {
+                write!(f, "function {} is not defined", name)
+            }
+            WarningKind::ConstantCondition(true) => {
+                write!(f, "condition is always true, else branch is unreachable")
+            }
+            WarningKind::ConstantCondition(false) => {
+                write!(f, "condition is always false, body is unreachable")
+            }
+        }
+    }
+}
+
+impl Template {
+    /// Checks all templates for unused variables and defines, calls of functions which are not
+    /// registered and constant conditions. See [`lint`].
+    pub fn lint(&self) -> Vec<Warning> {
+        lint(self)
+    }
+}
+
+/// Checks all templates of `template` for likely mistakes.
+///
+/// Variables are matched by name within each template. Unused templates are only reported if
+/// no template is invoked by a pipeline like `{{ template (.name) }}`.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::analysis::WarningKind;
+/// use gtmpl::Template;
+///
+/// let mut tmpl = Template::default();
+/// tmpl.parse(r#"{{ $x := 1 }}{{ define "unused" }}{{ if true }}a{{ else }}b{{ end }}{{ end }}"#)
+///     .unwrap();
+/// let kinds: Vec<_> = tmpl.lint().into_iter().map(|w| w.kind).collect();
+/// assert_eq!(
+///     kinds,
+///     vec![
+///         WarningKind::UnusedVariable("$x".to_owned()),
+///         WarningKind::ConstantCondition(true),
+///         WarningKind::UnusedTemplate("unused".to_owned()),
+///     ]
+/// );
+/// ```
+pub fn lint(template: &Template) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut invoked = HashSet::new();
+    let mut dynamic = false;
+    let mut names: Vec<&String> = template.tree_set.keys().collect();
+    names.sort();
+    for name in &names {
+        let root = match template.tree_set[*name].root {
+            Some(ref root) => root,
+            None => continue,
+        };
+        let mut lint = Lint {
+            template,
+            name,
+            warnings: &mut warnings,
+            declared: vec![],
+            used: HashSet::new(),
+            invoked: &mut invoked,
+            dynamic: &mut dynamic,
+        };
+        lint.check(root);
+        let Lint { declared, used, .. } = lint;
+        for (var, span) in declared {
+            if !used.contains(&var) {
+                warnings.push(Warning {
+                    template: name.to_string(),
+                    span,
+                    kind: WarningKind::UnusedVariable(var),
+                });
+            }
+        }
+    }
+    if !dynamic {
+        for name in names {
+            if *name != template.name && !invoked.contains(name.as_str()) {
+                warnings.push(Warning {
+                    template: name.clone(),
+                    span: template.tree_set[name].defined_at(),
+                    kind: WarningKind::UnusedTemplate(name.clone()),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+struct Lint<'a> {
+    template: &'a Template,
+    name: &'a str,
+    warnings: &'a mut Vec<Warning>,
+    declared: Vec<(String, Span)>,
+    used: HashSet<String>,
+    invoked: &'a mut HashSet<String>,
+    dynamic: &'a mut bool,
+}
+
+impl<'a> Lint<'a> {
+    fn warn(&mut self, span: Span, kind: WarningKind) {
+        self.warnings.push(Warning {
+            template: self.name.to_owned(),
+            span,
+            kind,
+        });
+    }
+
+    fn declare(&mut self, pipe: &PipeNode) {
+        for var in &pipe.decl {
+            self.declared.push((var.ident[0].clone(), var.span()));
+        }
+    }
+
+    // Marks templates rendered via the executor's `include` as invoked. Any template may be
+    // invoked by the text passed to `tpl`.
+    fn check_includes(&mut self, pipe: &PipeNode) {
+        for cmd in &pipe.cmds {
+            let func = match cmd.args.first() {
+                Some(Nodes::Identifier(ref n)) if !self.template.funcs.contains(&n.ident) => {
+                    n.ident.as_str()
+                }
+                _ => continue,
+            };
+            match (func, cmd.args.get(1)) {
+                ("include", Some(Nodes::String(ref name))) => {
+                    if let Value::String(ref name) = name.value {
+                        self.invoked.insert(name.clone());
+                    }
+                }
+                ("include", _) | ("tpl", _) => *self.dynamic = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn check(&mut self, node: &Nodes) {
+        match *node {
+            Nodes::Action(ref n) => self.check_includes(&n.pipe),
+            Nodes::If(ref n) | Nodes::With(ref n) | Nodes::Range(ref n) => {
+                self.check_includes(&n.pipe)
+            }
+            Nodes::Pipe(ref n) => self.check_includes(n),
+            Nodes::Template(ref n) => {
+                if let Some(ref pipe) = n.pipe {
+                    self.check_includes(pipe);
+                }
+            }
+            Nodes::Extension(ref n) => {
+                if let Some(ref pipe) = n.pipe {
+                    self.check_includes(pipe);
+                    self.declare(pipe);
+                }
+            }
+            _ => {}
+        }
+        match *node {
+            Nodes::Action(ref n) => self.declare(&n.pipe),
+            Nodes::If(ref n) | Nodes::With(ref n) => {
+                self.declare(&n.pipe);
+                if let Some(value) = constant(&n.pipe) {
+                    self.warn(n.pipe.span(), WarningKind::ConstantCondition(value));
+                }
+            }
+            Nodes::Range(ref n) => self.declare(&n.pipe),
+            Nodes::Variable(ref n) => {
+                self.used.insert(n.ident[0].clone());
+            }
+            Nodes::Identifier(ref n)
+                if !self.template.funcs.contains(&n.ident)
+                    && !self.template.funcs.contains_iterator(&n.ident)
+                    && !self.template.funcs.contains_context_func(&n.ident)
+                    && !self.template.funcs.exec_funcs().contains(&n.ident.as_str()) =>
+            {
+                self.warn(n.span(), WarningKind::UnknownFunction(n.ident.clone()));
+            }
+            Nodes::Template(ref n) => match n.name {
+                PipeOrString::String(ref name) => {
+                    self.invoked.insert(name.clone());
+                }
+                PipeOrString::Pipe(_) => *self.dynamic = true,
+            },
+            _ => {}
+        }
+        for child in node.children() {
+            self.check(child);
+        }
+    }
+}
+
+// The truth of a pipeline consisting of a single literal.
+fn constant(pipe: &PipeNode) -> Option<bool> {
+    if !pipe.decl.is_empty() || pipe.cmds.len() != 1 || pipe.cmds[0].args.len() != 1 {
+        return None;
+    }
+    match pipe.cmds[0].args[0] {
+        Nodes::Bool(ref n) => Some(is_true(&n.value)),
+        Nodes::Number(ref n) => Some(is_true(&n.value)),
+        Nodes::String(ref n) => Some(is_true(&n.value)),
+        Nodes::Nil(_) => Some(false),
+        _ => None,
+    }
+}
+
+fn collect(node: &Nodes, refs: &mut References) {
+    match *node {
+        Nodes::Field(ref n) => {
+            refs.fields.insert(format!(".{}", n.ident.join(".")));
+        }
+        Nodes::Variable(ref n) if n.ident.len() > 1 && n.ident[0] == "$" => {
+            refs.fields.insert(n.ident.join("."));
+        }
+        Nodes::Identifier(ref n) => {
+            refs.functions.insert(n.ident.clone());
+        }
+        _ => {}
+    }
+    for child in node.children() {
+        collect(child, refs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ if and .a $.b.c }}{{ range $x := .items }}{{ $x.y }}{{ end }}{{ end }}
+            {{ template "t" .d.e }}{{ define "t" }}{{ len . }}{{ end }}"#,
+        )
+        .unwrap();
+        let refs = references(&t);
+        assert_eq!(
+            refs.fields.into_iter().collect::<Vec<_>>(),
+            vec!["$.b.c", ".a", ".d.e", ".items"]
+        );
+        assert_eq!(
+            refs.functions.into_iter().collect::<Vec<_>>(),
+            vec!["and", "len"]
+        );
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut t = Template::with_name("t");
+        t.parse(
+            r#"{{ range $i, $e := .items }}{{ $e | len }}{{ end }}
+            {{ with $y := .y }}{{ $y }}{{ end }}{{ if 0 }}{{ end }}
+            {{ template "used" }}{{ define "used" }}{{ end }}{{ define "unused" }}{{ end }}"#,
+        )
+        .unwrap();
+        t.funcs = t.funcs.clone().without("len");
+        let warnings = t.lint();
+        let kinds: Vec<_> = warnings.iter().map(|w| w.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WarningKind::UnknownFunction("len".to_owned()),
+                WarningKind::ConstantCondition(false),
+                WarningKind::UnusedVariable("$i".to_owned()),
+                WarningKind::UnusedTemplate("unused".to_owned()),
+            ]
+        );
+        assert_eq!(warnings[2].to_string(), "t: variable $i is never used");
+        assert_eq!(
+            &t.tree_set["t"].source()[warnings[3].span.start..warnings[3].span.end],
+            "\"unused\""
+        );
+    }
+
+    #[test]
+    fn test_lint_include() {
+        let mut t = Template::default();
+        t.parse(r#"{{ include "x" . }}{{ define "x" }}{{ end }}{{ define "y" }}{{ end }}"#)
+            .unwrap();
+        let kinds: Vec<_> = t.lint().into_iter().map(|w| w.kind).collect();
+        assert_eq!(kinds, vec![WarningKind::UnusedTemplate("y".to_owned())]);
+        t.parse(r#"{{ include (print "x") . }}"#).unwrap();
+        assert!(t.lint().is_empty());
+        t.parse(r#"{{ tpl .text . }}"#).unwrap();
+        assert!(t.lint().is_empty());
+    }
+
+    #[cfg(feature = "gtmpl_dynamic_template")]
+    #[test]
+    fn test_lint_dynamic_template() {
+        let mut t = Template::default();
+        t.parse(r#"{{ template (print "x") }}{{ define "x" }}{{ end }}"#)
+            .unwrap();
+        assert!(t.lint().is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = Template::default();
+        old.parse("{{ .a }}{{ .b | len }}").unwrap();
+        assert!(diff(&old, &old).is_empty());
+        let mut new = Template::default();
+        new.parse("{{ .a }}{{ .c | print }}").unwrap();
+        let d = diff(&old, &new);
+        assert!(d.added_templates.is_empty());
+        assert_eq!(d.added_fields, vec![".c"]);
+        assert_eq!(d.removed_fields, vec![".b"]);
+        assert_eq!(d.added_functions, vec!["print"]);
+        assert_eq!(d.removed_functions, vec!["len"]);
+    }
+}
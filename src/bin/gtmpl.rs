@@ -0,0 +1,173 @@
+//! Renders a template file with a JSON or YAML context, see `gtmpl --help`.
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use gtmpl::funcs::{crypto, debug, dict, encoding, math, regex, strings, time, validate};
+use gtmpl::{from_json, Context, MissingKey, Template, Value};
+
+const USAGE: &str = "\
+Usage: gtmpl [OPTIONS] <TEMPLATE>
+
+Renders the template file TEMPLATE.
+
+Options:
+  -c, --context <FILE>    JSON or YAML (.yaml, .yml) file used as dot, - reads JSON from stdin
+  -t, --template <FILE>   Parses another template file, e.g. one with definitions
+  -s, --set <KEY=VALUE>   Sets the string KEY on top of the context, KEY may be a path like a.b
+  -o, --out <FILE>        Writes the output to FILE instead of stdout
+      --strict            Fails on missing keys and values
+  -h, --help              Prints this help
+";
+
+#[derive(Default)]
+struct Args {
+    template: PathBuf,
+    templates: Vec<PathBuf>,
+    context: Option<PathBuf>,
+    set: Vec<(String, String)>,
+    out: Option<PathBuf>,
+    strict: bool,
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("gtmpl: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("gtmpl: {}", e);
+        process::exit(1);
+    }
+}
+
+// Returns `None` if help was requested.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+    let mut template = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-c" | "--context" => parsed.context = Some(value(&arg)?.into()),
+            "-t" | "--template" => parsed.templates.push(value(&arg)?.into()),
+            "-o" | "--out" => parsed.out = Some(value(&arg)?.into()),
+            "-s" | "--set" => {
+                let set = value(&arg)?;
+                match set.split_once('=') {
+                    Some((key, val)) => parsed.set.push((key.to_owned(), val.to_owned())),
+                    None => return Err(format!("expected KEY=VALUE, got {}", set)),
+                }
+            }
+            "--strict" => parsed.strict = true,
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option {}", arg))
+            }
+            _ if template.is_none() => template = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    parsed.template = template.ok_or("missing TEMPLATE")?;
+    Ok(Some(parsed))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let missing_key = if args.strict {
+        MissingKey::Error
+    } else {
+        MissingKey::Default
+    };
+    let mut builder = Template::builder()
+        .funcs(crypto::CRYPTO_FUNCS)
+        .funcs(debug::DEBUG_FUNCS)
+        .funcs(dict::DICT_FUNCS)
+        .funcs(encoding::ENCODING_FUNCS)
+        .funcs(math::MATH_FUNCS)
+        .funcs(regex::REGEX_FUNCS)
+        .funcs(strings::STRINGS_FUNCS)
+        .funcs(time::TIME_FUNCS)
+        .funcs(validate::VALIDATE_FUNCS)
+        .option(missing_key)
+        .parse_file(&args.template);
+    for path in &args.templates {
+        builder = builder.parse_file(path);
+    }
+    let tmpl = builder.build().map_err(|e| e.to_string())?;
+
+    let data = match args.context {
+        Some(ref path) => read_context(path)?,
+        None => Value::Nil,
+    };
+    let ctx = if args.set.is_empty() {
+        Context::from(data)
+    } else {
+        let mut set = HashMap::new();
+        for (key, val) in &args.set {
+            insert_path(&mut set, key, val);
+        }
+        Context::layered(&[data, Value::Map(set)])
+    };
+    let output = tmpl
+        .render(&ctx)
+        .map_err(|e| match (e.context(), tmpl.error_snippet(&e)) {
+            (Some(ctx), Some(snippet)) => format!("{}: {}\n{}", ctx, e, snippet),
+            _ => e.to_string(),
+        })?;
+
+    match args.out {
+        Some(ref path) => fs::write(path, output)
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e)),
+        None => io::stdout()
+            .write_all(output.as_bytes())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn read_context(path: &Path) -> Result<Value, String> {
+    let read_err = |e: io::Error| format!("unable to read {}: {}", path.display(), e);
+    let mut text = String::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_string(&mut text).map_err(read_err)?;
+    } else {
+        text = fs::read_to_string(path).map_err(read_err)?;
+    }
+    let parse_err =
+        |e: &dyn std::fmt::Display| format!("unable to parse {}: {}", path.display(), e);
+    let json: serde_json::Value = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&text).map_err(|e| parse_err(&e))?,
+        _ => serde_json::from_str(&text).map_err(|e| parse_err(&e))?,
+    };
+    Ok(from_json(json))
+}
+
+// Inserts `val` at the dotted `key`, creating maps on the way.
+fn insert_path(map: &mut HashMap<String, Value>, key: &str, val: &str) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = map
+                .entry(head.to_owned())
+                .or_insert_with(|| Value::Map(HashMap::new()));
+            if !matches!(entry, Value::Map(_)) {
+                *entry = Value::Map(HashMap::new());
+            }
+            if let Value::Map(ref mut inner) = entry {
+                insert_path(inner, rest, val);
+            }
+        }
+        None => {
+            map.insert(key.to_owned(), Value::from(val));
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use crate::error::TemplateError;
+use crate::escape::OutputMode;
+use crate::exec::ExecOptions;
+use crate::funcs::FuncRegistry;
+use crate::template::{GoVersion, Template};
+
+/// What happens when a template accesses a key missing from a map, like Golang's `missingkey`
+/// option.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingKey {
+    /// Yields no value, printed as `<no value>`.
+    #[default]
+    Default,
+    /// Fails the execution, see [`Template::set_strict`].
+    Error,
+}
+
+enum Source {
+    Text(String),
+    File(PathBuf),
+}
+
+/// Configures a new [`Template`], created via [`Template::builder`].
+///
+/// By default the template starts with Golang's builtin functions, just like
+/// `Template::default()`. Texts and files are parsed by [`TemplateBuilder::build`] after all
+/// other settings are applied, so the order of the calls does not matter.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, Func, FuncError, MissingKey, Template, Value};
+///
+/// fn shout(args: &[Value]) -> Result<Value, FuncError> {
+///   Ok(Value::from(format!("{}!", args[0])))
+/// }
+///
+/// let tmpl = Template::builder()
+///     .name("greeting")
+///     .without_builtins()
+///     .funcs(&[("shout", shout as Func)])
+///     .delims("<%", "%>")
+///     .option(MissingKey::Error)
+///     .parse(r#"<% shout "hi" %>"#)
+///     .build()
+///     .unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "hi!");
+///
+/// let err = Template::builder().parse(r#"{{ printf "%s" "hi" }}"#).without_builtins().build();
+/// assert!(err.is_err());
+/// ```
+#[derive(Default)]
+pub struct TemplateBuilder {
+    template: Template,
+    sources: Vec<Source>,
+}
+
+impl TemplateBuilder {
+    /// Names the template. Without a name it is named after the first file passed to
+    /// [`TemplateBuilder::parse_file`].
+    pub fn name<T: Into<String>>(mut self, name: T) -> TemplateBuilder {
+        self.template.name = name.into();
+        self
+    }
+
+    /// Starts without any functions, not even Golang's builtins.
+    pub fn without_builtins(mut self) -> TemplateBuilder {
+        self.template.funcs = FuncRegistry::default();
+        self
+    }
+
+    /// Adds a set of functions like one of the [`funcs`](crate::funcs) packs. Functions with
+    /// the same name as a builtin or a previously added function replace it.
+    pub fn funcs<T: Into<String> + Clone>(mut self, funcs: &[(T, Func)]) -> TemplateBuilder {
+        self.template.funcs.extend(funcs);
+        self
+    }
+
+    /// Adds a constant readable via `data`, see [`Template::add_data`].
+    pub fn data<T: Into<String>>(mut self, name: T, value: Value) -> TemplateBuilder {
+        self.template.add_data(name, value);
+        self
+    }
+
+    /// Adds an extension block keyword, see [`Template::add_extension`].
+    pub fn extension<T, F>(mut self, name: T, handler: F) -> TemplateBuilder
+    where
+        T: Into<String>,
+        F: Fn(&Value, &str) -> Result<String, FuncError> + Send + Sync + 'static,
+    {
+        self.template.add_extension(name, handler);
+        self
+    }
+
+    /// Uses exactly the given functions.
+    pub fn func_registry(mut self, funcs: FuncRegistry) -> TemplateBuilder {
+        self.template.funcs = funcs;
+        self
+    }
+
+    /// Sets the action delimiters, see [`Template::set_delims`].
+    pub fn delims<L: Into<String>, R: Into<String>>(
+        mut self,
+        left: L,
+        right: R,
+    ) -> TemplateBuilder {
+        self.template.set_delims(left, right);
+        self
+    }
+
+    /// Sets how missing map keys are handled.
+    pub fn option(mut self, missing_key: MissingKey) -> TemplateBuilder {
+        self.template.set_strict(missing_key == MissingKey::Error);
+        self
+    }
+
+    /// Sets the execution options, see [`Template::set_exec_options`].
+    pub fn exec_options(mut self, options: ExecOptions) -> TemplateBuilder {
+        self.template.set_exec_options(options);
+        self
+    }
+
+    /// Sets the escaping of the output of actions, see [`Template::set_output_mode`].
+    pub fn output_mode(mut self, mode: OutputMode) -> TemplateBuilder {
+        self.template.set_output_mode(mode);
+        self
+    }
+
+    /// Sets the layout of pages, see [`Template::set_layout`].
+    pub fn layout<T: Into<String>>(mut self, name: T) -> TemplateBuilder {
+        self.template.set_layout(name);
+        self
+    }
+
+    /// Adds a filter post-processing the output, see [`Template::add_post_filter`].
+    pub fn post_filter<F>(mut self, filter: F) -> TemplateBuilder
+    where
+        F: Fn(String) -> String + Send + Sync + 'static,
+    {
+        self.template.add_post_filter(filter);
+        self
+    }
+
+    /// Enables trimming around block actions, see [`Template::set_trim_blocks`].
+    pub fn trim_blocks(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_trim_blocks(enabled);
+        self
+    }
+
+    /// Enables bracket index syntax, see [`Template::set_index_syntax`].
+    pub fn index_syntax(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_index_syntax(enabled);
+        self
+    }
+
+    /// Enables numeric fields on arrays, see [`Template::set_numeric_fields`].
+    pub fn numeric_fields(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_numeric_fields(enabled);
+        self
+    }
+
+    /// Enables arguments for the `template` action, see [`Template::set_template_args`].
+    pub fn template_args(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_template_args(enabled);
+        self
+    }
+
+    /// Allows calls of functions which are not defined yet, see
+    /// [`Template::set_allow_undefined_functions`].
+    pub fn allow_undefined_functions(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_allow_undefined_functions(enabled);
+        self
+    }
+
+    /// Emulates a Golang release, see [`Template::set_go_version`].
+    pub fn go_version(mut self, version: GoVersion) -> TemplateBuilder {
+        self.template.set_go_version(version);
+        self
+    }
+
+    /// Enables checking the number of arguments of builtin functions, see
+    /// [`Template::set_strict_parse`].
+    pub fn strict_parse(mut self, enabled: bool) -> TemplateBuilder {
+        self.template.set_strict_parse(enabled);
+        self
+    }
+
+    /// Adds `text` to be parsed as template body, see [`Template::parse`].
+    pub fn parse<T: Into<String>>(mut self, text: T) -> TemplateBuilder {
+        self.sources.push(Source::Text(text.into()));
+        self
+    }
+
+    /// Adds a file to be parsed. Like Golang's `ParseFiles` its content is the template named
+    /// after the base name of the file, which is the template itself if the names match.
+    pub fn parse_file<P: AsRef<Path>>(mut self, path: P) -> TemplateBuilder {
+        self.sources.push(Source::File(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Creates the template, failing if a text or file can't be read or parsed.
+    pub fn build(self) -> Result<Template, TemplateError> {
+        let mut tmpl = self.template;
+        if tmpl.name.is_empty() {
+            if let Some(Source::File(path)) =
+                self.sources.iter().find(|s| matches!(s, Source::File(_)))
+            {
+                tmpl.name = file_name(path);
+            }
+        }
+        for source in self.sources {
+            match source {
+                Source::Text(text) => {
+                    tmpl.parse(text)?;
+                }
+                Source::File(path) => {
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| TemplateError::Read(path.clone(), e))?;
+                    let name = file_name(&path);
+                    if name == tmpl.name {
+                        tmpl.parse(text)?;
+                    } else {
+                        tmpl.add_template(name, text)?;
+                    }
+                }
+            }
+        }
+        Ok(tmpl)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::exec::Context;
+    use gtmpl_value::{FuncError, Value};
+
+    fn foo(_: &[Value]) -> Result<Value, FuncError> {
+        Ok(Value::from("foo"))
+    }
+
+    #[test]
+    fn test_builtins() {
+        let t = Template::builder().build().unwrap();
+        assert!(t.funcs.contains("printf"));
+        let t = Template::builder().without_builtins().build().unwrap();
+        assert!(t.funcs.is_empty());
+    }
+
+    #[test]
+    fn test_funcs() {
+        let t = Template::builder()
+            .funcs(&[("foo", foo as Func), ("len", foo as Func)])
+            .build()
+            .unwrap();
+        assert!(t.funcs.contains("printf"));
+        assert!(t.funcs.contains("foo"));
+        let len = t.funcs.get("len").unwrap();
+        assert_eq!(len(&[]).unwrap(), Value::from("foo"));
+    }
+
+    #[test]
+    fn test_try_add_func() {
+        let mut t = Template::default();
+        assert!(matches!(
+            t.try_add_func("len", foo),
+            Err(TemplateError::FuncAlreadyDefined(ref name)) if name == "len"
+        ));
+        assert!(t.try_add_func("foo", foo).is_ok());
+        assert!(t.try_add_func("foo", foo).is_err());
+        assert!(t.try_add_func("include", foo).is_err());
+        t.add_iterator("items", |_: &[Value]| {
+            Ok(Box::new(std::iter::empty()) as Box<dyn Iterator<Item = Value>>)
+        });
+        assert!(t.try_add_func("items", foo).is_err());
+        assert!(Template::empty().try_add_func("include", foo).is_ok());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main.tmpl");
+        let part = dir.path().join("part.tmpl");
+        fs::write(&main, r#"a <% template "part.tmpl" . %>"#).unwrap();
+        fs::write(&part, "<% .x %>").unwrap();
+        let t = Template::builder()
+            .parse_file(&main)
+            .parse_file(&part)
+            .delims("<%", "%>")
+            .option(MissingKey::Error)
+            .build()
+            .unwrap();
+        assert_eq!(t.name, "main.tmpl");
+        let data = Value::Map(vec![("x".to_owned(), Value::from(1))].into_iter().collect());
+        assert_eq!(t.render(&Context::from(data)).unwrap(), "a 1");
+        let err = t.render(&Context::from(Value::Map(Default::default())));
+        assert_eq!(err.unwrap_err().context().unwrap().origin, "part.tmpl");
+
+        let t = Template::builder()
+            .name("part.tmpl")
+            .parse_file(&part)
+            .build()
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "<% .x %>");
+
+        let missing = dir.path().join("missing.tmpl");
+        assert!(matches!(
+            Template::builder().parse_file(&missing).build(),
+            Err(TemplateError::Read(ref path, _)) if path == &missing
+        ));
+    }
+}
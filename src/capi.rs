@@ -0,0 +1,278 @@
+//! C ABI for embedding gtmpl in other languages.
+//!
+//! Requires the `capi` feature. Build a library with e.g.
+//! `cargo rustc --release --features capi --crate-type staticlib` and generate a header with
+//! `cbindgen --config cbindgen.toml --output gtmpl.h`.
+//!
+//! Functions return a [`GtmplStatus`], [`GtmplStatus::Ok`] on success. The message of the last failure
+//! of a template is available via [`gtmpl_template_error`]. Strings returned by gtmpl are owned
+//! by the caller and have to be freed with [`gtmpl_string_free`].
+//!
+//! ```rust
+//! use std::ffi::{CStr, CString};
+//! use std::ptr;
+//!
+//! use gtmpl::capi::*;
+//!
+//! unsafe {
+//!     let tmpl = gtmpl_template_new(ptr::null());
+//!     let text = CString::new("Hello {{ .name }}!").unwrap();
+//!     assert_eq!(gtmpl_template_parse(tmpl, text.as_ptr()), GtmplStatus::Ok);
+//!
+//!     let data = CString::new(r#"{"name": "C"}"#).unwrap();
+//!     let mut out = ptr::null_mut();
+//!     assert_eq!(gtmpl_template_render(tmpl, data.as_ptr(), &mut out), GtmplStatus::Ok);
+//!     assert_eq!(CStr::from_ptr(out).to_str(), Ok("Hello C!"));
+//!     gtmpl_string_free(out);
+//!
+//!     let data = CString::new("{").unwrap();
+//!     let status = gtmpl_template_render(tmpl, data.as_ptr(), &mut out);
+//!     assert_eq!(status, GtmplStatus::InvalidJson);
+//!     assert!(!gtmpl_template_error(tmpl).is_null());
+//!     gtmpl_template_free(tmpl);
+//! }
+//! ```
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::exec::{from_json, Context};
+use crate::template::Template;
+
+/// Result of a C API call. The values are stable.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GtmplStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// A string argument was not valid UTF-8.
+    InvalidUtf8 = 2,
+    /// The template text could not be parsed.
+    ParseError = 3,
+    /// The context was not valid JSON.
+    InvalidJson = 4,
+    /// Executing the template failed.
+    ExecError = 5,
+    /// The output contained a nul byte.
+    InvalidOutput = 6,
+}
+
+/// A template together with the message of its last failure.
+pub struct GtmplTemplate {
+    template: Template,
+    error: Option<CString>,
+}
+
+impl GtmplTemplate {
+    fn fail(&mut self, status: GtmplStatus, msg: String) -> GtmplStatus {
+        self.error = CString::new(msg).ok();
+        status
+    }
+}
+
+/// Creates a template with Golang's builtin functions. `name` may be null for an unnamed
+/// template. Returns null if `name` is not valid UTF-8.
+///
+/// # Safety
+///
+/// `name` has to be null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_new(name: *const c_char) -> *mut GtmplTemplate {
+    let template = if name.is_null() {
+        Template::default()
+    } else {
+        match CStr::from_ptr(name).to_str() {
+            Ok(name) => Template::with_name(name),
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    Box::into_raw(Box::new(GtmplTemplate {
+        template,
+        error: None,
+    }))
+}
+
+/// Parses `text` as body of the template, see [`Template::parse`].
+///
+/// # Safety
+///
+/// `tmpl` has to be created by [`gtmpl_template_new`], `text` has to be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_parse(
+    tmpl: *mut GtmplTemplate,
+    text: *const c_char,
+) -> GtmplStatus {
+    let tmpl = match tmpl.as_mut() {
+        Some(tmpl) => tmpl,
+        None => return GtmplStatus::NullPointer,
+    };
+    let text = match to_str(tmpl, text) {
+        Ok(text) => text,
+        Err(status) => return status,
+    };
+    match tmpl.template.parse(text) {
+        Ok(_) => GtmplStatus::Ok,
+        Err(e) => tmpl.fail(GtmplStatus::ParseError, e.to_string()),
+    }
+}
+
+/// Adds the template `name` with the body `text`, see [`Template::add_template`].
+///
+/// # Safety
+///
+/// `tmpl` has to be created by [`gtmpl_template_new`], `name` and `text` have to be nul
+/// terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_add(
+    tmpl: *mut GtmplTemplate,
+    name: *const c_char,
+    text: *const c_char,
+) -> GtmplStatus {
+    let tmpl = match tmpl.as_mut() {
+        Some(tmpl) => tmpl,
+        None => return GtmplStatus::NullPointer,
+    };
+    let (name, text) = match (to_str(tmpl, name), to_str(tmpl, text)) {
+        (Ok(name), Ok(text)) => (name, text),
+        (Err(status), _) | (_, Err(status)) => return status,
+    };
+    match tmpl.template.add_template(name, text) {
+        Ok(_) => GtmplStatus::Ok,
+        Err(e) => tmpl.fail(GtmplStatus::ParseError, e.to_string()),
+    }
+}
+
+/// Renders the template with the JSON document `json` as dot. On success `*out` points to the
+/// output, which has to be freed with [`gtmpl_string_free`].
+///
+/// # Safety
+///
+/// `tmpl` has to be created by [`gtmpl_template_new`], `json` has to be a nul terminated string
+/// and `out` has to be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_render(
+    tmpl: *mut GtmplTemplate,
+    json: *const c_char,
+    out: *mut *mut c_char,
+) -> GtmplStatus {
+    let tmpl = match tmpl.as_mut() {
+        Some(tmpl) => tmpl,
+        None => return GtmplStatus::NullPointer,
+    };
+    if out.is_null() {
+        return GtmplStatus::NullPointer;
+    }
+    let json = match to_str(tmpl, json) {
+        Ok(json) => json,
+        Err(status) => return status,
+    };
+    let data = match serde_json::from_str(json) {
+        Ok(data) => Context::from(from_json(data)),
+        Err(e) => return tmpl.fail(GtmplStatus::InvalidJson, e.to_string()),
+    };
+    let output = match tmpl.template.render(&data) {
+        Ok(output) => output,
+        Err(e) => return tmpl.fail(GtmplStatus::ExecError, e.to_string()),
+    };
+    match CString::new(output) {
+        Ok(output) => {
+            *out = output.into_raw();
+            GtmplStatus::Ok
+        }
+        Err(e) => tmpl.fail(GtmplStatus::InvalidOutput, e.to_string()),
+    }
+}
+
+/// Returns the message of the last failure of `tmpl` or null. The message stays valid until
+/// the next call with `tmpl`.
+///
+/// # Safety
+///
+/// `tmpl` has to be null or created by [`gtmpl_template_new`].
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_error(tmpl: *const GtmplTemplate) -> *const c_char {
+    tmpl.as_ref()
+        .and_then(|tmpl| tmpl.error.as_ref())
+        .map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Frees a template.
+///
+/// # Safety
+///
+/// `tmpl` has to be null or created by [`gtmpl_template_new`] and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_template_free(tmpl: *mut GtmplTemplate) {
+    if !tmpl.is_null() {
+        drop(Box::from_raw(tmpl));
+    }
+}
+
+/// Frees a string returned by gtmpl.
+///
+/// # Safety
+///
+/// `s` has to be null or returned by gtmpl and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn gtmpl_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn to_str<'s>(tmpl: &mut GtmplTemplate, s: *const c_char) -> Result<&'s str, GtmplStatus> {
+    if s.is_null() {
+        return Err(GtmplStatus::NullPointer);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| tmpl.fail(GtmplStatus::InvalidUtf8, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_capi() {
+        unsafe {
+            let name = c("main");
+            let tmpl = gtmpl_template_new(name.as_ptr());
+            let (part, part_text) = (c("part"), c("{{ .x }}"));
+            let status = gtmpl_template_add(tmpl, part.as_ptr(), part_text.as_ptr());
+            assert_eq!(status, GtmplStatus::Ok);
+            let text = c(r#"a {{ template "part" . }}"#);
+            assert_eq!(gtmpl_template_parse(tmpl, text.as_ptr()), GtmplStatus::Ok);
+            assert!(gtmpl_template_error(tmpl).is_null());
+
+            let mut out = ptr::null_mut();
+            let data = c(r#"{"x": "b"}"#);
+            let status = gtmpl_template_render(tmpl, data.as_ptr(), &mut out);
+            assert_eq!(status, GtmplStatus::Ok);
+            assert_eq!(CStr::from_ptr(out).to_str(), Ok("a b"));
+            gtmpl_string_free(out);
+
+            let text = c("{{ if }}");
+            let status = gtmpl_template_parse(tmpl, text.as_ptr());
+            assert_eq!(status, GtmplStatus::ParseError);
+            let err = CStr::from_ptr(gtmpl_template_error(tmpl));
+            assert!(err.to_str().unwrap().contains("missing value for if"));
+
+            let text = c("{{ .x.y }}");
+            assert_eq!(gtmpl_template_parse(tmpl, text.as_ptr()), GtmplStatus::Ok);
+            let status = gtmpl_template_render(tmpl, data.as_ptr(), &mut out);
+            assert_eq!(status, GtmplStatus::ExecError);
+
+            let status = gtmpl_template_render(tmpl, ptr::null(), &mut out);
+            assert_eq!(status, GtmplStatus::NullPointer);
+            let status = gtmpl_template_render(ptr::null_mut(), data.as_ptr(), &mut out);
+            assert_eq!(status, GtmplStatus::NullPointer);
+            gtmpl_template_free(tmpl);
+        }
+    }
+}
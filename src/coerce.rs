@@ -0,0 +1,349 @@
+//! Conversion of function arguments as done by [`gtmpl_fn!`](crate::gtmpl_fn).
+//!
+//! Parameters are converted with [`Coerce`]. Integers are accepted for floats, also within
+//! arrays and maps, so `{{ half 3 }}` works for a parameter of type `f64`. Wrap a parameter in
+//! [`Lenient`] to also accept numbers given as strings and the other way round.
+//!
+//! ```rust
+//! use gtmpl::coerce::Lenient;
+//! use gtmpl::{gtmpl_fn, Context, FuncError, Template};
+//!
+//! gtmpl_fn!(
+//! fn scale(x: f64, factor: Lenient<f64>) -> Result<f64, FuncError> {
+//!     Ok(x * factor.0)
+//! });
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_func("scale", scale);
+//! tmpl.parse(r#"{{ scale 3 "1.5" }}"#).unwrap();
+//! assert_eq!(tmpl.render(&Context::empty()).unwrap(), "4.5");
+//!
+//! tmpl.parse(r#"{{ scale "3" 1 }}"#).unwrap();
+//! assert_eq!(
+//!     tmpl.render(&Context::empty()).unwrap_err().to_string(),
+//!     r#"function `scale` failed: argument 1 of scale: expected number, got string "3""#
+//! );
+//! ```
+use std::any::type_name;
+use std::collections::HashMap;
+
+use gtmpl_value::{FromValue, FuncError, Value};
+
+// Longer strings are left out of error messages.
+const MAX_QUOTED_LEN: usize = 32;
+
+/// Conversion of a function argument.
+///
+/// Implement it for own parameter types, e.g. by delegating to [`FromValue`]:
+///
+/// ```rust
+/// use std::convert::TryFrom;
+///
+/// use gtmpl::coerce::Coerce;
+/// use gtmpl::Value;
+///
+/// struct Port(u16);
+///
+/// impl Coerce for Port {
+///     fn coerce(val: &Value) -> Option<Port> {
+///         u64::coerce(val).and_then(|p| u16::try_from(p).ok()).map(Port)
+///     }
+///
+///     fn description() -> String {
+///         "port number".to_owned()
+///     }
+/// }
+/// ```
+pub trait Coerce: Sized {
+    /// Tries to convert `val`, widening numbers where needed.
+    fn coerce(val: &Value) -> Option<Self>;
+
+    /// Describes the expected value for error messages, e.g. `integer`.
+    ///
+    /// Defaults to the name of the type without its module path.
+    fn description() -> String {
+        short_type_name(type_name::<Self>())
+    }
+}
+
+impl Coerce for i64 {
+    fn coerce(val: &Value) -> Option<i64> {
+        i64::from_value(val)
+    }
+
+    fn description() -> String {
+        "integer".to_owned()
+    }
+}
+
+impl Coerce for u64 {
+    fn coerce(val: &Value) -> Option<u64> {
+        u64::from_value(val)
+    }
+
+    fn description() -> String {
+        "non-negative integer".to_owned()
+    }
+}
+
+impl Coerce for f64 {
+    fn coerce(val: &Value) -> Option<f64> {
+        match val {
+            Value::Number(n) => n
+                .as_f64()
+                .or_else(|| n.as_i64().map(|i| i as f64))
+                .or_else(|| n.as_u64().map(|u| u as f64)),
+            _ => None,
+        }
+    }
+
+    fn description() -> String {
+        "number".to_owned()
+    }
+}
+
+impl Coerce for bool {
+    fn coerce(val: &Value) -> Option<bool> {
+        match val {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn description() -> String {
+        "bool".to_owned()
+    }
+}
+
+impl Coerce for String {
+    fn coerce(val: &Value) -> Option<String> {
+        String::from_value(val)
+    }
+
+    fn description() -> String {
+        "string".to_owned()
+    }
+}
+
+impl Coerce for Value {
+    fn coerce(val: &Value) -> Option<Value> {
+        Some(val.clone())
+    }
+
+    fn description() -> String {
+        "value".to_owned()
+    }
+}
+
+impl<T: Coerce> Coerce for Vec<T> {
+    fn coerce(val: &Value) -> Option<Vec<T>> {
+        match val {
+            Value::Array(a) => a.iter().map(T::coerce).collect(),
+            _ => None,
+        }
+    }
+
+    fn description() -> String {
+        format!("array of {}", T::description())
+    }
+}
+
+impl<T: Coerce> Coerce for HashMap<String, T> {
+    fn coerce(val: &Value) -> Option<HashMap<String, T>> {
+        match val {
+            Value::Map(m) | Value::Object(m) => m
+                .iter()
+                .map(|(k, v)| T::coerce(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn description() -> String {
+        format!("map of {}", T::description())
+    }
+}
+
+/// A parameter also accepting numbers as strings and the other way round, e.g. `"8080"` for a
+/// `Lenient<u64>` or `8080` for a `Lenient<String>`.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct Lenient<T>(pub T);
+
+impl<T: Coerce> Coerce for Lenient<T> {
+    fn coerce(val: &Value) -> Option<Lenient<T>> {
+        if let Some(v) = T::coerce(val) {
+            return Some(Lenient(v));
+        }
+        let alt = match val {
+            Value::String(s) => parse_number(s.trim())?,
+            Value::Number(n) => Value::from(n.to_string()),
+            _ => return None,
+        };
+        T::coerce(&alt).map(Lenient)
+    }
+
+    fn description() -> String {
+        T::description()
+    }
+}
+
+/// Converts the argument at `index`, starting at 0, of the function `func`.
+///
+/// The error names the function, the position of the argument, the
+/// [description](Coerce::description) of the expected value and the given value.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{coerce, Value};
+///
+/// let x: f64 = coerce::arg("sqrt", 0, &Value::from(4u64)).unwrap();
+/// assert_eq!(x, 4.0);
+/// let err = coerce::arg::<Vec<i64>>("sum", 1, &Value::from(4)).unwrap_err();
+/// assert_eq!(err.to_string(), "argument 2 of sum: expected array of integer, got integer 4");
+/// ```
+pub fn arg<T: Coerce>(func: &str, index: usize, val: &Value) -> Result<T, FuncError> {
+    T::coerce(val).ok_or_else(|| {
+        FuncError::Generic(format!(
+            "argument {} of {}: expected {}, got {}",
+            index + 1,
+            func,
+            T::description(),
+            describe(val)
+        ))
+    })
+}
+
+fn parse_number(s: &str) -> Option<Value> {
+    if let Ok(u) = s.parse::<u64>() {
+        return Some(Value::from(u));
+    }
+    if let Ok(i) = s.parse::<i64>() {
+        return Some(Value::from(i));
+    }
+    s.parse::<f64>()
+        .ok()
+        .filter(|f| f.is_finite())
+        .map(Value::from)
+}
+
+fn describe(val: &Value) -> String {
+    match val {
+        Value::NoValue => "no value".to_owned(),
+        Value::Nil => "nil".to_owned(),
+        Value::Bool(b) => format!("bool {}", b),
+        Value::Number(n) if n.as_f64().is_some() => format!("float {}", n),
+        Value::Number(n) => format!("integer {}", n),
+        Value::String(s) if s.len() <= MAX_QUOTED_LEN => format!("string {:?}", s),
+        Value::String(_) => "string".to_owned(),
+        Value::Array(_) => "array".to_owned(),
+        Value::Map(_) => "map".to_owned(),
+        Value::Object(_) => "object".to_owned(),
+        Value::Function(_) => "function".to_owned(),
+    }
+}
+
+// Drops the module paths, `alloc::vec::Vec<alloc::string::String>` becomes `Vec<String>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut segment = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            segment.push(c);
+            continue;
+        }
+        short.push_str(segment.rsplit("::").next().unwrap_or_default());
+        segment.clear();
+        short.push(c);
+    }
+    short.push_str(segment.rsplit("::").next().unwrap_or_default());
+    short
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_arg() {
+        assert_eq!(arg::<i64>("f", 0, &Value::from(3u64)).unwrap(), 3);
+        assert_eq!(arg::<u64>("f", 0, &Value::from(3i64)).unwrap(), 3);
+        assert_eq!(arg::<f64>("f", 0, &Value::from(-3i64)).unwrap(), -3.0);
+        assert_eq!(
+            arg::<Vec<f64>>("f", 0, &Value::from(vec![1, 2])).unwrap(),
+            vec![1.0, 2.0]
+        );
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), Value::from(1));
+        let m: HashMap<String, f64> = arg("f", 0, &Value::Map(m)).unwrap();
+        assert_eq!(m["a"], 1.0);
+
+        let err = |res: Result<i64, FuncError>| res.unwrap_err().to_string();
+        assert_eq!(
+            err(arg("f", 0, &Value::from(1.5))),
+            "argument 1 of f: expected integer, got float 1.5"
+        );
+        assert_eq!(
+            err(arg("f", 2, &Value::from("x".repeat(40)))),
+            "argument 3 of f: expected integer, got string"
+        );
+        assert_eq!(
+            err(arg("f", 0, &Value::Nil)),
+            "argument 1 of f: expected integer, got nil"
+        );
+        assert!(arg::<Vec<u64>>("f", 0, &Value::from(vec![-1, 2])).is_err());
+        assert!(arg::<bool>("f", 0, &Value::from(true)).unwrap());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let lenient = |val: Value| arg::<Lenient<f64>>("f", 0, &val).map(|l| l.0);
+        assert_eq!(lenient(Value::from(" 2.5 ")).unwrap(), 2.5);
+        assert_eq!(lenient(Value::from("2")).unwrap(), 2.0);
+        let int: Lenient<i64> = arg("f", 0, &Value::from("2.0")).unwrap();
+        assert_eq!(int.0, 2);
+        assert!(lenient(Value::from("inf")).is_err());
+        assert!(lenient(Value::from(true)).is_err());
+        let s: Lenient<String> = arg("f", 0, &Value::from(-2)).unwrap();
+        assert_eq!(s.0, "-2");
+        let v: Vec<Lenient<u64>> = arg("f", 0, &Value::from(vec!["1", "2"])).unwrap();
+        assert_eq!(v, vec![Lenient(1), Lenient(2)]);
+        assert_eq!(
+            arg::<Lenient<u64>>("f", 0, &Value::from("-1"))
+                .unwrap_err()
+                .to_string(),
+            r#"argument 1 of f: expected non-negative integer, got string "-1""#
+        );
+    }
+
+    #[test]
+    fn test_description() {
+        struct Port;
+        impl Coerce for Port {
+            fn coerce(_: &Value) -> Option<Port> {
+                None
+            }
+        }
+        assert_eq!(Lenient::<String>::description(), "string");
+        assert_eq!(
+            HashMap::<String, Vec<Lenient<f64>>>::description(),
+            "map of array of number"
+        );
+        assert_eq!(Port::description(), "Port");
+        assert_eq!(
+            arg::<Vec<u64>>("f", 0, &Value::from(vec![-1]))
+                .unwrap_err()
+                .to_string(),
+            "argument 1 of f: expected array of non-negative integer, got array"
+        );
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name(type_name::<i64>()), "i64");
+        assert_eq!(
+            short_type_name(type_name::<HashMap<String, Vec<Lenient<f64>>>>()),
+            "HashMap<String, Vec<Lenient<f64>>>"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use crate::node::{Nodes, Pos};
+use crate::parse::Tree;
+use crate::path::lookup_field;
+use crate::template::Template;
+
+use gtmpl_value::Value;
+
+/// What a [`Completion`] candidate refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionKind {
+    Field,
+    Function,
+}
+
+/// A completion candidate as returned by [`Template::completions_at`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+}
+
+impl Template {
+    /// Returns the innermost node at byte `offset` of the text passed to [`Template::parse`].
+    ///
+    /// Besides the main tree this also searches templates defined within that text.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::node::Nodes;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse("Hello {{ .name }}!").unwrap();
+    /// assert!(matches!(tmpl.node_at(10), Some(Nodes::Field(_))));
+    /// ```
+    pub fn node_at(&self, offset: Pos) -> Option<&Nodes> {
+        self.tree_node_at(offset).map(|(_, node)| node)
+    }
+
+    /// Returns fields of `data` and function names which complete the identifier at `offset`.
+    ///
+    /// `data` is taken as dot, changes of dot by `with` or `range` are not followed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Completion, CompletionKind, Value};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut user = HashMap::new();
+    /// user.insert("name".to_owned(), Value::from("gopher"));
+    /// user.insert("nick".to_owned(), Value::from("go"));
+    /// user.insert("age".to_owned(), Value::from(7));
+    /// let mut data = HashMap::new();
+    /// data.insert("user".to_owned(), Value::Map(user));
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse("Hello {{ .user.n }}!").unwrap();
+    /// let labels: Vec<_> = tmpl
+    ///     .completions_at(16, &Value::Map(data))
+    ///     .into_iter()
+    ///     .map(|c| c.label)
+    ///     .collect();
+    /// assert_eq!(labels, vec!["name", "nick"]);
+    /// ```
+    pub fn completions_at(&self, offset: Pos, data: &Value) -> Vec<Completion> {
+        let (tree, node) = match self.tree_node_at(offset) {
+            Some(found) => found,
+            None => return vec![],
+        };
+        let start = node.span().start;
+        let typed = tree.source().get(start..offset).unwrap_or_default();
+        match *node {
+            Nodes::Dot(_) => field_completions(data, &[], ""),
+            Nodes::Field(_) => {
+                let path: Vec<&str> = typed.trim_start_matches('.').split('.').collect();
+                let (prefix, path) = path.split_last().unwrap_or((&"", &[]));
+                field_completions(data, path, prefix)
+            }
+            Nodes::Variable(_) if typed.starts_with("$.") => {
+                let path: Vec<&str> = typed["$.".len()..].split('.').collect();
+                let (prefix, path) = path.split_last().unwrap_or((&"", &[]));
+                field_completions(data, path, prefix)
+            }
+            Nodes::Identifier(_) => self.func_completions(typed),
+            Nodes::Action(_)
+            | Nodes::If(_)
+            | Nodes::Range(_)
+            | Nodes::With(_)
+            | Nodes::Extension(_) => self.func_completions(""),
+            _ => vec![],
+        }
+    }
+
+    fn tree_node_at(&self, offset: Pos) -> Option<(&Tree, &Nodes)> {
+        let main = self.tree_set.get(&self.name)?;
+        self.tree_set
+            .values()
+            .filter(|t| t.same_source(main))
+            .find_map(|t| t.root.as_ref()?.node_at(offset).map(|n| (t, n)))
+    }
+
+    fn func_completions(&self, prefix: &str) -> Vec<Completion> {
+        let mut names: Vec<&str> = self
+            .funcs
+            .names()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort_unstable();
+        names
+            .into_iter()
+            .map(|name| Completion {
+                label: name.to_owned(),
+                kind: CompletionKind::Function,
+            })
+            .collect()
+    }
+}
+
+fn field_completions(data: &Value, path: &[&str], prefix: &str) -> Vec<Completion> {
+    let value = match path.iter().try_fold(data, |v, name| lookup_field(v, name)) {
+        Some(v) => v,
+        None => return vec![],
+    };
+    let mut names: Vec<&String> = fields(value)
+        .map(|f| f.keys().filter(|k| k.starts_with(prefix)).collect())
+        .unwrap_or_default();
+    names.sort_unstable();
+    names
+        .into_iter()
+        .map(|name| Completion {
+            label: name.clone(),
+            kind: CompletionKind::Field,
+        })
+        .collect()
+}
+
+fn fields(value: &Value) -> Option<&HashMap<String, Value>> {
+    match *value {
+        Value::Object(ref o) | Value::Map(ref o) => Some(o),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Value {
+        let mut user = HashMap::new();
+        user.insert("name".to_owned(), Value::from("gopher"));
+        user.insert("age".to_owned(), Value::from(7));
+        let mut data = HashMap::new();
+        data.insert("user".to_owned(), Value::Object(user));
+        data.insert("title".to_owned(), Value::from("x"));
+        Value::Object(data)
+    }
+
+    fn labels(c: Vec<Completion>) -> Vec<String> {
+        c.into_iter().map(|c| c.label).collect()
+    }
+
+    #[test]
+    fn test_node_at() {
+        let mut t = Template::with_name("t");
+        t.parse(r#"a{{ if .user }}{{ len .title }}{{ end }}{{ define "d" }}{{ $.x }}{{ end }}"#)
+            .unwrap();
+        t.add_template("other", "{{ .other }}").unwrap();
+        assert!(matches!(t.node_at(0), Some(Nodes::Text(_))));
+        assert!(matches!(t.node_at(8), Some(Nodes::Field(_))));
+        assert!(matches!(t.node_at(13), Some(Nodes::If(_))));
+        assert!(matches!(t.node_at(19), Some(Nodes::Identifier(_))));
+        assert!(matches!(t.node_at(60), Some(Nodes::Variable(_))));
+        assert!(t.node_at(100).is_none());
+    }
+
+    #[test]
+    fn test_completions_at() {
+        let mut t = Template::with_name("t");
+        t.add_func("lex", |_| Ok(Value::Nil));
+        t.parse("{{ .user.a }}{{ . }}{{ $.t }}{{ lex .title }}")
+            .unwrap();
+        assert_eq!(labels(t.completions_at(10, &data())), vec!["age"]);
+        assert_eq!(labels(t.completions_at(4, &data())), vec!["title", "user"]);
+        assert_eq!(labels(t.completions_at(8, &data())), vec!["user"]);
+        assert_eq!(labels(t.completions_at(17, &data())), vec!["title", "user"]);
+        assert_eq!(labels(t.completions_at(26, &data())), vec!["title"]);
+        assert_eq!(
+            labels(t.completions_at(34, &data())),
+            vec!["le", "len", "lex"]
+        );
+        assert_eq!(
+            t.completions_at(34, &data())[0].kind,
+            CompletionKind::Function
+        );
+        assert!(t.completions_at(100, &data()).is_empty());
+    }
+}
@@ -1,17 +1,45 @@
+use crate::lexer::Span;
 use crate::node::{ChainNode, CommandNode, Nodes, PipeNode};
+use crate::parse::Tree;
 use gtmpl_value::{FuncError, Value};
 use std::{fmt, num::ParseIntError, string::FromUtf8Error};
 use thiserror::Error;
 
 #[derive(Debug)]
 pub struct ErrorContext {
+    /// Name of the template, which may be defined via `define` or `block`.
     pub name: String,
+    /// Name of the template or file the template is part of, see [`Tree::origin`].
+    pub origin: String,
     pub line: usize,
+    /// Column in chars, starting at 1.
+    pub col: usize,
+    /// Byte range of the offending item in the template source.
+    pub span: Span,
+}
+
+impl ErrorContext {
+    // Locates `span` of a node of `tree`.
+    pub(crate) fn in_tree(tree: &Tree, span: Span) -> ErrorContext {
+        let before = tree.source().get(..span.start).unwrap_or_default();
+        let line = match before.rfind('\n') {
+            Some(i) => &before[i + 1..],
+            // The lexer skips a byte order mark.
+            None => before.strip_prefix('\u{feff}').unwrap_or(before),
+        };
+        ErrorContext {
+            name: tree.name().to_owned(),
+            origin: tree.origin().to_owned(),
+            line: before.matches('\n').count() + 1,
+            col: line.chars().count() + 1,
+            span,
+        }
+    }
 }
 
 impl fmt::Display for ErrorContext {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{}", self.name, self.line)
+        write!(f, "{}:{}", self.origin, self.line)
     }
 }
 
@@ -29,32 +57,82 @@ pub enum ParseError {
     NodeError(#[from] NodeError),
     #[error("enable gtmpl_dynamic_template to use a pipeline as name")]
     NoDynamicTemplate,
-    #[error("unable to parse string: {0}")]
-    UnableToParseString(String),
+    #[error("unable to parse string {0}: {1}")]
+    UnableToParseString(String, UnquoteError),
+    #[error("{0} is not a single pipeline")]
+    NotAPipeline(String),
 }
 
 impl ParseError {
-    pub fn with_context(name: impl ToString, line: usize, msg: impl ToString) -> Self {
+    pub fn with_context(
+        name: impl ToString,
+        origin: impl ToString,
+        line: usize,
+        col: usize,
+        span: Span,
+        msg: impl ToString,
+    ) -> Self {
         Self::WithContext(
             ErrorContext {
                 name: name.to_string(),
+                origin: origin.to_string(),
                 line,
+                col,
+                span,
             },
             msg.to_string(),
         )
     }
+
+    /// Where the parser failed, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            ParseError::WithContext(ctx, _) => Some(ctx),
+            _ => None,
+        }
+    }
+
+    /// Byte range of the item the parser failed at, if known.
+    pub fn span(&self) -> Option<Span> {
+        self.context().map(|ctx| ctx.span)
+    }
+
+    /// Name of the template the parser failed in, if known.
+    pub fn template(&self) -> Option<&str> {
+        self.context().map(|ctx| ctx.name.as_str())
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum NodeError {
-    #[error("unable to unquote")]
-    UnquoteError,
+    #[error("unable to unquote: {0}")]
+    UnquoteError(#[from] UnquoteError),
     #[error("NaN")]
     NaN,
     #[error("not a tree node")]
     NaTN,
 }
 
+#[derive(Error, Debug, PartialEq)]
+pub enum UnquoteError {
+    #[error("missing quotes")]
+    MissingQuotes,
+    #[error("unterminated escape sequence")]
+    UnterminatedEscape,
+    #[error("invalid escape sequence \\{0}")]
+    InvalidEscape(char),
+    #[error("invalid hex digit {1:?} in \\{0} escape")]
+    InvalidHexDigit(char, char),
+    #[error("invalid code point {0:X}")]
+    InvalidCodePoint(u32),
+    #[error("unpaired surrogate")]
+    UnpairedSurrogate,
+    #[error("escaped bytes are not valid UTF-8")]
+    InvalidUtf8,
+    #[error("{0} is not a single character")]
+    NotASingleChar(String),
+}
+
 #[derive(Error, Debug)]
 pub enum PrintError {
     #[error("unable to process verb: {0}")]
@@ -99,6 +177,10 @@ pub enum ExecError {
     PipelineMustYieldString,
     #[error("template {0} not defined")]
     TemplateNotDefined(String),
+    #[error("extension {0} not defined")]
+    ExtensionNotDefined(String),
+    #[error("no layout set for rendering pages")]
+    LayoutNotSet,
     #[error("exceeded max template depth")]
     MaxTemplateDepth,
     #[error("error evaluating pipe: {0}")]
@@ -121,14 +203,91 @@ pub enum ExecError {
     UndefinedFunction(String),
     #[error(transparent)]
     FuncError(#[from] FuncError),
+    #[error("function `{0}` failed: {1:#}")]
+    FuncFailed(String, FuncError),
     #[error("can't give argument to non-function {0}")]
     ArgumentForNonFunction(Nodes),
     #[error("only maps and objects have fields")]
     OnlyMapsAndObjectsHaveFields,
+    #[error("index {0} out of range for array of length {1}")]
+    IndexOutOfRange(usize, usize),
     #[error("no field {0} for {1}")]
     NoFiledFor(String, Value),
     #[error("variable {0} not found")]
     VariableNotFound(String),
+    #[error("no value for {0}")]
+    NoValue(String),
+    #[error("{1}")]
+    AtSpan(ErrorContext, Box<ExecError>),
+}
+
+impl ExecError {
+    /// Where the failing node is, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            ExecError::AtSpan(ctx, _) => Some(ctx),
+            _ => None,
+        }
+    }
+
+    /// Byte range of the node that failed, within the source of the template it belongs to.
+    pub fn span(&self) -> Option<Span> {
+        self.context().map(|ctx| ctx.span)
+    }
+
+    /// Name of the template the failing node belongs to, if known.
+    pub fn template(&self) -> Option<&str> {
+        self.context().map(|ctx| ctx.name.as_str())
+    }
+
+    /// Attaches the span of the failing node of `tree` unless a more specific one is already
+    /// attached.
+    pub(crate) fn at(self, tree: &Tree, span: Span) -> ExecError {
+        match self {
+            ExecError::AtSpan(..) => self,
+            e => ExecError::AtSpan(ErrorContext::in_tree(tree, span), Box::new(e)),
+        }
+    }
+}
+
+// Renders the line of `source` containing the start of `span` with the span underlined, like
+//
+// ```text
+// 2 | a {{ .x.y }}
+//   |   ^^^^^^^^^^
+// ```
+//
+// Returns `None` if `span` isn't a valid range of `source`.
+pub(crate) fn snippet(source: &str, span: Span) -> Option<String> {
+    let before = source.get(..span.start)?;
+    source.get(span.start..span.end)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line_start = if line_start == 0 && source.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8().min(span.start)
+    } else {
+        line_start
+    };
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    let number = (before.matches('\n').count() + 1).to_string();
+    let indent: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let marked = source[span.start..span.end.min(line_end)]
+        .trim_end_matches('\r')
+        .chars()
+        .count();
+    Some(format!(
+        "{} | {}\n{} | {}{}",
+        number,
+        line,
+        " ".repeat(number.len()),
+        indent,
+        "^".repeat(marked.max(1))
+    ))
 }
 
 #[derive(Error, Debug)]
@@ -137,4 +296,84 @@ pub enum TemplateError {
     ExecError(#[from] ExecError),
     #[error(transparent)]
     ParseError(#[from] ParseError),
+    #[error("function {0} is already defined")]
+    FuncAlreadyDefined(String),
+    #[error("template {0} is already defined")]
+    TemplateAlreadyDefined(String),
+    #[error("data {0} is already defined")]
+    DataAlreadyDefined(String),
+    #[error("extension {0} is already defined")]
+    ExtensionAlreadyDefined(String),
+    #[error("unable to read {0}: {1}")]
+    Read(std::path::PathBuf, std::io::Error),
+    #[cfg(feature = "gtmpl_json")]
+    #[error("unable to parse output as json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(feature = "notify")]
+#[derive(Error, Debug)]
+pub enum WatchError {
+    #[error("unable to read {0}: {1}")]
+    Read(std::path::PathBuf, std::io::Error),
+    #[error(transparent)]
+    TemplateError(#[from] TemplateError),
+    #[error(transparent)]
+    Notify(#[from] notify::Error),
+}
+
+/// Attaches context to errors returned from template functions.
+///
+/// Any error convertible into [`anyhow::Error`] is wrapped into [`FuncError::Other`] with the
+/// message on top. Failed calls display the whole chain, outermost context first.
+///
+/// ## Example
+/// ```rust
+/// use gtmpl::{Context, FuncError, FuncResultExt, Template, Value};
+///
+/// fn port(args: &[Value]) -> Result<Value, FuncError> {
+///     let port: u16 = args[0]
+///         .to_string()
+///         .parse()
+///         .func_context("invalid port")?;
+///     Ok(Value::from(port))
+/// }
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_func("port", port);
+/// tmpl.parse(r#"{{ port "http" }}"#).unwrap();
+/// let err = tmpl.render(&Context::empty()).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "function `port` failed: invalid port: invalid digit found in string"
+/// );
+/// ```
+pub trait FuncResultExt<T> {
+    /// Wraps the error with the given message.
+    fn func_context<C>(self, context: C) -> Result<T, FuncError>
+    where
+        C: fmt::Display + Send + Sync + 'static;
+
+    /// Wraps the error with a lazily built message.
+    fn with_func_context<C, F>(self, f: F) -> Result<T, FuncError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C;
+}
+
+impl<T, E: Into<anyhow::Error>> FuncResultExt<T> for Result<T, E> {
+    fn func_context<C>(self, context: C) -> Result<T, FuncError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        self.map_err(|e| FuncError::Other(e.into().context(context)))
+    }
+
+    fn with_func_context<C, F>(self, f: F) -> Result<T, FuncError>
+    where
+        C: fmt::Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|e| FuncError::Other(e.into().context(f())))
+    }
 }
@@ -0,0 +1,201 @@
+//! Escaping of the output of actions for the file type generated, see
+//! [`Template::set_output_mode`](crate::Template::set_output_mode).
+//!
+//! The functions can also be combined with own ones in [`OutputMode::Custom`] or registered as
+//! template functions.
+//!
+//! ```rust
+//! use gtmpl::escape::OutputMode;
+//! use gtmpl::{Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.set_output_mode(OutputMode::Yaml);
+//! tmpl.parse("name: {{ . }}\nraw: {{ 42 | raw }}").unwrap();
+//! let output = tmpl.render(&Context::from("a: \"b\""));
+//! assert_eq!(&output.unwrap(), "name: \"a: \\\"b\\\"\"\nraw: 42");
+//! ```
+use std::fmt::Write;
+use std::path::Path;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+/// Escaping applied to the output of every action like `{{ .name }}`, unless its pipeline ends
+/// with `raw` like `{{ .port | raw }}`. The output of the formatter is escaped as well.
+#[derive(Clone, Debug, Default)]
+pub enum OutputMode {
+    /// Prints the output as it is.
+    #[default]
+    Text,
+    /// Double quoted YAML scalars, see [`yaml`].
+    Yaml,
+    /// JSON strings, see [`json`].
+    Json,
+    /// Single quoted shell words, see [`shell`].
+    Shell,
+    /// Chooses the mode by the extension of the template being executed, i.e. `.yaml`, `.yml`,
+    /// `.json` and `.sh`, so one template set can generate different file types. Other
+    /// templates are printed as they are.
+    ByExtension,
+    /// Pipes the output through the given functions, each getting the result of the previous
+    /// one.
+    Custom(Vec<Func>),
+}
+
+impl OutputMode {
+    /// The mode for a file named `name` by its extension, [`OutputMode::Text`] if it is unknown.
+    pub fn from_extension(name: &str) -> OutputMode {
+        match Path::new(name).extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => OutputMode::Yaml,
+            Some("json") => OutputMode::Json,
+            Some("sh") => OutputMode::Shell,
+            _ => OutputMode::Text,
+        }
+    }
+
+    // The functions applied to output of the template `name`.
+    pub(crate) fn escapers(&self, name: &str) -> Vec<(&'static str, Func)> {
+        match self {
+            OutputMode::Text => vec![],
+            OutputMode::Yaml => vec![("yaml", yaml as Func)],
+            OutputMode::Json => vec![("json", json as Func)],
+            OutputMode::Shell => vec![("shell", shell as Func)],
+            OutputMode::ByExtension => OutputMode::from_extension(name).escapers(name),
+            OutputMode::Custom(funcs) => funcs.iter().map(|&f| ("escaper", f)).collect(),
+        }
+    }
+}
+
+/// Quotes the textual representation of its argument as double quoted YAML scalar.
+///
+/// # Example
+/// ```
+/// use gtmpl::escape::yaml;
+/// use gtmpl::Value;
+/// let quoted = yaml(&[Value::from("a\n\"b\"")]).unwrap();
+/// assert_eq!(quoted, Value::from(r#""a\n\"b\"""#));
+/// ```
+pub fn yaml(args: &[Value]) -> Result<Value, FuncError> {
+    let s = text("yaml", args)?;
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            '\u{85}' => out.push_str("\\N"),
+            '\u{2028}' => out.push_str("\\L"),
+            '\u{2029}' => out.push_str("\\P"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(Value::from(out))
+}
+
+/// Quotes the textual representation of its argument as JSON string.
+///
+/// # Example
+/// ```
+/// use gtmpl::escape::json;
+/// use gtmpl::Value;
+/// let quoted = json(&[Value::from("a\t\"b\"")]).unwrap();
+/// assert_eq!(quoted, Value::from(r#""a\t\"b\"""#));
+/// ```
+pub fn json(args: &[Value]) -> Result<Value, FuncError> {
+    let s = text("json", args)?;
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(Value::from(out))
+}
+
+/// Quotes the textual representation of its argument as single word for POSIX shells.
+///
+/// # Example
+/// ```
+/// use gtmpl::escape::shell;
+/// use gtmpl::Value;
+/// let quoted = shell(&[Value::from("it's $HOME")]).unwrap();
+/// assert_eq!(quoted, Value::from(r#"'it'\''s $HOME'"#));
+/// ```
+pub fn shell(args: &[Value]) -> Result<Value, FuncError> {
+    let s = text("shell", args)?;
+    Ok(Value::from(format!("'{}'", s.replace('\'', r"'\''"))))
+}
+
+fn text(name: &str, args: &[Value]) -> Result<String, FuncError> {
+    match args {
+        [Value::String(s)] => Ok(s.clone()),
+        [val] => Ok(val.to_string()),
+        _ => Err(FuncError::ExactlyXArgs(name.into(), 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    fn escape(f: Func, s: &str) -> String {
+        f(&[Value::from(s)]).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_yaml() {
+        assert_eq!(escape(yaml, ""), r#""""#);
+        assert_eq!(escape(yaml, "a: b # c"), r#""a: b # c""#);
+        assert_eq!(escape(yaml, "\\\u{1}\u{7f}\u{2028}é"), r#""\\\x01\x7f\Lé""#);
+        assert_eq!(yaml(&[Value::from(1)]).unwrap(), Value::from(r#""1""#));
+        assert!(yaml(&[]).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(escape(json, "\\\u{1}\r</"), r#""\\\u0001\r</""#);
+        assert_eq!(escape(json, "\u{8}\u{c}\u{7f}"), "\"\\b\\f\u{7f}\"");
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(escape(shell, ""), "''");
+        assert_eq!(escape(shell, "a b;$(c)"), "'a b;$(c)'");
+        assert_eq!(escape(shell, "''"), r"''\'''\'''");
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert!(matches!(
+            OutputMode::from_extension("a/values.yml"),
+            OutputMode::Yaml
+        ));
+        assert!(matches!(
+            OutputMode::from_extension("run.sh"),
+            OutputMode::Shell
+        ));
+        assert!(matches!(
+            OutputMode::from_extension("json"),
+            OutputMode::Text
+        ));
+    }
+}
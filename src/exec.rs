@@ -1,36 +1,268 @@
-use std::collections::VecDeque;
-use std::io::Write;
-
-use crate::error::ExecError;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::error::{ExecError, ParseError};
+use crate::funcs;
 use crate::node::*;
-use crate::template::Template;
-use crate::utils::is_true;
+use crate::parse::{parse, Tree};
+use crate::path::lookup_field;
+use crate::print_verb::go_string;
+use crate::profile::ExecHook;
+use crate::template::{GoVersion, Template};
+use crate::utils::{ensure_stack, is_true, lower_camel, snake_case};
 
-use gtmpl_value::{Func, Value};
+use gtmpl_value::{Func, FuncError, Value};
 
 const MAX_TEMPLATE_DEPTH: usize = 100_000;
 
-struct Variable {
-    name: String,
-    value: Value,
+static NO_VALUE: Value = Value::NoValue;
+
+struct Variable<'a> {
+    name: Cow<'a, str>,
+    value: Cow<'a, Value>,
+}
+
+impl Variable<'_> {
+    fn borrowed(&self) -> Variable<'_> {
+        Variable {
+            name: Cow::Borrowed(&*self.name),
+            value: Cow::Borrowed(&*self.value),
+        }
+    }
 }
 
 struct State<'a, 'b, T: Write> {
     template: &'a Template,
+    // The template being executed.
+    tree: &'a Tree,
     writer: &'b mut T,
     node: Option<&'a Nodes>,
-    vars: VecDeque<VecDeque<Variable>>,
+    vars: VecDeque<VecDeque<Variable<'a>>>,
     depth: usize,
+    context: &'a Context,
+    // Templates defined by the text passed to `tpl`, shadowing the ones of `template`.
+    local: Option<&'a HashMap<String, Tree>>,
+    exec: Execution<'a>,
+    // Set by `break` or `continue` until the enclosing `range` handled it.
+    control: Option<LoopControl>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LoopControl {
+    Break,
+    Continue,
+}
+
+// State shared by all templates of one execution.
+#[derive(Clone, Copy, Default)]
+struct Execution<'a> {
+    cache: Option<&'a RefCell<TemplateCache>>,
+    // Collects the output if it is rendered as segments.
+    segments: Option<&'a RefCell<Vec<Segment>>>,
+}
+
+impl<'a> Execution<'a> {
+    // For executions into a buffer, whose output is part of the calling action.
+    fn buffered(self) -> Execution<'a> {
+        Execution {
+            segments: None,
+            ..self
+        }
+    }
+}
+
+/// A piece of rendered output, see [`Template::render_segments`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    /// Name of the template containing the node which produced the text.
+    pub template: String,
+    /// Span of the node in the source of `template`.
+    pub origin_span: Span,
+    pub kind: SegmentKind,
+}
+
+/// Origin of a [`Segment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Literal text of the template.
+    Text,
+    /// The output of an action like `{{ .name }}` or `{{ include "x" . }}`.
+    Action,
+}
+
+/// Options for executing a template, see [`Template::set_exec_options`].
+#[derive(Clone, Default)]
+pub struct ExecOptions {
+    /// Caches the output of `template` actions and `include` calls during one execution, keyed
+    /// by the template name and the data passed. An invocation with data equal to an earlier
+    /// one writes the earlier output instead of executing the template again.
+    ///
+    /// Only enable it if invoked templates render the same output for the same data, i.e. don't
+    /// call functions like `now` or `uuidv4`. The data is hashed on every invocation, which may
+    /// cost more than it saves for cheap templates with large data.
+    pub memoize: bool,
+    /// Instrumentation called while executing, e.g. a [`Profiler`](crate::Profiler).
+    pub hook: Option<Arc<dyn ExecHook>>,
+    /// Text printed for missing values, e.g. a key not in the data, instead of Go's
+    /// `<no value>`. Set it to an empty string to print nothing. It is also used for missing
+    /// values formatted by `%v` and `%s` of the builtin `printf`. A
+    /// [formatter](Template::set_formatter) takes precedence.
+    pub no_value: Option<String>,
+    /// Custom truthiness for `if`, `with`, `and`, `or` and `not`, e.g. for objects with an
+    /// `isEmpty` field.
+    pub truth: Option<Arc<dyn Truth>>,
+    /// Catches panics of functions and fails the execution with
+    /// [`ExecError::FuncFailed`](crate::error::ExecError::FuncFailed) instead of unwinding
+    /// through the caller. The panic hook still runs, i.e. the panic is printed to stderr by
+    /// default. Has no effect if panics abort.
+    pub catch_panics: bool,
+}
+
+impl ExecOptions {
+    // Whether `val` is true, asking `truth` first.
+    pub(crate) fn is_true(&self, val: &Value) -> bool {
+        self.truth
+            .as_ref()
+            .and_then(|t| t.is_true(val))
+            .unwrap_or_else(|| is_true(val))
+    }
+}
+
+/// Truthiness of values, set via [`ExecOptions::truth`]. It is implemented for closures.
+///
+/// ## Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Arc;
+/// use gtmpl::{Context, ExecOptions, Template, Value};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.set_exec_options(ExecOptions {
+///     truth: Some(Arc::new(|val: &Value| match val {
+///         Value::Object(o) => o.get("isEmpty").map(|e| e != &Value::Bool(true)),
+///         _ => None,
+///     })),
+///     ..Default::default()
+/// });
+/// tmpl.parse("{{ if . }}full{{ else }}empty{{ end }}").unwrap();
+/// let mut list = HashMap::new();
+/// list.insert("isEmpty".to_owned(), Value::Bool(true));
+/// let output = tmpl.render(&Context::from(Value::Object(list)));
+/// assert_eq!(&output.unwrap(), "empty");
+/// ```
+pub trait Truth: Send + Sync {
+    /// Returns whether `val` is true or `None` to fall back to Go's rules, i.e. empty, zero,
+    /// false and nil values are false.
+    fn is_true(&self, val: &Value) -> Option<bool>;
+}
+
+impl<F: Fn(&Value) -> Option<bool> + Send + Sync> Truth for F {
+    fn is_true(&self, val: &Value) -> Option<bool> {
+        self(val)
+    }
+}
+
+impl fmt::Debug for ExecOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecOptions")
+            .field("memoize", &self.memoize)
+            .field("hook", &self.hook.as_ref().map(|_| "ExecHook"))
+            .field("no_value", &self.no_value)
+            .field("truth", &self.truth.as_ref().map(|_| "Truth"))
+            .field("catch_panics", &self.catch_panics)
+            .finish()
+    }
+}
+
+// Whether the last command of `pipe` is `raw`, which disables the escaping of its output.
+fn is_raw(pipe: &PipeNode) -> bool {
+    pipe.cmds
+        .last()
+        .and_then(|cmd| cmd.args.first())
+        .is_some_and(|arg| matches!(*arg, Nodes::Identifier(ref i) if i.ident == "raw"))
+}
+
+// The message passed to `panic!`, if any.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
+}
+
+// The template name and the hash of its dot.
+type CacheKey = (String, u64);
+
+// Outputs of templates together with their dot, see `ExecOptions::memoize`.
+#[derive(Default)]
+struct TemplateCache {
+    outputs: HashMap<CacheKey, Vec<(Value, Vec<u8>)>>,
+}
+
+impl TemplateCache {
+    fn get(&self, key: &CacheKey, dot: &Value) -> Option<&[u8]> {
+        self.outputs
+            .get(key)?
+            .iter()
+            .find(|(v, _)| v == dot)
+            .map(|(_, out)| out.as_slice())
+    }
+
+    fn insert(&mut self, key: CacheKey, dot: Value, out: Vec<u8>) {
+        self.outputs.entry(key).or_default().push((dot, out));
+    }
+}
+
+// Hashes `val` such that equal values have equal hashes.
+fn hash_value(val: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mem::discriminant(val).hash(&mut hasher);
+    match val {
+        Value::NoValue | Value::Nil | Value::Function(_) => {}
+        Value::Bool(b) => b.hash(&mut hasher),
+        Value::String(s) => s.hash(&mut hasher),
+        Value::Number(n) => n.to_string().hash(&mut hasher),
+        Value::Array(a) => a.iter().for_each(|v| hash_value(v).hash(&mut hasher)),
+        Value::Object(m) | Value::Map(m) => {
+            // Independent of the arbitrary order of the entries.
+            let sum = m.iter().fold(0u64, |sum, (k, v)| {
+                let mut entry = DefaultHasher::new();
+                k.hash(&mut entry);
+                hash_value(v).hash(&mut entry);
+                sum.wrapping_add(entry.finish())
+            });
+            sum.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
 }
 
 /// A Context for the template. Passed to the template exectution.
 pub struct Context {
     dot: Value,
+    // Lower layers of a layered context, highest precedence first.
+    layers: Vec<Value>,
 }
 
 impl Context {
     pub fn empty() -> Context {
-        Context { dot: Value::Nil }
+        Context {
+            dot: Value::Nil,
+            layers: vec![],
+        }
     }
 
     pub fn from<T>(value: T) -> Context
@@ -38,47 +270,642 @@ impl Context {
         T: Into<Value>,
     {
         let serialized: Value = value.into();
-        Context { dot: serialized }
+        Context {
+            dot: serialized,
+            layers: vec![],
+        }
+    }
+
+    /// Creates a context from `layers` ordered from lowest to highest precedence.
+    ///
+    /// Layers are not merged. Instead field chains starting at the root (`.a.b` or `$.a.b`)
+    /// are looked up in the highest layer first and fall through to lower layers for each
+    /// field that is missing. Dot itself (`{{ . }}`) is the highest layer.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, Value};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut defaults = HashMap::new();
+    /// defaults.insert("host".to_owned(), Value::from("localhost"));
+    /// defaults.insert("port".to_owned(), Value::from(80));
+    /// let mut user = HashMap::new();
+    /// user.insert("port".to_owned(), Value::from(8080));
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ .host }}:{{ .port }}").unwrap();
+    /// let ctx = Context::layered(&[Value::Map(defaults), Value::Map(user)]);
+    /// assert_eq!(tmpl.render(&ctx).unwrap(), "localhost:8080");
+    /// ```
+    pub fn layered(layers: &[Value]) -> Context {
+        let mut layers: Vec<Value> = layers.iter().rev().cloned().collect();
+        if layers.is_empty() {
+            return Context::empty();
+        }
+        let dot = layers.remove(0);
+        Context { dot, layers }
     }
 }
 
 impl<'b> Template {
+    /// Executes the template writing the output to `writer`.
+    ///
+    /// Errors are reported as [`ExecError`], which converts into [`TemplateError`] together
+    /// with parse errors.
+    ///
+    /// [`TemplateError`]: crate::TemplateError
     pub fn execute<T: Write>(&self, writer: &'b mut T, data: &Context) -> Result<(), ExecError> {
+        self.execute_template(&self.name, writer, data)
+    }
+
+    /// Executes the template `name`, which was added or defined via `{{ define }}`, writing the
+    /// output to `writer`.
+    ///
+    /// Templates consisting only of whitespace and definitions produce no output, so files
+    /// containing only helpers can be parsed as main template.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ define \"hello\" }}Hello {{ . }}!{{ end }}\n").unwrap();
+    /// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "");
+    /// let mut out = vec![];
+    /// tmpl.execute_template("hello", &mut out, &Context::from("World")).unwrap();
+    /// assert_eq!(out, b"Hello World!");
+    /// ```
+    pub fn execute_template<T: Write>(
+        &self,
+        name: &str,
+        writer: &'b mut T,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        self.execute_filtered(name, None, writer, data)
+    }
+
+    /// Executes the [layout](Template::set_layout) for the page `name`, which was added by
+    /// [`Template::add_page`], writing the output to `writer`. The templates defined by the
+    /// page take precedence over the ones of the template while executing it.
+    pub fn execute_page<T: Write>(
+        &self,
+        name: &str,
+        writer: &'b mut T,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        let layout = self.layout.as_deref().ok_or(ExecError::LayoutNotSet)?;
+        let page = self
+            .pages
+            .get(name)
+            .ok_or_else(|| ExecError::TemplateNotDefined(name.to_owned()))?;
+        self.execute_filtered(layout, Some(page), writer, data)
+    }
+
+    // Executes the template `name`, preferring the templates in `local`, and applies the post
+    // filters to the output.
+    fn execute_filtered<T: Write>(
+        &self,
+        name: &str,
+        local: Option<&HashMap<String, Tree>>,
+        writer: &mut T,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        let cache = self.exec_options.memoize.then(RefCell::default);
+        let exec = Execution {
+            cache: cache.as_ref(),
+            segments: None,
+        };
+        if self.post_filters.is_empty() {
+            return self.execute_in(name, local, writer, data, exec);
+        }
+        let mut w: Vec<u8> = Vec::with_capacity(self.size_hint(name));
+        self.execute_in(name, local, &mut w, data, exec)?;
+        let out = String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)?;
+        let out = self
+            .post_filters
+            .iter()
+            .fold(out, |out, filter| filter(out));
+        writer.write_all(out.as_bytes()).map_err(ExecError::IOError)
+    }
+
+    fn execute_in<T: Write>(
+        &self,
+        name: &str,
+        local: Option<&HashMap<String, Tree>>,
+        writer: &mut T,
+        data: &Context,
+        exec: Execution,
+    ) -> Result<(), ExecError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("execute", name).entered();
         let mut vars: VecDeque<VecDeque<Variable>> = VecDeque::new();
         let mut dot = VecDeque::new();
         dot.push_back(Variable {
-            name: "$".to_owned(),
-            value: data.dot.clone(),
+            name: Cow::Borrowed("$"),
+            value: Cow::Borrowed(&data.dot),
         });
+        if self.parse_options.template_args {
+            dot.push_back(Variable {
+                name: Cow::Borrowed("$args"),
+                value: Cow::Owned(Value::Array(vec![])),
+            });
+        }
         vars.push_back(dot);
 
+        let (tree, root) = local
+            .and_then(|local| local.get(name))
+            .or_else(|| self.tree_set.get(name))
+            .and_then(|tree| Some((tree, tree.root.as_ref()?)))
+            .ok_or_else(|| ExecError::IncompleteTemplate(name.to_owned()))?;
         let mut state = State {
             template: self,
+            tree,
             writer,
             node: None,
             vars,
             depth: 0,
+            context: data,
+            local,
+            exec,
+            control: None,
         };
 
-        let root = self
-            .tree_set
-            .get(&self.name)
-            .and_then(|tree| tree.root.as_ref())
-            .ok_or_else(|| ExecError::IncompleteTemplate(self.name.clone()))?;
-        state.walk(data, root)?;
-
-        Ok(())
+        if let Ok(true) = root.is_empty_tree() {
+            return Ok(());
+        }
+        #[cfg(feature = "gtmpl_os")]
+        return crate::funcs::os::with_policy(self.os_policy.clone(), || {
+            state.walk(&data.dot, root)
+        });
+        #[cfg(not(feature = "gtmpl_os"))]
+        state.walk(&data.dot, root)
     }
 
+    /// Renders the template into a `String`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, TemplateError};
+    ///
+    /// fn greet(name: &str) -> Result<String, TemplateError> {
+    ///     let mut tmpl = Template::default();
+    ///     tmpl.parse("Hello {{ . }}!")?;
+    ///     Ok(tmpl.render(&Context::from(name))?)
+    /// }
+    /// assert_eq!(greet("World").unwrap(), "Hello World!");
+    /// ```
     pub fn render(&self, data: &Context) -> Result<String, ExecError> {
+        self.render_template(&self.name, data)
+    }
+
+    /// Renders the template `name` into a `String`, see [`Template::execute_template`].
+    pub fn render_template(&self, name: &str, data: &Context) -> Result<String, ExecError> {
+        let mut w: Vec<u8> = Vec::with_capacity(self.size_hint(name));
+        self.execute_template(name, &mut w, data)?;
+        String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)
+    }
+
+    /// Renders the page `name` into a `String`, see [`Template::execute_page`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_template(
+    ///     "layout",
+    ///     r#"<title>{{ block "title" . }}Site{{ end }}</title><main>{{ block "content" . }}{{ end }}</main>"#,
+    /// )
+    /// .unwrap();
+    /// tmpl.set_layout("layout");
+    /// tmpl.add_page("index", "Hello {{ . }}!").unwrap();
+    /// tmpl.add_page("about", r#"{{ define "title" }}About{{ end }}About {{ . }}"#).unwrap();
+    /// let ctx = Context::from("gtmpl");
+    /// assert_eq!(
+    ///     tmpl.render_page("index", &ctx).unwrap(),
+    ///     "<title>Site</title><main>Hello gtmpl!</main>"
+    /// );
+    /// assert_eq!(
+    ///     tmpl.render_page("about", &ctx).unwrap(),
+    ///     "<title>About</title><main>About gtmpl</main>"
+    /// );
+    /// ```
+    pub fn render_page(&self, name: &str, data: &Context) -> Result<String, ExecError> {
         let mut w: Vec<u8> = vec![];
-        self.execute(&mut w, data)?;
+        self.execute_page(name, &mut w, data)?;
         String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)
     }
+
+    /// Renders the template into a byte vector without checking that the output is valid
+    /// UTF-8, unless there are [post filters](Template::add_post_filter).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let output = tmpl.render_bytes(&Context::from("World"));
+    /// assert_eq!(output.unwrap(), b"Hello World!");
+    /// ```
+    pub fn render_bytes(&self, data: &Context) -> Result<Vec<u8>, ExecError> {
+        let mut w: Vec<u8> = Vec::with_capacity(self.size_hint(&self.name));
+        self.execute(&mut w, data)?;
+        Ok(w)
+    }
+
+    /// Renders the template once per context, e.g. for a batch of items. The output is
+    /// rendered into a buffer which is reused for all contexts.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let contexts = vec!["World", "gopher"].into_iter().map(Context::from);
+    /// let outputs: Result<Vec<_>, _> = tmpl.render_many(contexts).collect();
+    /// assert_eq!(outputs.unwrap(), vec!["Hello World!", "Hello gopher!"]);
+    /// ```
+    pub fn render_many<'a, I>(
+        &'a self,
+        contexts: I,
+    ) -> impl Iterator<Item = Result<String, ExecError>> + 'a
+    where
+        I: IntoIterator<Item = Context>,
+        I::IntoIter: 'a,
+    {
+        let mut buf = Vec::with_capacity(self.size_hint(&self.name));
+        contexts
+            .into_iter()
+            .map(move |data| self.render_reusing(&mut buf, &data))
+    }
+
+    /// Like [`Template::render_many`], but renders the contexts in parallel using rayon.
+    /// Requires the `rayon` feature.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    /// use rayon::prelude::*;
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ . }}").unwrap();
+    /// let contexts: Vec<_> = (0..100).map(Context::from).collect();
+    /// let outputs: Vec<_> = tmpl.par_render_many(contexts).collect();
+    /// assert_eq!(outputs[42].as_deref().unwrap(), "42");
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn par_render_many<'a, I>(
+        &'a self,
+        contexts: I,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = Result<String, ExecError>> + 'a
+    where
+        I: rayon::iter::IntoParallelIterator<Item = Context>,
+        I::Iter: rayon::iter::IndexedParallelIterator + 'a,
+    {
+        use rayon::iter::ParallelIterator;
+        let hint = self.size_hint(&self.name);
+        contexts.into_par_iter().map_init(
+            move || Vec::with_capacity(hint),
+            move |buf, data| self.render_reusing(buf, &data),
+        )
+    }
+
+    // Renders into `buf`, keeping its capacity for the next call, and returns a copy.
+    fn render_reusing(&self, buf: &mut Vec<u8>, data: &Context) -> Result<String, ExecError> {
+        buf.clear();
+        self.execute(buf, data)?;
+        String::from_utf8(buf.clone()).map_err(ExecError::Utf8ConversionFailed)
+    }
+
+    /// Renders the template into any `fmt::Write` like a `String`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let mut out = String::from(">> ");
+    /// tmpl.render_to(&mut out, &Context::from("World")).unwrap();
+    /// assert_eq!(&out, ">> Hello World!");
+    /// ```
+    pub fn render_to<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        let mut w = FmtWriter { inner: writer };
+        self.execute(&mut w, data)
+    }
+
+    /// Renders the template and passes the output to `post`, e.g. to parse generated data.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, TemplateError};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ . }},{{ . }}").unwrap();
+    /// let list: Result<Vec<String>, TemplateError> = tmpl
+    ///     .render_with(&Context::from("a"), |out| Ok(out.split(',').map(String::from).collect()));
+    /// assert_eq!(list.unwrap(), vec!["a", "a"]);
+    /// ```
+    pub fn render_with<T, E, F>(&self, data: &Context, post: F) -> Result<T, E>
+    where
+        F: FnOnce(String) -> Result<T, E>,
+        E: From<ExecError>,
+    {
+        post(self.render(data)?)
+    }
+
+    /// Renders the template and parses the output as JSON.
+    ///
+    /// Requires the `gtmpl_json` feature. JSON objects become `Value::Map`s.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{lookup_path, Context, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"{"name": "{{ . }}", "tags": [1, 2]}"#).unwrap();
+    /// let value = tmpl.render_value(&Context::from("gtmpl")).unwrap();
+    /// assert_eq!(lookup_path(&value, "name"), Some(&Value::from("gtmpl")));
+    /// assert_eq!(lookup_path(&value, "tags.1"), Some(&Value::from(2)));
+    /// ```
+    #[cfg(feature = "gtmpl_json")]
+    pub fn render_value(&self, data: &Context) -> Result<Value, crate::TemplateError> {
+        self.render_with(data, |out| {
+            let json: serde_json::Value = serde_json::from_str(&out)?;
+            Ok(from_json(json))
+        })
+    }
+
+    /// Renders the template into segments tagged with the node they originate from, e.g. to
+    /// highlight the output of actions. Text and actions of invoked templates become segments
+    /// of their own, whereas the output of functions like `include` is part of the calling
+    /// action. [`ExecOptions::memoize`] and [post filters](Template::add_post_filter) are
+    /// ignored.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, SegmentKind, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let segments = tmpl.render_segments(&Context::from("World")).unwrap();
+    /// let parts: Vec<_> = segments.iter().map(|s| (s.text.as_str(), s.kind)).collect();
+    /// assert_eq!(
+    ///     parts,
+    ///     vec![
+    ///         ("Hello ", SegmentKind::Text),
+    ///         ("World", SegmentKind::Action),
+    ///         ("!", SegmentKind::Text),
+    ///     ]
+    /// );
+    /// assert_eq!(segments[1].origin_span.start, 6);
+    /// ```
+    pub fn render_segments(&self, data: &Context) -> Result<Vec<Segment>, ExecError> {
+        let segments = RefCell::default();
+        let exec = Execution {
+            cache: None,
+            segments: Some(&segments),
+        };
+        self.execute_in(&self.name, None, &mut io::sink(), data, exec)?;
+        Ok(segments.into_inner())
+    }
+
+    // The length of all text nodes of the template `name`. Used to pre-size the output buffer.
+    fn size_hint(&self, name: &str) -> usize {
+        self.tree_set
+            .get(name)
+            .and_then(|tree| tree.root.as_ref())
+            .map(|root| root.text_len())
+            .unwrap_or(0)
+    }
+}
+
+/// Converts a JSON document into a [`Value`]. Objects become maps.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{from_json, Context, Template};
+///
+/// let json = serde_json::json!({ "user": { "name": "gopher" } });
+/// let mut tmpl = Template::default();
+/// tmpl.parse("{{ .user.name }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::from(from_json(json))).unwrap(), "gopher");
+/// ```
+#[cfg(feature = "gtmpl_json")]
+pub fn from_json(json: serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+    match json {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::from(b),
+        Json::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+            (Some(u), _, _) => Value::from(u),
+            (_, Some(i), _) => Value::from(i),
+            (_, _, f) => Value::from(f.unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::from(s),
+        Json::Array(a) => Value::Array(a.into_iter().map(from_json).collect()),
+        Json::Object(o) => Value::Map(o.into_iter().map(|(k, v)| (k, from_json(v))).collect()),
+    }
+}
+
+/// Evaluates a single pipeline like `.a.b | len` with `data` as dot and returns its value
+/// instead of printing it. `funcs` are available besides the builtins. Variables declared in
+/// the pipeline are discarded.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{eval_expr, gtmpl_map, Func, FuncError, Value};
+///
+/// fn double(args: &[Value]) -> Result<Value, FuncError> {
+///     match args {
+///         [Value::Number(n)] => Ok(Value::from(n.as_i64().unwrap_or_default() * 2)),
+///         _ => Err(FuncError::ExactlyXArgs("double".into(), 1)),
+///     }
+/// }
+///
+/// let data = gtmpl_map! { "a" => gtmpl_map! { "b" => "gopher" } };
+/// let funcs: &[(&str, Func)] = &[("double", double)];
+/// let len = eval_expr(".a.b | len | double", &data, funcs).unwrap();
+/// assert_eq!(len, Value::from(12));
+/// assert!(eval_expr("1 }}{{ 2", &data, &[]).is_err());
+/// ```
+pub fn eval_expr(
+    expr: &str,
+    data: &Value,
+    funcs: &[(&str, Func)],
+) -> Result<Value, crate::TemplateError> {
+    let mut tmpl = Template::default();
+    tmpl.add_funcs(funcs);
+    tmpl.parse(format!("{{{{{}}}}}", expr))?;
+    let tree = tmpl.tree_set.get(&tmpl.name);
+    let (tree, action) = match tree.map(|t| (t, t.root.as_ref())) {
+        Some((tree, Some(Nodes::List(list)))) => match list.nodes.as_slice() {
+            [Nodes::Action(action)] => (tree, action),
+            _ => return Err(ParseError::NotAPipeline(expr.to_owned()).into()),
+        },
+        _ => return Err(ParseError::NotAPipeline(expr.to_owned()).into()),
+    };
+    let context = Context::empty();
+    let mut vars = VecDeque::new();
+    vars.push_back(VecDeque::from(vec![Variable {
+        name: Cow::Borrowed("$"),
+        value: Cow::Borrowed(data),
+    }]));
+    let mut state = State {
+        template: &tmpl,
+        tree,
+        writer: &mut io::sink(),
+        node: None,
+        vars,
+        depth: 0,
+        context: &context,
+        local: None,
+        exec: Execution {
+            cache: None,
+            segments: None,
+        },
+        control: None,
+    };
+    let val = state
+        .eval_pipeline(data, &action.pipe)
+        .map_err(|e| e.at(tree, action.span()))?;
+    Ok(val.into_owned())
+}
+
+/// The state of the execution at the call of a context function, see
+/// [`Template::add_context_func`](crate::Template::add_context_func).
+pub struct ExecContext<'a> {
+    name: &'a str,
+    dot: &'a Value,
+    vars: &'a VecDeque<VecDeque<Variable<'a>>>,
+}
+
+impl ExecContext<'_> {
+    /// The name of the executed template, e.g. the one given to `define`.
+    pub fn template_name(&self) -> &str {
+        self.name
+    }
+
+    /// The value of dot at the call.
+    pub fn dot(&self) -> &Value {
+        self.dot
+    }
+
+    /// The value of `$`, i.e. dot when the executed template started.
+    pub fn root(&self) -> &Value {
+        self.var("$").unwrap_or(&NO_VALUE)
+    }
+
+    /// The value of the variable `name` including the `$`, like `$x`. Variables of inner
+    /// scopes shadow the ones of outer scopes.
+    pub fn var(&self, name: &str) -> Option<&Value> {
+        self.vars()
+            .filter(|&(n, _)| n == name)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// All variables in scope in the order of their declaration, starting with `$`. Shadowed
+    /// variables come before the ones shadowing them.
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.vars
+            .iter()
+            .flatten()
+            .map(|var| (var.name.as_ref(), var.value.as_ref()))
+    }
+}
+
+// Executes `tree` with `dot` and `$args`, called from a template at `depth`.
+#[allow(clippy::too_many_arguments)]
+fn exec_nested<W: Write>(
+    template: &Template,
+    local: Option<&HashMap<String, Tree>>,
+    exec: Execution,
+    context: &Context,
+    depth: usize,
+    tree: &Tree,
+    dot: &Value,
+    args: Vec<Value>,
+    writer: &mut W,
+) -> Result<(), ExecError> {
+    if depth >= MAX_TEMPLATE_DEPTH {
+        return Err(ExecError::MaxTemplateDepth);
+    }
+    let root = tree
+        .root
+        .as_ref()
+        .ok_or_else(|| ExecError::TemplateNotDefined(tree.name().to_owned()))?;
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("template", name = tree.name(), depth).entered();
+    let mut vars = VecDeque::new();
+    let mut root_vars = VecDeque::new();
+    root_vars.push_back(Variable {
+        name: Cow::Borrowed("$"),
+        value: Cow::Borrowed(dot),
+    });
+    if template.parse_options.template_args {
+        root_vars.push_back(Variable {
+            name: Cow::Borrowed("$args"),
+            value: Cow::Owned(Value::Array(args)),
+        });
+    }
+    vars.push_back(root_vars);
+    let mut state = State {
+        template,
+        tree,
+        writer,
+        node: None,
+        vars,
+        depth: depth + 1,
+        context,
+        local,
+        exec,
+        control: None,
+    };
+    state.walk(dot, root)
+}
+
+// Adapter to use a `fmt::Write` as output for `execute`. The executor only ever writes whole
+// `str`s so every chunk is valid utf-8.
+struct FmtWriter<'a, W: fmt::Write> {
+    inner: &'a mut W,
+}
+
+impl<'a, W: fmt::Write> Write for FmtWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.inner.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl<'a, 'b, T: Write> State<'a, 'b, T> {
-    fn set_kth_last_var_value(&mut self, k: usize, value: Value) -> Result<(), ExecError> {
+    fn set_kth_last_var_value(&mut self, k: usize, value: Cow<'a, Value>) -> Result<(), ExecError> {
         if let Some(last_vars) = self.vars.back_mut() {
             let i = last_vars.len() - k;
             if let Some(kth_last_var) = last_vars.get_mut(i) {
@@ -90,177 +917,611 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Err(ExecError::EmptyStack)
     }
 
-    fn var_value(&self, key: &str) -> Result<Value, ExecError> {
+    fn var_value(&self, key: &str) -> Result<&Value, ExecError> {
         for context in self.vars.iter().rev() {
             for var in context.iter().rev() {
                 if var.name == key {
-                    return Ok(var.value.clone());
+                    return Ok(&var.value);
                 }
             }
         }
         Err(ExecError::VariableNotFound(key.to_string()))
     }
 
-    fn walk_list(&mut self, ctx: &Context, node: &'a ListNode) -> Result<(), ExecError> {
+    fn walk_list(&mut self, dot: &Value, node: &'a ListNode) -> Result<(), ExecError> {
         for n in &node.nodes {
-            self.walk(ctx, n)?;
+            self.walk(dot, n)?;
+            if self.control.is_some() {
+                break;
+            }
         }
         Ok(())
     }
 
     // Top level walk function. Steps through the major parts for the template strcuture and
     // writes to the output.
-    fn walk(&mut self, ctx: &Context, node: &'a Nodes) -> Result<(), ExecError> {
+    fn walk(&mut self, dot: &Value, node: &'a Nodes) -> Result<(), ExecError> {
         self.node = Some(node);
+        let hook = self.template.exec_options.hook.as_deref();
+        if let Some(hook) = hook {
+            hook.on_node_enter(self.tree.name(), node);
+        }
+        let res =
+            ensure_stack(|| self.walk_node(dot, node)).map_err(|e| e.at(self.tree, node.span()));
+        if let Some(hook) = hook {
+            hook.on_node_exit(self.tree.name(), node);
+        }
+        res
+    }
+
+    fn walk_node(&mut self, dot: &Value, node: &'a Nodes) -> Result<(), ExecError> {
         match *node {
             Nodes::Action(ref n) => {
-                let val = self.eval_pipeline(ctx, &n.pipe)?;
+                let val = self.eval_pipeline(dot, &n.pipe)?;
                 if n.pipe.decl.is_empty() {
-                    self.print_value(&val)?;
+                    if self.template.strict && *val == Value::NoValue {
+                        return Err(ExecError::NoValue(n.pipe.to_string()));
+                    }
+                    self.print_value(node, &val)?;
                 }
                 Ok(())
             }
-            Nodes::If(_) | Nodes::With(_) => self.walk_if_or_with(node, ctx),
-            Nodes::Range(ref n) => self.walk_range(ctx, n),
-            Nodes::List(ref n) => self.walk_list(ctx, n),
-            Nodes::Text(ref n) => write!(self.writer, "{}", n).map_err(ExecError::IOError),
-            Nodes::Template(ref n) => self.walk_template(ctx, n),
+            Nodes::If(_) | Nodes::With(_) => self.walk_if_or_with(node, dot),
+            Nodes::Range(ref n) => self.walk_range(dot, n),
+            Nodes::List(ref n) => self.walk_list(dot, n),
+            Nodes::Text(ref n) => match self.exec.segments {
+                Some(segments) => {
+                    let text = n.to_string();
+                    self.writer
+                        .write_all(text.as_bytes())
+                        .map_err(ExecError::IOError)?;
+                    self.push_segment(segments, node, SegmentKind::Text, text);
+                    Ok(())
+                }
+                None => write!(self.writer, "{}", n).map_err(ExecError::IOError),
+            },
+            Nodes::Template(ref n) => self.walk_template(dot, n),
+            Nodes::Extension(ref n) => self.walk_extension(dot, n),
+            Nodes::Break(_) => {
+                self.control = Some(LoopControl::Break);
+                Ok(())
+            }
+            Nodes::Continue(_) => {
+                self.control = Some(LoopControl::Continue);
+                Ok(())
+            }
             _ => Err(ExecError::UnknownNode(node.clone())),
         }
     }
 
-    fn walk_template(&mut self, ctx: &Context, template: &TemplateNode) -> Result<(), ExecError> {
+    fn walk_template(&mut self, dot: &Value, template: &TemplateNode) -> Result<(), ExecError> {
         let name = match template.name {
             PipeOrString::String(ref name) => name.to_owned(),
             PipeOrString::Pipe(ref pipe) => {
-                if let Value::String(s) = self.eval_pipeline(ctx, pipe)? {
+                if let Value::String(s) = self.eval_pipeline(dot, pipe)?.into_owned() {
                     s
                 } else {
                     return Err(ExecError::PipelineMustYieldString);
                 }
             }
         };
-        if self.depth >= MAX_TEMPLATE_DEPTH {
-            return Err(ExecError::MaxTemplateDepth);
+        let value = match template.pipe {
+            Some(ref pipe) => self.eval_pipeline(dot, pipe)?,
+            None => Cow::Owned(Value::NoValue),
+        };
+        let args = template
+            .args
+            .iter()
+            .map(|arg| self.eval_arg(dot, arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tree = self.lookup_template(&name)?;
+        if args.is_empty() {
+            if let Some(out) = self.exec_memoized(tree, &value)? {
+                return self.writer.write_all(&out).map_err(ExecError::IOError);
+            }
         }
-        let tree = self.template.tree_set.get(&name);
-        if let Some(tree) = tree {
-            if let Some(ref root) = tree.root {
-                let mut vars = VecDeque::new();
-                let mut dot = VecDeque::new();
-                let value = if let Some(ref pipe) = template.pipe {
-                    self.eval_pipeline(ctx, pipe)?
-                } else {
-                    Value::NoValue
-                };
-                dot.push_back(Variable {
-                    name: "$".to_owned(),
-                    value: value.clone(),
-                });
-                vars.push_back(dot);
-                let mut new_state = State {
-                    template: self.template,
-                    writer: self.writer,
-                    node: None,
-                    vars,
-                    depth: self.depth + 1,
-                };
-                return new_state.walk(&Context::from(value), root);
-            }
-        }
-        Err(ExecError::TemplateNotDefined(name))
-    }
-
-    fn eval_pipeline(&mut self, ctx: &Context, pipe: &PipeNode) -> Result<Value, ExecError> {
-        let mut val: Option<Value> = None;
+        exec_nested(
+            self.template,
+            self.local,
+            self.exec,
+            self.context,
+            self.depth,
+            tree,
+            &value,
+            args,
+            self.writer,
+        )
+    }
+
+    fn walk_extension(&mut self, dot: &Value, node: &'a ExtensionNode) -> Result<(), ExecError> {
+        let handler = self
+            .template
+            .extensions
+            .get(&node.name)
+            .ok_or_else(|| ExecError::ExtensionNotDefined(node.name.clone()))?;
+        let mut body = vec![];
+        let mut value = Value::NoValue;
+        self.scoped(|s| {
+            if let Some(ref pipe) = node.pipe {
+                value = s.eval_pipeline(dot, pipe)?.into_owned();
+            }
+            // The body sees the variables in scope, but is written to a buffer.
+            let mut state = State {
+                template: s.template,
+                tree: s.tree,
+                writer: &mut body,
+                node: s.node,
+                vars: mem::take(&mut s.vars),
+                depth: s.depth,
+                context: s.context,
+                local: s.local,
+                exec: s.exec.buffered(),
+                control: None,
+            };
+            let res = state.walk_list(dot, &node.list);
+            s.vars = mem::take(&mut state.vars);
+            res
+        })?;
+        let body = String::from_utf8_lossy(&body);
+        let out =
+            handler(&value, &body).map_err(|e| ExecError::FuncFailed(node.name.clone(), e))?;
+        self.writer
+            .write_all(out.as_bytes())
+            .map_err(ExecError::IOError)
+    }
+
+    // Returns the output of `tree` from the cache or executes it into the cache.
+    // Returns `None` if memoization doesn't apply.
+    fn exec_memoized(&mut self, tree: &'a Tree, dot: &Value) -> Result<Option<Vec<u8>>, ExecError> {
+        let cache = match self.exec.cache {
+            Some(cache) if self.local.is_none() => cache,
+            _ => return Ok(None),
+        };
+        // Lookups in a layered context depend on dot being the root itself.
+        if !self.context.layers.is_empty() && std::ptr::eq(dot, &self.context.dot) {
+            return Ok(None);
+        }
+        let key = (tree.name().to_owned(), hash_value(dot));
+        if let Some(out) = cache.borrow().get(&key, dot) {
+            return Ok(Some(out.to_vec()));
+        }
+        let mut buf = vec![];
+        exec_nested(
+            self.template,
+            self.local,
+            self.exec.buffered(),
+            self.context,
+            self.depth,
+            tree,
+            dot,
+            vec![],
+            &mut buf,
+        )?;
+        cache.borrow_mut().insert(key, dot.clone(), buf.clone());
+        Ok(Some(buf))
+    }
+
+    fn lookup_template(&self, name: &str) -> Result<&'a Tree, ExecError> {
+        self.local
+            .and_then(|local| local.get(name))
+            .or_else(|| self.template.tree_set.get(name))
+            .filter(|tree| tree.root.is_some())
+            .ok_or_else(|| ExecError::TemplateNotDefined(name.to_owned()))
+    }
+
+    fn eval_pipeline<'d>(
+        &mut self,
+        dot: &'d Value,
+        pipe: &PipeNode,
+    ) -> Result<Cow<'d, Value>, ExecError> {
+        let val = ensure_stack(|| self.eval_pipeline_value(dot, pipe))?;
+        self.declare(pipe, &val)?;
+        Ok(val)
+    }
+
+    // Evaluates `pipe` without declaring its variables.
+    fn eval_pipeline_value<'d>(
+        &mut self,
+        dot: &'d Value,
+        pipe: &PipeNode,
+    ) -> Result<Cow<'d, Value>, ExecError> {
+        let mut val: Option<Cow<'d, Value>> = None;
         for cmd in &pipe.cmds {
-            val = Some(self.eval_command(ctx, cmd, &val)?);
+            val = Some(self.eval_command(dot, cmd, val.take().map(Cow::into_owned))?);
             // TODO
         }
-        let val = val.ok_or_else(|| ExecError::ErrorEvaluatingPipe(pipe.clone()))?;
+        val.ok_or_else(|| ExecError::ErrorEvaluatingPipe(pipe.clone()))
+    }
+
+    // Declares the variables of `pipe` with the value `val`.
+    fn declare(&mut self, pipe: &PipeNode, val: &Value) -> Result<(), ExecError> {
         for var in &pipe.decl {
             self.vars
                 .back_mut()
                 .map(|v| {
                     v.push_back(Variable {
-                        name: var.ident[0].clone(),
-                        value: val.clone(),
+                        name: Cow::Owned(var.ident[0].clone()),
+                        value: Cow::Owned(val.clone()),
                     })
                 })
                 .ok_or(ExecError::EmptyStack)?;
         }
-        Ok(val)
+        Ok(())
     }
 
-    fn eval_command(
+    // A state borrowing `self`, so variables can borrow values living shorter than `'a`, like
+    // the elements of a ranged array. The variables in scope are borrowed as well. All frames
+    // but the innermost one are merged, so nested ranges don't copy a frame per nesting level.
+    fn borrowing(&mut self) -> State<'_, '_, T> {
+        let mut vars = VecDeque::with_capacity(2);
+        if let Some(innermost) = self.vars.back() {
+            let outer = self.vars.len() - 1;
+            vars.push_back(
+                self.vars
+                    .iter()
+                    .take(outer)
+                    .flatten()
+                    .map(Variable::borrowed)
+                    .collect(),
+            );
+            vars.push_back(innermost.iter().map(Variable::borrowed).collect());
+        }
+        State {
+            template: self.template,
+            tree: self.tree,
+            writer: &mut *self.writer,
+            node: self.node,
+            vars,
+            depth: self.depth,
+            context: self.context,
+            local: self.local,
+            exec: self.exec,
+            control: None,
+        }
+    }
+
+    fn eval_command<'d>(
         &mut self,
-        ctx: &Context,
+        dot: &'d Value,
         cmd: &CommandNode,
-        val: &Option<Value>,
-    ) -> Result<Value, ExecError> {
+        val: Option<Value>,
+    ) -> Result<Cow<'d, Value>, ExecError> {
         let first_word = &cmd
             .args
             .first()
             .ok_or_else(|| ExecError::NoArgsForCommandNode(cmd.clone()))?;
 
         match *(*first_word) {
-            Nodes::Field(ref n) => return self.eval_field_node(ctx, n, &cmd.args, val),
-            Nodes::Variable(ref n) => return self.eval_variable_node(n, &cmd.args, val),
-            Nodes::Pipe(ref n) => return self.eval_pipeline(ctx, n),
-            Nodes::Chain(ref n) => return self.eval_chain_node(ctx, n, &cmd.args, val),
-            Nodes::Identifier(ref n) => return self.eval_function(ctx, n, &cmd.args, val),
+            Nodes::Field(ref n) => return self.eval_field_node(dot, n, &cmd.args, &val),
+            Nodes::Variable(ref n) => {
+                return self.eval_variable_node(n, &cmd.args, &val).map(Cow::Owned)
+            }
+            Nodes::Pipe(ref n) => {
+                // The arguments are all inside the parentheses.
+                not_a_function(&cmd.args, &val)?;
+                return self.eval_pipeline(dot, n);
+            }
+            Nodes::Chain(ref n) => {
+                return self
+                    .eval_chain_node(dot, n, &cmd.args, &val)
+                    .map(Cow::Owned)
+            }
+            Nodes::Identifier(ref n) => {
+                return self.eval_function(dot, n, &cmd.args, val).map(Cow::Owned)
+            }
             _ => {}
         }
-        not_a_function(&cmd.args, val)?;
+        not_a_function(&cmd.args, &val)?;
         match *(*first_word) {
-            Nodes::Bool(ref n) => Ok(n.value.clone()),
-            Nodes::Dot(_) => Ok(ctx.dot.clone()),
-            Nodes::Number(ref n) => Ok(n.value.clone()),
-            Nodes::String(ref n) => Ok(n.value.clone()),
+            Nodes::Bool(ref n) => Ok(Cow::Owned(n.value.clone())),
+            Nodes::Dot(_) => Ok(Cow::Borrowed(dot)),
+            Nodes::Number(ref n) => Ok(Cow::Owned(n.value.clone())),
+            Nodes::String(ref n) => Ok(Cow::Owned(n.value.clone())),
             _ => Err(ExecError::CannotEvaluateCommand((*first_word).clone())),
         }
     }
 
     fn eval_function(
         &mut self,
-        ctx: &Context,
+        dot: &Value,
         ident: &IdentifierNode,
         args: &[Nodes],
-        fin: &Option<Value>,
+        fin: Option<Value>,
     ) -> Result<Value, ExecError> {
         let name = &ident.ident;
-        let function = self
-            .template
-            .funcs
-            .get(name.as_str())
-            .ok_or_else(|| ExecError::UndefinedFunction(name.to_string()))?;
-        self.eval_call(ctx, *function, args, fin)
+        let exec_func = self.template.funcs.exec_funcs().contains(&name.as_str());
+        let function = match self.template.funcs.get(name.as_str()) {
+            Some(function) => function,
+            None if self.template.funcs.contains_context_func(name) => {
+                return self.eval_context_call(dot, name, args, fin)
+            }
+            None if exec_func && name == "include" => return self.eval_include(dot, args, fin),
+            None if exec_func && name == "tpl" => return self.eval_tpl(dot, args, fin),
+            None if exec_func && name == "raw" => return self.eval_raw(dot, args, fin),
+            None if exec_func && name == "data" => return self.eval_data(dot, args, fin),
+            None => match self.template.funcs.get_iterator(name) {
+                Some(iter) => {
+                    let arg_vals = self.eval_args(dot, args, fin)?;
+                    let iter =
+                        iter(&arg_vals).map_err(|e| ExecError::FuncFailed(name.clone(), e))?;
+                    return Ok(Value::Array(iter.collect()));
+                }
+                None => return Err(ExecError::UndefinedFunction(name.to_string())),
+            },
+        };
+        self.eval_call(dot, name, function, args, fin)
+    }
+
+    // Executes the template named by the first argument with the second one as dot and returns
+    // the output as string.
+    fn eval_include(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let arg_vals = self.eval_args(dot, args, fin)?;
+        let (name, data) = match arg_vals.as_slice() {
+            [Value::String(name), data] => (name, data),
+            [name, _] => {
+                let msg = format!("template name must be a string, got {}", name);
+                return Err(ExecError::FuncFailed(
+                    "include".to_owned(),
+                    FuncError::Generic(msg),
+                ));
+            }
+            _ => {
+                return Err(ExecError::FuncFailed(
+                    "include".to_owned(),
+                    FuncError::ExactlyXArgs("include".to_owned(), 2),
+                ))
+            }
+        };
+        let tree = self.lookup_template(name)?;
+        let buf = match self.exec_memoized(tree, data)? {
+            Some(out) => out,
+            None => {
+                let mut buf = vec![];
+                exec_nested(
+                    self.template,
+                    self.local,
+                    self.exec.buffered(),
+                    self.context,
+                    self.depth,
+                    tree,
+                    data,
+                    vec![],
+                    &mut buf,
+                )?;
+                buf
+            }
+        };
+        // The executor only writes whole `str`s.
+        Ok(Value::String(String::from_utf8(buf).unwrap_or_default()))
+    }
+
+    // Parses the first argument as template and executes it with the second one as dot,
+    // returning the output as string. The text may use the functions and templates of the
+    // enclosing template. Templates it defines are only visible while executing it.
+    fn eval_tpl(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let failed = |e| ExecError::FuncFailed("tpl".to_owned(), e);
+        let arg_vals = self.eval_args(dot, args, fin)?;
+        let (text, data) = match arg_vals.as_slice() {
+            [Value::String(text), data] => (text, data),
+            [text, _] => {
+                let msg = format!("template must be a string, got {}", text);
+                return Err(failed(FuncError::Generic(msg)));
+            }
+            _ => return Err(failed(FuncError::ExactlyXArgs("tpl".to_owned(), 2))),
+        };
+        let trees = parse(
+            "tpl".to_owned(),
+            text.clone(),
+            self.template.funcs.name_set(),
+            self.template.parse_options(),
+        )
+        .map_err(|e| failed(FuncError::Generic(e.to_string())))?;
+        let tree = match trees.get("tpl").filter(|tree| tree.root.is_some()) {
+            Some(tree) => tree,
+            None => return Ok(Value::from("")),
+        };
+        let mut buf = vec![];
+        exec_nested(
+            self.template,
+            Some(&trees),
+            self.exec.buffered(),
+            self.context,
+            self.depth,
+            tree,
+            data,
+            vec![],
+            &mut buf,
+        )?;
+        Ok(Value::String(String::from_utf8(buf).unwrap_or_default()))
+    }
+
+    // Returns the constant added via `Template::add_data` named by the argument.
+    fn eval_data(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let failed = |e| ExecError::FuncFailed("data".to_owned(), e);
+        let name = match self.eval_args(dot, args, fin)?.as_slice() {
+            [Value::String(name)] => name.clone(),
+            [name] => {
+                let msg = format!("data name must be a string, got {}", name);
+                return Err(failed(FuncError::Generic(msg)));
+            }
+            _ => return Err(failed(FuncError::ExactlyXArgs("data".to_owned(), 1))),
+        };
+        match self.template.data.get(&name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(failed(FuncError::Generic(format!(
+                "data {} not defined",
+                name
+            )))),
+        }
+    }
+
+    // `raw` returns its argument, `print_value` skips the escaping of actions ending with it.
+    fn eval_raw(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        match self.eval_args(dot, args, fin)?.as_slice() {
+            [val] => Ok(val.clone()),
+            _ => Err(ExecError::FuncFailed(
+                "raw".to_owned(),
+                FuncError::ExactlyXArgs("raw".to_owned(), 1),
+            )),
+        }
     }
 
     fn eval_call(
         &mut self,
-        ctx: &Context,
+        dot: &Value,
+        name: &str,
         function: Func,
         args: &[Nodes],
-        fin: &Option<Value>,
+        fin: Option<Value>,
     ) -> Result<Value, ExecError> {
+        let builtin = self.template.funcs.is_builtin(name);
+        let arg_vals = if builtin
+            && (name == "and" || name == "or")
+            && self.template.parse_options.go_version >= GoVersion::Go1_18
+        {
+            self.eval_args_short_circuit(dot, args, fin, name == "or")?
+        } else {
+            self.eval_args(dot, args, fin)?
+        };
+        let options = &self.template.exec_options;
+        let truth = |val: &Value| options.is_true(val);
+        let call = |args: &[Value]| match (options.no_value.as_ref(), name) {
+            (Some(text), "printf") if builtin => funcs::printf_no_value(args, Some(text)),
+            _ if options.truth.is_none() || !builtin => function(args),
+            (_, "and") => funcs::and_by(args, &truth),
+            (_, "or") => funcs::or_by(args, &truth),
+            (_, "not") => funcs::not_by(args, &truth),
+            _ => function(args),
+        };
+        self.invoke(name, &arg_vals, call)
+    }
+
+    fn eval_context_call(
+        &mut self,
+        dot: &Value,
+        name: &str,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let function = self
+            .template
+            .funcs
+            .get_context_func(name)
+            .ok_or_else(|| ExecError::UndefinedFunction(name.to_owned()))?;
+        let arg_vals = self.eval_args(dot, args, fin)?;
+        let context = ExecContext {
+            name: self.tree.name(),
+            dot,
+            vars: &self.vars,
+        };
+        self.invoke(name, &arg_vals, |args| function(args, &context))
+    }
+
+    // Calls the function `name` via `call`, catching panics and reporting to the hook as set
+    // in the exec options.
+    fn invoke(
+        &self,
+        name: &str,
+        arg_vals: &[Value],
+        call: impl Fn(&[Value]) -> Result<Value, FuncError>,
+    ) -> Result<Value, ExecError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "func",
+            name,
+            template = self.tree.name(),
+            pos = self.node.map(|n| n.span().start)
+        )
+        .entered();
+        let options = &self.template.exec_options;
+        let call = |args: &[Value]| {
+            if !options.catch_panics {
+                return call(args);
+            }
+            panic::catch_unwind(AssertUnwindSafe(|| call(args))).unwrap_or_else(|payload| {
+                Err(FuncError::Generic(format!(
+                    "panicked: {}",
+                    panic_message(payload.as_ref())
+                )))
+            })
+        };
+        let res = match options.hook {
+            Some(ref hook) => {
+                let start = Instant::now();
+                let res = call(arg_vals);
+                hook.on_func_call(name, start.elapsed());
+                res
+            }
+            None => call(arg_vals),
+        };
+        res.map_err(|e| ExecError::FuncFailed(name.to_owned(), e))
+    }
+
+    // Evaluates the arguments of a command, `args[0]` being the function itself.
+    fn eval_args(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Vec<Value>, ExecError> {
         let mut arg_vals = vec![];
         if !args.is_empty() {
             for arg in &args[1..] {
-                let val = self.eval_arg(ctx, arg)?;
+                let val = self.eval_arg(dot, arg)?;
                 arg_vals.push(val);
             }
         }
-        if let Some(ref f) = *fin {
-            arg_vals.push(f.clone());
+        if let Some(f) = fin {
+            arg_vals.push(f);
         }
+        Ok(arg_vals)
+    }
 
-        function(&arg_vals).map_err(Into::into)
+    // Evaluates the arguments of `and` or `or` up to the first one whose truth is `decisive`,
+    // which is the result, like Golang does since 1.18.
+    fn eval_args_short_circuit(
+        &mut self,
+        dot: &Value,
+        args: &[Nodes],
+        fin: Option<Value>,
+        decisive: bool,
+    ) -> Result<Vec<Value>, ExecError> {
+        let mut arg_vals = vec![];
+        for arg in args.iter().skip(1) {
+            let val = self.eval_arg(dot, arg)?;
+            let done = self.template.exec_options.is_true(&val) == decisive;
+            arg_vals.push(val);
+            if done {
+                return Ok(arg_vals);
+            }
+        }
+        arg_vals.extend(fin);
+        Ok(arg_vals)
     }
 
     fn eval_chain_node(
         &mut self,
-        ctx: &Context,
+        dot: &Value,
         chain: &ChainNode,
         args: &[Nodes],
         fin: &Option<Value>,
@@ -271,20 +1532,24 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         if let Nodes::Nil(_) = *chain.node {
             return Err(ExecError::NullInChain(chain.clone()));
         }
-        let pipe = self.eval_arg(ctx, &*chain.node)?;
+        let pipe = self.eval_arg(dot, &chain.node)?;
         self.eval_field_chain(&pipe, &chain.field, args, fin)
+            .map(Cow::into_owned)
+            .map_err(|e| no_value_at(e, self.tree, chain.span()))
     }
 
-    fn eval_arg(&mut self, ctx: &Context, node: &Nodes) -> Result<Value, ExecError> {
+    fn eval_arg(&mut self, dot: &Value, node: &Nodes) -> Result<Value, ExecError> {
         match *node {
-            Nodes::Dot(_) => Ok(ctx.dot.clone()),
+            Nodes::Dot(_) => Ok(dot.clone()),
             //Nodes::Nil
-            Nodes::Field(ref n) => self.eval_field_node(ctx, n, &[], &None), // args?
+            Nodes::Field(ref n) => self
+                .eval_field_node(dot, n, &[], &None)
+                .map(Cow::into_owned), // args?
             Nodes::Variable(ref n) => self.eval_variable_node(n, &[], &None),
-            Nodes::Pipe(ref n) => self.eval_pipeline(ctx, n),
+            Nodes::Pipe(ref n) => self.eval_pipeline(dot, n).map(Cow::into_owned),
             // Nodes::Identifier
-            Nodes::Identifier(ref n) => self.eval_function(ctx, n, &[], &None),
-            Nodes::Chain(ref n) => self.eval_chain_node(ctx, n, &[], &None),
+            Nodes::Identifier(ref n) => self.eval_function(dot, n, &[], None),
+            Nodes::Chain(ref n) => self.eval_chain_node(dot, n, &[], &None),
             Nodes::String(ref n) => Ok(n.value.clone()),
             Nodes::Bool(ref n) => Ok(n.value.clone()),
             Nodes::Number(ref n) => Ok(n.value.clone()),
@@ -292,58 +1557,145 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         }
     }
 
-    fn eval_field_node(
+    fn eval_field_node<'d>(
         &mut self,
-        ctx: &Context,
+        dot: &'d Value,
         field: &FieldNode,
         args: &[Nodes],
         fin: &Option<Value>,
-    ) -> Result<Value, ExecError> {
-        self.eval_field_chain(&ctx.dot, &field.ident, args, fin)
+    ) -> Result<Cow<'d, Value>, ExecError> {
+        self.eval_field_chain(dot, &field.ident, args, fin)
+            .map_err(|e| no_value_at(e, self.tree, field.span()))
     }
 
-    fn eval_field_chain(
-        &mut self,
-        receiver: &Value,
+    // Resolves a chain of fields. Intermediate values are only borrowed, a field is cloned only
+    // if it has to be returned.
+    fn eval_field_chain<'r>(
+        &self,
+        receiver: &'r Value,
         ident: &[String],
         args: &[Nodes],
         fin: &Option<Value>,
-    ) -> Result<Value, ExecError> {
-        let n = ident.len();
-        if n < 1 {
-            return Err(ExecError::FieldChainWithoutFields);
+    ) -> Result<Cow<'r, Value>, ExecError> {
+        if !self.context.layers.is_empty() && std::ptr::eq(receiver, &self.context.dot) {
+            return self.eval_layered_field_chain(ident, args, fin);
+        }
+        self.eval_plain_field_chain(receiver, ident, args, fin)
+    }
+
+    fn eval_plain_field_chain<'r>(
+        &self,
+        receiver: &'r Value,
+        ident: &[String],
+        args: &[Nodes],
+        fin: &Option<Value>,
+    ) -> Result<Cow<'r, Value>, ExecError> {
+        let (last, init) = ident
+            .split_last()
+            .ok_or(ExecError::FieldChainWithoutFields)?;
+        let mut r = Cow::Borrowed(receiver);
+        for id in init {
+            r = self.eval_field_cow(r, id, &[], &None)?;
         }
-        // TODO clean shit up
-        let mut r: Value = Value::from(0);
-        for (i, id) in ident.iter().enumerate().take(n - 1) {
-            r = self.eval_field(if i == 0 { receiver } else { &r }, id, &[], &None)?;
+        self.eval_field_cow(r, last, args, fin)
+    }
+
+    // Resolves a chain of fields on the root of a layered context. As long as the found values
+    // are objects or maps the lookup of the next field falls through the same path in lower
+    // layers, so nested fields are merged too.
+    fn eval_layered_field_chain<'r>(
+        &self,
+        ident: &[String],
+        args: &[Nodes],
+        fin: &Option<Value>,
+    ) -> Result<Cow<'r, Value>, ExecError> {
+        let mut layers: Vec<&'a Value> = vec![&self.context.dot];
+        layers.extend(self.context.layers.iter());
+        for (i, id) in ident.iter().enumerate() {
+            let found: Vec<&'a Value> = layers
+                .iter()
+                .filter_map(|l| self.lookup_field(l, id))
+                .collect();
+            let nested = i + 1 < ident.len()
+                && matches!(found.first(), Some(Value::Object(_)) | Some(Value::Map(_)));
+            if !nested {
+                let r = layers
+                    .iter()
+                    .find(|l| self.lookup_field(l, id).is_some())
+                    .unwrap_or(&layers[0]);
+                return self
+                    .eval_plain_field_chain(r, &ident[i..], args, fin)
+                    .map(|v| Cow::Owned(v.into_owned()));
+            }
+            layers = found
+                .into_iter()
+                .filter(|v| matches!(**v, Value::Object(_) | Value::Map(_)))
+                .collect();
         }
-        self.eval_field(if n == 1 { receiver } else { &r }, &ident[n - 1], args, fin)
+        Err(ExecError::FieldChainWithoutFields)
     }
 
-    fn eval_field(
-        &mut self,
-        receiver: &Value,
+    fn eval_field_cow<'r>(
+        &self,
+        receiver: Cow<'r, Value>,
         field_name: &str,
         args: &[Nodes],
         fin: &Option<Value>,
-    ) -> Result<Value, ExecError> {
+    ) -> Result<Cow<'r, Value>, ExecError> {
+        match receiver {
+            Cow::Borrowed(r) => self.eval_field(r, field_name, args, fin),
+            Cow::Owned(r) => self
+                .eval_field(&r, field_name, args, fin)
+                .map(|v| Cow::Owned(v.into_owned())),
+        }
+    }
+
+    fn eval_field<'r>(
+        &self,
+        receiver: &'r Value,
+        field_name: &str,
+        args: &[Nodes],
+        fin: &Option<Value>,
+    ) -> Result<Cow<'r, Value>, ExecError> {
         let has_args = args.len() > 1 || fin.is_some();
         if has_args {
             return Err(ExecError::NotAFunctionButArguments(field_name.to_string()));
         }
         let ret = match *receiver {
-            Value::Object(ref o) => o
-                .get(field_name)
-                .cloned()
-                .ok_or_else(|| ExecError::NoFiledFor(field_name.to_string(), receiver.clone())),
-            Value::Map(ref o) => Ok(o.get(field_name).cloned().unwrap_or(Value::NoValue)),
-            _ => Err(ExecError::OnlyMapsAndObjectsHaveFields),
+            Value::Object(_) => self
+                .lookup_field(receiver, field_name)
+                .ok_or_else(|| ExecError::NoFiledFor(field_name.to_string(), receiver.clone()))?,
+            Value::Map(_) => match self.lookup_field(receiver, field_name) {
+                Some(v) => v,
+                None if self.template.strict => {
+                    return Err(ExecError::NoValue(format!(".{}", field_name)))
+                }
+                None => &NO_VALUE,
+            },
+            // Numeric fields like `.0`, see `Template::set_numeric_fields`.
+            Value::Array(ref a) => match field_name.parse::<usize>() {
+                Ok(i) => a.get(i).ok_or(ExecError::IndexOutOfRange(i, a.len()))?,
+                Err(_) => return Err(ExecError::OnlyMapsAndObjectsHaveFields),
+            },
+            _ => return Err(ExecError::OnlyMapsAndObjectsHaveFields),
         };
-        if let Ok(Value::Function(ref f)) = ret {
-            return (f.f)(&[receiver.clone()]).map_err(Into::into);
+        if let Value::Function(ref f) = *ret {
+            return (f.f)(std::slice::from_ref(receiver))
+                .map(Cow::Owned)
+                .map_err(|e| ExecError::FuncFailed(format!(".{}", field_name), e));
         }
-        ret
+        Ok(Cow::Borrowed(ret))
+    }
+
+    // Looks up `name` and, if enabled, its lowerCamelCase and snake_case variants.
+    fn lookup_field<'r>(&self, receiver: &'r Value, name: &str) -> Option<&'r Value> {
+        lookup_field(receiver, name).or_else(|| {
+            if !self.template.field_fallback {
+                return None;
+            }
+            lookup_field(receiver, &lower_camel(name))
+                .or_else(|| lookup_field(receiver, &snake_case(name)))
+        })
     }
 
     fn eval_variable_node(
@@ -355,33 +1707,48 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         let val = self.var_value(&variable.ident[0])?;
         if variable.ident.len() == 1 {
             not_a_function(args, fin)?;
-            return Ok(val);
+            return Ok(val.clone());
         }
-        self.eval_field_chain(&val, &variable.ident[1..], args, fin)
+        self.eval_field_chain(val, &variable.ident[1..], args, fin)
+            .map(Cow::into_owned)
+            .map_err(|e| no_value_at(e, self.tree, variable.span()))
     }
 
     // Walks an `if` or `with` node. They behave the same, except that `with` sets dot.
-    fn walk_if_or_with(&mut self, node: &'a Nodes, ctx: &Context) -> Result<(), ExecError> {
+    // Runs `f` with a new variable scope. Like in Golang variables declared in the pipeline of
+    // `if`, `with` and `range` are visible in all branches but not after `end`.
+    fn scoped<F>(&mut self, f: F) -> Result<(), ExecError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), ExecError>,
+    {
+        self.vars.push_back(VecDeque::new());
+        let res = f(self);
+        self.vars.pop_back();
+        res
+    }
+
+    fn walk_if_or_with(&mut self, node: &'a Nodes, dot: &Value) -> Result<(), ExecError> {
+        self.scoped(|s| s.walk_if_or_with_scoped(node, dot))
+    }
+
+    fn walk_if_or_with_scoped(&mut self, node: &'a Nodes, dot: &Value) -> Result<(), ExecError> {
         let pipe = match *node {
             Nodes::If(ref n) | Nodes::With(ref n) => &n.pipe,
             _ => return Err(ExecError::ExpectedIfOrWith(node.clone())),
         };
-        let val = self.eval_pipeline(ctx, pipe)?;
-        let truth = is_true(&val);
+        let val = self.eval_pipeline(dot, pipe)?;
+        let truth = self.template.exec_options.is_true(&val);
         if truth {
             match *node {
-                Nodes::If(ref n) => self.walk_list(ctx, &n.list)?,
-                Nodes::With(ref n) => {
-                    let ctx = Context { dot: val };
-                    self.walk_list(&ctx, &n.list)?;
-                }
+                Nodes::If(ref n) => self.walk_list(dot, &n.list)?,
+                Nodes::With(ref n) => self.walk_list(&val, &n.list)?,
                 _ => {}
             }
         } else {
             match *node {
                 Nodes::If(ref n) | Nodes::With(ref n) => {
                     if let Some(ref otherwise) = n.else_list {
-                        self.walk_list(ctx, otherwise)?;
+                        self.walk_list(dot, otherwise)?;
                     }
                 }
                 _ => {}
@@ -390,50 +1757,184 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Ok(())
     }
 
+    // Returns the values of a registered iterator if `pipe` consists of a call to it only.
+    fn eval_iterator(
+        &mut self,
+        dot: &Value,
+        pipe: &PipeNode,
+    ) -> Result<Option<Box<dyn Iterator<Item = Value>>>, ExecError> {
+        let cmd = match pipe.cmds.as_slice() {
+            [cmd] => cmd,
+            _ => return Ok(None),
+        };
+        let (name, iter) = match cmd.args.first() {
+            Some(Nodes::Identifier(ref n)) if !self.template.funcs.contains(&n.ident) => {
+                match self.template.funcs.get_iterator(&n.ident) {
+                    Some(iter) => (&n.ident, iter),
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        let args = self.eval_args(dot, &cmd.args, None)?;
+        let iter = iter(&args).map_err(|e| ExecError::FuncFailed(name.clone(), e))?;
+        // Declared like by `eval_pipeline`, the values are set per iteration.
+        self.declare(pipe, &Value::NoValue)?;
+        Ok(Some(iter))
+    }
+
+    // Returns `false` if the body hit `break`.
     fn one_iteration(
         &mut self,
         key: Value,
-        val: Value,
+        val: &'a Value,
         range: &'a RangeNode,
-    ) -> Result<(), ExecError> {
+    ) -> Result<bool, ExecError> {
         if !range.pipe.decl.is_empty() {
-            self.set_kth_last_var_value(1, val.clone())?;
+            self.set_kth_last_var_value(1, Cow::Borrowed(val))?;
         }
         if range.pipe.decl.len() > 1 {
-            self.set_kth_last_var_value(2, key)?;
+            self.set_kth_last_var_value(2, Cow::Owned(key))?;
         }
         let vars = VecDeque::new();
         self.vars.push_back(vars);
-        let ctx = Context { dot: val };
-        self.walk_list(&ctx, &range.list)?;
+        self.walk_list(val, &range.list)?;
         self.vars.pop_back();
-        Ok(())
+        Ok(self.control.take() != Some(LoopControl::Break))
+    }
+
+    fn walk_range(&mut self, dot: &Value, range: &'a RangeNode) -> Result<(), ExecError> {
+        self.scoped(|s| s.walk_range_scoped(dot, range))
     }
 
-    fn walk_range(&mut self, ctx: &Context, range: &'a RangeNode) -> Result<(), ExecError> {
-        let val = self.eval_pipeline(ctx, &range.pipe)?;
-        match val {
+    fn walk_range_scoped(&mut self, dot: &Value, range: &'a RangeNode) -> Result<(), ExecError> {
+        if let Some(iter) = self.eval_iterator(dot, &range.pipe)? {
+            let mut empty = true;
+            for (k, v) in iter.enumerate() {
+                empty = false;
+                if !self.borrowing().one_iteration(Value::from(k), &v, range)? {
+                    break;
+                }
+            }
+            if let Some(ref else_list) = range.else_list {
+                if empty {
+                    self.walk_list(dot, else_list)?;
+                }
+            }
+            return Ok(());
+        }
+        let val = self.eval_pipeline_value(dot, &range.pipe)?;
+        // The variables are set per iteration, borrowing the elements instead of cloning them.
+        // For the else branch they hold the collection if it is empty, which is cheap to clone.
+        let empty = match *val {
+            Value::Object(ref map) | Value::Map(ref map) => map.is_empty(),
+            Value::Array(ref vec) => vec.is_empty(),
+            ref v => return Err(ExecError::InvalidRange(v.clone())),
+        };
+        self.declare(&range.pipe, if empty { &val } else { &NO_VALUE })?;
+        let mut state = self.borrowing();
+        match *val {
+            // Like Golang for maps, keys are visited in sorted order. Objects derived from
+            // structs don't keep the declaration order of their fields and are sorted as well.
             Value::Object(ref map) | Value::Map(ref map) => {
-                for (k, v) in map.clone() {
-                    self.one_iteration(Value::from(k), v, range)?;
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                for (k, v) in entries {
+                    if !state.one_iteration(Value::from(k.as_str()), v, range)? {
+                        break;
+                    }
                 }
             }
             Value::Array(ref vec) => {
                 for (k, v) in vec.iter().enumerate() {
-                    self.one_iteration(Value::from(k), v.clone(), range)?;
+                    if !state.one_iteration(Value::from(k), v, range)? {
+                        break;
+                    }
                 }
             }
-            _ => return Err(ExecError::InvalidRange(val)),
+            _ => {}
         }
         if let Some(ref else_list) = range.else_list {
-            self.walk_list(ctx, else_list)?;
+            if empty {
+                self.walk_list(dot, else_list)?;
+            }
         }
         Ok(())
     }
 
-    fn print_value(&mut self, val: &Value) -> Result<(), ExecError> {
-        write!(self.writer, "{}", val).map_err(ExecError::IOError)?;
-        Ok(())
+    fn print_value(&mut self, node: &Nodes, val: &Value) -> Result<(), ExecError> {
+        let text = match *node {
+            Nodes::Action(ref n) if !is_raw(&n.pipe) => self.escape_value(val)?,
+            _ => self.format_value(val),
+        };
+        if let Some(segments) = self.exec.segments {
+            let text = text.unwrap_or_else(|| go_string(val));
+            self.writer
+                .write_all(text.as_bytes())
+                .map_err(ExecError::IOError)?;
+            self.push_segment(segments, node, SegmentKind::Action, text);
+            return Ok(());
+        }
+        if let Some(formatted) = text {
+            return self
+                .writer
+                .write_all(formatted.as_bytes())
+                .map_err(ExecError::IOError);
+        }
+        self.writer
+            .write_all(go_string(val).as_bytes())
+            .map_err(ExecError::IOError)
+    }
+
+    // The formatted value piped through the escapers of the output mode, if any.
+    fn escape_value(&self, val: &Value) -> Result<Option<String>, ExecError> {
+        let escapers = self.template.output_mode.escapers(self.tree.name());
+        if escapers.is_empty() {
+            return Ok(self.format_value(val));
+        }
+        let text = self.format_value(val).unwrap_or_else(|| go_string(val));
+        let mut escaped = Value::from(text);
+        for (name, escape) in escapers {
+            escaped = escape(&[escaped]).map_err(|e| ExecError::FuncFailed(name.to_owned(), e))?;
+        }
+        Ok(Some(match escaped {
+            Value::String(s) => s,
+            other => other.to_string(),
+        }))
+    }
+
+    // The output of the formatter or the placeholder for a missing value, if any.
+    fn format_value(&self, val: &Value) -> Option<String> {
+        self.template
+            .formatter
+            .and_then(|f| f(val))
+            .or_else(|| match val {
+                Value::NoValue => self.template.exec_options.no_value.clone(),
+                _ => None,
+            })
+    }
+
+    fn push_segment(
+        &self,
+        segments: &RefCell<Vec<Segment>>,
+        node: &Nodes,
+        kind: SegmentKind,
+        text: String,
+    ) {
+        segments.borrow_mut().push(Segment {
+            text,
+            template: self.tree.name().to_owned(),
+            origin_span: node.span(),
+            kind,
+        });
+    }
+}
+
+// Points missing values in strict mode at the field which was missing.
+fn no_value_at(e: ExecError, tree: &Tree, span: Span) -> ExecError {
+    match e {
+        ExecError::NoValue(_) => e.at(tree, span),
+        e => e,
     }
 }
 
@@ -447,6 +1948,8 @@ fn not_a_function(args: &[Nodes], val: &Option<Value>) -> Result<(), ExecError>
 #[cfg(test)]
 mod tests_mocked {
     use super::*;
+    use crate::escape::OutputMode;
+    use crate::{gtmpl_list, gtmpl_map};
     use anyhow::anyhow;
     use gtmpl_derive::Gtmpl;
     use gtmpl_value::FuncError;
@@ -489,6 +1992,595 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "3000");
     }
 
+    #[test]
+    fn test_error_span() {
+        let src = r#"a{{ if . }}{{ .x.y }}{{ end }}"#;
+        let mut t = Template::default();
+        t.parse(src).unwrap();
+        let err = t.render(&Context::from(1)).unwrap_err();
+        assert_eq!(err.to_string(), "only maps and objects have fields");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "{{ .x.y }}");
+    }
+
+    #[test]
+    fn test_func_failed() {
+        fn shout(_: &[Value]) -> Result<Value, FuncError> {
+            Err(FuncError::Generic("too loud".to_owned()))
+        }
+        #[derive(Gtmpl)]
+        struct Foo {
+            shout: Func,
+        }
+        let mut t = Template::default();
+        t.add_func("shout", shout);
+        t.parse(r#"{{ shout 1 }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(err.to_string(), "function `shout` failed: too loud");
+        t.parse(r#"{{ .shout }}"#).unwrap();
+        let err = t.render(&Context::from(Foo { shout })).unwrap_err();
+        assert_eq!(err.to_string(), "function `.shout` failed: too loud");
+        t.parse(r#"{{ shout .x.y }}"#).unwrap();
+        let err = t.render(&Context::from(1)).unwrap_err();
+        assert_eq!(err.to_string(), "only maps and objects have fields");
+    }
+
+    #[test]
+    fn test_func_failed_context() {
+        use crate::FuncResultExt;
+        fn load(args: &[Value]) -> Result<Value, FuncError> {
+            let name = args[0].to_string();
+            let n: i64 = name
+                .parse()
+                .func_context("not a number")
+                .with_func_context(|| format!("loading {}", name))?;
+            Ok(Value::from(n))
+        }
+        let mut t = Template::default();
+        t.add_func("load", load);
+        t.parse(r#"{{ load "x" }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function `load` failed: loading x: not a number: invalid digit found in string"
+        );
+        t.parse(r#"{{ load "7" }}"#).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "7");
+    }
+
+    #[test]
+    fn test_include() {
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ define "item" }}<{{ . }}>{{ end -}}
+            {{ $x := include "item" .a }}{{ $x | len }}{{ $x }}{{ include "item" "b" | printf "[%s]" }}"#,
+        )
+        .unwrap();
+        let data = Value::Map(
+            vec![("a".to_owned(), Value::from("a"))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(t.render(&Context::from(data)).unwrap(), "3<a>[<b>]");
+
+        t.parse(r#"{{ include "missing" . }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(err.to_string(), "template missing not defined");
+        t.parse(r#"{{ include "item" }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function `include` failed: include requires exactly 2 argument(s)"
+        );
+        t.add_func("include", |_| Ok(Value::from("own")));
+        t.parse(r#"{{ include "item" . }}"#).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "own");
+    }
+
+    #[test]
+    fn test_tpl() {
+        let mut t = Template::default();
+        t.add_func("upper", |args| {
+            Ok(Value::from(args[0].to_string().to_uppercase()))
+        });
+        t.parse(r#"{{ define "name" }}{{ .name | upper }}{{ end }}{{ tpl .text . }}"#)
+            .unwrap();
+        let data = Value::Map(
+            vec![
+                ("name".to_owned(), Value::from("gopher")),
+                (
+                    "text".to_owned(),
+                    Value::from(r#"{{ define "hi" }}Hi {{ . }}{{ end }}{{ template "hi" include "name" . }}!"#),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(t.render(&Context::from(data)).unwrap(), "Hi GOPHER!");
+        assert!(t.render_template("hi", &Context::empty()).is_err());
+
+        t.parse(r#"{{ tpl "{{ if }}" . }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert!(err.to_string().starts_with("function `tpl` failed: "));
+        t.parse(r#"{{ tpl 1 . }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function `tpl` failed: template must be a string, got 1"
+        );
+        t.parse(r#"{{ tpl "{{ nope }}" . }}"#).unwrap();
+        assert!(t.render(&Context::empty()).is_err());
+    }
+
+    #[test]
+    fn test_memoize() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn count(args: &[Value]) -> Result<Value, FuncError> {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(args[0].clone())
+        }
+
+        let mut t = Template::default();
+        t.add_func("count", count);
+        t.parse(
+            r#"{{ define "item" }}<{{ count . }}>{{ end -}}
+            {{ range . }}{{ template "item" . }}{{ include "item" . }}{{ end }}"#,
+        )
+        .unwrap();
+        let data = Context::from(vec![
+            Value::from(1),
+            Value::from(2),
+            Value::from(1),
+            Value::from("1"),
+        ]);
+        assert_eq!(t.render(&data).unwrap(), "<1><1><2><2><1><1><1><1>");
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 8);
+
+        t.set_exec_options(ExecOptions {
+            memoize: true,
+            ..Default::default()
+        });
+        assert_eq!(t.render(&data).unwrap(), "<1><1><2><2><1><1><1><1>");
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 3);
+        // The cache lives for one execution.
+        t.render(&data).unwrap();
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_no_value() {
+        let mut t = Template::default();
+        t.parse(r#"a={{ .a }} b={{ .b }} {{ printf "[%v|%s|%d]" .a .a .a }}"#)
+            .unwrap();
+        let mut m = HashMap::new();
+        m.insert("b".to_owned(), Value::from(1));
+        let data = Context::from(Value::Map(m));
+        assert_eq!(
+            t.render(&data).unwrap(),
+            "a=<no value> b=1 [%!v(<nil>)|%!s(<nil>)|%!d(<nil>)]"
+        );
+
+        t.set_exec_options(ExecOptions {
+            no_value: Some(String::new()),
+            ..Default::default()
+        });
+        assert_eq!(t.render(&data).unwrap(), "a= b=1 [||%!d(<nil>)]");
+
+        t.set_exec_options(ExecOptions {
+            no_value: Some("-".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(t.render(&data).unwrap(), "a=- b=1 [-|-|%!d(<nil>)]");
+        t.set_formatter(|v| match v {
+            Value::NoValue => Some("?".to_owned()),
+            _ => None,
+        });
+        assert_eq!(t.render(&data).unwrap(), "a=? b=1 [-|-|%!d(<nil>)]");
+    }
+
+    #[test]
+    fn test_truth() {
+        let mut t = Template::default();
+        t.parse(
+            "{{ if . }}1{{ end }}{{ with . }}2{{ end }}{{ if and . true }}3{{ end }}\
+            {{ if or . false }}4{{ end }}{{ not . }}",
+        )
+        .unwrap();
+        let mut m = HashMap::new();
+        m.insert("isEmpty".to_owned(), Value::Bool(true));
+        let data = Context::from(Value::Object(m));
+        assert_eq!(t.render(&data).unwrap(), "1234false");
+
+        t.set_exec_options(ExecOptions {
+            truth: Some(Arc::new(|val: &Value| match val {
+                Value::Object(o) => o.get("isEmpty").map(|e| e != &Value::Bool(true)),
+                _ => None,
+            })),
+            ..Default::default()
+        });
+        assert_eq!(t.render(&data).unwrap(), "true");
+        assert_eq!(t.render(&Context::from(0)).unwrap(), "true");
+        assert_eq!(t.render(&Context::from(-1)).unwrap(), "1234false");
+    }
+
+    #[test]
+    fn test_catch_panics() {
+        fn boom(args: &[Value]) -> Result<Value, FuncError> {
+            match args {
+                [Value::String(s)] => panic!("boom {}", s),
+                _ => panic!("boom"),
+            }
+        }
+
+        let mut t = Template::default();
+        t.add_func("boom", boom);
+        t.set_exec_options(ExecOptions {
+            catch_panics: true,
+            ..Default::default()
+        });
+        t.parse("a{{ boom }}").unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(err.to_string(), "function `boom` failed: panicked: boom");
+        t.parse(r#"{{ "x" | boom }}"#).unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(err.to_string(), "function `boom` failed: panicked: boom x");
+
+        t.set_exec_options(ExecOptions::default());
+        let res = panic::catch_unwind(AssertUnwindSafe(|| t.render(&Context::empty())));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_context_funcs() {
+        let mut t = Template::default();
+        t.add_context_func("where", |args: &[Value], ctx: &ExecContext| {
+            let var = match args {
+                [Value::String(name)] => ctx.var(name).cloned().unwrap_or(Value::NoValue),
+                _ => Value::NoValue,
+            };
+            Ok(Value::from(format!(
+                "{}:{}:{}:{}",
+                ctx.template_name(),
+                ctx.dot(),
+                ctx.root(),
+                var
+            )))
+        });
+        t.add_context_func("boom", |_: &[Value], _: &ExecContext| panic!("boom"));
+        t.parse(concat!(
+            r#"{{ define "t" }}{{ $x := 1 }}{{ with 2 }}{{ $x := 3 }}{{ where "$x" }}{{ end }}"#,
+            r#" {{ "$x" | where }}{{ end }}{{ template "t" 0 }} {{ where "$y" }}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            t.render(&Context::from(5)).unwrap(),
+            "t:2:0:3 t:0:0:1 :5:5:<no value>"
+        );
+
+        fn shadow(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from("func"))
+        }
+        t.add_func("where", shadow);
+        t.parse(r#"{{ where "$x" }}"#).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "func");
+
+        t.set_exec_options(ExecOptions {
+            catch_panics: true,
+            ..Default::default()
+        });
+        t.parse("{{ boom }}").unwrap();
+        assert_eq!(
+            t.render(&Context::empty()).unwrap_err().to_string(),
+            "function `boom` failed: panicked: boom"
+        );
+    }
+
+    #[test]
+    fn test_output_mode() {
+        fn upper(args: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(args[0].to_string().to_uppercase()))
+        }
+
+        let mut t = Template::default();
+        t.add_template("values.yaml", "a: {{ . }}\nb: {{ . | raw }}")
+            .unwrap();
+        t.add_template("run.sh", r#"echo {{ . }}{{ "x" | printf "%s" | raw }}"#)
+            .unwrap();
+        t.add_template("out.txt", "{{ . }}").unwrap();
+        t.parse(r#"{{ template "values.yaml" . }}|{{ template "run.sh" . }}|{{ template "out.txt" . }}|{{ . }}"#)
+            .unwrap();
+        let data = Context::from("it's: \"x\"");
+        t.set_output_mode(OutputMode::ByExtension);
+        assert_eq!(
+            t.render(&data).unwrap(),
+            "a: \"it's: \\\"x\\\"\"\nb: it's: \"x\"|echo 'it'\\''s: \"x\"'x|it's: \"x\"|it's: \"x\""
+        );
+
+        t.set_output_mode(OutputMode::Custom(vec![upper, crate::escape::json]));
+        t.parse(r#"{{ .a }} {{ raw .a }} {{ with .a }}{{ . }}{{ end }}"#)
+            .unwrap();
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), Value::from("a\tb"));
+        let data = Context::from(Value::Map(m));
+        assert_eq!(t.render(&data).unwrap(), r#""A\tB" a	b "A\tB""#);
+
+        t.parse("{{ raw }}").unwrap();
+        let err = t.render(&data).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function `raw` failed: raw requires exactly 1 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        let mut t = Template::default();
+        t.add_data("sizes", Value::from(vec![1, 2, 3]));
+        t.add_data("name", Value::from("x"));
+        t.parse(r#"{{ range data "sizes" }}{{ . }}{{ end }}{{ data "name" | printf "-%s" }}"#)
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "123-x");
+
+        let error = |t: &mut Template, src: &str| {
+            t.parse(src).unwrap();
+            t.render(&Context::empty()).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&mut t, r#"{{ data "missing" }}"#),
+            "function `data` failed: data missing not defined"
+        );
+        assert_eq!(
+            error(&mut t, "{{ data 1 }}"),
+            "function `data` failed: data name must be a string, got 1"
+        );
+        assert_eq!(
+            error(&mut t, "{{ data }}"),
+            "function `data` failed: data requires exactly 1 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_hash_value() {
+        let a: HashMap<String, Value> = (0..10).map(|i| (i.to_string(), Value::from(i))).collect();
+        let b: HashMap<String, Value> = (0..10)
+            .rev()
+            .map(|i| (i.to_string(), Value::from(i)))
+            .collect();
+        assert_eq!(
+            hash_value(&Value::Map(a.clone())),
+            hash_value(&Value::Map(b))
+        );
+        assert_ne!(
+            hash_value(&Value::Map(a.clone())),
+            hash_value(&Value::Object(a))
+        );
+        assert_ne!(hash_value(&Value::from(1)), hash_value(&Value::from("1")));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        // Records the names of created spans.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.0.lock().unwrap();
+                spans.push(span.metadata().name().to_owned());
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut t = Template::default();
+            t.parse(r#"{{ define "x" }}{{ len . }}{{ end }}{{ template "x" "ab" }}"#)
+                .unwrap();
+            assert_eq!(t.render(&Context::empty()).unwrap(), "2");
+        });
+        let spans = recorder.0.lock().unwrap();
+        assert_eq!(*spans, vec!["parse", "execute", "template", "func"]);
+    }
+
+    #[test]
+    fn test_render_segments() {
+        let mut t = Template::default();
+        let src = r#"{{ define "x" }}<{{ . }}>{{ end }}a{{ template "x" 1 }}{{ include "x" 2 }}{{ $v := 3 }}"#;
+        t.parse(src).unwrap();
+        let segments = t.render_segments(&Context::empty()).unwrap();
+        let parts: Vec<_> = segments
+            .iter()
+            .map(|s| (s.template.as_str(), s.text.as_str(), s.kind))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("", "a", SegmentKind::Text),
+                ("x", "<", SegmentKind::Text),
+                ("x", "1", SegmentKind::Action),
+                ("x", ">", SegmentKind::Text),
+                ("", "<2>", SegmentKind::Action),
+            ]
+        );
+        let span = segments[4].origin_span;
+        assert_eq!(&src[span.start..span.end], r#"{{ include "x" 2 }}"#);
+        let out: String = segments.into_iter().map(|s| s.text).collect();
+        assert_eq!(out, t.render(&Context::empty()).unwrap());
+    }
+
+    #[test]
+    fn test_decl_scope() {
+        let cases = [
+            ("{{ $x := 1 }}{{ if $x := 2 }}{{ $x }}{{ end }}{{ $x }}", "21"),
+            ("{{ if $x := 0 }}{{ else }}{{ $x }}{{ end }}", "0"),
+            (
+                "{{ $x := 1 }}{{ with $x := 0 }}a{{ else }}{{ $x }}{{ end }}{{ $x }}",
+                "01",
+            ),
+            (
+                "{{ $x := 1 }}{{ with $x := 2 }}{{ $x := 3 }}{{ $x }}{{ end }}{{ $x }}",
+                "31",
+            ),
+            (
+                "{{ $x := 1 }}{{ if false }}{{ else }}{{ $x := 2 }}{{ $x }}{{ end }}{{ $x }}",
+                "21",
+            ),
+            ("{{ $x := 1 }}{{ range $x := . }}{{ $x }}{{ end }}{{ $x }}", "ab1"),
+            (
+                "{{ $i := 0 }}{{ range $i, $v := . }}{{ if $v := $i }}{{ $v }}{{ end }}{{ $v }}{{ end }}{{ $i }}",
+                "a1b0",
+            ),
+        ];
+        for (src, expected) in cases.iter() {
+            let mut t = Template::default();
+            t.parse(*src).unwrap();
+            let out = t.render(&Context::from(vec!["a", "b"])).unwrap();
+            assert_eq!(&out, expected, "{}", src);
+        }
+
+        let mut t = Template::default();
+        let err = t
+            .parse("{{ if $x := 1 }}{{ else }}{{ end }}{{ $x }}")
+            .unwrap_err();
+        assert!(err.to_string().contains("undefined variable $x"));
+    }
+
+    #[test]
+    fn test_iterator() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let mut t = Template::default();
+        t.add_iterator("rows", move |args: &[Value]| {
+            let n = match args {
+                [Value::Number(n)] => n.as_u64().unwrap_or(0),
+                _ => return Err(FuncError::ExactlyXArgs("rows".into(), 1)),
+            };
+            let counter = counter.clone();
+            Ok(Box::new((0..n).map(move |i| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Value::from(i * 10)
+            })) as Box<dyn Iterator<Item = Value>>)
+        });
+
+        // The output so far is written before the next row is created.
+        struct Check(Arc<AtomicUsize>, usize);
+        impl Write for Check {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.1 += buf.iter().filter(|&&b| b == b';').count();
+                assert!(self.0.load(Ordering::SeqCst) <= self.1 + 1);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        t.parse("{{ range $i, $r := rows . }}{{ $i }}={{ $r }};{{ end }}")
+            .unwrap();
+        let mut w = Check(created.clone(), 0);
+        t.execute(&mut w, &Context::from(1000)).unwrap();
+        assert_eq!(w.1, 1000);
+        assert_eq!(t.render(&Context::from(2)).unwrap(), "0=0;1=10;");
+
+        t.parse("{{ len (rows 3) }} {{ range rows . }}{{ . }}{{ end }}")
+            .unwrap();
+        assert_eq!(t.render(&Context::from(2)).unwrap(), "3 010");
+        t.parse("{{ range rows . }}{{ . }}{{ else }}none{{ end }}")
+            .unwrap();
+        assert_eq!(t.render(&Context::from(2)).unwrap(), "010");
+        assert_eq!(t.render(&Context::from(0)).unwrap(), "none");
+        t.parse("{{ range rows }}{{ end }}").unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "function `rows` failed: rows requires exactly 1 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_layered() {
+        fn map(entries: Vec<(&str, Value)>) -> Value {
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect(),
+            )
+        }
+        let defaults = map(vec![
+            (
+                "db",
+                map(vec![("host", "localhost".into()), ("port", 80.into())]),
+            ),
+            ("name", "default".into()),
+            ("debug", false.into()),
+        ]);
+        let env = map(vec![("db", map(vec![("port", 5432.into())]))]);
+        let user = map(vec![("name", "app".into())]);
+        let ctx = Context::layered(&[defaults, env, user]);
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ .name }} {{ $.db.host }}:{{ .db.port }} {{ .debug }}{{ .missing }}
+            {{- template "t" . }}{{ define "t" }} {{ .db.host }}{{ end }}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            t.render(&ctx).unwrap(),
+            "app localhost:5432 false<no value> localhost"
+        );
+    }
+
+    #[test]
+    fn test_define_only() {
+        let mut t = Template::default();
+        t.parse("{{ define \"a\" }} {{ end }}\n  {{ define \"b\" }}B{{ end }}\n")
+            .unwrap();
+        t.parse(r#"{{ define "a" }}A{{ template "b" }}{{ end }}"#)
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "");
+        assert_eq!(t.render_template("a", &Context::empty()).unwrap(), "AB");
+        assert!(t.render_template("c", &Context::empty()).is_err());
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), Value::Map(HashMap::new()));
+        let data = Context::from(Value::Map(map));
+        let mut t = Template::default();
+        t.add_func("nothing", |_| Ok(Value::NoValue));
+        t.set_strict(true);
+        let src = "{{ $a := .a }}{{ if .a }}{{ end }}{{ $a.b }}";
+        t.parse(src).unwrap();
+        let err = t.render(&data).unwrap_err();
+        assert_eq!(err.to_string(), "no value for .b");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "$a.b");
+
+        let mut t = Template::default();
+        t.add_func("nothing", |_| Ok(Value::NoValue));
+        t.set_strict(true);
+        t.parse("{{ nothing }}").unwrap();
+        let err = t.render(&data).unwrap_err();
+        assert_eq!(err.to_string(), "no value for nothing");
+    }
+
     #[test]
     fn test_dot() {
         let data = Context::from(1);
@@ -741,6 +2833,71 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "foobar2000");
     }
 
+    #[test]
+    fn test_range_sorted_keys() {
+        let mut t = Template::default();
+        t.parse(r#"{{ range $k, $v := . }}{{ $k }}={{ $v }} {{ end }}"#)
+            .unwrap();
+        let keys = ["delta", "alpha", "charlie", "echo", "bravo", "foxtrot"];
+        let object: HashMap<String, Value> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), Value::from(i)))
+            .collect();
+        let expected = "alpha=1 bravo=4 charlie=2 delta=0 echo=3 foxtrot=5 ";
+        let data = Context::from(Value::Object(object.clone()));
+        assert_eq!(t.render(&data).unwrap(), expected);
+        let data = Context::from(Value::Map(object));
+        assert_eq!(t.render(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_range_nested_fields() {
+        let mut item = HashMap::new();
+        item.insert("name".to_owned(), Value::from("foo"));
+        let mut map = HashMap::new();
+        map.insert("items".to_owned(), Value::from(vec![Value::Map(item)]));
+        map.insert("sep".to_owned(), Value::from(":"));
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t
+            .parse(r#"{{ range .items }}{{ $.sep }}{{ .name }}{{ end }}"#)
+            .is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), ":foo");
+    }
+
+    #[test]
+    fn test_range_vars() {
+        let render = |src: &str| {
+            let mut t = Template::default();
+            t.add_iterator("upTo", |args: &[Value]| {
+                let n = args[0].to_string().parse::<u64>().unwrap();
+                Ok(Box::new((1..=n).map(Value::from)) as Box<dyn Iterator<Item = Value>>)
+            });
+            t.parse(src).unwrap();
+            let ctx = gtmpl_map! {
+                "rows" => gtmpl_list![gtmpl_map! { "a" => 1 }, gtmpl_map! { "a" => 2 }],
+                "empty" => gtmpl_list![],
+            };
+            t.render(&Context::from(ctx)).unwrap()
+        };
+        assert_eq!(
+            render("{{ $s := 1 }}{{ range $i, $r := .rows }}{{ range $j, $n := upTo 2 }}{{ $s }}{{ $i }}{{ $r.a }}{{ $j }}{{ $n }} {{ end }}{{ end }}"),
+            "10101 10112 11201 11212 "
+        );
+        assert_eq!(
+            render("{{ range $r := .rows }}{{ $x := $r.a }}{{ range $.rows }}{{ $x }}{{ .a }}{{ end }}{{ end }}"),
+            "11122122"
+        );
+        assert_eq!(
+            render("{{ range $r := .empty }}{{ else }}{{ $r }}{{ end }}"),
+            "[]"
+        );
+    }
+
     #[test]
     fn test_proper_range() {
         let vec = vec!["a".to_string(), "b".to_string()];
@@ -859,6 +3016,372 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "2000");
     }
 
+    #[test]
+    fn test_parenthesized_args() {
+        let render = |src: &str| {
+            let mut t = Template::default();
+            t.parse(src).unwrap();
+            t.render(&Context::from(vec!["a", "bc"]))
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(render(r#"{{ index . (len (index . 0)) }}"#).unwrap(), "bc");
+        assert_eq!(
+            render(r#"{{ printf "%v-%v" (1) ((len (index . 1))) }}"#).unwrap(),
+            "1-2"
+        );
+        assert_eq!(
+            render(r#"{{ "x" | printf "%v%v" (len .) }}"#).unwrap(),
+            "2x"
+        );
+        assert_eq!(
+            render(r#"{{ (index . 0) | printf "<%v>" }}"#).unwrap(),
+            "<a>"
+        );
+        assert_eq!(
+            render("{{ (1) 2 }}").unwrap_err(),
+            "can't give argument to non-function 1"
+        );
+        assert_eq!(
+            render(r#"{{ 1 | (printf "%v") }}"#).unwrap_err(),
+            r#"can't give argument to non-function printf "%v""#
+        );
+    }
+
+    #[test]
+    fn test_chain_on_pipeline() {
+        fn user(args: &[Value]) -> Result<Value, FuncError> {
+            Ok(gtmpl_map! {
+                "name" => args.first().cloned().unwrap_or(Value::Nil),
+                "roles" => gtmpl_list!["admin", "dev"],
+                "address" => gtmpl_map! { "city" => "Berlin" },
+            })
+        }
+        let render = |src: &str| {
+            let mut t = Template::default();
+            t.add_func("user", user);
+            t.parse(src).map_err(|e| e.to_string())?;
+            t.render(&Context::from(
+                gtmpl_map! { "id" => gtmpl_map! { "n" => 7 } },
+            ))
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(render("{{ (user .id).name.n }}").unwrap(), "7");
+        assert_eq!(render("{{ (user).address.city }}").unwrap(), "Berlin");
+        assert_eq!(render("{{ user.address.city }}").unwrap(), "Berlin");
+        assert_eq!(render("{{ ((user).address).city }}").unwrap(), "Berlin");
+        assert_eq!(render("{{ (.id).n }}").unwrap(), "7");
+        assert_eq!(
+            render(r#"{{ printf "%v-%v" (user 1).name (index (user).roles 1) }}"#).unwrap(),
+            "1-dev"
+        );
+        assert_eq!(
+            render(r#"{{ (user "a").name | printf "<%v>" }}"#).unwrap(),
+            "<a>"
+        );
+        assert_eq!(
+            render("{{ with (user).address }}{{ .city }}{{ end }}").unwrap(),
+            "Berlin"
+        );
+        assert_eq!(
+            render("{{ range (user).roles }}{{ . }};{{ end }}").unwrap(),
+            "admin;dev;"
+        );
+        assert_eq!(render("{{ $u := user 2 }}{{ ($u).name }}").unwrap(), "2");
+        assert_eq!(
+            render("{{ (user).name.x }}").unwrap_err(),
+            "only maps and objects have fields"
+        );
+        assert_eq!(
+            render("{{ 1 | (user).name }}").unwrap_err(),
+            "name has arguments but cannot be invoked as function"
+        );
+        assert_eq!(
+            render("{{ (nil).a }}").unwrap_err(),
+            "cannot evaluate command: nil"
+        );
+    }
+
+    #[test]
+    fn test_numeric_fields() {
+        let render = |src: &str, numeric_fields: bool| {
+            let mut t = Template::default();
+            t.set_numeric_fields(numeric_fields);
+            t.parse(src).map_err(|e| e.to_string())?;
+            let data = gtmpl_map! {
+                "pair" => gtmpl_list!["a", gtmpl_map! { "b" => 1 }],
+                "nested" => gtmpl_list![gtmpl_list![1, 2]],
+            };
+            t.render(&Context::from(data)).map_err(|e| e.to_string())
+        };
+        assert_eq!(render("{{ .pair.0 }}{{ .pair.1.b }}", true).unwrap(), "a1");
+        assert_eq!(
+            render("{{ $p := .nested.0 }}{{ $p.1 }}{{ (.nested).0.0 }}", true).unwrap(),
+            "21"
+        );
+        assert_eq!(render("{{ .pair.0 | printf \"%v\" }}", true).unwrap(), "a");
+        assert_eq!(
+            render("{{ add .5 1 }}", true).unwrap_err(),
+            "template: :1:function add not defined"
+        );
+        assert_eq!(
+            render("{{ .pair.2 }}", true).unwrap_err(),
+            "index 2 out of range for array of length 2"
+        );
+        assert_eq!(
+            render("{{ .pair.x }}", true).unwrap_err(),
+            "only maps and objects have fields"
+        );
+        assert_eq!(
+            render("{{ .pair.0 }}", false).unwrap_err(),
+            "template: :1:unexpected .0 in operand"
+        );
+    }
+
+    #[test]
+    fn test_go_version() {
+        fn die(_: &[Value]) -> Result<Value, FuncError> {
+            Err(FuncError::Generic("die".to_owned()))
+        }
+        let render = |src: &str, version: GoVersion| {
+            let mut t = Template::default();
+            t.set_go_version(version);
+            t.add_func("die", die);
+            t.add_iterator("upTo", |_: &[Value]| {
+                Ok(Box::new((1..10).map(Value::from)) as Box<dyn Iterator<Item = Value>>)
+            });
+            t.add_extension("quote", |_: &Value, body: &str| Ok(format!("{:?}", body)));
+            t.parse(src).map_err(|e| e.to_string())?;
+            t.render(&Context::from(vec![1, 2, 3, 4]))
+                .map_err(|e| e.to_string())
+        };
+        let latest = GoVersion::default();
+
+        let src = "{{ range . }}{{ with . }}{{ if eq . 2 }}{{ continue }}{{ end }}{{ if eq . 4 }}{{ break }}{{ end }}{{ end }}{{ . }}{{ end }}";
+        assert_eq!(render(src, latest).unwrap(), "13");
+        let src = "{{ range upTo }}{{ if eq . 3 }}{{ break }}{{ end }}{{ . }}{{ end }}";
+        assert_eq!(render(src, latest).unwrap(), "12");
+        assert_eq!(
+            render("{{ range . }}{{ break 1 }}{{ end }}", latest).unwrap_err(),
+            "template: :1:unexpected 1 in {{break}}"
+        );
+        assert_eq!(
+            render(
+                r#"{{ range . }}{{ block "b" . }}{{ break }}{{ end }}{{ end }}"#,
+                latest
+            )
+            .unwrap_err(),
+            "template: :1:{{break}} outside {{range}}"
+        );
+        assert_eq!(
+            render(
+                "{{ range . }}{{ quote }}{{ continue }}{{ end }}{{ end }}",
+                latest
+            )
+            .unwrap_err(),
+            "template: :1:{{continue}} outside {{range}}"
+        );
+        assert_eq!(
+            render("{{ range . }}{{ break }}{{ end }}", GoVersion::Go1_17).unwrap_err(),
+            "template: :1:function break not defined"
+        );
+
+        assert_eq!(
+            render("{{ or 0 1 (die) }}", GoVersion::Go1_18).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            render("{{ and 1 0 (die) }}", GoVersion::Go1_18).unwrap(),
+            "0"
+        );
+        assert_eq!(render("{{ 1 | and 1 }}", GoVersion::Go1_18).unwrap(), "1");
+        assert_eq!(
+            render("{{ or 0 1 (die) }}", GoVersion::Go1_17).unwrap_err(),
+            "function `die` failed: die"
+        );
+
+        let mut t = Template::default();
+        t.add_func("die", die);
+        t.add_func("and", |args: &[Value]| Ok(Value::from(args.len())));
+        t.add_func("or", |args: &[Value]| Ok(Value::from(args.len())));
+        t.parse("{{ and 0 1 2 }}{{ or 1 0 2 }}").unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "33");
+        t.parse("{{ and 1 0 (die) }}").unwrap();
+        assert!(t.render(&Context::empty()).is_err());
+
+        let src = "{{ with 0 }}a{{ else with 2 }}{{ . }}{{ else }}c{{ end }}";
+        assert_eq!(render(src, latest).unwrap(), "2");
+        assert!(render(src, GoVersion::Go1_18).is_err());
+        let mut t = Template::default();
+        t.parse(src).unwrap();
+        assert_eq!(
+            t.tree("").unwrap().root().unwrap().to_string(),
+            "{{with 0}}a{{else}}{{with 2}}{{.}}{{else}}c{{end}}{{end}}"
+        );
+    }
+
+    #[test]
+    fn test_template_args() {
+        let render = |src: &str, template_args: bool| {
+            let mut t = Template::default();
+            t.set_template_args(template_args);
+            t.parse(src).map_err(|e| e.to_string())?;
+            let data = gtmpl_map! { "a" => 1, "b" => "x" };
+            t.render(&Context::from(data)).map_err(|e| e.to_string())
+        };
+        let define = r#"{{ define "t" }}{{ .a }}{{ range $args }} {{ . }}{{ end }}{{ end }}"#;
+        assert_eq!(
+            render(
+                &format!(r#"{define}{{{{ template "t" . .b 2 (len $args) }}}}"#),
+                true
+            )
+            .unwrap(),
+            "1 x 2 0"
+        );
+        assert_eq!(
+            render(&format!(r#"{define}{{{{ template "t" . }}}}"#), true).unwrap(),
+            "1"
+        );
+        let call =
+            r#"{{ define "p" }}{{ . }}{{ $args }}{{ end }}{{ template "p" printf "%v" .a }}"#;
+        assert_eq!(render(call, true).unwrap(), "1[]");
+        assert_eq!(render(r#"{{ len $args }}"#, true).unwrap(), "0");
+        let nested = r#"{{ define "u" }}{{ $args }}{{ end }}{{ define "t" }}{{ template "u" . }}{{ $args }}{{ end }}"#;
+        assert_eq!(
+            render(&format!(r#"{nested}{{{{ template "t" . 1 }}}}"#), true).unwrap(),
+            "[][1]"
+        );
+        assert_eq!(
+            render(r#"{{ $args }}"#, false).unwrap_err(),
+            "template: :1:undefined variable $args"
+        );
+        assert_eq!(
+            render(r#"{{ define "d" }}{{ end }}{{ template "d" . 1 }}"#, false).unwrap_err(),
+            "can't give argument to non-function ."
+        );
+        let mut t = Template::default();
+        t.set_template_args(true);
+        t.parse(r#"{{ template "t" .a $ (.b) }}"#).unwrap();
+        assert_eq!(
+            t.tree("").unwrap().root().unwrap().to_string(),
+            r#"{{template "t" .a $ (.b)}}"#
+        );
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut t = Template::default();
+        t.add_page("a", "a").unwrap();
+        let ctx = Context::from("x");
+        assert_eq!(
+            t.render_page("a", &ctx).unwrap_err().to_string(),
+            "no layout set for rendering pages"
+        );
+        t.add_template(
+            "layout",
+            r#"[{{ block "title" . }}-{{ end }}]{{ template "content" . }}"#,
+        )
+        .unwrap();
+        t.add_template("shout", "{{ . }}!").unwrap();
+        t.set_layout("layout");
+        t.add_post_filter(|out: String| out.to_uppercase());
+        t.add_page(
+            "b",
+            r#"{{ define "title" }}b{{ end }}{{ define "content" }}{{ template "shout" . }}{{ end }}"#,
+        )
+        .unwrap();
+        assert_eq!(t.render_page("a", &ctx).unwrap(), "[-]A");
+        assert_eq!(t.render_page("b", &ctx).unwrap(), "[B]X!");
+        assert_eq!(t.render_page("a", &ctx).unwrap(), "[-]A");
+        assert_eq!(
+            t.render_page("c", &ctx).unwrap_err().to_string(),
+            "template c not defined"
+        );
+        assert!(!t.tree_set.contains_key("content"));
+        t.set_layout("nope");
+        assert_eq!(
+            t.render_page("a", &ctx).unwrap_err().to_string(),
+            "nope is an incomplete or empty template"
+        );
+    }
+
+    #[test]
+    fn test_render_bytes() {
+        let mut t = Template::default();
+        t.parse("{{ . }} ü{{ printf \"%c\" 128512 }}").unwrap();
+        let ctx = Context::from("a\u{0}");
+        assert_eq!(
+            t.render_bytes(&ctx).unwrap(),
+            t.render(&ctx).unwrap().into_bytes()
+        );
+        t.add_post_filter(|out: String| out.replace('ü', "ue"));
+        assert_eq!(t.render_bytes(&ctx).unwrap(), "a\u{0} ue😀".as_bytes());
+    }
+
+    #[test]
+    fn test_post_filters() {
+        let mut t = Template::default();
+        t.add_post_filter(|out: String| out.to_uppercase());
+        t.add_post_filter(|out: String| format!("<{}>", out));
+        t.parse(r#"{{ define "x" }}x{{ end }}a{{ template "x" }}{{ include "x" . }}"#)
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "<AXX>");
+        assert_eq!(t.render_template("x", &Context::empty()).unwrap(), "<X>");
+        let mut w = String::new();
+        t.render_to(&mut w, &Context::empty()).unwrap();
+        assert_eq!(w, "<AXX>");
+
+        t.parse("{{ .a.b }}").unwrap();
+        let mut w: Vec<u8> = vec![];
+        assert!(t.execute(&mut w, &Context::from(1)).is_err());
+        assert!(w.is_empty());
+    }
+
+    #[test]
+    fn test_extension() {
+        let render = |src: &str| {
+            let mut t = Template::default();
+            t.set_output_mode(OutputMode::Json);
+            t.add_extension("upper", |_: &Value, body: &str| Ok(body.to_uppercase()));
+            t.add_extension("wrap", |val: &Value, body: &str| match val {
+                Value::String(s) => Ok(format!("{}{}{}", s, body, s)),
+                Value::NoValue => Ok(format!("[{}]", body)),
+                _ => Err(FuncError::Generic("wrap needs a string".to_owned())),
+            });
+            t.add_func("upper", |_: &[Value]| Ok(Value::from("fn")));
+            t.parse(src).map_err(|e| e.to_string())?;
+            t.render(&Context::from(
+                gtmpl_map! { "a" => "x", "l" => gtmpl_list![1, 2] },
+            ))
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            render("{{ upper }}a {{ .a }}{{ end }}").unwrap(),
+            r#"A "X""#
+        );
+        assert_eq!(
+            render(r#"{{ $v := .a }}{{ wrap "*" }}{{ $v }}{{ upper }}{{ $v }}{{ end }}{{ end }}{{ $v }}"#)
+                .unwrap(),
+            r#"*"x""X"*"x""#
+        );
+        assert_eq!(
+            render("{{ range .l }}{{ wrap }}{{ . }}{{ end }}{{ end }}").unwrap(),
+            r#"["1"]["2"]"#
+        );
+        assert_eq!(
+            render("{{ wrap $x := .a }}{{ $x }}{{ end }}").unwrap(),
+            r#"x"x"x"#
+        );
+        assert_eq!(render("{{ 1 | upper }}").unwrap(), r#""fn""#);
+        assert_eq!(
+            render("{{ wrap 1 }}{{ end }}").unwrap_err(),
+            "function `wrap` failed: wrap needs a string"
+        );
+        assert_eq!(
+            render("{{ upper }}{{ .a.b }}{{ end }}").unwrap_err(),
+            "only maps and objects have fields"
+        );
+    }
+
     #[test]
     fn test_function() {
         let mut w: Vec<u8> = vec![];
@@ -938,6 +3461,56 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "true");
     }
 
+    #[test]
+    fn test_formatter() {
+        fn keys(val: &Value) -> Option<String> {
+            if let Value::Map(ref m) = *val {
+                let mut keys: Vec<&str> = m.keys().map(String::as_str).collect();
+                keys.sort_unstable();
+                return Some(keys.join(","));
+            }
+            None
+        }
+        let mut map = HashMap::new();
+        map.insert("a".to_owned(), 1);
+        map.insert("b".to_owned(), 2);
+        let data = Context::from(map);
+        let mut t = Template::default();
+        t.set_formatter(keys);
+        assert!(t
+            .parse(r#"{{ define "x" }}{{ . }}{{ end }}{{ . }} {{ .a }} {{ template "x" . }}"#)
+            .is_ok());
+        assert_eq!(t.render(&data).unwrap(), "a,b 1 a,b");
+    }
+
+    #[test]
+    fn test_field_fallback() {
+        let mut map = HashMap::new();
+        map.insert("user_id".to_owned(), 1);
+        map.insert("firstName".to_owned(), 2);
+        map.insert("Exact".to_owned(), 3);
+        let data = Context::from(map);
+        let mut t = Template::default();
+        assert!(t
+            .parse(r#"{{ .UserID }} {{ .FirstName }} {{ .Exact }}"#)
+            .is_ok());
+        assert_eq!(t.render(&data).unwrap(), "<no value> <no value> 3");
+        t.set_field_fallback(true);
+        assert_eq!(t.render(&data).unwrap(), "1 2 3");
+    }
+
+    #[test]
+    fn test_render_to() {
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{ range . }}{{ . }} → {{ end }}end"#).is_ok());
+        let data = Context::from(vec![1, 2]);
+        let mut out = String::new();
+        let res = t.render_to(&mut out, &data);
+        assert!(res.is_ok());
+        assert_eq!(out, "1 → 2 → end");
+        assert_eq!(t.size_hint(&t.name), " → end".len());
+    }
+
     #[test]
     fn test_assign_string() {
         let mut w: Vec<u8> = vec![];
@@ -950,4 +3523,102 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "bar");
     }
+
+    #[test]
+    fn test_deeply_nested() {
+        let depth = 10_000;
+        let open = "{{ if true }}\n{{ with $ }}\n{{ range . }}\n";
+        let src = format!(
+            "{}{{{{ . }}}}{}",
+            open.repeat(depth),
+            "{{ end }}\n".repeat(3 * depth)
+        );
+        let mut t = Template::default();
+        t.parse(&src).unwrap();
+        let out = t.render(&Context::from(vec![1])).unwrap();
+        assert_eq!(out.trim(), "1");
+
+        let parens = depth / 10;
+        let src = format!(
+            "{{{{ {}1{} }}}}",
+            "(print ".repeat(parens),
+            ")".repeat(parens)
+        );
+        t.parse(src).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_eval_expr() {
+        let data = gtmpl_map! { "a" => gtmpl_list![1, 2, 3], "b" => "x" };
+        let eval = |expr: &str| eval_expr(expr, &data, &[]);
+        assert_eq!(eval(".a | len").unwrap(), Value::from(3));
+        assert_eq!(eval("index $.a 1").unwrap(), Value::from(2));
+        assert_eq!(eval("$x := .b").unwrap(), Value::from("x"));
+        assert_eq!(eval(".a").unwrap(), gtmpl_list![1, 2, 3]);
+        assert_eq!(eval(".c").unwrap(), Value::NoValue);
+
+        let err = |expr: &str| eval(expr).unwrap_err().to_string();
+        assert_eq!(err("1 }}a{{ 2"), "1 }}a{{ 2 is not a single pipeline");
+        assert_eq!(
+            err("if 1 }}a{{ end"),
+            "if 1 }}a{{ end is not a single pipeline"
+        );
+        assert_eq!(err("foo"), "template: :1:function foo not defined");
+        assert_eq!(
+            err("index .a 5"),
+            "function `index` failed: index out of range: 5"
+        );
+    }
+
+    #[test]
+    fn test_render_many() {
+        let mut t = Template::default();
+        t.add_func("fail", |_| Err(FuncError::Generic("empty".to_owned())));
+        t.parse(r#"{{ if . }}{{ . }}{{ else }}{{ fail "empty" }}{{ end }}"#)
+            .unwrap();
+        let contexts = vec![
+            Context::from("a long one"),
+            Context::from(""),
+            Context::from(1),
+        ];
+        let outputs: Vec<_> = t
+            .render_many(contexts)
+            .map(|r| r.map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                Ok("a long one".to_owned()),
+                Err("function `fail` failed: empty".to_owned()),
+                Ok("1".to_owned()),
+            ]
+        );
+        assert_eq!(t.render_many(vec![]).count(), 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_render_many() {
+        use rayon::iter::ParallelIterator;
+        let mut t = Template::default();
+        t.parse("{{ . }}").unwrap();
+        let contexts: Vec<_> = (0..1000).map(Context::from).collect();
+        let outputs: Vec<_> = t.par_render_many(contexts).map(Result::unwrap).collect();
+        let expected: Vec<_> = (0..1000).map(|i| i.to_string()).collect();
+        assert_eq!(outputs, expected);
+    }
+
+    #[test]
+    fn test_allow_undefined_functions() {
+        let src = r#"{{ . | greet }}{{ if false }}{{ missing }}{{ end }}"#;
+        let mut t = Template::default();
+        assert!(t.parse(src).is_err());
+        t.set_allow_undefined_functions(true);
+        t.parse(src).unwrap();
+        let err = t.render(&Context::from("x")).unwrap_err();
+        assert_eq!(err.to_string(), "greet is not a defined function");
+        t.add_func("greet", |args| Ok(Value::from(format!("hi {}", args[0]))));
+        assert_eq!(t.render(&Context::from("x")).unwrap(), "hi x");
+    }
 }
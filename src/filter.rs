@@ -0,0 +1,92 @@
+//! Filters post-processing the complete output of a template, see
+//! [`Template::add_post_filter`](crate::Template::add_post_filter).
+//!
+//! ```rust
+//! use gtmpl::{filter, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_post_filter(filter::squash_blank_lines);
+//! tmpl.add_post_filter(filter::trailing_newline);
+//! tmpl.parse("a:\n{{ range . }}\n  - {{ . }}\n{{ end }}\n\nb: 1").unwrap();
+//! let output = tmpl.render(&Context::from(vec![1, 2]));
+//! assert_eq!(&output.unwrap(), "a:\n\n  - 1\n\n  - 2\n\nb: 1\n");
+//! ```
+
+/// Replaces runs of blank lines, i.e. lines containing only whitespace, by a single empty line.
+///
+/// # Example
+/// ```
+/// use gtmpl::filter::squash_blank_lines;
+/// assert_eq!(squash_blank_lines("a\n\n  \n\nb\n".to_owned()), "a\n\nb\n");
+/// ```
+pub fn squash_blank_lines(out: String) -> String {
+    let mut squashed = String::with_capacity(out.len());
+    let mut blank = false;
+    for line in out.split_inclusive('\n') {
+        if line.trim().is_empty() && line.ends_with('\n') {
+            if !blank {
+                squashed.push_str(if line.ends_with("\r\n") { "\r\n" } else { "\n" });
+            }
+            blank = true;
+        } else {
+            squashed.push_str(line);
+            blank = false;
+        }
+    }
+    squashed
+}
+
+/// Ends non-empty output with exactly one newline.
+///
+/// # Example
+/// ```
+/// use gtmpl::filter::trailing_newline;
+/// assert_eq!(trailing_newline("a\n\n".to_owned()), "a\n");
+/// assert_eq!(trailing_newline("a".to_owned()), "a\n");
+/// assert_eq!(trailing_newline(String::new()), "");
+/// ```
+pub fn trailing_newline(out: String) -> String {
+    if out.is_empty() {
+        return out;
+    }
+    let mut out = out;
+    out.truncate(out.trim_end_matches(['\n', '\r']).len());
+    out.push('\n');
+    out
+}
+
+/// Replaces CRLF line endings by LF, e.g. for templates edited on Windows.
+///
+/// # Example
+/// ```
+/// use gtmpl::filter::normalize_newlines;
+/// assert_eq!(normalize_newlines("a\r\nb\r\n".to_owned()), "a\nb\n");
+/// ```
+pub fn normalize_newlines(out: String) -> String {
+    if out.contains('\r') {
+        out.replace("\r\n", "\n")
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_squash_blank_lines() {
+        let squash = |s: &str| squash_blank_lines(s.to_owned());
+        assert_eq!(squash(""), "");
+        assert_eq!(squash("\n\n\na"), "\na");
+        assert_eq!(squash("a\n \t\n\nb\n\n"), "a\n\nb\n\n");
+        assert_eq!(squash("a\r\n\r\n\r\nb  "), "a\r\n\r\nb  ");
+        assert_eq!(squash("a\n\n  "), "a\n\n  ");
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        assert_eq!(trailing_newline("a\r\n\n".to_owned()), "a\n");
+        assert_eq!(trailing_newline("\n".to_owned()), "\n");
+    }
+}
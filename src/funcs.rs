@@ -1,24 +1,45 @@
 //! Builtin functions.
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::fmt::Write;
 
 use gtmpl_value::{Func, FuncError, Value};
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
-use crate::printf::sprintf;
+use crate::print_verb::go_string;
+use crate::printf::{sprintf, type_name};
 use crate::utils::is_true;
 
-const QUERY_ENCODE: &AsciiSet = &CONTROLS
-    .add(b' ')
-    .add(b'"')
-    .add(b'<')
-    .add(b'>')
-    .add(b'#')
-    .add(b'`')
-    .add(b'`')
-    .add(b'?')
-    .add(b'{')
-    .add(b'}');
+mod registry;
+
+pub use registry::{ContextFunc, FuncRegistry, IterFunc};
+
+#[cfg(feature = "gtmpl_crypto")]
+pub mod crypto;
+#[cfg(feature = "gtmpl_debug")]
+pub mod debug;
+#[cfg(feature = "gtmpl_dict")]
+pub mod dict;
+#[cfg(feature = "gtmpl_encoding")]
+pub mod encoding;
+#[cfg(feature = "gtmpl_math")]
+pub mod math;
+#[cfg(feature = "gtmpl_os")]
+pub mod os;
+#[cfg(feature = "gtmpl_regex")]
+pub mod regex;
+#[cfg(feature = "gtmpl_strings")]
+pub mod strings;
+#[cfg(feature = "gtmpl_time")]
+pub mod time;
+#[cfg(feature = "gtmpl_validate")]
+pub mod validate;
+
+// Go's `url.QueryEscape` keeps only letters, digits and `-_.~`.
+const QUERY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
 
 pub static BUILTINS: &[(&str, Func)] = &[
     ("eq", eq as Func),
@@ -39,6 +60,35 @@ pub static BUILTINS: &[(&str, Func)] = &[
     ("call", call as Func),
 ];
 
+/// Functions implemented by the executor as they need access to the other templates. A
+/// registered function with the same name takes precedence.
+pub(crate) static EXEC_FUNCS: &[&str] = &["include", "tpl", "raw", "data"];
+
+/// Minimum and, if limited, maximum number of arguments of a function.
+pub(crate) type Arity = (usize, Option<usize>);
+
+/// Argument counts of the builtin and executor functions which are checked at parse time, see
+/// [`Template::set_strict_parse`](crate::Template::set_strict_parse).
+pub(crate) static ARITIES: &[(&str, Arity)] = &[
+    ("eq", (2, None)),
+    ("ne", (2, Some(2))),
+    ("lt", (2, Some(2))),
+    ("le", (2, Some(2))),
+    ("gt", (2, Some(2))),
+    ("ge", (2, Some(2))),
+    ("len", (1, Some(1))),
+    ("and", (1, None)),
+    ("or", (1, None)),
+    ("not", (1, Some(1))),
+    ("printf", (1, None)),
+    ("index", (2, None)),
+    ("call", (1, None)),
+    ("include", (2, Some(2))),
+    ("tpl", (2, Some(2))),
+    ("raw", (1, Some(1))),
+    ("data", (1, Some(1))),
+];
+
 macro_rules! val {
     ($x:expr) => {
         Value::from($x)
@@ -46,6 +96,9 @@ macro_rules! val {
 }
 
 /// Help to write new functions for gtmpl.
+///
+/// Arguments are converted as described in [`coerce`](crate::coerce), e.g. integers are accepted
+/// for `f64` parameters.
 #[macro_export]
 macro_rules! gtmpl_fn {
  (
@@ -73,9 +126,7 @@ macro_rules! gtmpl_fn {
    if args.is_empty() {
     return Err(FuncError::AtLeastXArgs(stringify!($name).into(), 1));
    }
-   let x = &args[0];
-   let $arg0: $typ0 = $crate::from_value(x)
-    .ok_or(FuncError::UnableToConvertFromValue)?;
+   let $arg0: $typ0 = $crate::coerce::arg(stringify!($name), 0, &args[0])?;
    fn inner($arg0 : $typ0) -> Result<$otyp, FuncError> {
     $($body)*
    }
@@ -92,18 +143,14 @@ macro_rules! gtmpl_fn {
   pub fn $name(
    args: &[$crate::Value]
   ) -> Result<$crate::Value, FuncError> {
-   #[allow(unused_mut)]
-   let mut args = args;
-   if args.is_empty() {
-    return Err(FuncError::AtLeastXArgs(stringify!($name).into(), 1));
+   let arity = 1 $(+ { let _ = stringify!($arg); 1 })*;
+   if args.len() < arity {
+    return Err(FuncError::AtLeastXArgs(stringify!($name).into(), arity));
    }
-   let x = &args[0];
-   let $arg0: $typ0 = $crate::from_value(x)
-    .ok_or(FuncError::UnableToConvertFromValue)?;
-   $(args = &args[1..];
-     let x = &args[0];
-     let $arg: $typ = $crate::from_value(x)
-    .ok_or(FuncError::UnableToConvertFromValue)?;)*
+   let $arg0: $typ0 = $crate::coerce::arg(stringify!($name), 0, &args[0])?;
+   let mut index = 0;
+   $(index += 1;
+     let $arg: $typ = $crate::coerce::arg(stringify!($name), index, &args[index])?;)*
    fn inner($arg0 : $typ0, $($arg : $typ,)*) -> Result<$otyp, FuncError> {
     $($body)*
    }
@@ -147,14 +194,18 @@ macro_rules! gn {
 /// assert_eq!(&equal.unwrap(), "1");
 /// ```
 pub fn or(args: &[Value]) -> Result<Value, FuncError> {
+    or_by(args, &is_true)
+}
+
+// `or` with a custom truthiness, see `ExecOptions::truth`.
+pub(crate) fn or_by(args: &[Value], truth: &dyn Fn(&Value) -> bool) -> Result<Value, FuncError> {
     for arg in args {
-        if is_true(arg) {
+        if truth(arg) {
             return Ok(arg.clone());
         }
     }
-    args.iter()
+    args.last()
         .cloned()
-        .last()
         .ok_or_else(|| FuncError::AtLeastXArgs("or".into(), 1))
 }
 
@@ -170,14 +221,18 @@ pub fn or(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "foo");
 /// ```
 pub fn and(args: &[Value]) -> Result<Value, FuncError> {
+    and_by(args, &is_true)
+}
+
+// `and` with a custom truthiness, see `ExecOptions::truth`.
+pub(crate) fn and_by(args: &[Value], truth: &dyn Fn(&Value) -> bool) -> Result<Value, FuncError> {
     for arg in args {
-        if !is_true(arg) {
+        if !truth(arg) {
             return Ok(arg.clone());
         }
     }
-    args.iter()
+    args.last()
         .cloned()
-        .last()
         .ok_or_else(|| FuncError::AtLeastXArgs("and".into(), 1))
 }
 
@@ -190,15 +245,23 @@ pub fn and(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "true");
 /// ```
 pub fn not(args: &[Value]) -> Result<Value, FuncError> {
+    not_by(args, &is_true)
+}
+
+// `not` with a custom truthiness, see `ExecOptions::truth`.
+pub(crate) fn not_by(args: &[Value], truth: &dyn Fn(&Value) -> bool) -> Result<Value, FuncError> {
     if args.len() != 1 {
         Err(FuncError::ExactlyXArgs("not".into(), 1))
     } else {
-        Ok(val!(!is_true(&args[0])))
+        Ok(val!(!truth(&args[0])))
     }
 }
 
 /// Returns the integer length of its argument.
 ///
+/// Like in Go, strings have their length in bytes, arrays and maps their number of elements
+/// and other values have no length. Diverging from Go, objects have their number of fields.
+///
 /// # Example
 /// ```
 /// use gtmpl::template;
@@ -213,9 +276,15 @@ pub fn len(args: &[Value]) -> Result<Value, FuncError> {
     let len = match *arg {
         Value::String(ref s) => s.len(),
         Value::Array(ref a) => a.len(),
-        Value::Object(ref o) => o.len(),
+        Value::Map(ref m) | Value::Object(ref m) => m.len(),
+        Value::Nil | Value::NoValue => {
+            return Err(FuncError::Generic("len of untyped nil".into()));
+        }
         _ => {
-            return Err(FuncError::Generic(format!("unable to call len on {}", arg)));
+            return Err(FuncError::Generic(format!(
+                "len of type {}",
+                type_name(arg)
+            )));
         }
     };
 
@@ -252,29 +321,27 @@ pub fn call(args: &[Value]) -> Result<Value, FuncError> {
 /// An implementation of golang's fmt.Sprint
 ///
 /// Golang's Sprint formats using the default formats for its operands and returns the
-/// resulting string. Spaces are added between operands when neither is a string.
+/// resulting string. Spaces are added between operands when neither is a string. Missing
+/// values are printed as `<nil>` like Golang's nil.
 ///
 /// # Example
 /// ```
 /// use gtmpl::template;
 /// let equal = template(r#"{{ print "Hello " . "!" }}"#, "world");
 /// assert_eq!(&equal.unwrap(), "Hello world!");
+/// let equal = template(r#"{{ print 1 2 "a" 3 "b" "c" }}"#, 0);
+/// assert_eq!(&equal.unwrap(), "1 2a3bc");
 /// ```
 pub fn print(args: &[Value]) -> Result<Value, FuncError> {
-    let mut no_space = true;
     let mut s = String::new();
-    for val in args {
-        if let Value::String(ref v) = *val {
-            no_space = true;
-            s.push_str(v);
-        } else {
-            if no_space {
-                s += &val.to_string();
-            } else {
-                s += &format!(" {}", val.to_string())
-            }
-            no_space = false;
+    let mut prev_string = false;
+    for (i, val) in args.iter().enumerate() {
+        let is_string = matches!(val, Value::String(_));
+        if i > 0 && !is_string && !prev_string {
+            s.push(' ');
         }
+        s.push_str(&sprint_operand(val));
+        prev_string = is_string;
     }
     Ok(val!(s))
 }
@@ -291,30 +358,17 @@ pub fn print(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "Hello world !\n");
 /// ```
 pub fn println(args: &[Value]) -> Result<Value, FuncError> {
-    let mut iter = args.iter();
-    let s = match iter.next() {
-        None => String::from("\n"),
-        Some(first_elt) => {
-            let (lower, _) = iter.size_hint();
-            let mut result = String::with_capacity(lower + 1);
-            if let Value::String(ref v) = *first_elt {
-                result.push_str(v);
-            } else {
-                write!(&mut result, "{}", first_elt).unwrap();
-            }
-            for elt in iter {
-                result.push(' ');
-                if let Value::String(ref v) = *elt {
-                    result.push_str(v);
-                } else {
-                    write!(&mut result, "{}", elt).unwrap();
-                }
-            }
-            result.push('\n');
-            result
-        }
-    };
-    Ok(val!(s))
+    let operands: Vec<String> = args.iter().map(sprint_operand).collect();
+    Ok(val!(operands.join(" ") + "\n"))
+}
+
+// Formats an operand of `print` and `println` like golang's `%v`.
+fn sprint_operand(val: &Value) -> String {
+    match val {
+        Value::String(s) => s.clone(),
+        Value::NoValue => "<nil>".to_owned(),
+        val => go_string(val),
+    }
 }
 
 /// An implementation of golang's fmt.Sprintf
@@ -323,18 +377,29 @@ pub fn println(args: &[Value]) -> Result<Value, FuncError> {
 ///   * `g`, `G`, and `b` are weired and not implement yet
 /// - pretty sure there are more
 ///
+/// Like in Go, missing, extra and mismatched arguments are reported in the output, e.g.
+/// `%!d(MISSING)`, `%!(EXTRA int=1)` or `%!d(string=a)`.
+///
 /// # Example
 /// ```
 /// use gtmpl::template;
 /// let equal = template(r#"{{ printf "%v %s %v" "Hello" . "!" }}"#, "world");
 /// assert_eq!(&equal.unwrap(), "Hello world !");
+/// let mismatch = template(r#"{{ printf "%d %d" . }}"#, "world");
+/// assert_eq!(&mismatch.unwrap(), "%!d(string=world) %!d(MISSING)");
 /// ```
 pub fn printf(args: &[Value]) -> Result<Value, FuncError> {
+    printf_no_value(args, None)
+}
+
+// `printf` printing missing values as `no_value`, called instead of it if
+// `ExecOptions::no_value` is set.
+pub(crate) fn printf_no_value(args: &[Value], no_value: Option<&str>) -> Result<Value, FuncError> {
     if args.is_empty() {
         return Err(FuncError::AtLeastXArgs("printf".into(), 1));
     }
     if let Value::String(ref s) = args[0] {
-        let s = sprintf(s, &args[1..]).map_err(|e| FuncError::Other(e.into()))?;
+        let s = sprintf(s, &args[1..], no_value).map_err(|e| FuncError::Other(e.into()))?;
         Ok(val!(s))
     } else {
         Err(FuncError::Generic("printf requires a format string".into()))
@@ -343,7 +408,15 @@ pub fn printf(args: &[Value]) -> Result<Value, FuncError> {
 
 /// Returns the result of indexing its first argument by the
 /// following arguments. Thus "index x 1 2 3" is, in Go syntax,
-/// x[1][2][3]. Each indexed item must be a map, slice or array.
+/// `x[1][2][3]`. Each indexed item must be a map, slice or array.
+///
+/// Like in Go, arrays and strings are indexed by integers, where strings yield the byte at
+/// that position, and maps by strings, yielding no value for missing keys. Indexes out of
+/// range and other types fail. Diverging from Go, where map keys are typed:
+///
+/// * maps are also indexed by integers, which are converted to their decimal text, e.g. for
+///   maps created from integer keys,
+/// * objects are indexed like maps, but fail for missing keys like missing fields.
 ///
 /// # Example
 /// ```
@@ -356,55 +429,85 @@ pub fn index(args: &[Value]) -> Result<Value, FuncError> {
     if args.len() < 2 {
         return Err(FuncError::AtLeastXArgs("index".into(), 2));
     }
-    let mut col = &args[0];
+    let mut col = Cow::Borrowed(&args[0]);
     for val in &args[1..] {
-        col = get_item(col, val)?;
+        col = match col {
+            Cow::Borrowed(col) => get_item(col, val)?,
+            Cow::Owned(col) => Cow::Owned(get_item(&col, val)?.into_owned()),
+        };
     }
 
-    Ok(col.clone())
+    Ok(col.into_owned())
 }
 
-fn get_item<'a>(col: &'a Value, key: &Value) -> Result<&'a Value, FuncError> {
-    let ret = match (col, key) {
-        (&Value::Array(ref a), &Value::Number(ref n)) => {
-            if let Some(i) = n.as_u64() {
-                a.get(i as usize)
-            } else {
-                None
+fn get_item<'a>(col: &'a Value, key: &Value) -> Result<Cow<'a, Value>, FuncError> {
+    let err = |msg: String| Err(FuncError::Generic(msg));
+    match (col, key) {
+        (Value::Nil, _) | (Value::NoValue, _) => err("index of untyped nil".to_owned()),
+        (Value::Array(a), _) => Ok(Cow::Borrowed(&a[array_index(key, a.len())?])),
+        (Value::String(s), _) => Ok(Cow::Owned(Value::from(
+            s.as_bytes()[array_index(key, s.len())?],
+        ))),
+        (Value::Map(m), _) | (Value::Object(m), _) => {
+            let name = match key {
+                Value::String(s) => Cow::Borrowed(s.as_str()),
+                Value::Number(n) if n.as_f64().is_none() => Cow::Owned(n.to_string()),
+                Value::Nil | Value::NoValue => {
+                    return err("value is nil; should be of type string".to_owned());
+                }
+                _ => {
+                    return err(format!(
+                        "value has type {}; should be string",
+                        type_name(key)
+                    ))
+                }
+            };
+            match (m.get(name.as_ref()), col) {
+                (Some(val), _) => Ok(Cow::Borrowed(val)),
+                (None, Value::Map(_)) => Ok(Cow::Owned(Value::NoValue)),
+                (None, _) => err(format!("unable to get {} in {}", key, col)),
             }
         }
-        (&Value::Object(ref o), &Value::Number(ref n))
-        | (&Value::Map(ref o), &Value::Number(ref n)) => o.get(&n.to_string()),
-        (&Value::Object(ref o), &Value::String(ref s))
-        | (&Value::Map(ref o), &Value::String(ref s)) => o.get(s),
-        _ => None,
-    };
-    match *col {
-        Value::Map(_) => Ok(ret.unwrap_or(&Value::NoValue)),
-        _ => ret.ok_or_else(|| FuncError::Generic(format!("unable to get {} in {}", key, col))),
+        _ => err(format!("can't index item of type {}", type_name(col))),
     }
 }
 
+// Checks that `key` is an integer index into an array or string of length `len`.
+fn array_index(key: &Value, len: usize) -> Result<usize, FuncError> {
+    let msg = match key {
+        Value::Number(n) => match n.as_u64() {
+            Some(i) if i < len as u64 => return Ok(i as usize),
+            _ if n.as_f64().is_none() => format!("index out of range: {}", n),
+            _ => "cannot index slice/array with type float64".to_owned(),
+        },
+        Value::Nil | Value::NoValue => "cannot index slice/array with nil".to_owned(),
+        _ => format!("cannot index slice/array with type {}", type_name(key)),
+    };
+    Err(FuncError::Generic(msg))
+}
+
 /// Returns the escaped value of the textual representation of
 /// its arguments in a form suitable for embedding in a URL query.
 ///
+/// Like in Go, the arguments are joined as by `print` and escaped like `url.QueryEscape`,
+/// i.e. spaces become `+`.
+///
 /// # Example
 /// ```
 /// use gtmpl::template;
 /// let url = template(r#"{{ urlquery "foo bar?" }}"#, 0);
-/// assert_eq!(&url.unwrap(), "foo%20bar%3F");
+/// assert_eq!(&url.unwrap(), "foo+bar%3F");
+/// let url = template(r#"{{ urlquery "a/" . 2 }}"#, 1);
+/// assert_eq!(&url.unwrap(), "a%2F1+2");
 /// ```
 pub fn urlquery(args: &[Value]) -> Result<Value, FuncError> {
-    if args.len() != 1 {
-        return Err(FuncError::ExactlyXArgs("urlquery".into(), 1));
-    }
-    let val = &args[0];
-    match *val {
-        Value::String(ref s) => Ok(val!(utf8_percent_encode(s, QUERY_ENCODE).to_string())),
-        _ => Err(FuncError::Generic(
-            "Arguments need to be of type String".into(),
-        )),
-    }
+    let s = match args {
+        [Value::String(ref s)] => s.clone(),
+        _ => print(args)?.to_string(),
+    };
+    // `%` is escaped as well, so `%20` only stems from spaces.
+    let escaped = utf8_percent_encode(&s, QUERY_ENCODE).to_string();
+    Ok(val!(escaped.replace("%20", "+")))
 }
 
 /// Returns the boolean truth of arg1 == arg2 [== arg3 ...]
@@ -526,7 +629,7 @@ ge(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
 
 fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
     match (left, right) {
-        (&Value::Number(ref l), &Value::Number(ref r)) => {
+        (Value::Number(l), Value::Number(r)) => {
             if let (Some(lf), Some(rf)) = (l.as_f64(), r.as_f64()) {
                 return lf.partial_cmp(&rf);
             }
@@ -538,9 +641,9 @@ fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
             }
             None
         }
-        (&Value::Bool(ref l), &Value::Bool(ref r)) => l.partial_cmp(r),
-        (&Value::String(ref l), &Value::String(ref r)) => l.partial_cmp(r),
-        (&Value::Array(ref l), &Value::Array(ref r)) => l.len().partial_cmp(&r.len()),
+        (Value::Bool(l), Value::Bool(r)) => l.partial_cmp(r),
+        (Value::String(l), Value::String(r)) => l.partial_cmp(r),
+        (Value::Array(l), Value::Array(r)) => l.len().partial_cmp(&r.len()),
         _ => None,
     }
 }
@@ -548,6 +651,7 @@ fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
 #[cfg(test)]
 mod tests_mocked {
     use super::*;
+    use crate::gtmpl_map;
     use std::collections::HashMap;
 
     #[test]
@@ -711,6 +815,20 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::from("\n"));
     }
 
+    #[test]
+    fn test_urlquery() {
+        let vals: Vec<Value> = vec![val!("a b&c=d/é%~-_.")];
+        let ret = urlquery(&vals);
+        assert_eq!(ret.unwrap(), Value::from("a+b%26c%3Dd%2F%C3%A9%25~-_."));
+
+        let vals: Vec<Value> = vec![val!("x"), val!(1), val!(2.5), val!(true)];
+        let ret = urlquery(&vals);
+        assert_eq!(ret.unwrap(), Value::from("x1+2.5+true"));
+
+        let ret = urlquery(&[]);
+        assert_eq!(ret.unwrap(), Value::from(""));
+    }
+
     #[test]
     fn test_index() {
         let vals: Vec<Value> = vec![val!(vec![vec![1, 2], vec![3, 4]]), val!(1), val!(0)];
@@ -732,6 +850,101 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::NoValue);
     }
 
+    #[test]
+    fn test_index_keys() {
+        let err = |col: Value, key: Value| index(&[col, key]).unwrap_err().to_string();
+        let list = val!(vec![1, 2]);
+        assert_eq!(index(&[val!("ab"), val!(1)]).unwrap(), Value::from(98));
+        assert_eq!(err(list.clone(), val!(2)), "index out of range: 2");
+        assert_eq!(err(list.clone(), val!(-1)), "index out of range: -1");
+        assert_eq!(err(val!("ab"), val!(2)), "index out of range: 2");
+        assert_eq!(
+            err(list.clone(), val!(0.5)),
+            "cannot index slice/array with type float64"
+        );
+        assert_eq!(
+            err(list.clone(), val!("0")),
+            "cannot index slice/array with type string"
+        );
+        assert_eq!(err(list, Value::Nil), "cannot index slice/array with nil");
+
+        let map = gtmpl_map! { "1" => "one", "true" => "yes" };
+        assert_eq!(index(&[map.clone(), val!(1)]).unwrap(), Value::from("one"));
+        assert_eq!(index(&[map.clone(), val!(2)]).unwrap(), Value::NoValue);
+        assert_eq!(
+            err(map.clone(), val!(true)),
+            "value has type bool; should be string"
+        );
+        assert_eq!(
+            err(map.clone(), val!(1.5)),
+            "value has type float64; should be string"
+        );
+        assert_eq!(
+            err(map, Value::Nil),
+            "value is nil; should be of type string"
+        );
+
+        let object = Value::Object(vec![("a".to_owned(), val!(1))].into_iter().collect());
+        assert_eq!(index(&[object.clone(), val!("a")]).unwrap(), Value::from(1));
+        assert!(index(&[object, val!("b")]).is_err());
+        assert_eq!(err(Value::Nil, val!(1)), "index of untyped nil");
+        assert_eq!(err(val!(true), val!(1)), "can't index item of type bool");
+        assert_eq!(
+            index(&[gtmpl_map! { "s" => "xy" }, val!("s"), val!(1)]).unwrap(),
+            Value::from(b'y')
+        );
+    }
+
+    #[test]
+    fn test_print_spacing() {
+        // Expected values are the output of Golang's fmt.Sprint and fmt.Sprintln.
+        let cases: Vec<(Vec<Value>, &str)> = vec![
+            (vec![val!("a"), val!("b")], "ab"),
+            (vec![val!(1), val!(2)], "1 2"),
+            (vec![val!("a"), val!(1), val!(2), val!("b")], "a1 2b"),
+            (vec![val!(1.5), val!(true), val!("x")], "1.5 truex"),
+            (vec![Value::Nil, Value::Nil], "<nil> <nil>"),
+            (vec![val!("a"), Value::Nil, val!(1)], "a<nil> 1"),
+            (vec![val!(vec![1, 2]), val!(vec![3])], "[1 2] [3]"),
+            (
+                vec![val!(vec!["a", "b"]), val!("c"), val!(vec![1])],
+                "[a b]c[1]",
+            ),
+            (vec![val!(""), val!(1), val!(""), val!(2)], "12"),
+            (
+                vec![Value::Nil, val!(vec![1, 2]), val!("s")],
+                "<nil> [1 2]s",
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(print(&args).unwrap(), Value::from(expected), "{:?}", args);
+        }
+        let cases: Vec<(Vec<Value>, &str)> = vec![
+            (vec![val!("a"), val!("b")], "a b\n"),
+            (
+                vec![val!("a"), val!(1), Value::Nil, val!(vec![1, 2])],
+                "a 1 <nil> [1 2]\n",
+            ),
+            (vec![], "\n"),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(println(&args).unwrap(), Value::from(expected), "{:?}", args);
+        }
+        assert_eq!(
+            print(&[val!(1), Value::NoValue, val!("a"), Value::Nil]).unwrap(),
+            Value::from("1 <nil>a<nil>")
+        );
+    }
+
+    #[test]
+    fn test_len() {
+        let err = |val: Value| len(&[val]).unwrap_err().to_string();
+        assert_eq!(len(&[val!("ü")]).unwrap(), Value::from(2));
+        assert_eq!(len(&[gtmpl_map! { "a" => 1 }]).unwrap(), Value::from(1));
+        assert_eq!(err(val!(3)), "len of type int");
+        assert_eq!(err(Value::NoValue), "len of untyped nil");
+    }
+
     #[test]
     fn test_builtins() {
         let vals: Vec<Value> = vec![val!("foo".to_owned()), val!("foo".to_owned())];
@@ -763,5 +976,20 @@ mod tests_mocked {
         let vals: Vec<Value> = vec![val!("foobar"), val!("foo")];
         let ret = has_prefix(&vals);
         assert_eq!(ret.unwrap(), Value::from(true));
+
+        gtmpl_fn!(
+            fn mul(a: f64, b: f64) -> Result<f64, FuncError> {
+                Ok(a * b)
+            }
+        );
+        assert_eq!(mul(&[val!(2), val!(1.5)]).unwrap(), Value::from(3.0));
+        assert_eq!(
+            mul(&[val!(2)]).unwrap_err().to_string(),
+            "mul requires at least 2 argument(s)"
+        );
+        assert_eq!(
+            mul(&[val!(2), val!("x")]).unwrap_err().to_string(),
+            r#"argument 2 of mul: expected number, got string "x""#
+        );
     }
 }
@@ -0,0 +1,172 @@
+//! Hash and uuid functions.
+//!
+//! Requires the `gtmpl_crypto` feature. The functions are not part of the builtins and have to be
+//! registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(CRYPTO_FUNCS);
+//! tmpl.parse(r#"checksum/config: {{ "data" | sha256sum }}"#).unwrap();
+//! assert_eq!(
+//!     &tmpl.render(&Context::empty()).unwrap(),
+//!     "checksum/config: 3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7"
+//! );
+//! ```
+//!
+//! Like in Sprig the hash functions take a single string and return the lower case hex digest,
+//! except for `adler32sum` which returns the checksum as decimal string.
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use gtmpl_value::{Func, FuncError, Value};
+
+/// All hash and uuid functions, ready to be passed to `Template::add_funcs`.
+pub static CRYPTO_FUNCS: &[(&str, Func)] = &[
+    ("sha1sum", sha1sum as Func),
+    ("sha256sum", sha256sum as Func),
+    ("md5sum", md5sum as Func),
+    ("adler32sum", adler32sum as Func),
+    ("uuidv4", uuidv4 as Func),
+];
+
+fn single_str<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, FuncError> {
+    match args {
+        [Value::String(s)] => Ok(s),
+        [val] => Err(FuncError::Generic(format!(
+            "{} requires a string, got {}",
+            name, val
+        ))),
+        _ => Err(FuncError::ExactlyXArgs(name.into(), 1)),
+    }
+}
+
+fn hex_digest<D: Digest>(s: &str) -> Value {
+    let digest = D::digest(s.as_bytes());
+    Value::from(
+        digest
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>(),
+    )
+}
+
+/// Returns the hex encoded SHA-1 digest of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(CRYPTO_FUNCS);
+/// tmpl.parse(r#"{{ sha1sum "abc" }}"#).unwrap();
+/// assert_eq!(
+///     &tmpl.render(&Context::empty()).unwrap(),
+///     "a9993e364706816aba3e25717850c26c9cd0d89d"
+/// );
+/// ```
+pub fn sha1sum(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(hex_digest::<Sha1>(single_str("sha1sum", args)?))
+}
+
+/// Returns the hex encoded SHA-256 digest of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(CRYPTO_FUNCS);
+/// tmpl.parse(r#"{{ sha256sum "abc" }}"#).unwrap();
+/// assert_eq!(
+///     &tmpl.render(&Context::empty()).unwrap(),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+pub fn sha256sum(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(hex_digest::<Sha256>(single_str("sha256sum", args)?))
+}
+
+/// Returns the hex encoded MD5 digest of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(CRYPTO_FUNCS);
+/// tmpl.parse(r#"{{ md5sum "abc" }}"#).unwrap();
+/// assert_eq!(
+///     &tmpl.render(&Context::empty()).unwrap(),
+///     "900150983cd24fb0d6963f7d28e17f72"
+/// );
+/// ```
+pub fn md5sum(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(hex_digest::<Md5>(single_str("md5sum", args)?))
+}
+
+/// Returns the Adler-32 checksum of a string as decimal string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(CRYPTO_FUNCS);
+/// tmpl.parse(r#"{{ adler32sum "Wikipedia" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "300286872");
+/// ```
+pub fn adler32sum(args: &[Value]) -> Result<Value, FuncError> {
+    let s = single_str("adler32sum", args)?;
+    let mut hash = adler32::RollingAdler32::new();
+    hash.update_buffer(s.as_bytes());
+    Ok(Value::from(hash.hash().to_string()))
+}
+
+/// Returns a new random (version 4) UUID.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::crypto::CRYPTO_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(CRYPTO_FUNCS);
+/// tmpl.parse(r#"{{ uuidv4 }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap().len(), 36);
+/// ```
+pub fn uuidv4(args: &[Value]) -> Result<Value, FuncError> {
+    if !args.is_empty() {
+        return Err(FuncError::ExactlyXArgs("uuidv4".into(), 0));
+    }
+    Ok(Value::from(uuid::Uuid::new_v4().to_string()))
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(
+            sha1sum(&[Value::from("")]).unwrap(),
+            Value::from("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        );
+        assert_eq!(adler32sum(&[Value::from("")]).unwrap(), Value::from("1"));
+    }
+
+    #[test]
+    fn test_uuidv4() {
+        let a = uuidv4(&[]).unwrap();
+        let b = uuidv4(&[]).unwrap();
+        assert_ne!(a, b);
+        if let Value::String(s) = a {
+            assert_eq!(s.as_bytes()[14], b'4');
+        } else {
+            panic!("uuidv4 must return a string");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(sha256sum(&[]).is_err());
+        assert!(md5sum(&[Value::from(1)]).is_err());
+        assert!(uuidv4(&[Value::from(1)]).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Functions for troubleshooting templates and their contexts.
+//!
+//! Requires the `gtmpl_debug` feature. The functions are not part of the builtins and have to be
+//! registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::debug::DEBUG_FUNCS, gtmpl_list, gtmpl_map, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(DEBUG_FUNCS);
+//! tmpl.parse("{{ dump . }}").unwrap();
+//! let data = gtmpl_map! { "name" => "app", "ports" => gtmpl_list![80, 443] };
+//! let expected = r#"map[string]interface {} (2 entries) {
+//!   "name": string "app"
+//!   "ports": []interface {} (2 elements) [
+//!     int 80
+//!     int 443
+//!   ]
+//! }"#;
+//! assert_eq!(tmpl.render(&Context::from(data)).unwrap(), expected);
+//! ```
+use std::fmt::Write;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use crate::print_verb::go_string;
+use crate::printf::type_name;
+
+/// All debug functions, ready to be passed to `Template::add_funcs`.
+pub static DEBUG_FUNCS: &[(&str, Func)] = &[("dump", dump as Func)];
+
+#[derive(Clone, Copy)]
+struct Limits {
+    depth: Option<usize>,
+    len: Option<usize>,
+}
+
+/// Renders the structure of a value with the types of all elements, one element per line.
+///
+/// The optional second and third arguments limit the depth and length: collections nested
+/// deeper than the maximum depth are collapsed to `[...]` or `{...}`, only the first elements
+/// of longer collections are shown and longer strings are cut off. `0` means no limit. Keys of
+/// maps and objects are sorted, objects are shown as `object` with unquoted field names.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::debug::DEBUG_FUNCS, gtmpl_list, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(DEBUG_FUNCS);
+/// tmpl.parse(r#"{{ dump . 1 2 }}"#).unwrap();
+/// let data = gtmpl_list!["abc", gtmpl_list![1], true];
+/// let expected = r#"[]interface {} (3 elements) [
+///   string "ab"... (3 chars)
+///   []interface {} (1 element) [...]
+///   ... 1 more
+/// ]"#;
+/// assert_eq!(tmpl.render(&Context::from(data)).unwrap(), expected);
+/// ```
+pub fn dump(args: &[Value]) -> Result<Value, FuncError> {
+    let (value, limits) = match args {
+        [value, limits @ ..] if limits.len() <= 2 => (value, limits),
+        [] => return Err(FuncError::AtLeastXArgs("dump".into(), 1)),
+        _ => {
+            return Err(FuncError::Generic(
+                "dump requires at most 3 arguments".to_owned(),
+            ))
+        }
+    };
+    let limits = limits
+        .iter()
+        .map(|limit| match limit {
+            Value::Number(n) => n.as_u64().map(|n| n as usize),
+            _ => None,
+        })
+        .map(|limit| {
+            limit.ok_or_else(|| {
+                FuncError::Generic("dump limits must be non-negative integers".to_owned())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let limit = |i: usize| limits.get(i).copied().filter(|&n| n > 0);
+    let limits = Limits {
+        depth: limit(0),
+        len: limit(1),
+    };
+    let mut out = String::new();
+    write_value(&mut out, value, limits, 0);
+    Ok(Value::from(out))
+}
+
+fn write_value(out: &mut String, value: &Value, limits: Limits, level: usize) {
+    match value {
+        Value::Array(a) => {
+            let entries = a.iter().map(|v| (None, v)).collect();
+            write_collection(
+                out,
+                "[]interface {}",
+                ("element", "elements"),
+                entries,
+                limits,
+                level,
+            );
+        }
+        Value::Map(m) => {
+            let mut entries: Vec<_> = m
+                .iter()
+                .map(|(k, v)| (Some(format!("{:?}", k)), v))
+                .collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            write_collection(
+                out,
+                "map[string]interface {}",
+                ("entry", "entries"),
+                entries,
+                limits,
+                level,
+            );
+        }
+        Value::Object(o) => {
+            let mut entries: Vec<_> = o.iter().map(|(k, v)| (Some(k.clone()), v)).collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            write_collection(out, "object", ("field", "fields"), entries, limits, level);
+        }
+        Value::String(s) => match limits.len {
+            Some(len) if s.chars().count() > len => {
+                let cut: String = s.chars().take(len).collect();
+                let _ = write!(out, "string {:?}... ({} chars)", cut, s.chars().count());
+            }
+            _ => {
+                let _ = write!(out, "string {:?}", s);
+            }
+        },
+        Value::NoValue => out.push_str("<no value>"),
+        Value::Nil => out.push_str("<nil>"),
+        Value::Function(_) => out.push_str("func"),
+        v => {
+            let _ = write!(out, "{} {}", type_name(v), go_string(v));
+        }
+    }
+}
+
+fn write_collection(
+    out: &mut String,
+    typ: &str,
+    noun: (&str, &str),
+    entries: Vec<(Option<String>, &Value)>,
+    limits: Limits,
+    level: usize,
+) {
+    let count = entries.len();
+    let noun = if count == 1 { noun.0 } else { noun.1 };
+    let _ = write!(out, "{} ({} {})", typ, count, noun);
+    if count == 0 {
+        return;
+    }
+    let (open, close) = if typ.starts_with('[') {
+        ('[', ']')
+    } else {
+        ('{', '}')
+    };
+    if limits.depth.is_some_and(|depth| level >= depth) {
+        let _ = write!(out, " {}...{}", open, close);
+        return;
+    }
+    out.push(' ');
+    out.push(open);
+    out.push('\n');
+    let shown = limits.len.map_or(count, |len| len.min(count));
+    for (key, value) in entries.into_iter().take(shown) {
+        indent(out, level + 1);
+        if let Some(key) = key {
+            let _ = write!(out, "{}: ", key);
+        }
+        write_value(out, value, limits, level + 1);
+        out.push('\n');
+    }
+    if shown < count {
+        indent(out, level + 1);
+        let _ = writeln!(out, "... {} more", count - shown);
+    }
+    indent(out, level);
+    out.push(close);
+}
+
+fn indent(out: &mut String, level: usize) {
+    for _ in 0..level {
+        out.push_str("  ");
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::{gtmpl_list, gtmpl_map};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_dump() {
+        let dumped = |args: &[Value]| dump(args).unwrap().to_string();
+        assert_eq!(dumped(&[Value::from(1)]), "int 1");
+        assert_eq!(dumped(&[Value::from(1.5)]), "float64 1.5");
+        assert_eq!(dumped(&[Value::from(true)]), "bool true");
+        assert_eq!(dumped(&[Value::Nil]), "<nil>");
+        assert_eq!(dumped(&[Value::NoValue]), "<no value>");
+        assert_eq!(dumped(&[Value::from("a\"b")]), r#"string "a\"b""#);
+        assert_eq!(dumped(&[gtmpl_list![]]), "[]interface {} (0 elements)");
+        assert_eq!(
+            dumped(&[gtmpl_map! { "a" => 1 }]),
+            "map[string]interface {} (1 entry) {\n  \"a\": int 1\n}"
+        );
+
+        let mut object = HashMap::new();
+        object.insert("b".to_owned(), Value::from(2));
+        object.insert("a".to_owned(), gtmpl_map! { "x" => gtmpl_list![1, 2, 3] });
+        let object = Value::Object(object);
+        assert_eq!(
+            dumped(std::slice::from_ref(&object)),
+            concat!(
+                "object (2 fields) {\n",
+                "  a: map[string]interface {} (1 entry) {\n",
+                "    \"x\": []interface {} (3 elements) [\n",
+                "      int 1\n",
+                "      int 2\n",
+                "      int 3\n",
+                "    ]\n",
+                "  }\n",
+                "  b: int 2\n",
+                "}"
+            )
+        );
+        assert_eq!(
+            dumped(&[object.clone(), Value::from(1)]),
+            "object (2 fields) {\n  a: map[string]interface {} (1 entry) {...}\n  b: int 2\n}"
+        );
+        assert_eq!(
+            dumped(&[object.clone(), Value::from(0), Value::from(1)]),
+            "object (2 fields) {\n  a: map[string]interface {} (1 entry) {\n    \"x\": []interface {} (3 elements) [\n      int 1\n      ... 2 more\n    ]\n  }\n  ... 1 more\n}"
+        );
+
+        assert!(dump(&[]).is_err());
+        assert!(dump(&[object.clone(), Value::from(-1)]).is_err());
+        assert!(dump(&[object.clone(), Value::from("1")]).is_err());
+        assert!(dump(&[object, Value::from(1), Value::from(1), Value::from(1)]).is_err());
+    }
+}
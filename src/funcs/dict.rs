@@ -0,0 +1,198 @@
+//! Functions working on maps and objects.
+//!
+//! Requires the `gtmpl_dict` feature. The functions are not part of the builtins and have to be
+//! registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::dict::DICT_FUNCS, Context, Template, Value};
+//! use std::collections::HashMap;
+//!
+//! let mut defaults = HashMap::new();
+//! defaults.insert("replicas".to_owned(), Value::from(1));
+//! defaults.insert("name".to_owned(), Value::from("app"));
+//! let mut values = HashMap::new();
+//! values.insert("replicas".to_owned(), Value::from(3));
+//! let mut data = HashMap::new();
+//! data.insert("defaults".to_owned(), Value::Map(defaults));
+//! data.insert("values".to_owned(), Value::Map(values));
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(DICT_FUNCS);
+//! tmpl.parse("{{ with merge .values .defaults }}{{ .name }}: {{ .replicas }}{{ end }}")
+//!     .unwrap();
+//! assert_eq!(&tmpl.render(&Context::from(data)).unwrap(), "app: 3");
+//! ```
+use std::collections::HashMap;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use crate::utils::is_true;
+
+/// All dict functions, ready to be passed to `Template::add_funcs`.
+pub static DICT_FUNCS: &[(&str, Func)] = &[
+    ("merge", merge as Func),
+    ("mergeOverwrite", merge_overwrite as Func),
+];
+
+/// Deep merges maps or objects into the first one and returns the result.
+///
+/// Like Sprig's `merge` values of the destination take precedence. Only keys which are missing
+/// or empty in the destination are taken from the following arguments. Nested maps and objects
+/// are merged recursively. The type of the first argument is kept.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::dict::DICT_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(DICT_FUNCS);
+/// tmpl.parse(r#"{{ with merge .a .b }}{{ .x }}{{ .y }}{{ end }}"#).unwrap();
+/// # use gtmpl::Value;
+/// # use std::collections::HashMap;
+/// # let mut a = HashMap::new();
+/// # a.insert("x".to_owned(), Value::from(1));
+/// # let mut b = HashMap::new();
+/// # b.insert("x".to_owned(), Value::from(2));
+/// # b.insert("y".to_owned(), Value::from(3));
+/// # let mut data = HashMap::new();
+/// # data.insert("a".to_owned(), Value::Map(a));
+/// # data.insert("b".to_owned(), Value::Map(b));
+/// assert_eq!(&tmpl.render(&Context::from(data)).unwrap(), "13");
+/// ```
+pub fn merge(args: &[Value]) -> Result<Value, FuncError> {
+    merge_all("merge", args, false)
+}
+
+/// Deep merges maps or objects into the first one, overwriting existing values.
+///
+/// Like Sprig's `mergeOverwrite` later arguments take precedence, but empty values (see `if`)
+/// never overwrite existing ones. Nested maps and objects are merged recursively. The type of
+/// the first argument is kept.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::dict::DICT_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(DICT_FUNCS);
+/// tmpl.parse(r#"{{ with mergeOverwrite .a .b }}{{ .x }}{{ .y }}{{ end }}"#).unwrap();
+/// # use gtmpl::Value;
+/// # use std::collections::HashMap;
+/// # let mut a = HashMap::new();
+/// # a.insert("x".to_owned(), Value::from(1));
+/// # a.insert("y".to_owned(), Value::from(true));
+/// # let mut b = HashMap::new();
+/// # b.insert("x".to_owned(), Value::from(2));
+/// # b.insert("y".to_owned(), Value::from(false));
+/// # let mut data = HashMap::new();
+/// # data.insert("a".to_owned(), Value::Map(a));
+/// # data.insert("b".to_owned(), Value::Map(b));
+/// assert_eq!(&tmpl.render(&Context::from(data)).unwrap(), "2true");
+/// ```
+pub fn merge_overwrite(args: &[Value]) -> Result<Value, FuncError> {
+    merge_all("mergeOverwrite", args, true)
+}
+
+fn merge_all(name: &str, args: &[Value], overwrite: bool) -> Result<Value, FuncError> {
+    if args.len() < 2 {
+        return Err(FuncError::AtLeastXArgs(name.into(), 2));
+    }
+    let mut dst = args[0].clone();
+    for src in &args[1..] {
+        let src = fields(src).ok_or_else(|| not_a_map(name, src))?;
+        match dst {
+            Value::Object(ref mut d) | Value::Map(ref mut d) => merge_into(d, src, overwrite),
+            _ => return Err(not_a_map(name, &dst)),
+        }
+    }
+    Ok(dst)
+}
+
+fn merge_into(dst: &mut HashMap<String, Value>, src: &HashMap<String, Value>, overwrite: bool) {
+    for (k, v) in src {
+        let d = match dst.get_mut(k) {
+            Some(d) => d,
+            None => {
+                dst.insert(k.clone(), v.clone());
+                continue;
+            }
+        };
+        if let (Value::Object(d) | Value::Map(d), Some(v)) = (&mut *d, fields(v)) {
+            merge_into(d, v, overwrite);
+        } else if is_true(v) && (overwrite || !is_true(d)) {
+            *d = v.clone();
+        }
+    }
+}
+
+fn fields(value: &Value) -> Option<&HashMap<String, Value>> {
+    match *value {
+        Value::Object(ref o) | Value::Map(ref o) => Some(o),
+        _ => None,
+    }
+}
+
+fn not_a_map(name: &str, value: &Value) -> FuncError {
+    FuncError::Generic(format!("{} requires maps or objects, got {}", name, value))
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_merge() {
+        let dst = map(vec![
+            ("a", 1.into()),
+            ("empty", "".into()),
+            ("nested", map(vec![("x", 1.into())])),
+        ]);
+        let src = map(vec![
+            ("a", 2.into()),
+            ("b", 2.into()),
+            ("empty", "filled".into()),
+            ("nested", map(vec![("x", 2.into()), ("y", 2.into())])),
+        ]);
+        let expected = map(vec![
+            ("a", 1.into()),
+            ("b", 2.into()),
+            ("empty", "filled".into()),
+            ("nested", map(vec![("x", 1.into()), ("y", 2.into())])),
+        ]);
+        assert_eq!(merge(&[dst, src]).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_merge_overwrite() {
+        let dst = map(vec![
+            ("a", 1.into()),
+            ("keep", true.into()),
+            ("nested", map(vec![("x", 1.into()), ("z", 1.into())])),
+        ]);
+        let src1 = map(vec![("a", 2.into()), ("keep", false.into())]);
+        let src2 = map(vec![
+            ("a", 3.into()),
+            ("nested", map(vec![("x", 3.into())])),
+        ]);
+        let expected = map(vec![
+            ("a", 3.into()),
+            ("keep", true.into()),
+            ("nested", map(vec![("x", 3.into()), ("z", 1.into())])),
+        ]);
+        assert_eq!(merge_overwrite(&[dst, src1, src2]).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(merge(&[map(vec![])]).is_err());
+        assert!(merge(&[Value::from(1), map(vec![])]).is_err());
+        assert!(merge_overwrite(&[map(vec![]), Value::from("x")]).is_err());
+    }
+}
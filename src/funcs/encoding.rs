@@ -0,0 +1,131 @@
+//! Base64 and hex encoding functions.
+//!
+//! Requires the `gtmpl_encoding` feature. The functions are not part of the builtins and have to
+//! be registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::encoding::ENCODING_FUNCS, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(ENCODING_FUNCS);
+//! tmpl.parse(r#"{{ "secret" | b64enc }}"#).unwrap();
+//! assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "c2VjcmV0");
+//! ```
+//!
+//! All functions take a single string. Decoded data has to be valid utf-8.
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use gtmpl_value::{Func, FuncError, Value};
+
+/// All encoding functions, ready to be passed to `Template::add_funcs`.
+pub static ENCODING_FUNCS: &[(&str, Func)] = &[
+    ("b64enc", b64enc as Func),
+    ("b64dec", b64dec as Func),
+    ("hexenc", hexenc as Func),
+    ("hexdec", hexdec as Func),
+];
+
+fn single_str<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, FuncError> {
+    match args {
+        [Value::String(s)] => Ok(s),
+        [val] => Err(FuncError::Generic(format!(
+            "{} requires a string, got {}",
+            name, val
+        ))),
+        _ => Err(FuncError::ExactlyXArgs(name.into(), 1)),
+    }
+}
+
+fn from_utf8(name: &str, bytes: Vec<u8>) -> Result<Value, FuncError> {
+    String::from_utf8(bytes)
+        .map(Value::from)
+        .map_err(|e| FuncError::Generic(format!("{}: {}", name, e)))
+}
+
+/// Returns the standard base64 encoding (with padding) of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::encoding::ENCODING_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(ENCODING_FUNCS);
+/// tmpl.parse(r#"{{ b64enc "hello" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "aGVsbG8=");
+/// ```
+pub fn b64enc(args: &[Value]) -> Result<Value, FuncError> {
+    let s = single_str("b64enc", args)?;
+    Ok(Value::from(STANDARD.encode(s)))
+}
+
+/// Decodes a standard base64 encoded string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::encoding::ENCODING_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(ENCODING_FUNCS);
+/// tmpl.parse(r#"{{ b64dec "aGVsbG8=" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "hello");
+/// ```
+pub fn b64dec(args: &[Value]) -> Result<Value, FuncError> {
+    let s = single_str("b64dec", args)?;
+    let bytes = STANDARD
+        .decode(s)
+        .map_err(|e| FuncError::Generic(format!("b64dec: {}", e)))?;
+    from_utf8("b64dec", bytes)
+}
+
+/// Returns the lower case hex encoding of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::encoding::ENCODING_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(ENCODING_FUNCS);
+/// tmpl.parse(r#"{{ hexenc "hi!" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "686921");
+/// ```
+pub fn hexenc(args: &[Value]) -> Result<Value, FuncError> {
+    let s = single_str("hexenc", args)?;
+    Ok(Value::from(hex::encode(s)))
+}
+
+/// Decodes a hex encoded string. Upper and lower case digits are accepted.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::encoding::ENCODING_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(ENCODING_FUNCS);
+/// tmpl.parse(r#"{{ hexdec "68692A" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "hi*");
+/// ```
+pub fn hexdec(args: &[Value]) -> Result<Value, FuncError> {
+    let s = single_str("hexdec", args)?;
+    let bytes = hex::decode(s).map_err(|e| FuncError::Generic(format!("hexdec: {}", e)))?;
+    from_utf8("hexdec", bytes)
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let input = Value::from("-----BEGIN CERTIFICATE-----\nMIIB→\n");
+        let encoded = b64enc(std::slice::from_ref(&input)).unwrap();
+        assert_eq!(b64dec(&[encoded]).unwrap(), input);
+        let encoded = hexenc(std::slice::from_ref(&input)).unwrap();
+        assert_eq!(hexdec(&[encoded]).unwrap(), input);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(b64dec(&[Value::from("not base64!")]).is_err());
+        assert!(b64dec(&[Value::from("/w==")]).is_err());
+        assert!(hexdec(&[Value::from("abc")]).is_err());
+        assert!(hexdec(&[Value::from("zz")]).is_err());
+        assert!(b64enc(&[Value::from(1)]).is_err());
+        assert!(hexenc(&[]).is_err());
+    }
+}
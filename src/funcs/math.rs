@@ -0,0 +1,381 @@
+//! Arithmetic functions.
+//!
+//! Requires the `gtmpl_math` feature. The functions are not part of the builtins and have to be
+//! registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(MATH_FUNCS);
+//! tmpl.parse("{{ add 1 2 | mul 3 }}").unwrap();
+//! assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "9");
+//! ```
+//!
+//! Arguments are coerced like Sprig does: numbers are used as is, strings are parsed, booleans
+//! count as `0` or `1` and `nil` or missing values as `0`. If any argument is a float the
+//! calculation is done in floating point, otherwise integer arithmetic is used.
+use std::cmp::Ordering;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+/// All arithmetic functions, ready to be passed to `Template::add_funcs`.
+pub static MATH_FUNCS: &[(&str, Func)] = &[
+    ("add", add as Func),
+    ("sub", sub as Func),
+    ("mul", mul as Func),
+    ("div", div as Func),
+    ("mod", modulo as Func),
+    ("max", max as Func),
+    ("min", min as Func),
+    ("floor", floor as Func),
+    ("ceil", ceil as Func),
+    ("round", round as Func),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(i) => i as f64,
+            Num::Float(f) => f,
+        }
+    }
+}
+
+impl From<Num> for Value {
+    fn from(n: Num) -> Value {
+        match n {
+            Num::Int(i) => Value::from(i),
+            Num::Float(f) => Value::from(f),
+        }
+    }
+}
+
+fn to_num(val: &Value) -> Result<Num, FuncError> {
+    match *val {
+        Value::Number(ref n) => n
+            .as_i64()
+            .map(Num::Int)
+            .or_else(|| n.as_f64().map(Num::Float))
+            .or_else(|| n.as_u64().map(|u| Num::Float(u as f64)))
+            .ok_or(FuncError::UnableToConvertFromValue),
+        Value::String(ref s) => {
+            let s = s.trim();
+            s.parse::<i64>()
+                .map(Num::Int)
+                .or_else(|_| s.parse::<f64>().map(Num::Float))
+                .map_err(|_| FuncError::Generic(format!("unable to use {:?} as number", s)))
+        }
+        Value::Bool(b) => Ok(Num::Int(b as i64)),
+        Value::Nil | Value::NoValue => Ok(Num::Int(0)),
+        _ => Err(FuncError::Generic(format!(
+            "unable to use {} as number",
+            val
+        ))),
+    }
+}
+
+fn overflow(name: &str) -> FuncError {
+    FuncError::Generic(format!("integer overflow in {}", name))
+}
+
+// Folds all arguments with `int` or, if any of them is a float, with `float`.
+fn fold(
+    name: &str,
+    args: &[Value],
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value, FuncError> {
+    let nums = args.iter().map(to_num).collect::<Result<Vec<_>, _>>()?;
+    let (first, rest) = nums
+        .split_first()
+        .ok_or_else(|| FuncError::AtLeastXArgs(name.into(), 1))?;
+    let mut acc = *first;
+    for n in rest {
+        acc = match (acc, *n) {
+            (Num::Int(a), Num::Int(b)) => Num::Int(int(a, b).ok_or_else(|| overflow(name))?),
+            (a, b) => Num::Float(float(a.as_f64(), b.as_f64())),
+        };
+    }
+    Ok(acc.into())
+}
+
+fn binary(name: &str, args: &[Value]) -> Result<(Num, Num), FuncError> {
+    if args.len() != 2 {
+        return Err(FuncError::ExactlyXArgs(name.into(), 2));
+    }
+    Ok((to_num(&args[0])?, to_num(&args[1])?))
+}
+
+fn unary(name: &str, args: &[Value]) -> Result<Num, FuncError> {
+    if args.len() != 1 {
+        return Err(FuncError::ExactlyXArgs(name.into(), 1));
+    }
+    to_num(&args[0])
+}
+
+/// Returns the sum of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse(r#"{{ add 1 2 "3" }} {{ add 1 0.5 }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "6 1.5");
+/// ```
+pub fn add(args: &[Value]) -> Result<Value, FuncError> {
+    fold("add", args, i64::checked_add, |a, b| a + b)
+}
+
+/// Returns the first argument minus the second.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ sub 5 7 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "-2");
+/// ```
+pub fn sub(args: &[Value]) -> Result<Value, FuncError> {
+    let (a, b) = binary("sub", args)?;
+    fold("sub", &[a.into(), b.into()], i64::checked_sub, |a, b| a - b)
+}
+
+/// Returns the product of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ mul 2 3 4 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "24");
+/// ```
+pub fn mul(args: &[Value]) -> Result<Value, FuncError> {
+    fold("mul", args, i64::checked_mul, |a, b| a * b)
+}
+
+/// Returns the first argument divided by the second. Integer division truncates, dividing a
+/// float by zero results in an infinite value or NaN like float division does.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ div 7 2 }} {{ div 7.5 2 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "3 3.75");
+/// ```
+pub fn div(args: &[Value]) -> Result<Value, FuncError> {
+    match binary("div", args)? {
+        (Num::Int(_), Num::Int(0)) => Err(FuncError::Generic("integer divide by zero".into())),
+        (a, b) => fold("div", &[a.into(), b.into()], i64::checked_div, |a, b| a / b),
+    }
+}
+
+/// Returns the remainder of dividing the first argument by the second, NaN for a float divided
+/// by zero.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ mod 7 3 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "1");
+/// ```
+pub fn modulo(args: &[Value]) -> Result<Value, FuncError> {
+    match binary("mod", args)? {
+        (Num::Int(_), Num::Int(0)) => Err(FuncError::Generic("integer divide by zero".into())),
+        (a, b) => fold("mod", &[a.into(), b.into()], i64::checked_rem, |a, b| a % b),
+    }
+}
+
+fn pick(name: &str, args: &[Value], keep: Ordering) -> Result<Value, FuncError> {
+    let nums = args.iter().map(to_num).collect::<Result<Vec<_>, _>>()?;
+    let float = nums.iter().any(|n| matches!(n, Num::Float(_)));
+    let mut iter = nums.into_iter();
+    let mut acc = iter
+        .next()
+        .ok_or_else(|| FuncError::AtLeastXArgs(name.into(), 1))?;
+    for n in iter {
+        if n.as_f64().partial_cmp(&acc.as_f64()) == Some(keep) {
+            acc = n;
+        }
+    }
+    if float {
+        acc = Num::Float(acc.as_f64());
+    }
+    Ok(acc.into())
+}
+
+/// Returns the largest of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ max 1 5 3 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "5");
+/// ```
+pub fn max(args: &[Value]) -> Result<Value, FuncError> {
+    pick("max", args, Ordering::Greater)
+}
+
+/// Returns the smallest of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ min 4 -1 3 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "-1");
+/// ```
+pub fn min(args: &[Value]) -> Result<Value, FuncError> {
+    pick("min", args, Ordering::Less)
+}
+
+/// Returns the greatest integer value less than or equal to its argument as float.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ floor 1.7 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "1");
+/// ```
+pub fn floor(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::from(unary("floor", args)?.as_f64().floor()))
+}
+
+/// Returns the least integer value greater than or equal to its argument as float.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ ceil 1.2 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "2");
+/// ```
+pub fn ceil(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::from(unary("ceil", args)?.as_f64().ceil()))
+}
+
+/// Rounds its first argument to the number of decimal places given by the optional second
+/// argument (default `0`). Halfway cases are rounded away from zero.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::math::MATH_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(MATH_FUNCS);
+/// tmpl.parse("{{ round 2.5 }} {{ round 3.14159 2 }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "3 3.14");
+/// ```
+pub fn round(args: &[Value]) -> Result<Value, FuncError> {
+    let (x, places) = match args.len() {
+        1 => (to_num(&args[0])?, 0),
+        2 => match to_num(&args[1])? {
+            Num::Int(p) => (to_num(&args[0])?, p),
+            Num::Float(_) => {
+                return Err(FuncError::Generic(
+                    "round requires an integer precision".into(),
+                ))
+            }
+        },
+        0 => return Err(FuncError::AtLeastXArgs("round".into(), 1)),
+        _ => {
+            return Err(FuncError::Generic(
+                "round requires at most 2 arguments".into(),
+            ))
+        }
+    };
+    let factor = 10f64.powi(places.clamp(-308, 308) as i32);
+    Ok(Value::from((x.as_f64() * factor).round() / factor))
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_coercion() {
+        assert_eq!(to_num(&Value::from("42")).unwrap(), Num::Int(42));
+        assert_eq!(to_num(&Value::from(" 1.5 ")).unwrap(), Num::Float(1.5));
+        assert_eq!(to_num(&Value::from(true)).unwrap(), Num::Int(1));
+        assert_eq!(to_num(&Value::Nil).unwrap(), Num::Int(0));
+        assert_eq!(to_num(&Value::NoValue).unwrap(), Num::Int(0));
+        assert!(to_num(&Value::from("foo")).is_err());
+        assert!(to_num(&Value::from(vec![1])).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            add(&[Value::from(1), Value::from(2)]).unwrap(),
+            Value::from(3)
+        );
+        assert_eq!(
+            sub(&[Value::from(1), Value::from(2.5)]).unwrap(),
+            Value::from(-1.5)
+        );
+        assert_eq!(
+            modulo(&[Value::from(-7), Value::from(3)]).unwrap(),
+            Value::from(-1)
+        );
+        assert_eq!(
+            div(&[Value::from(1.5), Value::from(0)]).unwrap(),
+            Value::from(f64::INFINITY)
+        );
+        assert_eq!(
+            div(&[Value::from("1.5"), Value::from(0.0)]).unwrap(),
+            Value::from(f64::INFINITY)
+        );
+        assert!(matches!(
+            modulo(&[Value::from(1.5), Value::from(0)]).unwrap(),
+            Value::Number(ref n) if n.as_f64().is_some_and(f64::is_nan)
+        ));
+        assert_eq!(
+            max(&[Value::from(1), Value::from(2.5)]).unwrap(),
+            Value::from(2.5)
+        );
+        assert_eq!(
+            min(&[Value::from(1), Value::from(2.5)]).unwrap(),
+            Value::from(1.0)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(div(&[Value::from(1), Value::from(0)]).is_err());
+        assert!(modulo(&[Value::from(1), Value::from(0)]).is_err());
+        assert!(add(&[Value::from(i64::MAX), Value::from(1)]).is_err());
+        assert!(add(&[]).is_err());
+        assert!(sub(&[Value::from(1)]).is_err());
+        assert!(round(&[Value::from(1.5), Value::from(0.5)]).is_err());
+        assert_eq!(
+            round(&[Value::from(1.5), Value::from(1), Value::from(2)])
+                .unwrap_err()
+                .to_string(),
+            "round requires at most 2 arguments"
+        );
+        assert_eq!(
+            div(&[Value::from(1), Value::from(0)])
+                .unwrap_err()
+                .to_string(),
+            "integer divide by zero"
+        );
+    }
+}
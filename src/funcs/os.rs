@@ -0,0 +1,220 @@
+//! Environment and file system functions.
+//!
+//! Requires the `gtmpl_os` feature. The functions give templates access to the host, so they are
+//! only available after enabling them with an [`OsPolicy`] via
+//! [`Template::enable_os_funcs`](crate::Template::enable_os_funcs). The policy denies everything
+//! that is not explicitly allowed:
+//!
+//! ```rust
+//! use gtmpl::{funcs::os::OsPolicy, Context, Template};
+//!
+//! std::env::set_var("GTMPL_GREETING", "hello");
+//! let mut tmpl = Template::default();
+//! tmpl.enable_os_funcs(OsPolicy::default().allow_env("GTMPL_*"));
+//! tmpl.parse(r#"{{ env "GTMPL_GREETING" }}"#).unwrap();
+//! assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "hello");
+//!
+//! tmpl.parse(r#"{{ env "HOME" }}"#).unwrap();
+//! assert!(tmpl.render(&Context::empty()).is_err());
+//! ```
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use crate::error::FuncResultExt;
+
+/// All environment and file system functions.
+///
+/// Registering them directly with `add_funcs` without a policy denies every access.
+pub static OS_FUNCS: &[(&str, Func)] = &[
+    ("env", env_var as Func),
+    ("readFile", read_file as Func),
+    ("fileExists", file_exists as Func),
+];
+
+/// Controls which environment variables and paths templates may access.
+#[derive(Clone, Debug, Default)]
+pub struct OsPolicy {
+    env: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+impl OsPolicy {
+    /// Allows reading the environment variable `name`. A trailing `*` allows all variables
+    /// starting with the given prefix, a single `*` allows all variables.
+    pub fn allow_env<T: Into<String>>(mut self, name: T) -> Self {
+        self.env.push(name.into());
+        self
+    }
+
+    /// Allows reading files within the directory `root` (or the file `root` itself).
+    pub fn allow_path<P: AsRef<Path>>(mut self, root: P) -> Self {
+        let root = root.as_ref();
+        self.paths
+            .push(fs::canonicalize(root).unwrap_or_else(|_| normalize(root)));
+        self
+    }
+
+    fn env_allowed(&self, name: &str) -> bool {
+        self.env
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => allowed == name,
+            })
+    }
+
+    fn path_allowed(&self, path: &Path) -> bool {
+        self.paths.iter().any(|root| path.starts_with(root))
+    }
+}
+
+thread_local! {
+    static POLICY: RefCell<Option<Arc<OsPolicy>>> = const { RefCell::new(None) };
+}
+
+// Runs `f` with `policy` being the active policy of the current thread.
+pub(crate) fn with_policy<R>(policy: Option<Arc<OsPolicy>>, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<Arc<OsPolicy>>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            POLICY.with(|p| *p.borrow_mut() = previous);
+        }
+    }
+    let _reset = Reset(POLICY.with(|p| p.replace(policy)));
+    f()
+}
+
+fn check<T>(what: &str, allowed: impl FnOnce(&OsPolicy) -> Option<T>) -> Result<T, FuncError> {
+    POLICY
+        .with(|p| p.borrow().as_deref().and_then(allowed))
+        .ok_or_else(|| FuncError::Generic(format!("access to {} denied", what)))
+}
+
+fn single_str<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, FuncError> {
+    match args {
+        [Value::String(s)] => Ok(s),
+        [val] => Err(FuncError::Generic(format!(
+            "{} requires a string, got {}",
+            name, val
+        ))),
+        _ => Err(FuncError::ExactlyXArgs(name.into(), 1)),
+    }
+}
+
+// Makes `path` absolute and resolves `.` and `..` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Returns the value of an allowed environment variable or an empty string if it is not set.
+pub fn env_var(args: &[Value]) -> Result<Value, FuncError> {
+    let name = single_str("env", args)?;
+    check(&format!("environment variable {}", name), |p| {
+        p.env_allowed(name).then_some(())
+    })?;
+    Ok(Value::from(env::var(name).unwrap_or_default()))
+}
+
+/// Returns the content of an allowed file.
+pub fn read_file(args: &[Value]) -> Result<Value, FuncError> {
+    let name = single_str("readFile", args)?;
+    // Paths are checked before reporting any errors to not leak what exists outside the sandbox.
+    let path = fs::canonicalize(name).unwrap_or_else(|_| normalize(Path::new(name)));
+    check(name, |p| p.path_allowed(&path).then_some(()))?;
+    fs::read_to_string(&path)
+        .map(Value::from)
+        .with_func_context(|| name.to_owned())
+}
+
+/// Returns true if an allowed path exists.
+pub fn file_exists(args: &[Value]) -> Result<Value, FuncError> {
+    let name = single_str("fileExists", args)?;
+    let (path, exists) = match fs::canonicalize(name) {
+        Ok(path) => (path, true),
+        Err(_) => (normalize(Path::new(name)), false),
+    };
+    check(name, |p| p.path_allowed(&path).then_some(()))?;
+    Ok(Value::from(exists))
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    fn call(f: Func, arg: &str, policy: OsPolicy) -> Result<Value, FuncError> {
+        with_policy(Some(Arc::new(policy)), || f(&[Value::from(arg)]))
+    }
+
+    #[test]
+    fn test_env() {
+        env::set_var("GTMPL_TEST_ENV", "foo");
+        let policy = OsPolicy::default().allow_env("GTMPL_TEST_*");
+        assert_eq!(
+            call(env_var, "GTMPL_TEST_ENV", policy.clone()).unwrap(),
+            Value::from("foo")
+        );
+        assert_eq!(
+            call(env_var, "GTMPL_TEST_UNSET", policy.clone()).unwrap(),
+            Value::from("")
+        );
+        assert!(call(env_var, "PATH", policy).is_err());
+        assert!(env_var(&[Value::from("GTMPL_TEST_ENV")]).is_err());
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowed = dir.path().join("allowed");
+        fs::create_dir(&allowed).unwrap();
+        fs::write(allowed.join("a"), "content").unwrap();
+        fs::write(dir.path().join("secret"), "secret").unwrap();
+        let policy = OsPolicy::default().allow_path(&allowed);
+        let path = |p: &str| allowed.join(p).to_string_lossy().into_owned();
+
+        assert_eq!(
+            call(read_file, &path("a"), policy.clone()).unwrap(),
+            Value::from("content")
+        );
+        assert!(call(read_file, &path("../secret"), policy.clone()).is_err());
+        assert_eq!(
+            call(file_exists, &path("a"), policy.clone()).unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(
+            call(file_exists, &path("b"), policy.clone()).unwrap(),
+            Value::from(false)
+        );
+        assert!(call(file_exists, &path("../secret"), policy.clone()).is_err());
+        assert!(call(file_exists, &path("../../nope"), policy).is_err());
+        assert!(file_exists(&[Value::from(path("a"))]).is_err());
+    }
+
+    #[test]
+    fn test_policy_reset() {
+        let policy = OsPolicy::default().allow_env("*");
+        with_policy(Some(Arc::new(policy)), || {
+            with_policy(None, || assert!(env_var(&[Value::from("PATH")]).is_err()));
+            assert!(env_var(&[Value::from("PATH")]).is_ok());
+        });
+        assert!(env_var(&[Value::from("PATH")]).is_err());
+    }
+}
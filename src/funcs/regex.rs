@@ -0,0 +1,218 @@
+//! Regular expression functions.
+//!
+//! Requires the `gtmpl_regex` feature. The functions are registered via
+//! [`Template::add_regex_funcs`](crate::Template::add_regex_funcs) or by passing `REGEX_FUNCS`
+//! to `add_funcs`. Like in Sprig the regular expression always comes first, so the input can be
+//! piped in:
+//!
+//! ```rust
+//! use gtmpl::{Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_regex_funcs();
+//! tmpl.parse(r#"{{ "foo@example.com" | regexMatch "^[a-z]+@" }}"#).unwrap();
+//! assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "true");
+//! ```
+//!
+//! The syntax is the one of the [regex](https://docs.rs/regex) crate which is close to Go's
+//! RE2 syntax. Compiled expressions are cached.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use gtmpl_value::{Func, FuncError, Value};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// All regular expression functions, ready to be passed to `Template::add_funcs`.
+pub static REGEX_FUNCS: &[(&str, Func)] = &[
+    ("regexMatch", regex_match as Func),
+    ("regexFind", regex_find as Func),
+    ("regexFindAll", regex_find_all as Func),
+    ("regexReplaceAll", regex_replace_all as Func),
+    ("regexSplit", regex_split as Func),
+];
+
+const MAX_CACHED: usize = 256;
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+}
+
+fn compile(name: &str, re: &str) -> Result<Regex, FuncError> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(regex) = cache.get(re) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(re).map_err(|e| FuncError::Generic(format!("{}: {}", name, e)))?;
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(re.to_owned(), regex.clone());
+    Ok(regex)
+}
+
+fn str_arg<'a>(name: &str, val: &'a Value) -> Result<&'a str, FuncError> {
+    if let Value::String(ref s) = *val {
+        Ok(s)
+    } else {
+        Err(FuncError::Generic(format!(
+            "{} requires a string, got {}",
+            name, val
+        )))
+    }
+}
+
+fn int_arg(name: &str, val: &Value) -> Result<i64, FuncError> {
+    if let Value::Number(ref n) = *val {
+        if let Some(i) = n.as_i64() {
+            return Ok(i);
+        }
+    }
+    Err(FuncError::Generic(format!(
+        "{} requires an integer, got {}",
+        name, val
+    )))
+}
+
+// Checks the number of arguments and returns the compiled expression and the input string.
+fn regex_args<'a>(
+    name: &str,
+    args: &'a [Value],
+    len: usize,
+) -> Result<(Regex, &'a str), FuncError> {
+    if args.len() != len {
+        return Err(FuncError::ExactlyXArgs(name.into(), len));
+    }
+    let regex = compile(name, str_arg(name, &args[0])?)?;
+    Ok((regex, str_arg(name, &args[1])?))
+}
+
+fn strings<'a>(iter: impl Iterator<Item = &'a str>) -> Value {
+    Value::Array(iter.map(Value::from).collect())
+}
+
+/// Returns true if the string contains a match of the regular expression.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_regex_funcs();
+/// tmpl.parse(r#"{{ regexMatch "^[0-9]+$" "1234" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "true");
+/// ```
+pub fn regex_match(args: &[Value]) -> Result<Value, FuncError> {
+    let (regex, s) = regex_args("regexMatch", args, 2)?;
+    Ok(Value::from(regex.is_match(s)))
+}
+
+/// Returns the first match of the regular expression or an empty string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_regex_funcs();
+/// tmpl.parse(r#"{{ regexFind "[0-9]+" "abc123def456" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "123");
+/// ```
+pub fn regex_find(args: &[Value]) -> Result<Value, FuncError> {
+    let (regex, s) = regex_args("regexFind", args, 2)?;
+    Ok(Value::from(regex.find(s).map(|m| m.as_str()).unwrap_or("")))
+}
+
+/// Returns at most `n` matches of the regular expression. A negative `n` returns all matches.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_regex_funcs();
+/// tmpl.parse(r#"{{ range regexFindAll "[0-9]" "a1b2c3" 2 }}<{{ . }}>{{ end }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "<1><2>");
+/// ```
+pub fn regex_find_all(args: &[Value]) -> Result<Value, FuncError> {
+    let (regex, s) = regex_args("regexFindAll", args, 3)?;
+    let n = int_arg("regexFindAll", &args[2])?;
+    let matches = regex.find_iter(s).map(|m| m.as_str());
+    Ok(if n < 0 {
+        strings(matches)
+    } else {
+        strings(matches.take(n as usize))
+    })
+}
+
+/// Replaces all matches of the regular expression with the replacement. Inside the replacement
+/// `$1` or `${name}` refer to capture groups.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_regex_funcs();
+/// tmpl.parse(r#"{{ regexReplaceAll "(\\w+)@(\\w+)" "me@host" "$2 at $1" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "host at me");
+/// ```
+pub fn regex_replace_all(args: &[Value]) -> Result<Value, FuncError> {
+    let (regex, s) = regex_args("regexReplaceAll", args, 3)?;
+    let replacement = str_arg("regexReplaceAll", &args[2])?;
+    Ok(Value::from(regex.replace_all(s, replacement).into_owned()))
+}
+
+/// Splits the string at matches of the regular expression into at most `n` parts. A negative
+/// `n` returns all parts.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_regex_funcs();
+/// tmpl.parse(r#"{{ range regexSplit ", *" "a,b,  c" -1 }}<{{ . }}>{{ end }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "<a><b><c>");
+/// ```
+pub fn regex_split(args: &[Value]) -> Result<Value, FuncError> {
+    let (regex, s) = regex_args("regexSplit", args, 3)?;
+    let n = int_arg("regexSplit", &args[2])?;
+    Ok(if n < 0 {
+        strings(regex.split(s))
+    } else {
+        strings(regex.splitn(s, n as usize))
+    })
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_find_all() {
+        let args = [Value::from("[0-9]"), Value::from("a1b2c3"), Value::from(-1)];
+        assert_eq!(
+            regex_find_all(&args).unwrap(),
+            Value::from(vec!["1", "2", "3"])
+        );
+        let args = [Value::from("[0-9]"), Value::from("a1b2c3"), Value::from(0)];
+        assert_eq!(regex_find_all(&args).unwrap(), Value::Array(vec![]));
+    }
+
+    #[test]
+    fn test_split() {
+        let args = [Value::from("-"), Value::from("a-b-c"), Value::from(2)];
+        assert_eq!(regex_split(&args).unwrap(), Value::from(vec!["a", "b-c"]));
+    }
+
+    #[test]
+    fn test_find_no_match() {
+        let args = [Value::from("x"), Value::from("abc")];
+        assert_eq!(regex_find(&args).unwrap(), Value::from(""));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(regex_match(&[Value::from("("), Value::from("a")]).is_err());
+        assert!(regex_match(&[Value::from("a")]).is_err());
+        assert!(regex_match(&[Value::from("a"), Value::from(1)]).is_err());
+        let args = [Value::from("a"), Value::from("a"), Value::from("1")];
+        assert!(regex_split(&args).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use super::{Arity, ARITIES, BUILTINS, EXEC_FUNCS};
+use crate::exec::ExecContext;
+
+/// The functions available to a template.
+///
+/// `FuncRegistry::default()` contains no registered functions, `FuncRegistry::builtins()`
+/// contains Golang's builtin functions like every new `Template` does. Both provide the
+/// functions implemented by the executor, `include`, `tpl`, `raw` and `data`, unless
+/// [`FuncRegistry::without_exec_funcs`] is used.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{FuncRegistry, Template};
+///
+/// let funcs = FuncRegistry::builtins().without("urlquery").without("call");
+/// assert!(!funcs.contains("urlquery"));
+///
+/// let mut tmpl = Template::default();
+/// tmpl.funcs = funcs;
+/// assert!(tmpl.parse(r#"{{ urlquery "a b" }}"#).is_err());
+/// ```
+#[derive(Clone, Default)]
+pub struct FuncRegistry {
+    funcs: HashMap<String, Func>,
+    iterators: HashMap<String, IterFunc>,
+    context_funcs: HashMap<String, ContextFunc>,
+    // Names still bound to the builtin function, which the executor may evaluate specially.
+    builtins: HashSet<String>,
+    exec_funcs_disabled: bool,
+}
+
+/// Creates a sequence of values from its arguments, which `range` consumes lazily. See
+/// [`Template::add_iterator`](crate::Template::add_iterator).
+pub type IterFunc =
+    Arc<dyn Fn(&[Value]) -> Result<Box<dyn Iterator<Item = Value>>, FuncError> + Send + Sync>;
+
+/// A function which also gets the dot and variables at the call. See
+/// [`Template::add_context_func`](crate::Template::add_context_func).
+pub type ContextFunc =
+    Arc<dyn Fn(&[Value], &ExecContext<'_>) -> Result<Value, FuncError> + Send + Sync>;
+
+impl FuncRegistry {
+    /// Creates a registry containing all builtin functions, i.e. Golang's `eq`, `ne`, `lt`,
+    /// `le`, `gt`, `ge`, `len`, `and`, `or`, `not`, `urlquery`, `print`, `println`, `printf`,
+    /// `index` and `call`, see [`BUILTINS`](crate::funcs::BUILTINS).
+    pub fn builtins() -> FuncRegistry {
+        FuncRegistry {
+            funcs: BUILTINS.iter().map(|&(k, v)| (k.to_owned(), v)).collect(),
+            builtins: BUILTINS.iter().map(|&(k, _)| k.to_owned()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Removes the functions implemented by the executor, `include`, `tpl`, `raw` and `data`.
+    /// Registered functions of the same name are kept.
+    pub fn without_exec_funcs(mut self) -> FuncRegistry {
+        self.exec_funcs_disabled = true;
+        self
+    }
+
+    // The names of the available functions implemented by the executor.
+    pub(crate) fn exec_funcs(&self) -> &'static [&'static str] {
+        if self.exec_funcs_disabled {
+            &[]
+        } else {
+            EXEC_FUNCS
+        }
+    }
+
+    /// Adds or overrides the function `name`.
+    pub fn with<T: Into<String>>(mut self, name: T, func: Func) -> FuncRegistry {
+        self.register(name, func);
+        self
+    }
+
+    /// Adds or overrides all given functions.
+    pub fn with_funcs<T: Into<String> + Clone>(mut self, funcs: &[(T, Func)]) -> FuncRegistry {
+        self.extend(funcs);
+        self
+    }
+
+    /// Removes the function `name`.
+    pub fn without(mut self, name: &str) -> FuncRegistry {
+        self.deregister(name);
+        self
+    }
+
+    /// Adds the function `name` and returns the function it replaced.
+    pub fn register<T: Into<String>>(&mut self, name: T, func: Func) -> Option<Func> {
+        let name = name.into();
+        self.builtins.remove(&name);
+        self.funcs.insert(name, func)
+    }
+
+    /// Adds or overrides all given functions.
+    pub fn extend<T: Into<String> + Clone>(&mut self, funcs: &[(T, Func)]) {
+        for (name, func) in funcs.iter().cloned() {
+            self.register(name, func);
+        }
+    }
+
+    /// Removes the function `name` and returns it.
+    pub fn deregister(&mut self, name: &str) -> Option<Func> {
+        self.builtins.remove(name);
+        self.funcs.remove(name)
+    }
+
+    // Takes the function `name` of `other`, staying a builtin if it is one there.
+    pub(crate) fn import(&mut self, other: &FuncRegistry, name: &str) {
+        if let Some(func) = other.get(name) {
+            self.register(name, func);
+            if other.is_builtin(name) {
+                self.builtins.insert(name.to_owned());
+            }
+        }
+    }
+
+    // Returns `true` if `name` is still bound to the builtin function of that name.
+    pub(crate) fn is_builtin(&self, name: &str) -> bool {
+        self.builtins.contains(name)
+    }
+
+    /// Returns `true` if a function called `name` is registered.
+    pub fn contains(&self, name: &str) -> bool {
+        self.funcs.contains_key(name)
+    }
+
+    /// Returns the function `name`.
+    pub fn get(&self, name: &str) -> Option<Func> {
+        self.funcs.get(name).copied()
+    }
+
+    /// Adds the iterator `name` and returns the iterator it replaced.
+    pub fn register_iterator<T: Into<String>>(
+        &mut self,
+        name: T,
+        iter: IterFunc,
+    ) -> Option<IterFunc> {
+        self.iterators.insert(name.into(), iter)
+    }
+
+    /// Returns `true` if an iterator called `name` is registered.
+    pub fn contains_iterator(&self, name: &str) -> bool {
+        self.iterators.contains_key(name)
+    }
+
+    /// Returns the iterator `name`.
+    pub fn get_iterator(&self, name: &str) -> Option<IterFunc> {
+        self.iterators.get(name).cloned()
+    }
+
+    /// Adds the context function `name` and returns the context function it replaced.
+    pub fn register_context_func<T: Into<String>>(
+        &mut self,
+        name: T,
+        func: ContextFunc,
+    ) -> Option<ContextFunc> {
+        self.context_funcs.insert(name.into(), func)
+    }
+
+    /// Returns `true` if a context function called `name` is registered.
+    pub fn contains_context_func(&self, name: &str) -> bool {
+        self.context_funcs.contains_key(name)
+    }
+
+    /// Returns the context function `name`.
+    pub fn get_context_func(&self, name: &str) -> Option<ContextFunc> {
+        self.context_funcs.get(name).cloned()
+    }
+
+    /// Returns the names of all registered functions in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.funcs.keys().map(String::as_str)
+    }
+
+    /// Returns the names of all registered iterators in no particular order.
+    pub fn iterator_names(&self) -> impl Iterator<Item = &str> {
+        self.iterators.keys().map(String::as_str)
+    }
+
+    /// Returns the names of all registered context functions in no particular order.
+    pub fn context_func_names(&self) -> impl Iterator<Item = &str> {
+        self.context_funcs.keys().map(String::as_str)
+    }
+
+    /// Returns the number of registered functions.
+    pub fn len(&self) -> usize {
+        self.funcs.len()
+    }
+
+    /// Returns `true` if no functions are registered.
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+
+    // The names known to the parser, including functions implemented by the executor.
+    pub(crate) fn name_set(&self) -> HashSet<String> {
+        self.funcs
+            .keys()
+            .chain(self.iterators.keys())
+            .chain(self.context_funcs.keys())
+            .cloned()
+            .chain(self.exec_funcs().iter().map(|&f| f.to_owned()))
+            .collect()
+    }
+
+    // The argument counts of the builtin and executor functions which are not overridden.
+    pub(crate) fn arities(&self) -> HashMap<String, Arity> {
+        ARITIES
+            .iter()
+            .filter(|&&(name, _)| {
+                !self.iterators.contains_key(name)
+                    && !self.context_funcs.contains_key(name)
+                    && (self.is_builtin(name)
+                        || (!self.contains(name) && self.exec_funcs().contains(&name)))
+            })
+            .map(|&(name, arity)| (name.to_owned(), arity))
+            .collect()
+    }
+}
+
+impl fmt::Debug for FuncRegistry {
+    /// Lists the registered names in sorted order, functions themselves have no stable identity.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&str> = self.names().collect();
+        names.sort_unstable();
+        let mut iterators: Vec<&str> = self.iterators.keys().map(String::as_str).collect();
+        iterators.sort_unstable();
+        let mut context_funcs: Vec<&str> = self.context_func_names().collect();
+        context_funcs.sort_unstable();
+        f.debug_struct("FuncRegistry")
+            .field("funcs", &names)
+            .field("iterators", &iterators)
+            .field("context_funcs", &context_funcs)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use gtmpl_value::{FuncError, Value};
+
+    fn foo(_: &[Value]) -> Result<Value, FuncError> {
+        Ok(Value::from("foo"))
+    }
+
+    #[test]
+    fn test_register() {
+        let mut funcs = FuncRegistry::default();
+        assert!(funcs.is_empty());
+        assert!(funcs.register("foo", foo).is_none());
+        assert!(funcs.register("foo", foo).is_some());
+        assert!(funcs.contains("foo"));
+        assert_eq!(funcs.names().collect::<Vec<_>>(), vec!["foo"]);
+        funcs.register("bar", foo);
+        funcs.register_iterator("baz", Arc::new(|_| Ok(Box::new(std::iter::empty()))));
+        assert!(funcs.contains_iterator("baz"));
+        assert!(!funcs.contains("baz"));
+        assert!(funcs.name_set().contains("baz"));
+        assert_eq!(
+            format!("{:?}", funcs),
+            r#"FuncRegistry { funcs: ["bar", "foo"], iterators: ["baz"], context_funcs: [] }"#
+        );
+        funcs.deregister("bar");
+        assert!(funcs.deregister("foo").is_some());
+        assert!(funcs.deregister("foo").is_none());
+        assert!(funcs.is_empty());
+    }
+
+    #[test]
+    fn test_builder() {
+        let funcs = FuncRegistry::builtins()
+            .with("foo", foo)
+            .with_funcs(&[("bar", foo as Func)])
+            .without("printf");
+        assert_eq!(funcs.len(), BUILTINS.len() + 1);
+        assert!(funcs.contains("eq"));
+        assert!(funcs.contains("bar"));
+        assert!(!funcs.contains("printf"));
+    }
+
+    #[test]
+    fn test_arities() {
+        let funcs = FuncRegistry::builtins()
+            .with("len", foo)
+            .with("raw", foo)
+            .without("not");
+        let arities = funcs.arities();
+        assert_eq!(arities.get("ne"), Some(&(2, Some(2))));
+        assert_eq!(arities.get("include"), Some(&(2, Some(2))));
+        assert!(!arities.contains_key("len"));
+        assert!(!arities.contains_key("raw"));
+        assert!(!arities.contains_key("not"));
+        assert!(!arities.contains_key("print"));
+    }
+
+    #[test]
+    fn test_without_exec_funcs() {
+        let funcs = FuncRegistry::default();
+        assert!(funcs.name_set().contains("include"));
+        let funcs = funcs.without_exec_funcs().with("raw", foo);
+        let names = funcs.name_set();
+        assert!(names.contains("raw"));
+        assert!(!names.contains("include"));
+        assert!(!funcs.arities().contains_key("tpl"));
+    }
+}
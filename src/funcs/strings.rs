@@ -0,0 +1,234 @@
+//! Functions for assembling text without `printf`, named like their Sprig counterparts.
+//!
+//! Requires the `gtmpl_strings` feature. The functions are not part of the builtins and have to
+//! be registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::strings::STRINGS_FUNCS, gtmpl_map, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(STRINGS_FUNCS);
+//! tmpl.parse(r#"{{ .n }} {{ plural .n "file" "files" }}: {{ .names | join ", " }}"#)
+//!     .unwrap();
+//! let data = gtmpl_map! { "n" => 2, "names" => vec!["a.txt", "b.txt"] };
+//! assert_eq!(
+//!     &tmpl.render(&Context::from(data)).unwrap(),
+//!     "2 files: a.txt, b.txt"
+//! );
+//! ```
+//!
+//! Values are converted to strings like `print` does, `nil` and missing values are left out
+//! by `cat` and `join`. Counts and lengths may also be given as strings.
+use std::convert::TryInto;
+
+use gtmpl_value::{Func, FuncError, Value};
+
+use crate::coerce::{self, Lenient};
+use crate::print_verb::go_string;
+
+/// All string functions, ready to be passed to `Template::add_funcs`.
+pub static STRINGS_FUNCS: &[(&str, Func)] = &[
+    ("cat", cat as Func),
+    ("join", join as Func),
+    ("nospace", nospace as Func),
+    ("trunc", trunc as Func),
+    ("abbrev", abbrev as Func),
+    ("plural", plural as Func),
+];
+
+fn exactly<'a, const N: usize>(name: &str, args: &'a [Value]) -> Result<&'a [Value; N], FuncError> {
+    args.try_into()
+        .map_err(|_| FuncError::ExactlyXArgs(name.into(), N))
+}
+
+fn int_arg(name: &str, index: usize, val: &Value) -> Result<i64, FuncError> {
+    coerce::arg::<Lenient<i64>>(name, index, val).map(|n| n.0)
+}
+
+fn str_arg(name: &str, index: usize, val: &Value) -> Result<String, FuncError> {
+    coerce::arg::<Lenient<String>>(name, index, val).map(|s| s.0)
+}
+
+// The values to print, leaving out `nil` and missing ones.
+fn strings<'a>(values: impl IntoIterator<Item = &'a Value>) -> Vec<String> {
+    values
+        .into_iter()
+        .filter(|v| !matches!(v, Value::Nil | Value::NoValue))
+        .map(go_string)
+        .collect()
+}
+
+/// Concatenates its arguments separated by spaces.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, gtmpl_map, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ cat "a" .n .missing true }}"#).unwrap();
+/// let data = gtmpl_map! { "n" => 1 };
+/// assert_eq!(&tmpl.render(&Context::from(data)).unwrap(), "a 1 true");
+/// ```
+pub fn cat(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::from(strings(args).join(" ")))
+}
+
+/// Joins the elements of a list with a separator: `join sep list`. A single value which is
+/// not a list is printed as it is.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ join "-" . }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::from(vec![1, 2, 3])).unwrap(), "1-2-3");
+/// ```
+pub fn join(args: &[Value]) -> Result<Value, FuncError> {
+    let [sep, list] = exactly("join", args)?;
+    let sep = str_arg("join", 0, sep)?;
+    let joined = match list {
+        Value::Array(a) => strings(a).join(&sep),
+        v => strings(Some(v)).join(&sep),
+    };
+    Ok(Value::from(joined))
+}
+
+/// Removes all whitespace from a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ nospace " a b\tc " }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "abc");
+/// ```
+pub fn nospace(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exactly("nospace", args)?;
+    let s = str_arg("nospace", 0, s)?;
+    Ok(Value::from(
+        s.chars().filter(|c| !c.is_whitespace()).collect::<String>(),
+    ))
+}
+
+/// Keeps the first `n` characters of a string: `trunc n s`. A negative `n` keeps the last
+/// `-n` characters.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ trunc 3 "gopher" }} {{ trunc -3 "gopher" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "gop her");
+/// ```
+pub fn trunc(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exactly("trunc", args)?;
+    let n = int_arg("trunc", 0, n)?;
+    let s = str_arg("trunc", 1, s)?;
+    let len = s.chars().count();
+    let keep = n.unsigned_abs().min(len as u64) as usize;
+    let truncated: String = if n < 0 {
+        s.chars().skip(len - keep).collect()
+    } else {
+        s.chars().take(keep).collect()
+    };
+    Ok(Value::from(truncated))
+}
+
+/// Shortens a string to at most `width` characters, ending with `...` if it was cut:
+/// `abbrev width s`. Widths below 4 leave the string as it is.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ abbrev 8 "hello world" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "hello...");
+/// ```
+pub fn abbrev(args: &[Value]) -> Result<Value, FuncError> {
+    let [width, s] = exactly("abbrev", args)?;
+    let width = int_arg("abbrev", 0, width)?;
+    let s = str_arg("abbrev", 1, s)?;
+    if width < 4 || s.chars().count() as u64 <= width as u64 {
+        return Ok(Value::from(s));
+    }
+    let mut abbreviated: String = s.chars().take(width as usize - 3).collect();
+    abbreviated.push_str("...");
+    Ok(Value::from(abbreviated))
+}
+
+/// Picks the singular or plural form for a count: `plural n singular plural`.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::strings::STRINGS_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(STRINGS_FUNCS);
+/// tmpl.parse(r#"{{ plural 1 "item" "items" }}, {{ plural "0" "item" "items" }}"#)
+///     .unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "item, items");
+/// ```
+pub fn plural(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, singular, plural] = exactly("plural", args)?;
+    let n = int_arg("plural", 0, n)?;
+    let form = if n == 1 { singular } else { plural };
+    Ok(Value::from(str_arg(
+        "plural",
+        if n == 1 { 1 } else { 2 },
+        form,
+    )?))
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::gtmpl_list;
+
+    #[test]
+    fn test_strings() {
+        let call = |f: Func, args: &[Value]| f(args).unwrap().to_string();
+        assert_eq!(call(cat, &[]), "");
+        assert_eq!(call(cat, &["a".into(), Value::Nil, 1.5.into()]), "a 1.5");
+        assert_eq!(call(cat, &[gtmpl_list![1, 2]]), "[1 2]");
+        assert_eq!(call(join, &[", ".into(), gtmpl_list!["a", 1]]), "a, 1");
+        assert_eq!(call(join, &[1.into(), gtmpl_list!["a", "b"]]), "a1b");
+        assert_eq!(call(join, &[",".into(), "a".into()]), "a");
+        assert_eq!(call(join, &[",".into(), Value::NoValue]), "");
+        assert_eq!(call(nospace, &["\ta b\n".into()]), "ab");
+        assert_eq!(call(trunc, &[2.into(), "äöü".into()]), "äö");
+        assert_eq!(call(trunc, &["-2".into(), "äöü".into()]), "öü");
+        assert_eq!(call(trunc, &[10.into(), "äöü".into()]), "äöü");
+        assert_eq!(call(trunc, &[(-10).into(), "äöü".into()]), "äöü");
+        assert_eq!(call(trunc, &[0.into(), 12345.into()]), "");
+        assert_eq!(call(abbrev, &[5.into(), "äöüäöü".into()]), "äö...");
+        assert_eq!(call(abbrev, &[6.into(), "äöüäöü".into()]), "äöüäöü");
+        assert_eq!(call(abbrev, &[3.into(), "äöüäöü".into()]), "äöüäöü");
+        assert_eq!(call(plural, &[1.into(), "a".into(), "b".into()]), "a");
+        assert_eq!(call(plural, &[(-1).into(), "a".into(), "b".into()]), "b");
+        assert_eq!(call(plural, &["1".into(), "a".into(), "b".into()]), "a");
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |f: Func, args: &[Value]| f(args).unwrap_err().to_string();
+        assert_eq!(
+            err(join, &[",".into()]),
+            "join requires exactly 2 argument(s)"
+        );
+        assert_eq!(
+            err(trunc, &["x".into(), "abc".into()]),
+            r#"argument 1 of trunc: expected integer, got string "x""#
+        );
+        assert_eq!(
+            err(plural, &[1.5.into(), "a".into(), "b".into()]),
+            "argument 1 of plural: expected integer, got float 1.5"
+        );
+        assert_eq!(
+            err(nospace, &[gtmpl_list![1]]),
+            "argument 1 of nospace: expected string, got array"
+        );
+    }
+}
@@ -0,0 +1,485 @@
+//! Date and time functions.
+//!
+//! Requires the `gtmpl_time` feature. The functions are not part of the builtins and have to be
+//! registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(TIME_FUNCS);
+//! tmpl.parse(r#"{{ dateInZone "Mon Jan 2 15:04:05 MST 2006" 0 "UTC" }}"#).unwrap();
+//! assert_eq!(
+//!     &tmpl.render(&Context::empty()).unwrap(),
+//!     "Thu Jan 1 00:00:00 UTC 1970"
+//! );
+//! ```
+//!
+//! Times are passed around as RFC 3339 strings (as returned by `now`) or as seconds since the
+//! unix epoch. Layouts use Go's reference time `Mon Jan 2 15:04:05 MST 2006` just like Go's
+//! `time.Format`, e.g. `2006-01-02` for an ISO date.
+use std::fmt::Write;
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, SecondsFormat, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use gtmpl_value::{Func, FuncError, Value};
+
+/// All time functions, ready to be passed to `Template::add_funcs`.
+pub static TIME_FUNCS: &[(&str, Func)] = &[
+    ("now", now as Func),
+    ("date", date as Func),
+    ("dateInZone", date_in_zone as Func),
+    ("unixEpoch", unix_epoch as Func),
+    ("duration", duration as Func),
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+fn to_time(val: &Value) -> Result<DateTime<Utc>, FuncError> {
+    let time = match *val {
+        Value::String(ref s) => DateTime::parse_from_rfc3339(s)
+            .map(|t| t.with_timezone(&Utc))
+            .ok(),
+        Value::Number(ref n) => {
+            if let Some(secs) = n.as_i64() {
+                Utc.timestamp_opt(secs, 0).single()
+            } else if let Some(secs) = n.as_f64() {
+                float_to_time(secs)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    time.ok_or_else(|| FuncError::Generic(format!("unable to use {} as time", val)))
+}
+
+// The fraction is taken from the floor to stay positive for negative timestamps and carried
+// into the seconds if it rounds up to a full second.
+fn float_to_time(secs: f64) -> Option<DateTime<Utc>> {
+    let floor = secs.floor();
+    let nanos = ((secs - floor) * 1e9).round() as u32;
+    let (secs, nanos) = if nanos >= 1_000_000_000 {
+        (floor as i64 + 1, nanos - 1_000_000_000)
+    } else {
+        (floor as i64, nanos)
+    };
+    Utc.timestamp_opt(secs, nanos).single()
+}
+
+fn to_str<'a>(name: &str, val: &'a Value) -> Result<&'a str, FuncError> {
+    if let Value::String(ref s) = *val {
+        Ok(s)
+    } else {
+        Err(FuncError::Generic(format!(
+            "{} requires a string, got {}",
+            name, val
+        )))
+    }
+}
+
+/// Returns the current local time as RFC 3339 string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(TIME_FUNCS);
+/// tmpl.parse(r#"{{ now | date "2006" }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap().len(), 4);
+/// ```
+pub fn now(args: &[Value]) -> Result<Value, FuncError> {
+    if !args.is_empty() {
+        return Err(FuncError::ExactlyXArgs("now".into(), 0));
+    }
+    Ok(Value::from(
+        Local::now().to_rfc3339_opts(SecondsFormat::AutoSi, false),
+    ))
+}
+
+/// Formats a time in the local time zone using a Go layout.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(TIME_FUNCS);
+/// tmpl.parse(r#"{{ "2021-03-04T05:06:07Z" | date "2006" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "2021");
+/// ```
+pub fn date(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() != 2 {
+        return Err(FuncError::ExactlyXArgs("date".into(), 2));
+    }
+    let layout = to_str("date", &args[0])?;
+    let time = to_time(&args[1])?.with_timezone(&Local);
+    Ok(Value::from(format_go(&time.fixed_offset(), None, layout)))
+}
+
+/// Formats a time in the given IANA time zone (e.g. `Europe/Berlin`) using a Go layout.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(TIME_FUNCS);
+/// tmpl.parse(
+///     r#"{{ dateInZone "2006-01-02 15:04 MST" "2021-07-01T10:00:00Z" "Europe/Berlin" }}"#,
+/// )
+/// .unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "2021-07-01 12:00 CEST");
+/// ```
+pub fn date_in_zone(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() != 3 {
+        return Err(FuncError::ExactlyXArgs("dateInZone".into(), 3));
+    }
+    let layout = to_str("dateInZone", &args[0])?;
+    let zone = to_str("dateInZone", &args[2])?;
+    let tz: Tz = zone
+        .parse()
+        .map_err(|_| FuncError::Generic(format!("unknown time zone {}", zone)))?;
+    let time = to_time(&args[1])?.with_timezone(&tz);
+    let abbreviation = time.format("%Z").to_string();
+    Ok(Value::from(format_go(
+        &time.fixed_offset(),
+        Some(&abbreviation),
+        layout,
+    )))
+}
+
+/// Returns the seconds since the unix epoch of a time.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(TIME_FUNCS);
+/// tmpl.parse(r#"{{ unixEpoch "1970-01-02T00:00:00+01:00" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "82800");
+/// ```
+pub fn unix_epoch(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() != 1 {
+        return Err(FuncError::ExactlyXArgs("unixEpoch".into(), 1));
+    }
+    Ok(Value::from(to_time(&args[0])?.timestamp()))
+}
+
+/// Formats a number of seconds like Go's `time.Duration`.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::time::TIME_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(TIME_FUNCS);
+/// tmpl.parse(r#"{{ duration 95 }} {{ duration 0.25 }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::empty()).unwrap(), "1m35s 250ms");
+/// ```
+pub fn duration(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() != 1 {
+        return Err(FuncError::ExactlyXArgs("duration".into(), 1));
+    }
+    let nanos = match args[0] {
+        Value::Number(ref n) => n
+            .as_i64()
+            .map(|s| s as i128 * 1_000_000_000)
+            .or_else(|| n.as_f64().map(|s| (s * 1e9).round() as i128)),
+        Value::String(ref s) => s
+            .trim()
+            .parse::<i64>()
+            .map(|s| s as i128 * 1_000_000_000)
+            .ok(),
+        _ => None,
+    }
+    .ok_or_else(|| FuncError::Generic(format!("unable to use {} as duration", args[0])))?;
+    Ok(Value::from(format_duration(nanos)))
+}
+
+fn format_duration(nanos: i128) -> String {
+    if nanos == 0 {
+        return "0s".to_owned();
+    }
+    let mut out = String::new();
+    if nanos < 0 {
+        out.push('-');
+    }
+    let u = nanos.unsigned_abs();
+    if u < 1_000_000_000 {
+        let (unit, scale, digits) = if u < 1_000 {
+            ("ns", 1, 0)
+        } else if u < 1_000_000 {
+            ("µs", 1_000, 3)
+        } else {
+            ("ms", 1_000_000, 6)
+        };
+        write_frac(&mut out, u / scale, u % scale, digits);
+        out.push_str(unit);
+    } else {
+        let secs = u / 1_000_000_000;
+        let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+        if h > 0 {
+            let _ = write!(out, "{}h", h);
+        }
+        if h > 0 || m > 0 {
+            let _ = write!(out, "{}m", m);
+        }
+        write_frac(&mut out, s, u % 1_000_000_000, 9);
+        out.push('s');
+    }
+    out
+}
+
+fn write_frac(out: &mut String, int: u128, frac: u128, digits: usize) {
+    let _ = write!(out, "{}", int);
+    if frac > 0 {
+        let frac = format!("{:0width$}", frac, width = digits);
+        out.push('.');
+        out.push_str(frac.trim_end_matches('0'));
+    }
+}
+
+fn write_offset(out: &mut String, offset: i32, colon: bool, minutes: bool) {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    let _ = write!(out, "{}{:02}", sign, offset / 3600);
+    if minutes {
+        if colon {
+            out.push(':');
+        }
+        let _ = write!(out, "{:02}", offset / 60 % 60);
+    }
+}
+
+// Formats `time` like Go's `time.Format` does for `layout`.
+fn format_go(time: &DateTime<FixedOffset>, zone: Option<&str>, layout: &str) -> String {
+    let mut out = String::with_capacity(layout.len() + 10);
+    let offset = time.offset().local_minus_utc();
+    let mut rest = layout;
+    while let Some(c) = rest.chars().next() {
+        let consumed = if rest.starts_with("January") {
+            out.push_str(MONTHS[time.month0() as usize]);
+            7
+        } else if rest.starts_with("Jan") {
+            out.push_str(&MONTHS[time.month0() as usize][..3]);
+            3
+        } else if rest.starts_with("Monday") {
+            out.push_str(WEEKDAYS[time.weekday().num_days_from_monday() as usize]);
+            6
+        } else if rest.starts_with("Mon") {
+            out.push_str(&WEEKDAYS[time.weekday().num_days_from_monday() as usize][..3]);
+            3
+        } else if rest.starts_with("MST") {
+            match zone {
+                Some(zone) => out.push_str(zone),
+                None if offset == 0 => out.push_str("UTC"),
+                None => write_offset(&mut out, offset, false, true),
+            }
+            3
+        } else if rest.starts_with("2006") {
+            let _ = write!(out, "{:04}", time.year());
+            4
+        } else if rest.starts_with("002") {
+            let _ = write!(out, "{:03}", time.ordinal());
+            3
+        } else if rest.starts_with("__2") {
+            let _ = write!(out, "{:>3}", time.ordinal());
+            3
+        } else if rest.starts_with("_2") {
+            let _ = write!(out, "{:>2}", time.day());
+            2
+        } else if rest.starts_with("01") {
+            let _ = write!(out, "{:02}", time.month());
+            2
+        } else if rest.starts_with("02") {
+            let _ = write!(out, "{:02}", time.day());
+            2
+        } else if rest.starts_with("03") {
+            let _ = write!(out, "{:02}", time.hour12().1);
+            2
+        } else if rest.starts_with("04") {
+            let _ = write!(out, "{:02}", time.minute());
+            2
+        } else if rest.starts_with("05") {
+            let _ = write!(out, "{:02}", time.second());
+            2
+        } else if rest.starts_with("06") {
+            let _ = write!(out, "{:02}", time.year().rem_euclid(100));
+            2
+        } else if rest.starts_with("15") {
+            let _ = write!(out, "{:02}", time.hour());
+            2
+        } else if rest.starts_with('1') {
+            let _ = write!(out, "{}", time.month());
+            1
+        } else if rest.starts_with('2') {
+            let _ = write!(out, "{}", time.day());
+            1
+        } else if rest.starts_with('3') {
+            let _ = write!(out, "{}", time.hour12().1);
+            1
+        } else if rest.starts_with('4') {
+            let _ = write!(out, "{}", time.minute());
+            1
+        } else if rest.starts_with('5') {
+            let _ = write!(out, "{}", time.second());
+            1
+        } else if rest.starts_with("PM") {
+            out.push_str(if time.hour12().0 { "PM" } else { "AM" });
+            2
+        } else if rest.starts_with("pm") {
+            out.push_str(if time.hour12().0 { "pm" } else { "am" });
+            2
+        } else if let Some(len) = offset_layout(rest) {
+            if rest.starts_with('Z') && offset == 0 {
+                out.push('Z');
+            } else {
+                write_offset(&mut out, offset, rest[..len].contains(':'), len > 3);
+            }
+            len
+        } else if let Some(len) = fraction_layout(rest) {
+            let digits = format!("{:09}", time.nanosecond() % 1_000_000_000);
+            let wanted = &digits[..len - 1];
+            if rest[1..].starts_with('9') {
+                let trimmed = wanted.trim_end_matches('0');
+                if !trimmed.is_empty() {
+                    out.push_str(&rest[..1]);
+                    out.push_str(trimmed);
+                }
+            } else {
+                out.push_str(&rest[..1]);
+                out.push_str(wanted);
+            }
+            len
+        } else {
+            out.push(c);
+            c.len_utf8()
+        };
+        rest = &rest[consumed..];
+    }
+    out
+}
+
+// Length of a zone offset layout like `-07:00` or `Z0700` at the start of `layout`.
+fn offset_layout(layout: &str) -> Option<usize> {
+    ["-07:00", "-0700", "-07", "Z07:00", "Z0700", "Z07"]
+        .iter()
+        .find(|l| layout.starts_with(*l))
+        .map(|l| l.len())
+}
+
+// Length of a fractional second layout like `.000` or `,999` at the start of `layout`.
+fn fraction_layout(layout: &str) -> Option<usize> {
+    let mut chars = layout.chars();
+    if !matches!(chars.next(), Some('.') | Some(',')) {
+        return None;
+    }
+    let digit = chars.next().filter(|&c| c == '0' || c == '9')?;
+    let len = 2 + chars.clone().take_while(|&c| c == digit).count();
+    if len > 10 || layout[len..].starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    fn fmt(layout: &str, time: &str) -> String {
+        let time = DateTime::parse_from_rfc3339(time).unwrap();
+        format_go(&time, None, layout)
+    }
+
+    #[test]
+    fn test_format_go() {
+        let t = "2009-11-10T23:04:05.120+01:30";
+        assert_eq!(
+            fmt("2006-01-02T15:04:05Z07:00", t),
+            "2009-11-10T23:04:05+01:30"
+        );
+        assert_eq!(
+            fmt("Mon Jan _2 3:4:5 PM -0700", t),
+            "Tue Nov 10 11:4:5 PM +0130"
+        );
+        assert_eq!(fmt("Monday, January 2, 06", t), "Tuesday, November 10, 09");
+        assert_eq!(fmt("05.000 05.999 05,9 .0", t), "05.120 05.12 05,1 .1");
+        assert_eq!(fmt("002 MST -07", t), "314 +0130 +01");
+        assert_eq!(
+            fmt("15:04 Z07:00 MST", "2009-11-10T23:04:05Z"),
+            "23:04 Z UTC"
+        );
+        assert_eq!(fmt("2 → 1%", t), "10 → 11%");
+        // A colon later in the layout doesn't belong to the offset.
+        assert_eq!(fmt("-0700 15:04", t), "+0130 23:04");
+        assert_eq!(fmt("Z0700 15:04", t), "+0130 23:04");
+        assert_eq!(fmt("-07 15:04:05", t), "+01 23:04:05");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(1), "1ns");
+        assert_eq!(format_duration(1_500), "1.5µs");
+        assert_eq!(format_duration(-2_000_000), "-2ms");
+        assert_eq!(format_duration(3_600_000_000_000), "1h0m0s");
+        assert_eq!(format_duration(3_661_500_000_000), "1h1m1.5s");
+    }
+
+    #[test]
+    fn test_to_time() {
+        let epoch = Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(to_time(&Value::from(0)).unwrap(), epoch);
+        assert_eq!(
+            to_time(&Value::from("1970-01-01T01:00:00+01:00")).unwrap(),
+            epoch
+        );
+        assert_eq!(
+            to_time(&Value::from(1.5)).unwrap().nanosecond(),
+            500_000_000
+        );
+        assert_eq!(
+            to_time(&Value::from(1.9999999999)).unwrap(),
+            Utc.timestamp_opt(2, 0).unwrap()
+        );
+        // `Value::from` turns negative floats into integers.
+        assert_eq!(
+            float_to_time(-1.5).unwrap(),
+            Utc.timestamp_opt(-2, 500_000_000).unwrap()
+        );
+        assert_eq!(float_to_time(-0.0000000001).unwrap(), epoch);
+        assert_eq!(
+            float_to_time(-1.9999999999).unwrap(),
+            Utc.timestamp_opt(-2, 0).unwrap()
+        );
+        assert!(to_time(&Value::from("yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(date_in_zone(&[Value::from("2006"), Value::from(0), Value::from("Mars")]).is_err());
+        assert!(date(&[Value::from(0), Value::from(0)]).is_err());
+        assert!(duration(&[Value::from("soon")]).is_err());
+    }
+}
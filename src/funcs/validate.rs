@@ -0,0 +1,100 @@
+//! Validation functions aborting the execution, as known from Helm.
+//!
+//! Requires the `gtmpl_validate` feature. The functions are not part of the builtins and have to
+//! be registered explicitly:
+//!
+//! ```rust
+//! use gtmpl::{funcs::validate::VALIDATE_FUNCS, Context, Template};
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_funcs(VALIDATE_FUNCS);
+//! tmpl.parse(r#"{{ required "name is required" . }}"#).unwrap();
+//! let err = tmpl.render(&Context::empty()).unwrap_err();
+//! assert_eq!(err.to_string(), "function `required` failed: name is required");
+//! assert_eq!(err.span().map(|s| s.start), Some(0));
+//! ```
+//!
+//! The error is an [`ExecError`](crate::error::ExecError) carrying the position of the failing
+//! action.
+use gtmpl_value::{Func, FuncError, Value};
+
+/// All validation functions, ready to be passed to `Template::add_funcs`.
+pub static VALIDATE_FUNCS: &[(&str, Func)] =
+    &[("required", required as Func), ("fail", fail as Func)];
+
+/// Returns the second argument unless it is missing, nil or an empty string, in which case the
+/// execution fails with the first argument as message.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::validate::VALIDATE_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(VALIDATE_FUNCS);
+/// tmpl.parse(r#"{{ . | required "port is required" }}"#).unwrap();
+/// assert_eq!(&tmpl.render(&Context::from(0)).unwrap(), "0");
+/// ```
+pub fn required(args: &[Value]) -> Result<Value, FuncError> {
+    match args {
+        [Value::String(msg), val] => match val {
+            Value::NoValue | Value::Nil => Err(FuncError::Generic(msg.clone())),
+            Value::String(s) if s.is_empty() => Err(FuncError::Generic(msg.clone())),
+            _ => Ok(val.clone()),
+        },
+        [msg, _] => Err(FuncError::Generic(format!(
+            "required requires a string message, got {}",
+            msg
+        ))),
+        _ => Err(FuncError::ExactlyXArgs("required".into(), 2)),
+    }
+}
+
+/// Fails the execution with the given message.
+///
+/// # Example
+/// ```
+/// use gtmpl::{funcs::validate::VALIDATE_FUNCS, Context, Template};
+/// let mut tmpl = Template::default();
+/// tmpl.add_funcs(VALIDATE_FUNCS);
+/// tmpl.parse(r#"{{ if lt . 1 }}{{ fail "replicas must be positive" }}{{ end }}"#).unwrap();
+/// let err = tmpl.render(&Context::from(0)).unwrap_err();
+/// assert_eq!(err.to_string(), "function `fail` failed: replicas must be positive");
+/// ```
+pub fn fail(args: &[Value]) -> Result<Value, FuncError> {
+    match args {
+        [Value::String(msg)] => Err(FuncError::Generic(msg.clone())),
+        [msg] => Err(FuncError::Generic(msg.to_string())),
+        _ => Err(FuncError::ExactlyXArgs("fail".into(), 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::{Context, Template};
+
+    #[test]
+    fn test_required() {
+        let msg = Value::from("msg");
+        assert_eq!(
+            required(&[msg.clone(), Value::from(false)]).unwrap(),
+            Value::from(false)
+        );
+        assert!(required(&[msg.clone(), Value::Nil]).is_err());
+        assert!(required(&[msg.clone(), Value::NoValue]).is_err());
+        assert!(required(&[msg.clone(), Value::from("")]).is_err());
+        assert!(required(&[Value::from(1), Value::Nil]).is_err());
+        assert!(required(&[msg]).is_err());
+    }
+
+    #[test]
+    fn test_position() {
+        let src = "a\n{{ . }}{{ fail \"stop\" }}";
+        let mut tmpl = Template::default();
+        tmpl.add_funcs(VALIDATE_FUNCS);
+        tmpl.parse(src).unwrap();
+        let err = tmpl.render(&Context::empty()).unwrap_err();
+        assert_eq!(err.to_string(), "function `fail` failed: stop");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "{{ fail \"stop\" }}");
+    }
+}
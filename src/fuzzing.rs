@@ -0,0 +1,26 @@
+//! Entry points for the fuzz targets in `fuzz/`. Not part of the public API.
+use gtmpl_value::Value;
+
+use crate::funcs::FuncRegistry;
+use crate::lexer::Lexer;
+
+/// Lexes `text` and returns the number of items.
+pub fn lex(text: &str) -> usize {
+    Lexer::new(text.to_owned()).count()
+}
+
+/// Parses `text` with all builtin functions defined.
+pub fn parse(text: &str) -> bool {
+    crate::parse::parse(
+        String::from("fuzz"),
+        text.to_owned(),
+        FuncRegistry::builtins().name_set(),
+        Default::default(),
+    )
+    .is_ok()
+}
+
+/// Formats `args` according to `format`.
+pub fn sprintf(format: &str, args: &[Value]) -> bool {
+    crate::printf::sprintf(format, args, None).is_ok()
+}
@@ -1,13 +1,14 @@
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt;
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
+use std::ops::Deref;
+use std::sync::Arc;
 
 type Pos = usize;
 
-static LEFT_TRIM_MARKER: &str = "- ";
-static RIGHT_TRIM_MARKER: &str = " -";
+static TRIM_MARKER: char = '-';
+static BOM: char = '\u{feff}';
 static LEFT_DELIM: &str = "{{";
 static RIGHT_DELIM: &str = "}}";
 static LEFT_COMMENT: &str = "/*";
@@ -67,21 +68,107 @@ pub enum ItemType {
     ItemWith,     // with keyword
 }
 
+/// A slice of the shared template source. Cloning only bumps a reference count, the text is
+/// materialized on demand via `Deref` or `to_string`.
+#[derive(Clone)]
+pub struct StrSpan {
+    src: Arc<str>,
+    start: Pos,
+    end: Pos,
+}
+
+impl StrSpan {
+    pub fn new(src: Arc<str>, start: Pos, end: Pos) -> StrSpan {
+        StrSpan { src, start, end }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.src[self.start..self.end]
+    }
+}
+
+impl Deref for StrSpan {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for StrSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for StrSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl PartialEq<str> for StrSpan {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for StrSpan {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl From<String> for StrSpan {
+    fn from(s: String) -> StrSpan {
+        let end = s.len();
+        StrSpan::new(Arc::from(s), 0, end)
+    }
+}
+
+impl<'a> From<&'a str> for StrSpan {
+    fn from(s: &'a str) -> StrSpan {
+        StrSpan::new(Arc::from(s), 0, s.len())
+    }
+}
+
+/// Byte range `start..end` in the template source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+impl Span {
+    pub fn new(start: Pos, end: Pos) -> Span {
+        Span { start, end }
+    }
+}
+
 #[derive(Debug)]
 pub struct Item {
     pub typ: ItemType,
     pub pos: Pos,
-    pub val: String,
+    pub val: StrSpan,
     pub line: usize,
+    pub col: usize,
 }
 
 impl Item {
-    pub fn new<T: Into<String>>(typ: ItemType, pos: Pos, val: T, line: usize) -> Item {
+    pub fn new<T: Into<StrSpan>>(typ: ItemType, pos: Pos, val: T, line: usize, col: usize) -> Item {
         Item {
             typ,
             pos,
             val: val.into(),
             line,
+            col,
+        }
+    }
+
+    /// Byte range of the item in the template source. Error items span their start position only.
+    pub fn span(&self) -> Span {
+        match self.typ {
+            ItemType::ItemError => Span::new(self.pos, self.pos),
+            _ => Span::new(self.pos, self.pos + self.val.len()),
         }
     }
 }
@@ -97,20 +184,28 @@ impl fmt::Display for Item {
 }
 
 pub struct Lexer {
-    last_pos: Pos,                  // position of most recent item returned by nextItem
-    items_receiver: Receiver<Item>, // channel of scanned items
-    finished: bool,                 // flag if lexer is finished
+    last_pos: Pos,              // position of most recent item returned by nextItem
+    machine: LexerStateMachine, // scans items on demand
+    finished: bool,             // flag if lexer is finished
 }
 
 struct LexerStateMachine {
-    input: String,              // the string being scanned
-    state: State,               // the next lexing function to enter
-    pos: Pos,                   // current position in the input
-    start: Pos,                 // start position of this item
-    width: Pos,                 // width of last rune read from input
-    items_sender: Sender<Item>, // channel of scanned items
-    paren_depth: usize,         // nesting depth of ( ) exprs
-    line: usize,                // 1+number of newlines seen
+    input: Arc<str>,           // the string being scanned
+    state: State,              // the next lexing function to enter
+    pos: Pos,                  // current position in the input
+    start: Pos,                // start position of this item
+    width: Pos,                // width of last rune read from input
+    items: VecDeque<Item>,     // scanned items not yet returned by the Lexer
+    paren_depth: usize,        // nesting depth of ( ) exprs
+    opened_at: (usize, usize), // line and column of the current action or comment
+    line: usize,               // 1+number of newlines seen up to line_pos
+    line_start: Pos,           // position of the first character of line
+    line_pos: Pos,             // position up to which newlines have been counted
+    trim_blocks: bool,         // flag to remove whole lines of block actions
+    numeric_fields: bool,      // flag to lex `.0` as field instead of number
+    in_block_action: bool,     // flag if the current action is trimmed as block action
+    left_delim: String,        // start of an action
+    right_delim: String,       // end of an action
 }
 
 #[derive(Debug)]
@@ -137,53 +232,68 @@ impl Iterator for Lexer {
         if self.finished {
             return None;
         }
-        let item = match self.items_receiver.recv() {
-            Ok(item) => {
-                self.last_pos = item.pos;
-                if item.typ == ItemType::ItemError || item.typ == ItemType::ItemEOF {
-                    self.finished = true;
-                }
-                item
-            }
-            Err(e) => {
+        while self.machine.items.is_empty() {
+            if !self.machine.step() {
                 self.finished = true;
-                Item::new(ItemType::ItemError, 0, format!("{}", e), 0)
+                return None;
             }
-        };
+        }
+        let item = self.machine.items.pop_front()?;
+        self.last_pos = item.pos;
+        if item.typ == ItemType::ItemError || item.typ == ItemType::ItemEOF {
+            self.finished = true;
+        }
         Some(item)
     }
 }
 
 impl Lexer {
-    pub fn new(input: String) -> Lexer {
-        let (tx, rx) = channel();
-        let mut l = LexerStateMachine {
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub fn new<T: Into<Arc<str>>>(input: T) -> Lexer {
+        Lexer::with_options(input, LEFT_DELIM, RIGHT_DELIM, false, false)
+    }
+
+    /// Creates a lexer for actions enclosed in `left_delim` and `right_delim`, empty delimiters
+    /// fall back to `{{` and `}}`. If `trim_blocks` is set, it drops the indentation before and
+    /// the newline after block actions like `{{ if }}` or `{{ end }}` starting their line. If `numeric_fields` is set, `.0` is lexed as field instead of number.
+    pub fn with_options<T: Into<Arc<str>>>(
+        input: T,
+        left_delim: &str,
+        right_delim: &str,
+        trim_blocks: bool,
+        numeric_fields: bool,
+    ) -> Lexer {
+        let input: Arc<str> = input.into();
+        // A leading byte order mark is skipped, spans still point into the whole input.
+        let bom = if input.starts_with(BOM) {
+            BOM.len_utf8()
+        } else {
+            0
+        };
+        let machine = LexerStateMachine {
             input,
             state: State::LexText,
-            pos: 0,
-            start: 0,
+            pos: bom,
+            start: bom,
             width: 0,
-            items_sender: tx,
+            items: VecDeque::new(),
             paren_depth: 0,
+            opened_at: (1, 1),
             line: 1,
+            line_start: bom,
+            line_pos: bom,
+            trim_blocks,
+            numeric_fields,
+            in_block_action: false,
+            left_delim: or_default(left_delim, LEFT_DELIM),
+            right_delim: or_default(right_delim, RIGHT_DELIM),
         };
-        thread::spawn(move || l.run());
         Lexer {
             last_pos: 0,
-            items_receiver: rx,
+            machine,
             finished: false,
         }
     }
-
-    pub fn drain(&mut self) {
-        for _ in self.items_receiver.iter() {}
-    }
-}
-
-impl Drop for Lexer {
-    fn drop(&mut self) {
-        self.drain();
-    }
 }
 
 impl Iterator for LexerStateMachine {
@@ -193,9 +303,6 @@ impl Iterator for LexerStateMachine {
             Some(c) => {
                 self.width = c.len_utf8();
                 self.pos += self.width;
-                if c == '\n' {
-                    self.line += 1;
-                }
                 Some(c)
             }
             None => {
@@ -207,40 +314,30 @@ impl Iterator for LexerStateMachine {
 }
 
 impl LexerStateMachine {
-    fn run(&mut self) {
-        loop {
-            self.state = match self.state {
-                State::LexText => self.lex_text(),
-                State::LexComment => self.lex_comment(),
-                State::LexLeftDelim => self.lex_left_delim(),
-                State::LexRightDelim => self.lex_right_delim(),
-                State::LexInsideAction => self.lex_inside_action(),
-                State::LexSpace => self.lex_space(),
-                State::LexIdentifier => self.lex_identifier(),
-                State::LexField => self.lex_field(),
-                State::LexVariable => self.lex_variable(),
-                State::LexChar => self.lex_char(),
-                State::LexNumber => self.lex_number(),
-                State::LexQuote => self.lex_quote(),
-                State::LexRawQuote => self.lex_raw_quote(),
-                State::End => {
-                    return;
-                }
-            }
-        }
+    /// Runs the current state, which queues up to a few items. Returns `false` once lexing
+    /// has ended.
+    fn step(&mut self) -> bool {
+        self.state = match self.state {
+            State::LexText => self.lex_text(),
+            State::LexComment => self.lex_comment(),
+            State::LexLeftDelim => self.lex_left_delim(),
+            State::LexRightDelim => self.lex_right_delim(),
+            State::LexInsideAction => self.lex_inside_action(),
+            State::LexSpace => self.lex_space(),
+            State::LexIdentifier => self.lex_identifier(),
+            State::LexField => self.lex_field(),
+            State::LexVariable => self.lex_variable(),
+            State::LexChar => self.lex_char(),
+            State::LexNumber => self.lex_number(),
+            State::LexQuote => self.lex_quote(),
+            State::LexRawQuote => self.lex_raw_quote(),
+            State::End => return false,
+        };
+        true
     }
 
     fn backup(&mut self) {
-        self.pos -= 1;
-        if self.width == 1
-            && self.input[self.pos..]
-                .chars()
-                .next()
-                .and_then(|c| if c == '\n' { Some(()) } else { None })
-                .is_some()
-        {
-            self.line -= 1;
-        }
+        self.pos -= self.width;
     }
 
     fn peek(&mut self) -> Option<char> {
@@ -250,21 +347,28 @@ impl LexerStateMachine {
     }
 
     fn emit(&mut self, t: ItemType) {
-        let s = &self.input[self.start..self.pos];
-        let lines = match t {
-            ItemType::ItemText
-            | ItemType::ItemRawString
-            | ItemType::ItemLeftDelim
-            | ItemType::ItemRightDelim => 1,
-            _ => s.chars().filter(|c| *c == '\n').count(),
-        };
-        self.items_sender
-            .send(Item::new(t, self.start, s, self.line))
-            .unwrap();
-        self.line += lines;
+        let (line, col) = self.position(self.start);
+        let val = StrSpan::new(self.input.clone(), self.start, self.pos);
+        self.send(Item::new(t, self.start, val, line, col));
         self.start = self.pos;
     }
 
+    /// Returns line and column (counted in chars, both starting at 1) of `pos`. Items are emitted
+    /// in order, so newlines only have to be counted once.
+    fn position(&mut self, pos: Pos) -> (usize, usize) {
+        for (i, c) in self.input[self.line_pos..pos].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.line_pos + i + 1;
+            }
+        }
+        self.line_pos = pos;
+        (
+            self.line,
+            self.input[self.line_start..pos].chars().count() + 1,
+        )
+    }
+
     fn ignore(&mut self) {
         self.start = self.pos;
     }
@@ -282,21 +386,36 @@ impl LexerStateMachine {
     }
 
     fn errorf(&mut self, msg: &str) -> State {
-        self.items_sender
-            .send(Item::new(ItemType::ItemError, self.start, msg, self.line))
-            .unwrap();
+        let (line, col) = self.position(self.start);
+        self.send(Item::new(ItemType::ItemError, self.start, msg, line, col));
         State::End
     }
 
+    // Reports an error about the current action or comment including where it was opened.
+    fn errorf_opened(&mut self, msg: &str) -> State {
+        let (line, col) = self.opened_at;
+        self.errorf(&format!("{} started at {}:{}", msg, line, col))
+    }
+
+    /// Queues an item for the Lexer.
+    fn send(&mut self, item: Item) {
+        self.items.push_back(item);
+    }
+
     fn lex_text(&mut self) -> State {
         self.width = 0;
-        let x = self.input[self.pos..].find(&LEFT_DELIM);
+        let x = self.input[self.pos..].find(self.left_delim.as_str());
         match x {
             Some(x) => {
                 self.pos += x;
-                let ld = self.pos + LEFT_DELIM.len();
-                let trim = if self.input[ld..].starts_with(LEFT_TRIM_MARKER) {
+                let ld = self.pos + self.left_delim.len();
+                self.in_block_action = self.trim_blocks
+                    && is_block_action(&self.input[ld..], &self.right_delim)
+                    && self.at_line_start();
+                let trim = if left_trim_marker_len(&self.input[ld..]) > 0 {
                     rtrim_len(&self.input[self.start..self.pos])
+                } else if self.in_block_action {
+                    self.indent_len()
                 } else {
                     0
                 };
@@ -319,20 +438,24 @@ impl LexerStateMachine {
         }
     }
 
-    fn at_right_delim(&mut self) -> (bool, bool) {
-        if self.input[self.pos..].starts_with(&RIGHT_DELIM) {
-            return (true, false);
+    // Checks for a right delimiter at the current position, returns whether there is one and
+    // the length of the trim marker preceding it.
+    fn at_right_delim(&mut self) -> (bool, usize) {
+        let rest = &self.input[self.pos..];
+        if rest.starts_with(self.right_delim.as_str()) {
+            return (true, 0);
         }
-        if self.input[self.pos..].starts_with(&format!("{}{}", RIGHT_TRIM_MARKER, RIGHT_DELIM)) {
-            return (true, true);
+        let marker = right_trim_marker_len(rest);
+        if marker > 0 && rest[marker..].starts_with(self.right_delim.as_str()) {
+            return (true, marker);
         }
-        (false, false)
+        (false, 0)
     }
 
     fn lex_left_delim(&mut self) -> State {
-        self.pos += LEFT_DELIM.len();
-        let trim = self.input[self.pos..].starts_with(LEFT_TRIM_MARKER);
-        let after_marker = if trim { LEFT_TRIM_MARKER.len() } else { 0 };
+        self.opened_at = self.position(self.pos);
+        self.pos += self.left_delim.len();
+        let after_marker = left_trim_marker_len(&self.input[self.pos..]);
         if self.input[(self.pos + after_marker)..].starts_with(LEFT_COMMENT) {
             self.pos += after_marker;
             self.ignore();
@@ -351,24 +474,20 @@ impl LexerStateMachine {
         let i = match self.input[self.pos..].find(RIGHT_COMMENT) {
             Some(i) => i,
             None => {
-                return self.errorf("unclosed comment");
+                return self.errorf_opened("unclosed comment");
             }
         };
 
         self.pos += i + RIGHT_COMMENT.len();
-        let (delim, trim) = self.at_right_delim();
+        let (delim, marker) = self.at_right_delim();
 
         if !delim {
             return self.errorf("comment end before closing delimiter");
         }
 
-        if trim {
-            self.pos += RIGHT_TRIM_MARKER.len();
-        }
-
-        self.pos += RIGHT_DELIM.len();
+        self.pos += marker + self.right_delim.len();
 
-        if trim {
+        if marker > 0 {
             self.pos += ltrim_len(&self.input[self.pos..]);
         }
 
@@ -377,20 +496,43 @@ impl LexerStateMachine {
     }
 
     fn lex_right_delim(&mut self) -> State {
-        let trim = self.input[self.pos..].starts_with(RIGHT_TRIM_MARKER);
+        let marker = right_trim_marker_len(&self.input[self.pos..]);
+        let trim = marker > 0;
         if trim {
-            self.pos += RIGHT_TRIM_MARKER.len();
+            self.pos += marker;
             self.ignore();
         }
-        self.pos += RIGHT_DELIM.len();
+        self.pos += self.right_delim.len();
         self.emit(ItemType::ItemRightDelim);
         if trim {
             self.pos += ltrim_len(&self.input[self.pos..]);
             self.ignore();
+        } else if self.in_block_action {
+            let rest = &self.input[self.pos..];
+            self.pos += ["\r\n", "\n"]
+                .iter()
+                .find(|nl| rest.starts_with(*nl))
+                .map_or(0, |nl| nl.len());
+            self.ignore();
         }
+        self.in_block_action = false;
         State::LexText
     }
 
+    // The length of the spaces and tabs right before the current position.
+    fn indent_len(&self) -> usize {
+        let line = &self.input[..self.pos];
+        line.len() - line.trim_end_matches([' ', '\t']).len()
+    }
+
+    // Checks if only spaces and tabs precede the current position on its line.
+    fn at_line_start(&self) -> bool {
+        let line_start = self.pos - self.indent_len();
+        let before = &self.input[..line_start];
+        line_start >= self.start
+            && (before.trim_start_matches(BOM).is_empty() || before.ends_with('\n'))
+    }
+
     fn lex_inside_action(&mut self) -> State {
         let (delim, _) = self.at_right_delim();
         if delim {
@@ -401,7 +543,7 @@ impl LexerStateMachine {
         }
 
         match self.next() {
-            None | Some('\r') | Some('\n') => self.errorf("unclosed action"),
+            None | Some('\r') | Some('\n') => self.errorf_opened("unclosed action"),
             Some(c) => {
                 match c {
                     '"' => State::LexQuote,
@@ -433,7 +575,7 @@ impl LexerStateMachine {
                         State::LexInsideAction
                     }
                     '.' => match self.input[self.pos..].chars().next() {
-                        Some('0'..='9') => {
+                        Some('0'..='9') if !self.numeric_fields => {
                             self.backup();
                             State::LexNumber
                         }
@@ -513,12 +655,15 @@ impl LexerStateMachine {
         match self.peek() {
             Some(c) => {
                 match c {
-                    '.' | ',' | '|' | ':' | ')' | '(' | ' ' | '\t' | '\r' | '\n' => true,
+                    '.' | ',' | '|' | ':' | ')' | '(' | '[' | ']' | ' ' | '\t' | '\r' | '\n' => {
+                        true
+                    }
                     // this is what golang does to detect a delimiter
-                    _ => RIGHT_DELIM.starts_with(c),
+                    _ => self.right_delim.starts_with(c),
                 }
             }
-            None => false,
+            // Reported as unclosed action by `lex_inside_action`.
+            None => true,
         }
     }
 
@@ -573,7 +718,7 @@ impl LexerStateMachine {
             }
         }
         // Let's ignore imaginary numbers for now.
-        if self.peek().map(|c| c.is_alphanumeric()).unwrap_or(true) {
+        if self.peek().is_some_and(|c| c.is_alphanumeric()) {
             self.next();
             return false;
         }
@@ -604,9 +749,7 @@ impl LexerStateMachine {
     }
 
     fn lex_raw_quote(&mut self) -> State {
-        let start_line = self.line;
         if !self.any(|c| c == '`') {
-            self.line = start_line;
             return self.errorf("unterminated raw quoted string");
         }
         self.emit(ItemType::ItemRawString);
@@ -614,9 +757,59 @@ impl LexerStateMachine {
     }
 }
 
+// Checks if the action following a left delimiter starts with a keyword opening or closing
+// a block.
+fn is_block_action(action: &str, right_delim: &str) -> bool {
+    let action = &action[left_trim_marker_len(action)..];
+    let action = action.trim_start();
+    let end = action
+        .char_indices()
+        .find(|&(i, c)| {
+            c.is_whitespace() || c == ')' || c == '-' || action[i..].starts_with(right_delim)
+        })
+        .map_or(action.len(), |(i, _)| i);
+    ["block", "define", "else", "end", "if", "range", "with"].contains(&&action[..end])
+}
+
+// The length of a trim marker at the start of an action: a `-` followed by a space, tab or
+// line break, where `\r\n` counts as one line break.
+fn left_trim_marker_len(s: &str) -> usize {
+    match s.strip_prefix(TRIM_MARKER) {
+        Some(rest) => match space_len(rest) {
+            0 => 0,
+            n => n + 1,
+        },
+        None => 0,
+    }
+}
+
+// The length of a trim marker at the end of an action: a space, tab or line break followed by
+// a `-`.
+fn right_trim_marker_len(s: &str) -> usize {
+    match space_len(s) {
+        0 => 0,
+        n if s[n..].starts_with(TRIM_MARKER) => n + 1,
+        _ => 0,
+    }
+}
+
+fn space_len(s: &str) -> usize {
+    if s.starts_with("\r\n") {
+        return 2;
+    }
+    match s.chars().next() {
+        Some(' ' | '\t' | '\r' | '\n') => 1,
+        _ => 0,
+    }
+}
+
+fn or_default(delim: &str, default: &str) -> String {
+    if delim.is_empty() { default } else { delim }.to_owned()
+}
+
 fn rtrim_len(s: &str) -> usize {
-    match s.rfind(|c: char| !c.is_whitespace()) {
-        Some(i) => s.len() - 1 - i,
+    match s.char_indices().rev().find(|(_, c)| !c.is_whitespace()) {
+        Some((i, c)) => s.len() - i - c.len_utf8(),
         None => s.len(),
     }
 }
@@ -635,7 +828,27 @@ mod tests {
         let mut l = Lexer::new("abc".to_owned());
         let i1 = l.next().unwrap();
         assert_eq!(i1.typ, ItemType::ItemText);
-        assert_eq!(&i1.val, "abc");
+        assert_eq!(i1.val, "abc");
+    }
+
+    #[test]
+    fn test_str_span() {
+        let mut l = Lexer::new("abc{{ .foo }}".to_owned());
+        let text = l.next().unwrap();
+        let field = l.nth(2).unwrap();
+        assert_eq!(field.typ, ItemType::ItemField);
+        assert_eq!(field.val, ".foo");
+        assert!(Arc::ptr_eq(&text.val.src, &field.val.src));
+    }
+
+    #[test]
+    fn test_lazy() {
+        let mut l = Lexer::new("a {{ .b }} c {{ d }}");
+        assert_eq!(l.next().unwrap().val, "a ");
+        assert_eq!(l.machine.pos, 2);
+        assert_eq!(l.nth(2).unwrap().val, ".b");
+        assert_eq!(l.machine.pos, 7);
+        assert_eq!(l.count(), 9);
     }
 
     #[test]
@@ -649,7 +862,10 @@ mod tests {
     fn test_whitespace() {
         let s = r#"something {{  .foo  }}"#;
         let l = Lexer::new(s.to_owned());
-        let s_ = l.map(|i| i.val).collect::<Vec<String>>().join("");
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
         assert_eq!(s_, s);
     }
 
@@ -657,7 +873,10 @@ mod tests {
     fn test_input() {
         let s = r#"something {{ .foo }}"#;
         let l = Lexer::new(s.to_owned());
-        let s_ = l.map(|i| i.val).collect::<Vec<String>>().join("");
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
         assert_eq!(s_, s);
     }
 
@@ -665,7 +884,10 @@ mod tests {
     fn test_underscore() {
         let s = r#"something {{ .foo_bar }}"#;
         let l = Lexer::new(s.to_owned());
-        let s_ = l.map(|i| i.val).collect::<Vec<String>>().join("");
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
         assert_eq!(s_, s);
     }
 
@@ -673,15 +895,211 @@ mod tests {
     fn test_trim() {
         let s = r#"something {{- .foo -}} 2000"#;
         let l = Lexer::new(s.to_owned());
-        let s_ = l.map(|i| i.val).collect::<Vec<String>>().join("");
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
         assert_eq!(s_, r#"something{{.foo}}2000"#);
     }
 
+    #[test]
+    fn test_delims() {
+        let s = "{{ a }}<% .foo -%> b %><%/* c */%>";
+        let l = Lexer::with_options(s, "<%", "%>", false, false);
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
+        assert_eq!(s_, "{{ a }}<% .foo%>b %>");
+    }
+
+    #[test]
+    fn test_trim_blocks() {
+        let s = "a:\n  {{ if .x }}\r\n  b {{ end }}\n\t{{- else }}\n{{ .y }}\n  {{ end }}c";
+        let l = Lexer::with_options(s, "", "", true, false);
+        let s_ = l
+            .filter(|i| i.typ != ItemType::ItemSpace)
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
+        assert_eq!(s_, "a:\n{{if.x}}  b {{end}}{{else}}{{.y}}\n{{end}}c");
+
+        let s = "a:\n  {{ end_x }}\n  {{ if_x }}\n  {{end}}\n  <%else%>\n";
+        let text = |l: Lexer| {
+            l.filter(|i| i.typ == ItemType::ItemText)
+                .map(|i| i.val.to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            text(Lexer::with_options(s, "", "", true, false)),
+            vec!["a:\n  ", "\n  ", "\n", "  <%else%>\n"]
+        );
+        assert_eq!(
+            text(Lexer::with_options(
+                "x{{ if . }}y{{ end }}\nz",
+                "",
+                "",
+                true,
+                false
+            )),
+            vec!["x", "y", "\nz"]
+        );
+        assert_eq!(
+            text(Lexer::with_options(
+                "key: {{ if . }}\n  val\n",
+                "",
+                "",
+                true,
+                false
+            )),
+            vec!["key: ", "\n  val\n"]
+        );
+        assert_eq!(
+            text(Lexer::with_options(s, "<%", "%>", true, false)),
+            vec!["a:\n  {{ end_x }}\n  {{ if_x }}\n  {{end}}\n"]
+        );
+    }
+
+    #[test]
+    fn test_numeric_fields() {
+        let s = "{{ .a.0.b $.1 $x.2 (.c).3 .4 1.5 }}";
+        let l = Lexer::with_options(s, "", "", false, true);
+        let fields: Vec<_> = l
+            .filter(|i| i.typ == ItemType::ItemField || i.typ == ItemType::ItemNumber)
+            .map(|i| format!("{:?} {}", i.typ, i.val))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "ItemField .a",
+                "ItemField .0",
+                "ItemField .b",
+                "ItemField .1",
+                "ItemField .2",
+                "ItemField .c",
+                "ItemField .3",
+                "ItemField .4",
+                "ItemNumber 1.5",
+            ]
+        );
+        let l = Lexer::with_options("{{ .a.0 }}", "", "", false, false);
+        assert!(l.map(|i| i.typ).any(|t| t == ItemType::ItemNumber));
+    }
+
+    #[test]
+    fn test_multibyte() {
+        let s = "ü {{- .foo -}} ü";
+        let l = Lexer::new(s.to_owned());
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
+        assert_eq!(s_, "ü{{.foo}}ü");
+        let l = Lexer::new("{{ .fü→ }}".to_owned());
+        let last = l.last().unwrap();
+        assert_eq!(last.typ, ItemType::ItemError);
+        let l = Lexer::new("{{ 1é }}".to_owned());
+        let last = l.last().unwrap();
+        assert_eq!(last.typ, ItemType::ItemError);
+    }
+
+    #[test]
+    fn test_positions() {
+        let l = Lexer::new("ä\nb{{ .x }}c\n{{/* c\n */}}{{ `r\ns` 'é' }}".to_owned());
+        let items: Vec<_> = l
+            .map(|i| (i.span(), i.typ, i.line, i.col))
+            .filter(|(_, typ, ..)| *typ != ItemType::ItemSpace)
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (Span::new(0, 4), ItemType::ItemText, 1, 1),
+                (Span::new(4, 6), ItemType::ItemLeftDelim, 2, 2),
+                (Span::new(7, 9), ItemType::ItemField, 2, 5),
+                (Span::new(10, 12), ItemType::ItemRightDelim, 2, 8),
+                (Span::new(12, 14), ItemType::ItemText, 2, 10),
+                (Span::new(26, 28), ItemType::ItemLeftDelim, 4, 6),
+                (Span::new(29, 34), ItemType::ItemRawString, 4, 9),
+                (Span::new(35, 39), ItemType::ItemCharConstant, 5, 4),
+                (Span::new(40, 42), ItemType::ItemRightDelim, 5, 8),
+                (Span::new(42, 42), ItemType::ItemEOF, 5, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_windows_newlines() {
+        let s = "\u{feff}{{ .a }}\r\n{{- .b\r\n-}}\r\n\tc{{-\r\n.d -}}\r\n";
+        let items: Vec<_> = Lexer::new(s)
+            .filter(|i| i.typ != ItemType::ItemSpace)
+            .map(|i| (i.val.to_string(), i.line, i.col))
+            .collect();
+        let expected = [
+            ("{{", 1, 1),
+            (".a", 1, 4),
+            ("}}", 1, 7),
+            ("{{", 2, 1),
+            (".b", 2, 5),
+            ("}}", 3, 2),
+            ("c", 4, 2),
+            ("{{", 4, 3),
+            (".d", 5, 1),
+            ("}}", 5, 5),
+            ("", 6, 1),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(val, line, col)| (val.to_owned(), line, col))
+            .collect();
+        assert_eq!(items, expected);
+        let trimmed =
+            Lexer::with_options("\u{feff}  {{ if 1 }}\r\na{{ end }}", "", "", true, false)
+                .map(|i| i.val.to_string())
+                .collect::<String>();
+        assert_eq!(trimmed, "{{ if 1 }}a{{ end }}");
+    }
+
+    #[test]
+    fn test_unclosed() {
+        let error = |s: &str| {
+            let last = Lexer::new(s.to_owned()).last().unwrap();
+            assert_eq!(last.typ, ItemType::ItemError);
+            (last.val.to_string(), last.line)
+        };
+        assert_eq!(
+            error("a\n  {{ if .x\n}}"),
+            ("unclosed action started at 2:3".to_owned(), 2)
+        );
+        assert_eq!(
+            error("a\r\n  {{ if .x\r\n}}"),
+            ("unclosed action started at 2:3".to_owned(), 2)
+        );
+        for src in ["{{ .y", "{{ $x", "{{ if", "{{ 1", "{{ 1.5e3", "{{ .x.y"] {
+            assert_eq!(
+                error(src),
+                ("unclosed action started at 1:1".to_owned(), 1),
+                "{}",
+                src
+            );
+        }
+        assert_eq!(
+            error("a\n{{ .x }}\n{{ .y"),
+            ("unclosed action started at 3:1".to_owned(), 3)
+        );
+        assert_eq!(
+            error("{{ 1 }}\nä {{- /* x"),
+            ("unclosed comment started at 2:3".to_owned(), 2)
+        );
+    }
+
     #[test]
     fn test_comment() {
         let s = r#"something {{- /* foo */ -}} 2000"#;
         let l = Lexer::new(s.to_owned());
-        let s_ = l.map(|i| i.val).collect::<Vec<String>>().join("");
+        let s_ = l
+            .map(|i| i.val.to_string())
+            .collect::<Vec<String>>()
+            .join("");
         assert_eq!(s_, r#"something2000"#);
     }
 }
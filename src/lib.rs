@@ -7,32 +7,93 @@
 //! let output = gtmpl::template("Finally! Some {{ . }} for Rust", "gtmpl");
 //! assert_eq!(&output.unwrap(), "Finally! Some gtmpl for Rust");
 //! ```
+#![allow(clippy::result_large_err)]
+
+#[cfg(all(
+    target_arch = "wasm32",
+    target_os = "unknown",
+    any(feature = "gtmpl_os", feature = "notify")
+))]
+compile_error!("`gtmpl_os` and `notify` need a file system, which wasm32-unknown-unknown lacks");
+
+pub mod analysis;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod coerce;
+mod completion;
 pub mod error;
+pub mod escape;
 mod exec;
+pub mod filter;
 pub mod funcs;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
 mod lexer;
-mod node;
+mod macros;
+pub mod node;
 mod parse;
+mod path;
 mod print_verb;
 mod printf;
+pub mod profile;
 mod template;
+mod template_set;
+pub mod testing;
 mod utils;
+#[cfg(feature = "notify")]
+mod watch;
+
+#[doc(inline)]
+pub use crate::template::{
+    Collision, Defined, ExtensionFunc, Formatter, GoVersion, PostFilter, Template,
+};
+
+#[doc(inline)]
+pub use crate::parse::Tree;
+
+#[doc(inline)]
+pub use crate::builder::{MissingKey, TemplateBuilder};
+
+#[doc(inline)]
+pub use crate::template_set::TemplateSet;
+
+#[doc(inline)]
+pub use crate::profile::{ExecHook, Profiler};
+
+#[cfg(feature = "notify")]
+#[doc(inline)]
+pub use crate::watch::TemplateWatcher;
 
 #[doc(inline)]
-pub use crate::template::Template;
+pub use crate::exec::{eval_expr, Context, ExecContext, ExecOptions, Segment, SegmentKind, Truth};
 
+#[cfg(feature = "gtmpl_json")]
 #[doc(inline)]
-pub use crate::exec::Context;
+pub use crate::exec::from_json;
 
 #[doc(inline)]
 pub use gtmpl_value::Func;
 
+#[doc(inline)]
+pub use crate::funcs::{ContextFunc, FuncRegistry, IterFunc};
+
+#[doc(inline)]
+pub use crate::node::Span;
+
+#[doc(inline)]
+pub use crate::completion::{Completion, CompletionKind};
+
+#[doc(inline)]
+pub use crate::path::{lookup_field, lookup_path};
+
 pub use gtmpl_value::FuncError;
 
 #[doc(inline)]
 pub use gtmpl_value::from_value;
 
-pub use error::TemplateError;
+pub use error::{FuncResultExt, TemplateError};
 pub use gtmpl_value::Value;
 
 /// Provides simple basic templating given just a template sting and context.
@@ -0,0 +1,78 @@
+/// Creates a [`Value::Map`](crate::Value::Map) from `key => value` pairs. Keys are converted
+/// with `String::from`, values with `Value::from`, so maps and lists can be nested.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{gtmpl_list, gtmpl_map, Context, Template};
+///
+/// let data = gtmpl_map! {
+///     "name" => "app",
+///     "ports" => gtmpl_list![80, 443],
+///     "env" => gtmpl_map! { "DEBUG" => false },
+/// };
+/// let mut tmpl = Template::default();
+/// tmpl.parse("{{ .name }} {{ index .ports 1 }} {{ .env.DEBUG }}").unwrap();
+/// assert_eq!(&tmpl.render(&Context::from(data)).unwrap(), "app 443 false");
+/// ```
+#[macro_export]
+macro_rules! gtmpl_map {
+    () => {
+        $crate::Value::Map(::std::collections::HashMap::new())
+    };
+    ($($key:expr => $value:expr),+ $(,)?) => {{
+        let mut map = ::std::collections::HashMap::new();
+        $(map.insert(::std::string::String::from($key), $crate::Value::from($value));)+
+        $crate::Value::Map(map)
+    }};
+}
+
+/// Creates a [`Value::Array`](crate::Value::Array) of values of possibly different types, each
+/// converted with `Value::from`.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{gtmpl_list, gtmpl_map, FuncError, Value};
+///
+/// fn pair(args: &[Value]) -> Result<Value, FuncError> {
+///     Ok(gtmpl_list![args.len(), "args", gtmpl_map! { "first" => args[0].clone() }])
+/// }
+///
+/// let list = pair(&[Value::from(1)]).unwrap();
+/// let tmpl = r#"{{ index . 0 }} {{ index . 1 }} {{ (index . 2).first }}"#;
+/// assert_eq!(&gtmpl::template(tmpl, list).unwrap(), "1 args 1");
+/// ```
+#[macro_export]
+macro_rules! gtmpl_list {
+    ($($value:expr),* $(,)?) => {
+        $crate::Value::Array(::std::vec![$($crate::Value::from($value)),*])
+    };
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use std::collections::HashMap;
+
+    use crate::Value;
+
+    #[test]
+    fn test_gtmpl_map() {
+        assert_eq!(gtmpl_map! {}, Value::Map(HashMap::new()));
+        let key = String::from("b");
+        let map = gtmpl_map! { "a" => 1, key => gtmpl_list![Value::Nil] };
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), Value::from(1));
+        expected.insert("b".to_owned(), Value::Array(vec![Value::Nil]));
+        assert_eq!(map, Value::Map(expected));
+    }
+
+    #[test]
+    fn test_gtmpl_list() {
+        assert_eq!(gtmpl_list![], Value::Array(vec![]));
+        assert_eq!(
+            gtmpl_list![1, "a", 2.5,],
+            Value::Array(vec![Value::from(1), Value::from("a"), Value::from(2.5)])
+        );
+    }
+}
@@ -1,8 +1,14 @@
+//! The syntax tree of a parsed template.
+//!
+//! Every node carries the [`Span`] of the source text it was parsed from. Actions and control
+//! structures include their delimiters, e.g. the span of an `if` node reaches from its `{{` to
+//! the end of the matching `{{end}}`.
 use std::fmt::{Display, Formatter};
 
 use crate::error::NodeError;
-use crate::lexer::ItemType;
-use crate::utils::unquote_char;
+pub use crate::lexer::Span;
+use crate::lexer::{ItemType, StrSpan};
+use crate::utils::{ensure_stack, unquote_char};
 
 use gtmpl_value::Value;
 
@@ -35,9 +41,9 @@ macro_rules! nodes {
                     $(Nodes::$name(ref t) => t.typ(),)*
                 }
             }
-            pub fn pos(&self) -> Pos {
+            pub fn span(&self) -> Span {
                 match *self {
-                    $(Nodes::$name(ref t) => t.pos(),)*
+                    $(Nodes::$name(ref t) => t.span(),)*
                 }
             }
             pub fn tree(&self) -> TreeId {
@@ -89,7 +95,13 @@ nodes!(
     RangeNode,
     Range,
     TemplateNode,
-    Template
+    Template,
+    ExtensionNode,
+    Extension,
+    BreakNode,
+    Break,
+    ContinueNode,
+    Continue
 );
 
 pub type Pos = usize;
@@ -98,7 +110,8 @@ pub type TreeId = usize;
 
 pub trait Node: Display {
     fn typ(&self) -> &NodeType;
-    fn pos(&self) -> Pos;
+    /// Byte range of the node in the source of its template.
+    fn span(&self) -> Span;
     fn tree(&self) -> TreeId;
 }
 
@@ -110,7 +123,7 @@ macro_rules! node {
         #[derive(Debug)]
         pub struct $name {
             typ: NodeType,
-            pos: Pos,
+            span: Span,
             tr: TreeId,
             $(pub $field: $typ,)*
         }
@@ -118,13 +131,19 @@ macro_rules! node {
             fn typ(&self) -> &NodeType {
                 &self.typ
             }
-            fn pos(&self) -> Pos {
-                self.pos
+            fn span(&self) -> Span {
+                self.span
             }
             fn tree(&self) -> TreeId {
                 self.tr
             }
         }
+        impl $name {
+            pub fn set_span(&mut self, span: Span) -> &mut $name {
+                self.span = span;
+                self
+            }
+        }
     }
 }
 
@@ -132,15 +151,80 @@ impl Nodes {
     pub fn is_empty_tree(&self) -> Result<bool, NodeError> {
         match *self {
             Nodes::List(ref n) => n.is_empty_tree(),
-            Nodes::Text(ref n) => Ok(n.text.is_empty()),
+            Nodes::Text(ref n) => Ok(n.text.trim().is_empty()),
             Nodes::Action(_)
             | Nodes::If(_)
             | Nodes::Range(_)
             | Nodes::Template(_)
-            | Nodes::With(_) => Ok(false),
+            | Nodes::With(_)
+            | Nodes::Extension(_)
+            | Nodes::Break(_)
+            | Nodes::Continue(_) => Ok(false),
             _ => Err(NodeError::NaTN),
         }
     }
+
+    /// Returns the innermost node whose span contains `offset` (the end is inclusive, so a cursor
+    /// right after an identifier still hits it).
+    pub fn node_at(&self, offset: Pos) -> Option<&Nodes> {
+        let span = self.span();
+        if offset < span.start || offset > span.end {
+            return None;
+        }
+        self.children()
+            .into_iter()
+            .find_map(|n| n.node_at(offset))
+            .or(Some(self))
+    }
+
+    /// Direct child nodes. Pipelines and commands are flattened into their arguments.
+    pub fn children(&self) -> Vec<&Nodes> {
+        fn pipe_args(pipe: &PipeNode) -> impl Iterator<Item = &Nodes> {
+            pipe.cmds.iter().flat_map(|c| c.args.iter())
+        }
+        match *self {
+            Nodes::List(ref n) => n.nodes.iter().collect(),
+            Nodes::Action(ref n) => pipe_args(&n.pipe).collect(),
+            Nodes::Pipe(ref n) => pipe_args(n).collect(),
+            Nodes::Command(ref n) => n.args.iter().collect(),
+            Nodes::Chain(ref n) => vec![&*n.node],
+            Nodes::If(ref n) | Nodes::Range(ref n) | Nodes::With(ref n) => pipe_args(&n.pipe)
+                .chain(n.list.nodes.iter())
+                .chain(n.else_list.iter().flat_map(|l| l.nodes.iter()))
+                .collect(),
+            Nodes::Template(ref n) => {
+                let name = match n.name {
+                    PipeOrString::Pipe(ref p) => Some(p),
+                    PipeOrString::String(_) => None,
+                };
+                name.into_iter()
+                    .chain(n.pipe.iter())
+                    .flat_map(pipe_args)
+                    .chain(n.args.iter())
+                    .collect()
+            }
+            Nodes::Extension(ref n) => n
+                .pipe
+                .iter()
+                .flat_map(pipe_args)
+                .chain(n.list.nodes.iter())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Sum of the lengths of all text nodes within this node.
+    pub fn text_len(&self) -> usize {
+        ensure_stack(|| match *self {
+            Nodes::List(ref n) => n.text_len(),
+            Nodes::Text(ref n) => n.text.len(),
+            Nodes::If(ref n) | Nodes::Range(ref n) | Nodes::With(ref n) => {
+                n.list.text_len() + n.else_list.as_ref().map(|l| l.text_len()).unwrap_or(0)
+            }
+            Nodes::Extension(ref n) => n.list.text_len(),
+            _ => 0,
+        })
+    }
 }
 
 node!(
@@ -151,12 +235,13 @@ node!(
 
 impl ListNode {
     pub fn append(&mut self, n: Nodes) {
+        self.span.end = n.span().end;
         self.nodes.push(n);
     }
-    pub fn new(tr: TreeId, pos: Pos) -> ListNode {
+    pub fn new(tr: TreeId, span: Span) -> ListNode {
         ListNode {
             typ: NodeType::List,
-            pos,
+            span,
             tr,
             nodes: vec![],
         }
@@ -171,28 +256,39 @@ impl ListNode {
         }
         Ok(true)
     }
+    pub fn text_len(&self) -> usize {
+        self.nodes.iter().map(|n| n.text_len()).sum()
+    }
+}
+
+// Nested lists are dropped recursively, grow the stack for deeply nested templates.
+impl Drop for ListNode {
+    fn drop(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        ensure_stack(move || drop(nodes));
+    }
 }
 
 impl Display for ListNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        for n in &self.nodes {
-            if let Err(e) = n.fmt(f) {
-                return Err(e);
+        ensure_stack(|| {
+            for n in &self.nodes {
+                n.fmt(f)?;
             }
-        }
-        Ok(())
+            Ok(())
+        })
     }
 }
 
-node!(TextNode { text: String });
+node!(TextNode { text: StrSpan });
 
 impl TextNode {
-    pub fn new(tr: TreeId, pos: Pos, text: String) -> TextNode {
+    pub fn new<T: Into<StrSpan>>(tr: TreeId, span: Span, text: T) -> TextNode {
         TextNode {
             typ: NodeType::Text,
-            pos,
+            span,
             tr,
-            text,
+            text: text.into(),
         }
     }
 }
@@ -211,17 +307,18 @@ node!(
 );
 
 impl PipeNode {
-    pub fn new(tr: TreeId, pos: Pos, decl: Vec<VariableNode>) -> PipeNode {
+    pub fn new(tr: TreeId, span: Span, decl: Vec<VariableNode>) -> PipeNode {
         PipeNode {
             typ: NodeType::Pipe,
             tr,
-            pos,
+            span,
             decl,
             cmds: vec![],
         }
     }
 
     pub fn append(&mut self, cmd: CommandNode) {
+        self.span.end = cmd.span.end;
         self.cmds.push(cmd);
     }
 }
@@ -258,11 +355,11 @@ impl Display for PipeNode {
 node!(ActionNode { pipe: PipeNode });
 
 impl ActionNode {
-    pub fn new(tr: TreeId, pos: Pos, pipe: PipeNode) -> ActionNode {
+    pub fn new(tr: TreeId, span: Span, pipe: PipeNode) -> ActionNode {
         ActionNode {
             typ: NodeType::Action,
             tr,
-            pos,
+            span,
             pipe,
         }
     }
@@ -281,31 +378,43 @@ node!(
 );
 
 impl CommandNode {
-    pub fn new(tr: TreeId, pos: Pos) -> CommandNode {
+    pub fn new(tr: TreeId, span: Span) -> CommandNode {
         CommandNode {
             typ: NodeType::Command,
-            pos,
+            span,
             tr,
             args: vec![],
         }
     }
 
     pub fn append(&mut self, node: Nodes) {
+        self.span.end = node.span().end;
         self.args.push(node);
     }
 }
 
+// Parenthesized pipelines are nested within the arguments, see `ListNode`.
+impl Drop for CommandNode {
+    fn drop(&mut self) {
+        let args = std::mem::take(&mut self.args);
+        ensure_stack(move || drop(args));
+    }
+}
+
 impl Display for CommandNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        let s = self
-            .args
-            .iter()
-            .map(|n|
-                // Handle PipeNode.
-                n.to_string())
-            .collect::<Vec<String>>()
-            .join(" ");
-        write!(f, "{}", s)
+        ensure_stack(|| {
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(" ")?;
+                }
+                match *arg {
+                    Nodes::Pipe(ref pipe) => write!(f, "({})", pipe)?,
+                    ref arg => write!(f, "{}", arg)?,
+                }
+            }
+            Ok(())
+        })
     }
 }
 
@@ -316,16 +425,11 @@ impl IdentifierNode {
         IdentifierNode {
             typ: NodeType::Identifier,
             tr: 0,
-            pos: 0,
+            span: Span::default(),
             ident,
         }
     }
 
-    pub fn set_pos(&mut self, pos: Pos) -> &IdentifierNode {
-        self.pos = pos;
-        self
-    }
-
     pub fn set_tree(&mut self, tr: TreeId) -> &IdentifierNode {
         self.tr = tr;
         self
@@ -345,11 +449,11 @@ node!(
 );
 
 impl VariableNode {
-    pub fn new(tr: TreeId, pos: Pos, ident: &str) -> VariableNode {
+    pub fn new(tr: TreeId, span: Span, ident: &str) -> VariableNode {
         VariableNode {
             typ: NodeType::Variable,
             tr,
-            pos,
+            span,
             ident: ident.split('.').map(|s| s.to_owned()).collect(),
         }
     }
@@ -364,11 +468,11 @@ impl Display for VariableNode {
 node!(DotNode {});
 
 impl DotNode {
-    pub fn new(tr: TreeId, pos: Pos) -> DotNode {
+    pub fn new(tr: TreeId, span: Span) -> DotNode {
         DotNode {
             typ: NodeType::Dot,
             tr,
-            pos,
+            span,
         }
     }
 }
@@ -388,11 +492,11 @@ impl Display for NilNode {
 }
 
 impl NilNode {
-    pub fn new(tr: TreeId, pos: Pos) -> NilNode {
+    pub fn new(tr: TreeId, span: Span) -> NilNode {
         NilNode {
             typ: NodeType::Nil,
             tr,
-            pos,
+            span,
         }
     }
 }
@@ -404,11 +508,11 @@ node!(
 );
 
 impl FieldNode {
-    pub fn new(tr: TreeId, pos: Pos, ident: &str) -> FieldNode {
+    pub fn new(tr: TreeId, span: Span, ident: &str) -> FieldNode {
         FieldNode {
             typ: NodeType::Field,
             tr,
-            pos,
+            span,
             ident: ident[..]
                 .split('.')
                 .filter_map(|s| {
@@ -425,7 +529,10 @@ impl FieldNode {
 
 impl Display for FieldNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "{}", self.ident.join("."))
+        for ident in &self.ident {
+            write!(f, ".{}", ident)?;
+        }
+        Ok(())
     }
 }
 
@@ -437,17 +544,18 @@ node!(
 );
 
 impl ChainNode {
-    pub fn new(tr: TreeId, pos: Pos, node: Nodes) -> ChainNode {
+    pub fn new(tr: TreeId, span: Span, node: Nodes) -> ChainNode {
         ChainNode {
             typ: NodeType::Chain,
             tr,
-            pos,
+            span,
             node: Box::new(node),
             field: vec![],
         }
     }
 
-    pub fn add(&mut self, val: &str) {
+    pub fn add(&mut self, val: &str, end: Pos) {
+        self.span.end = end;
         let val = val.trim_start_matches('.').to_owned();
         self.field.push(val);
     }
@@ -455,16 +563,12 @@ impl ChainNode {
 
 impl Display for ChainNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        if let Err(e) = {
-            // Handle PipeNode.
-            write!(f, "{}", self.node)
-        } {
-            return Err(e);
+        match *self.node {
+            Nodes::Pipe(ref pipe) => write!(f, "({})", pipe)?,
+            ref node => write!(f, "{}", node)?,
         }
         for field in &self.field {
-            if let Err(e) = write!(f, ".{}", field) {
-                return Err(e);
-            }
+            write!(f, ".{}", field)?;
         }
         Ok(())
     }
@@ -473,11 +577,11 @@ impl Display for ChainNode {
 node!(BoolNode { value: Value });
 
 impl BoolNode {
-    pub fn new(tr: TreeId, pos: Pos, val: bool) -> BoolNode {
+    pub fn new(tr: TreeId, span: Span, val: bool) -> BoolNode {
         BoolNode {
             typ: NodeType::Bool,
             tr,
-            pos,
+            span,
             value: Value::from(val),
         }
     }
@@ -507,10 +611,10 @@ node!(NumberNode {
 });
 
 impl NumberNode {
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::float_cmp))]
+    #[allow(clippy::float_cmp)]
     pub fn new(
         tr: TreeId,
-        pos: Pos,
+        span: Span,
         text: String,
         item_typ: &ItemType,
     ) -> Result<NumberNode, NodeError> {
@@ -519,7 +623,7 @@ impl NumberNode {
                 .map(|c| NumberNode {
                     typ: NodeType::Number,
                     tr,
-                    pos,
+                    span,
                     is_i64: true,
                     is_u64: true,
                     is_f64: true,
@@ -527,7 +631,7 @@ impl NumberNode {
                     number_typ: NumberType::Char,
                     value: Value::from(c as u64),
                 })
-                .ok_or(NodeError::UnquoteError),
+                .map_err(NodeError::from),
             _ => {
                 let mut number_typ = NumberType::Float;
 
@@ -593,7 +697,7 @@ impl NumberNode {
                 Ok(NumberNode {
                     typ: NodeType::Number,
                     tr,
-                    pos,
+                    span,
                     is_i64,
                     is_u64,
                     is_f64,
@@ -618,11 +722,11 @@ node!(StringNode {
 });
 
 impl StringNode {
-    pub fn new(tr: TreeId, pos: Pos, orig: String, text: String) -> StringNode {
+    pub fn new(tr: TreeId, span: Span, orig: String, text: String) -> StringNode {
         StringNode {
             typ: NodeType::String,
             tr,
-            pos,
+            span,
             quoted: orig,
             value: Value::from(text),
         }
@@ -638,11 +742,11 @@ impl Display for StringNode {
 node!(EndNode {});
 
 impl EndNode {
-    pub fn new(tr: TreeId, pos: Pos) -> EndNode {
+    pub fn new(tr: TreeId, span: Span) -> EndNode {
         EndNode {
             typ: NodeType::End,
             tr,
-            pos,
+            span,
         }
     }
 }
@@ -653,14 +757,50 @@ impl Display for EndNode {
     }
 }
 
+node!(BreakNode {});
+
+impl BreakNode {
+    pub fn new(tr: TreeId, span: Span) -> BreakNode {
+        BreakNode {
+            typ: NodeType::Break,
+            tr,
+            span,
+        }
+    }
+}
+
+impl Display for BreakNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{{{{break}}}}")
+    }
+}
+
+node!(ContinueNode {});
+
+impl ContinueNode {
+    pub fn new(tr: TreeId, span: Span) -> ContinueNode {
+        ContinueNode {
+            typ: NodeType::Continue,
+            tr,
+            span,
+        }
+    }
+}
+
+impl Display for ContinueNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{{{{continue}}}}")
+    }
+}
+
 node!(ElseNode {});
 
 impl ElseNode {
-    pub fn new(tr: TreeId, pos: Pos) -> ElseNode {
+    pub fn new(tr: TreeId, span: Span) -> ElseNode {
         ElseNode {
             typ: NodeType::Else,
             tr,
-            pos,
+            span,
         }
     }
 }
@@ -686,7 +826,7 @@ pub type RangeNode = BranchNode;
 impl BranchNode {
     pub fn new_if(
         tr: TreeId,
-        pos: Pos,
+        span: Span,
         pipe: PipeNode,
         list: ListNode,
         else_list: Option<ListNode>,
@@ -694,7 +834,7 @@ impl BranchNode {
         IfNode {
             typ: NodeType::If,
             tr,
-            pos,
+            span,
             pipe,
             list,
             else_list,
@@ -703,7 +843,7 @@ impl BranchNode {
 
     pub fn new_with(
         tr: TreeId,
-        pos: Pos,
+        span: Span,
         pipe: PipeNode,
         list: ListNode,
         else_list: Option<ListNode>,
@@ -711,7 +851,7 @@ impl BranchNode {
         WithNode {
             typ: NodeType::With,
             tr,
-            pos,
+            span,
             pipe,
             list,
             else_list,
@@ -720,7 +860,7 @@ impl BranchNode {
 
     pub fn new_range(
         tr: TreeId,
-        pos: Pos,
+        span: Span,
         pipe: PipeNode,
         list: ListNode,
         else_list: Option<ListNode>,
@@ -728,7 +868,7 @@ impl BranchNode {
         RangeNode {
             typ: NodeType::Range,
             tr,
-            pos,
+            span,
             pipe,
             list,
             else_list,
@@ -760,28 +900,77 @@ impl Display for BranchNode {
 node!(
     TemplateNode {
         name: PipeOrString,
-        pipe: Option<PipeNode>
+        pipe: Option<PipeNode>,
+        args: Vec<Nodes>
     }
 );
 
 impl TemplateNode {
-    pub fn new(tr: TreeId, pos: Pos, name: PipeOrString, pipe: Option<PipeNode>) -> TemplateNode {
+    pub fn new(tr: TreeId, span: Span, name: PipeOrString, pipe: Option<PipeNode>) -> TemplateNode {
         TemplateNode {
             typ: NodeType::Template,
             tr,
-            pos,
+            span,
             name,
             pipe,
+            args: vec![],
         }
     }
 }
 
 impl Display for TemplateNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.name {
+            PipeOrString::String(ref name) => write!(f, "{{{{template {:?}", name)?,
+            PipeOrString::Pipe(ref pipe) => write!(f, "{{{{template ({})", pipe)?,
+        }
+        if let Some(ref pipe) = self.pipe {
+            write!(f, " {}", pipe)?;
+        }
+        for arg in &self.args {
+            match *arg {
+                Nodes::Pipe(ref pipe) => write!(f, " ({})", pipe)?,
+                ref arg => write!(f, " {}", arg)?,
+            }
+        }
+        f.write_str("}}")
+    }
+}
+
+node!(
+    ExtensionNode {
+        name: String,
+        pipe: Option<PipeNode>,
+        list: ListNode
+    }
+);
+
+impl ExtensionNode {
+    pub fn new(
+        tr: TreeId,
+        span: Span,
+        name: String,
+        pipe: Option<PipeNode>,
+        list: ListNode,
+    ) -> ExtensionNode {
+        ExtensionNode {
+            typ: NodeType::Extension,
+            tr,
+            span,
+            name,
+            pipe,
+            list,
+        }
+    }
+}
+
+impl Display for ExtensionNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self.pipe {
-            Some(ref pipe) => write!(f, "{{{{template {} {}}}}}", self.name, pipe),
-            None => write!(f, "{{{{template {}}}}}", self.name),
+            Some(ref pipe) => write!(f, "{{{{{} {}}}}}", self.name, pipe)?,
+            None => write!(f, "{{{{{}}}}}", self.name)?,
         }
+        write!(f, "{}{{{{end}}}}", self.list)
     }
 }
 
@@ -806,16 +995,35 @@ mod tests {
 
     #[test]
     fn test_clone() {
-        let t1 = TextNode::new(1, 0, "foo".to_owned());
+        let t1 = TextNode::new(1, Span::new(0, 3), "foo".to_owned());
         let mut t2 = t1.clone();
-        t2.text = "bar".to_owned();
+        t2.text = "bar".into();
         assert_eq!(t1.to_string(), "foo");
         assert_eq!(t2.to_string(), "bar");
     }
 
+    #[test]
+    fn test_parenthesized() {
+        let span = Span::default();
+        let mut inner = CommandNode::new(1, span);
+        inner.append(Nodes::Identifier(IdentifierNode::new("f".to_owned())));
+        inner.append(Nodes::Dot(DotNode::new(1, span)));
+        let mut pipe = PipeNode::new(1, span, vec![]);
+        pipe.append(inner);
+        let mut chain = ChainNode::new(1, span, Nodes::Pipe(pipe.clone()));
+        chain.add(".a", 0);
+        chain.add(".b", 0);
+        assert_eq!(chain.to_string(), "(f .).a.b");
+        let mut cmd = CommandNode::new(1, span);
+        cmd.append(Nodes::Identifier(IdentifierNode::new("g".to_owned())));
+        cmd.append(Nodes::Pipe(pipe));
+        cmd.append(Nodes::Chain(chain));
+        assert_eq!(cmd.to_string(), "g (f .) (f .).a.b");
+    }
+
     #[test]
     fn test_end() {
-        let t1 = EndNode::new(1, 0);
+        let t1 = EndNode::new(1, Span::new(0, 7));
         assert_eq!(t1.to_string(), "{{end}}");
     }
 }
@@ -1,15 +1,24 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 
-use crate::error::ParseError;
-use crate::lexer::{Item, ItemType, Lexer};
+use crate::error::{NodeError, ParseError};
+use crate::funcs::Arity;
+use crate::lexer::{Item, ItemType, Lexer, Span, StrSpan};
 use crate::node::*;
+use crate::template::GoVersion;
 use crate::utils::*;
 
+// Like Golang's `maxStackDepth`, the deepest nesting of parenthesized pipelines.
+const MAX_PAREN_DEPTH: usize = 10_000;
+
 pub struct Parser {
     name: String,
     pub funcs: HashSet<String>,
+    source: Arc<str>,
     lex: Option<Lexer>,
     line: usize,
+    col: usize,
+    span: Span,
     token: VecDeque<Item>,
     peek_count: usize,
     pub tree_set: HashMap<String, Tree>,
@@ -17,13 +26,39 @@ pub struct Parser {
     tree: Option<Tree>,
     tree_stack: VecDeque<Tree>,
     max_tree_id: TreeId,
+    index_syntax: bool,
+    template_args: bool,
+    allow_undefined_functions: bool,
+    go_version: GoVersion,
+    // Number of `range` bodies being parsed, `break` and `continue` are only allowed within.
+    range_depth: usize,
+    // Number of parenthesized pipelines being parsed.
+    paren_depth: usize,
+    // Errors recovered from, `None` unless parsing with `parse_recovering`.
+    errors: Option<Vec<ParseError>>,
+    // Position of the token the last recovery stopped at, to always make progress.
+    resumed_at: Option<usize>,
+    // Set once the lexer stopped because of an error.
+    lex_failed: bool,
+    arities: HashMap<String, Arity>,
+    extensions: HashSet<String>,
 }
 
+/// A parsed template, i.e. the main template or one defined via `define` or `block`.
+///
+/// Trees are created by parsing, see [`Template::tree`](crate::Template::tree) to inspect
+/// them.
+#[derive(Clone)]
 pub struct Tree {
     name: String,
+    origin: String,
     id: TreeId,
-    pub root: Option<Nodes>,
+    source: Arc<str>,
+    pub(crate) root: Option<Nodes>,
     vars: Vec<String>,
+    line: usize, // position of the name in the defining action
+    col: usize,
+    span: Span,
 }
 
 impl Parser {
@@ -31,8 +66,11 @@ impl Parser {
         Parser {
             name,
             funcs: HashSet::new(),
+            source: Arc::from(""),
             lex: None,
             line: 0,
+            col: 0,
+            span: Span::default(),
             token: VecDeque::new(),
             peek_count: 0,
             tree_set: HashMap::new(),
@@ -40,38 +78,242 @@ impl Parser {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            index_syntax: false,
+            template_args: false,
+            allow_undefined_functions: false,
+            go_version: GoVersion::default(),
+            range_depth: 0,
+            paren_depth: 0,
+            errors: None,
+            resumed_at: None,
+            lex_failed: false,
+            arities: HashMap::new(),
+            extensions: HashSet::new(),
         }
     }
 }
 
 impl Tree {
-    fn new(name: String, id: TreeId) -> Tree {
+    fn new(name: String, origin: String, id: TreeId, source: Arc<str>) -> Tree {
         Tree {
             name,
+            origin,
             id,
+            source,
             root: None,
             vars: vec![],
+            line: 1,
+            col: 1,
+            span: Span::default(),
         }
     }
 
-    pub fn pop_vars(&mut self, n: usize) {
+    fn is_empty(&self) -> Result<bool, NodeError> {
+        self.root.as_ref().map_or(Ok(true), |r| r.is_empty_tree())
+    }
+
+    /// Returns `true` if this tree replaces `old` of the same name. Like in Golang a later
+    /// definition overrides an earlier one, unless it is empty (e.g. the main tree of a text
+    /// containing only definitions).
+    pub(crate) fn replaces(&self, old: Option<&Tree>) -> bool {
+        old.is_none() || !self.is_empty().unwrap_or(false)
+    }
+
+    /// Returns `true` if both this tree and `old` of the same name have a body, i.e. neither
+    /// is just a placeholder for the other.
+    pub(crate) fn clashes(&self, old: Option<&Tree>) -> bool {
+        old.is_some_and(|old| !self.is_empty().unwrap_or(false) && !old.is_empty().unwrap_or(false))
+    }
+
+    pub(crate) fn id(&self) -> TreeId {
+        self.id
+    }
+
+    /// The name of the template.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name the text of this tree was parsed as, like Golang's `ParseName`. For a
+    /// template defined via `define` or `block` this is the template or file it is part of.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// The root node, a [`Nodes::List`], or `None` if the template has no body.
+    pub fn root(&self) -> Option<&Nodes> {
+        self.root.as_ref()
+    }
+
+    /// The variables declared outside of control structures, like `$x` in `{{ $x := 1 }}`, in
+    /// the order of their declaration.
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    /// The span of the name in the `define` or `block` action, empty for the main template.
+    pub fn defined_at(&self) -> Span {
+        self.span
+    }
+
+    /// The template text this tree was parsed from. Node spans index into it.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns `true` if both trees were parsed from the same text.
+    pub fn same_source(&self, other: &Tree) -> bool {
+        Arc::ptr_eq(&self.source, &other.source)
+    }
+
+    pub(crate) fn pop_vars(&mut self, n: usize) {
         self.vars.truncate(n);
     }
 }
 
+/// Settings of a template which change how its texts are parsed.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ParseOptions {
+    pub trim_blocks: bool,
+    pub index_syntax: bool,
+    pub numeric_fields: bool,
+    pub template_args: bool,
+    /// Defers checking that called functions exist to the execution.
+    pub allow_undefined_functions: bool,
+    pub go_version: GoVersion,
+    /// Action delimiters, empty ones mean `{{` and `}}`.
+    pub left_delim: String,
+    pub right_delim: String,
+    /// Argument counts of functions checked at parse time, empty unless parsing strictly.
+    pub arities: HashMap<String, Arity>,
+    /// Keywords of extension blocks like `{{ markdown }}...{{ end }}`.
+    pub extensions: HashSet<String>,
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(name = %name))
+)]
 pub fn parse(
     name: String,
-    text: String,
+    text: impl Into<Arc<str>>,
     funcs: HashSet<String>,
+    options: ParseOptions,
 ) -> Result<HashMap<String, Tree>, ParseError> {
-    let mut p = Parser::new(name);
-    p.funcs = funcs;
-    p.lex = Some(Lexer::new(text));
+    let mut p = Parser::with_options(name, text, funcs, options);
     p.parse_tree()?;
     Ok(p.tree_set)
 }
 
+/// Like `parse`, but recovers from errors at the end of the failed action and returns all of
+/// them. Errors may cause follow-up errors, e.g. an `end` without a matching `if`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(name = %name))
+)]
+pub fn parse_recovering(
+    name: String,
+    text: impl Into<Arc<str>>,
+    funcs: HashSet<String>,
+    options: ParseOptions,
+) -> Result<HashMap<String, Tree>, Vec<ParseError>> {
+    let mut p = Parser::with_options(name, text, funcs, options);
+    p.errors = Some(vec![]);
+    let res = p.parse_tree();
+    let mut errors = p.errors.take().unwrap_or_default();
+    if let Err(e) = res {
+        errors.push(e);
+    }
+    if errors.is_empty() {
+        Ok(p.tree_set)
+    } else {
+        Err(errors)
+    }
+}
+
+// Splits the operands after the first one off the single command of `pipe`, they are the
+// arguments of `{{ template "x" . arg1 arg2 }}`. Function calls like `{{ template "x" f 1 }}`
+// and longer pipelines are left alone.
+fn split_template_args(pipe: &mut PipeNode) -> Vec<Nodes> {
+    match pipe.cmds.as_mut_slice() {
+        [cmd] if pipe.decl.is_empty() && cmd.args.len() > 1 => match cmd.args[0] {
+            Nodes::Identifier(_) => vec![],
+            _ => cmd.args.split_off(1),
+        },
+        _ => vec![],
+    }
+}
+
 impl Parser {
+    fn with_options(
+        name: String,
+        text: impl Into<Arc<str>>,
+        funcs: HashSet<String>,
+        options: ParseOptions,
+    ) -> Parser {
+        let mut p = Parser::new(name);
+        p.funcs = funcs;
+        p.index_syntax = options.index_syntax;
+        p.template_args = options.template_args;
+        p.allow_undefined_functions = options.allow_undefined_functions;
+        p.go_version = options.go_version;
+        p.arities = options.arities;
+        p.extensions = options.extensions;
+        p.source = text.into();
+        p.lex = Some(Lexer::with_options(
+            p.source.clone(),
+            &options.left_delim,
+            &options.right_delim,
+            options.trim_blocks,
+            options.numeric_fields,
+        ));
+        p
+    }
+
+    // When recovering records `e` and skips the rest of the failed action, otherwise returns
+    // `e`. Errors after the lexer failed are follow-up errors and dropped.
+    fn recover(&mut self, e: ParseError) -> Result<(), ParseError> {
+        match self.errors {
+            Some(ref mut errors) if !self.lex_failed => errors.push(e),
+            Some(_) => {}
+            None => return Err(e),
+        }
+        loop {
+            match self.next() {
+                Some(t) if t.typ == ItemType::ItemRightDelim => break,
+                // Text and delimiters never occur inside an action, so the failed one ended.
+                Some(t)
+                    if matches!(
+                        t.typ,
+                        ItemType::ItemLeftDelim | ItemType::ItemText | ItemType::ItemEOF
+                    ) && (t.typ == ItemType::ItemEOF || self.resumed_at != Some(t.pos)) =>
+                {
+                    self.resumed_at = Some(t.pos);
+                    self.backup(t);
+                    break;
+                }
+                // The lexer stops after an error, everything after it is unreliable.
+                Some(t) if t.typ == ItemType::ItemError => {
+                    let e = self.error_msg(&t.val);
+                    if let Some(ref mut errors) = self.errors {
+                        errors.push(e);
+                    }
+                    self.lex_failed = true;
+                }
+                Some(_) => {}
+                None => {
+                    self.lex_failed = true;
+                    let end = self.source.len();
+                    let val = StrSpan::new(self.source.clone(), end, end);
+                    let eof = Item::new(ItemType::ItemEOF, end, val, self.line, self.col);
+                    self.backup(eof);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn next_from_lex(&mut self) -> Option<Item> {
         match self.lex {
             Some(ref mut l) => l.next(),
@@ -135,12 +377,14 @@ impl Parser {
         self.error(&format!("unexpected end in {}", context))
     }
 
-    fn start_parse(&mut self, name: String, id: TreeId) {
+    // Starts a new tree. Every tree gets a fresh id, trees are looked up by name only.
+    fn start_parse(&mut self, name: String) {
         if let Some(t) = self.tree.take() {
             self.tree_stack.push_back(t);
         }
-        self.tree_id = id;
-        let t = Tree::new(name, id);
+        self.max_tree_id += 1;
+        self.tree_id = self.max_tree_id;
+        let t = Tree::new(name, self.name.clone(), self.tree_id, self.source.clone());
         self.tree = Some(t);
     }
 
@@ -151,10 +395,19 @@ impl Parser {
         Ok(())
     }
 
+    // Records the position of the name `token` of the current tree's `define` or `block`.
+    fn set_defined_at(&mut self, token: &Item) {
+        if let Some(tree) = self.tree.as_mut() {
+            tree.line = token.line;
+            tree.col = token.col;
+            tree.span = token.span();
+        }
+    }
+
     // top level parser
     fn parse_tree(&mut self) -> Result<(), ParseError> {
         let name = self.name.clone();
-        self.start_parse(name, 1);
+        self.start_parse(name);
         self.parse()?;
         self.stop_parse()?;
         Ok(())
@@ -174,7 +427,7 @@ impl Parser {
         } else {
             &self.name
         };
-        ParseError::with_context(name, self.line, msg)
+        ParseError::with_context(name, &self.name, self.line, self.col, self.span, msg)
     }
 
     fn expect(&mut self, expected: &ItemType, context: &str) -> Result<Item, ParseError> {
@@ -200,19 +453,22 @@ impl Parser {
         Ok(())
     }
 
+    // Adds the finished tree like Golang does: empty trees never replace defined ones and
+    // defining a template twice in one text is an error.
     fn add_to_tree_set(&mut self) -> Result<(), ParseError> {
         let tree = self.tree.take().ok_or_else(|| self.error_msg("no tree"))?;
-        if let Some(t) = self.tree_set.get(tree.name.as_str()) {
-            if let Some(ref r) = t.root {
-                match r.is_empty_tree() {
-                    Err(e) => return Err(e.into()),
-                    Ok(false) => {
-                        let err =
-                            format!("template multiple definitions of template {}", &tree.name);
-                        return self.error(&err);
-                    }
-                    Ok(true) => {}
+        if let Some(old) = self.tree_set.get(tree.name.as_str()) {
+            if !old.is_empty()? {
+                if tree.is_empty()? {
+                    return Ok(());
                 }
+                let msg = format!(
+                    "template multiple definitions of template {}, first defined at {}:{}:{}",
+                    tree.name, self.name, old.line, old.col
+                );
+                return Err(ParseError::with_context(
+                    &self.name, &self.name, tree.line, tree.col, tree.span, msg,
+                ));
             }
         }
         self.add_tree(tree.name.clone(), tree);
@@ -223,6 +479,35 @@ impl Parser {
         self.funcs.contains(name)
     }
 
+    // A namespace is the part before the last `.` of a namespaced function like `strings.upper`.
+    fn has_namespace(&self, name: &str) -> bool {
+        self.funcs.iter().any(|f| {
+            f.len() > name.len() && f.starts_with(name) && f[name.len()..].starts_with('.')
+        })
+    }
+
+    // Joins an identifier with the following fields as long as they name a namespaced function.
+    fn namespaced_identifier(&mut self, ident: &Item) -> (String, Span) {
+        let mut name = ident.val.to_string();
+        let mut span = ident.span();
+        while self.has_namespace(&name) {
+            let (field, end) = match self.peek() {
+                Some(item) if item.typ == ItemType::ItemField => {
+                    (item.val.to_string(), item.span().end)
+                }
+                _ => break,
+            };
+            let joined = format!("{}{}", name, field);
+            if !self.has_func(&joined) && !self.has_namespace(&joined) {
+                break;
+            }
+            self.next();
+            name = joined;
+            span.end = end;
+        }
+        (name, span)
+    }
+
     fn parse(&mut self) -> Result<(), ParseError> {
         if self.tree.is_none() {
             return self.error("no tree");
@@ -233,14 +518,22 @@ impl Parser {
             Some(t) => t,
         };
         if let Some(tree) = self.tree.as_mut() {
-            tree.root = Some(Nodes::List(ListNode::new(id, t.pos)));
+            tree.root = Some(Nodes::List(ListNode::new(id, Span::new(t.pos, t.pos))));
         }
         while t.typ != ItemType::ItemEOF {
             if t.typ == ItemType::ItemLeftDelim {
                 let nns = self.next_non_space();
                 match nns {
                     Some(ref item) if item.typ == ItemType::ItemDefine => {
-                        self.parse_definition()?;
+                        let depth = self.tree_stack.len();
+                        if let Err(e) = self.parse_definition() {
+                            // Drops the failed definition.
+                            while self.tree_stack.len() > depth {
+                                self.tree = self.tree_stack.pop_back();
+                            }
+                            self.tree_id = id;
+                            self.recover(e)?;
+                        }
                         t = match self.next() {
                             None => return self.error(&format!("unable to peek for tree {}", id)),
                             Some(t) => t,
@@ -258,10 +551,20 @@ impl Parser {
                 self.backup(t);
             }
             let node = match self.text_or_action() {
-                Ok(Nodes::Else(node)) => return self.error(&format!("unexpected {}", node)),
-                Ok(Nodes::End(node)) => return self.error(&format!("unexpected {}", node)),
+                Ok(Nodes::Else(node)) => Err(self.error_msg(&format!("unexpected {}", node))),
+                Ok(Nodes::End(node)) => Err(self.error_msg(&format!("unexpected {}", node))),
+                res => res,
+            };
+            let node = match node {
                 Ok(node) => node,
-                Err(e) => return Err(e),
+                Err(e) => {
+                    self.recover(e)?;
+                    t = match self.next() {
+                        None => return self.error(&format!("unable to peek for tree {}", id)),
+                        Some(t) => t,
+                    };
+                    continue;
+                }
             };
             self.tree
                 .as_mut()
@@ -287,11 +590,11 @@ impl Parser {
 
     fn parse_definition(&mut self) -> Result<(), ParseError> {
         let context = "define clause";
-        let id = self.tree_id;
         let token = self.next_non_space_must(context)?;
         let name = self.parse_template_name(&token, context)?;
         self.expect(&ItemType::ItemRightDelim, "define end")?;
-        self.start_parse(name, id + 1);
+        self.start_parse(name);
+        self.set_defined_at(&token);
         let (list, end) = self.item_list()?;
         if *end.typ() != NodeType::End {
             return Err(self.unexpected(&end, context));
@@ -302,11 +605,26 @@ impl Parser {
         self.stop_parse()
     }
 
+    // Parses an item list which doesn't belong to the enclosing `range` body, like the body of
+    // a `block`, so `break` and `continue` are not allowed.
+    fn item_list_outside_range(&mut self) -> Result<(ListNode, Nodes), ParseError> {
+        let range_depth = std::mem::take(&mut self.range_depth);
+        let res = self.item_list();
+        self.range_depth = range_depth;
+        res
+    }
+
     fn item_list(&mut self) -> Result<(ListNode, Nodes), ParseError> {
         let pos = self.peek_non_space_must("item list")?.pos;
-        let mut list = ListNode::new(self.tree_id, pos);
+        let mut list = ListNode::new(self.tree_id, Span::new(pos, pos));
         while self.peek_non_space_must("item list")?.typ != ItemType::ItemEOF {
-            let node = self.text_or_action()?;
+            let node = match self.text_or_action() {
+                Ok(node) => node,
+                Err(e) => {
+                    self.recover(e)?;
+                    continue;
+                }
+            };
             match *node.typ() {
                 NodeType::End | NodeType::Else => return Ok((list, node)),
                 _ => list.append(node),
@@ -316,65 +634,98 @@ impl Parser {
     }
 
     fn text_or_action(&mut self) -> Result<Nodes, ParseError> {
+        ensure_stack(|| self.text_or_action_inner())
+    }
+
+    fn text_or_action_inner(&mut self) -> Result<Nodes, ParseError> {
         match self.next_non_space() {
             Some(ref item) if item.typ == ItemType::ItemText => Ok(Nodes::Text(TextNode::new(
                 self.tree_id,
-                item.pos,
+                item.span(),
                 item.val.clone(),
             ))),
-            Some(ref item) if item.typ == ItemType::ItemLeftDelim => self.action(),
+            Some(ref item) if item.typ == ItemType::ItemLeftDelim => self.action(item.pos),
             Some(ref item) => Err(self.unexpected(item, "input")),
             _ => self.error("unexpected end of input"),
         }
     }
 
-    fn action(&mut self) -> Result<Nodes, ParseError> {
+    // `start` is the position of the left delimiter, all action spans include the delimiters.
+    fn action(&mut self, start: Pos) -> Result<Nodes, ParseError> {
         let token = self.next_non_space_must("action")?;
         match token.typ {
-            ItemType::ItemBlock => return self.block_control(),
-            ItemType::ItemElse => return self.else_control(),
-            ItemType::ItemEnd => return self.end_control(),
-            ItemType::ItemIf => return self.if_control(),
-            ItemType::ItemRange => return self.range_control(),
-            ItemType::ItemTemplate => return self.template_control(),
-            ItemType::ItemWith => return self.with_control(),
+            ItemType::ItemBlock => return self.block_control(start),
+            ItemType::ItemElse => return self.else_control(start),
+            ItemType::ItemEnd => return self.end_control(start),
+            ItemType::ItemIf => return self.if_control(start),
+            ItemType::ItemRange => return self.range_control(start),
+            ItemType::ItemTemplate => return self.template_control(start),
+            ItemType::ItemWith => return self.with_control(start),
+            ItemType::ItemIdentifier if self.extensions.contains(&*token.val) => {
+                return self.extension_control(start, token.val.to_string())
+            }
+            ItemType::ItemIdentifier
+                if self.go_version >= GoVersion::Go1_18
+                    && (&*token.val == "break" || &*token.val == "continue") =>
+            {
+                return self.loop_control(start, &token.val)
+            }
             _ => {}
         }
-        let pos = token.pos;
         self.backup(token);
+        let pipe = self.pipeline("command", ItemType::ItemRightDelim)?;
         Ok(Nodes::Action(ActionNode::new(
             self.tree_id,
-            pos,
-            self.pipeline("command")?,
+            Span::new(start, self.span.end),
+            pipe,
         )))
     }
 
+    // `else_chain` is the keyword which may follow `else` to continue the chain, like `if` in
+    // `{{ else if }}`.
     fn parse_control(
         &mut self,
-        allow_else_if: bool,
+        start: Pos,
+        else_chain: Option<ItemType>,
         context: &str,
-    ) -> Result<(Pos, PipeNode, ListNode, Option<ListNode>), ParseError> {
+    ) -> Result<(Span, PipeNode, ListNode, Option<ListNode>), ParseError> {
         let vars_len = self
             .tree
             .as_ref()
             .map(|t| t.vars.len())
             .ok_or(ParseError::NoTree)?;
-        let pipe = self.pipeline(context)?;
-        let (list, next) = self.item_list()?;
-        let else_list = match *next.typ() {
-            NodeType::End => None,
+        let pipe = self.pipeline(context, ItemType::ItemRightDelim)?;
+        let in_range = context == "range";
+        self.range_depth += in_range as usize;
+        let list = self.item_list();
+        self.range_depth -= in_range as usize;
+        let (list, next) = list?;
+        let (else_list, end) = match *next.typ() {
+            NodeType::End => (None, next.span().end),
             NodeType::Else => {
-                if allow_else_if && self.peek_must("else if")?.typ == ItemType::ItemIf {
-                    self.next_must("else if")?;
-                    let mut else_list = ListNode::new(self.tree_id, next.pos());
-                    else_list.append(self.if_control()?);
-                    Some(else_list)
+                let chained = match else_chain {
+                    Some(keyword) => self.peek_must(context)?.typ == keyword,
+                    None => false,
+                };
+                if chained {
+                    let keyword = self.next_must(context)?;
+                    let else_start = next.span().start;
+                    let mut else_list =
+                        ListNode::new(self.tree_id, Span::new(else_start, else_start));
+                    let control = if keyword.typ == ItemType::ItemWith {
+                        self.with_control(else_start)?
+                    } else {
+                        self.if_control(else_start)?
+                    };
+                    else_list.append(control);
+                    let end = else_list.span().end;
+                    (Some(else_list), end)
                 } else {
                     let (else_list, next) = self.item_list()?;
                     if *next.typ() != NodeType::End {
                         return self.error(&format!("expected end; found {}", next));
                     }
-                    Some(else_list)
+                    (Some(else_list), next.span().end)
                 }
             }
             _ => return self.error(&format!("expected end; found {}", next)),
@@ -382,68 +733,133 @@ impl Parser {
         if let Some(t) = self.tree.as_mut() {
             t.pop_vars(vars_len);
         }
-        Ok((pipe.pos(), pipe, list, else_list))
+        Ok((Span::new(start, end), pipe, list, else_list))
     }
 
-    fn if_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(true, "if")?;
+    fn if_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
+        let (span, pipe, list, else_list) =
+            self.parse_control(start, Some(ItemType::ItemIf), "if")?;
         Ok(Nodes::If(IfNode::new_if(
             self.tree_id,
-            pos,
+            span,
             pipe,
             list,
             else_list,
         )))
     }
 
-    fn range_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "range")?;
+    fn range_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
+        let (span, pipe, list, else_list) = self.parse_control(start, None, "range")?;
         Ok(Nodes::Range(RangeNode::new_range(
             self.tree_id,
-            pos,
+            span,
             pipe,
             list,
             else_list,
         )))
     }
 
-    fn with_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "with")?;
+    fn with_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
+        let else_with = Some(ItemType::ItemWith).filter(|_| self.go_version >= GoVersion::Go1_23);
+        let (span, pipe, list, else_list) = self.parse_control(start, else_with, "with")?;
         Ok(Nodes::With(WithNode::new_with(
             self.tree_id,
-            pos,
+            span,
             pipe,
             list,
             else_list,
         )))
     }
 
-    fn end_control(&mut self) -> Result<Nodes, ParseError> {
+    // Parses a block of an extension registered via `Template::add_extension`. Unlike `with`
+    // its pipeline is optional.
+    fn extension_control(&mut self, start: Pos, name: String) -> Result<Nodes, ParseError> {
+        let context = format!("{} clause", name);
+        let vars_len = self
+            .tree
+            .as_ref()
+            .map(|t| t.vars.len())
+            .ok_or(ParseError::NoTree)?;
+        let next = self.next_non_space_must(&context)?;
+        let pipe = if next.typ == ItemType::ItemRightDelim {
+            None
+        } else {
+            self.backup(next);
+            Some(self.pipeline(&context, ItemType::ItemRightDelim)?)
+        };
+        let (list, end) = self.item_list_outside_range()?;
+        if end.typ() != &NodeType::End {
+            return self.error(&format!("unexpected {} in {}", end, context));
+        }
+        if let Some(t) = self.tree.as_mut() {
+            t.pop_vars(vars_len);
+        }
+        Ok(Nodes::Extension(ExtensionNode::new(
+            self.tree_id,
+            Span::new(start, end.span().end),
+            name,
+            pipe,
+            list,
+        )))
+    }
+
+    // Parses `{{ break }}` or `{{ continue }}` for the innermost `range`.
+    fn loop_control(&mut self, start: Pos, keyword: &str) -> Result<Nodes, ParseError> {
+        let context = format!("{{{{{}}}}}", keyword);
+        let token = self.next_non_space_must(&context)?;
+        if token.typ != ItemType::ItemRightDelim {
+            return Err(self.unexpected(&token, &context));
+        }
+        if self.range_depth == 0 {
+            return self.error(&format!("{} outside {{{{range}}}}", context));
+        }
+        let span = Span::new(start, token.span().end);
+        Ok(if keyword == "break" {
+            Nodes::Break(BreakNode::new(self.tree_id, span))
+        } else {
+            Nodes::Continue(ContinueNode::new(self.tree_id, span))
+        })
+    }
+
+    fn end_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
+        let end = self.expect(&ItemType::ItemRightDelim, "end")?.span().end;
         Ok(Nodes::End(EndNode::new(
             self.tree_id,
-            self.expect(&ItemType::ItemRightDelim, "end")?.pos,
+            Span::new(start, end),
         )))
     }
 
-    fn else_control(&mut self) -> Result<Nodes, ParseError> {
-        if self.peek_non_space_must("else")?.typ == ItemType::ItemIf {
-            let peek = self.peek_non_space_must("else")?;
-            return Ok(Nodes::Else(ElseNode::new(peek.pos, peek.line)));
+    fn else_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
+        // For `else if` the span ends before the `if`, which belongs to the nested if node.
+        // Likewise for `else with`.
+        let chained = match self.peek_non_space_must("else")?.typ {
+            ItemType::ItemIf => true,
+            ItemType::ItemWith => self.go_version >= GoVersion::Go1_23,
+            _ => false,
+        };
+        if chained {
+            let end = self.peek_non_space_must("else")?.pos;
+            return Ok(Nodes::Else(ElseNode::new(
+                self.tree_id,
+                Span::new(start, end),
+            )));
         }
-        let token = self.expect(&ItemType::ItemRightDelim, "else")?;
-        Ok(Nodes::Else(ElseNode::new(token.pos, token.line)))
+        let end = self.expect(&ItemType::ItemRightDelim, "else")?.span().end;
+        Ok(Nodes::Else(ElseNode::new(
+            self.tree_id,
+            Span::new(start, end),
+        )))
     }
 
-    fn block_control(&mut self) -> Result<Nodes, ParseError> {
+    fn block_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
         let context = "block clause";
         let token = self.next_non_space_must(context)?;
         let name = self.parse_template_name(&token, context)?;
-        let pipe = self.pipeline(context)?;
+        let pipe = self.pipeline(context, ItemType::ItemRightDelim)?;
 
-        self.max_tree_id += 1;
-        let tree_id = self.max_tree_id;
-        self.start_parse(name.clone(), tree_id);
-        let (root, end) = self.item_list()?;
+        self.start_parse(name.clone());
+        self.set_defined_at(&token);
+        let (root, end) = self.item_list_outside_range()?;
         if let Some(tree) = self.tree.as_mut() {
             tree.root = Some(Nodes::List(root));
         }
@@ -453,92 +869,89 @@ impl Parser {
         self.stop_parse()?;
         Ok(Nodes::Template(TemplateNode::new(
             self.tree_id,
-            token.pos,
+            Span::new(start, end.span().end),
             PipeOrString::String(name),
             Some(pipe),
         )))
     }
 
-    fn template_control(&mut self) -> Result<Nodes, ParseError> {
+    fn template_control(&mut self, start: Pos) -> Result<Nodes, ParseError> {
         let context = "template clause";
         let token = self.next_non_space().ok_or(ParseError::UnexpectedEnd)?;
         let name = if let ItemType::ItemLeftParen = token.typ {
             #[cfg(feature = "gtmpl_dynamic_template")]
             {
-                let pipe = self.pipeline(context)?;
-                self.next_must("template name pipeline end")?;
-                PipeOrString::Pipe(pipe)
+                PipeOrString::Pipe(self.pipeline(context, ItemType::ItemRightParen)?)
             }
             #[cfg(not(feature = "gtmpl_dynamic_template"))]
-            return Err(ParseError::NoDynamicTemplate.into());
+            return Err(ParseError::NoDynamicTemplate);
         } else {
             PipeOrString::String(self.parse_template_name(&token, context)?)
         };
         let next = self.next_non_space().ok_or(ParseError::UnexpectedEnd)?;
-        let pipe = if next.typ != ItemType::ItemRightDelim {
+        let mut pipe = if next.typ != ItemType::ItemRightDelim {
             self.backup(next);
-            Some(self.pipeline(context)?)
+            Some(self.pipeline(context, ItemType::ItemRightDelim)?)
         } else {
             None
         };
-        Ok(Nodes::Template(TemplateNode::new(
-            self.tree_id,
-            token.pos,
-            name,
-            pipe,
-        )))
+        let args = match pipe {
+            Some(ref mut pipe) if self.template_args => split_template_args(pipe),
+            _ => vec![],
+        };
+        let mut node = TemplateNode::new(self.tree_id, Span::new(start, self.span.end), name, pipe);
+        node.args = args;
+        Ok(Nodes::Template(node))
     }
 
-    fn pipeline(&mut self, context: &str) -> Result<PipeNode, ParseError> {
+    fn pipeline(&mut self, context: &str, end: ItemType) -> Result<PipeNode, ParseError> {
+        let pos = self.peek_non_space_must("pipeline")?.pos;
         let mut decl = vec![];
-        let mut token = self.next_non_space_must("pipeline")?;
-        let pos = token.pos;
-        // TODO: test this hard!
-        if token.typ == ItemType::ItemVariable {
-            while token.typ == ItemType::ItemVariable {
-                let token_after_var = self.next_must("variable")?;
-                let next = if token_after_var.typ == ItemType::ItemSpace {
-                    let next = self.next_non_space_must("variable")?;
-                    if next.typ != ItemType::ItemColonEquals
-                        && !(next.typ == ItemType::ItemChar && next.val == ",")
-                    {
-                        self.backup3(token, token_after_var, next);
-                        break;
-                    }
-                    next
-                } else {
-                    token_after_var
-                };
-                if next.typ == ItemType::ItemColonEquals
-                    || (next.typ == ItemType::ItemChar && next.val == ",")
-                {
-                    let variable = VariableNode::new(self.tree_id, token.pos, &token.val);
-                    self.add_var(token.val.clone())?;
-                    decl.push(variable);
-                    if next.typ == ItemType::ItemChar && next.val == "," {
-                        if context == "range" && decl.len() < 2 {
-                            token = self.next_non_space_must("variable")?;
-                            continue;
-                        }
-                        return self.error(&format!("to many decalarations in {}", context));
-                    }
-                } else {
-                    self.backup2(token, next);
+        // Declarations like `$x :=` and, only in range, `$i, $e :=`. As spaces are tokens, a
+        // variable followed by something else needs up to three tokens to be pushed back.
+        loop {
+            let var = self.next_non_space_must("pipeline")?;
+            if var.typ != ItemType::ItemVariable {
+                self.backup(var);
+                break;
+            }
+            let after_var = self.next_must("variable")?;
+            let (space, next) = if after_var.typ == ItemType::ItemSpace {
+                (Some(after_var), self.next_non_space_must("variable")?)
+            } else {
+                (None, after_var)
+            };
+            let comma = next.typ == ItemType::ItemChar && next.val == ",";
+            if next.typ != ItemType::ItemColonEquals && !comma {
+                // The variable is an operand.
+                match space {
+                    Some(space) => self.backup3(var, space, next),
+                    None => self.backup2(var, next),
                 }
                 break;
             }
-        } else {
-            self.backup(token);
+            decl.push(VariableNode::new(self.tree_id, var.span(), &var.val));
+            self.add_var(var.val.to_string())?;
+            if !comma {
+                break;
+            }
+            if context == "range" && decl.len() < 2 {
+                match self.peek_non_space_must("variable")?.typ {
+                    ItemType::ItemVariable
+                    | ItemType::ItemRightDelim
+                    | ItemType::ItemRightParen => continue,
+                    _ => return self.error("range can only initialize variables"),
+                }
+            }
+            return self.error(&format!("too many declarations in {}", context));
         }
-        let mut pipe = PipeNode::new(self.tree_id, pos, decl);
+        let decl_end = decl.last().map(|v| v.span().end).unwrap_or(pos);
+        let mut pipe = PipeNode::new(self.tree_id, Span::new(pos, decl_end), decl);
         let mut token = self.next_non_space_must("pipeline")?;
         loop {
             match token.typ {
-                ItemType::ItemRightDelim | ItemType::ItemRightParen => {
+                _ if token.typ == end => {
                     self.check_pipeline(&mut pipe, context)?;
-                    if token.typ == ItemType::ItemRightParen {
-                        self.backup(token);
-                    }
                     return Ok(pipe);
                 }
                 ItemType::ItemBool
@@ -552,8 +965,13 @@ impl Parser {
                 | ItemType::ItemString
                 | ItemType::ItemVariable
                 | ItemType::ItemLeftParen => {
+                    let (line, col) = (token.line, token.col);
                     self.backup(token);
-                    pipe.append(self.command()?);
+                    let cmd = self.command()?;
+                    // A command after the first one gets the previous result as last argument.
+                    let piped = !pipe.cmds.is_empty();
+                    self.check_arity(&cmd, piped, line, col)?;
+                    pipe.append(cmd);
                 }
                 _ => return Err(self.unexpected(&token, context)),
             }
@@ -562,37 +980,76 @@ impl Parser {
     }
 
     fn check_pipeline(&mut self, pipe: &mut PipeNode, context: &str) -> Result<(), ParseError> {
+        // Reject empty pipelines.
         if pipe.cmds.is_empty() {
             return self.error(&format!("missing value for {}", context));
         }
-        for (i, c) in pipe.cmds.iter().enumerate().skip(1) {
-            match c.args.first() {
-                Some(n) => match *n.typ() {
+        // Only the first command of a pipeline can start with a non executable operand.
+        for (i, c) in pipe.cmds[1..].iter().enumerate() {
+            let executable = c.args.first().is_some_and(|n| {
+                !matches!(
+                    *n.typ(),
                     NodeType::Bool
-                    | NodeType::Dot
-                    | NodeType::Nil
-                    | NodeType::Number
-                    | NodeType::String => {
-                        return self.error(&format!(
-                            "non executable command in pipeline stage {}",
-                            i + 2
-                        ))
-                    }
-                    _ => {}
-                },
-                None => {
-                    return self.error(&format!(
-                        "non executable command in pipeline stage {}",
-                        i + 2
-                    ))
-                }
+                        | NodeType::Dot
+                        | NodeType::Nil
+                        | NodeType::Number
+                        | NodeType::String
+                )
+            });
+            if !executable {
+                // With A|B|C, pipeline stage 2 is B.
+                return self.error(&format!(
+                    "non executable command in pipeline stage {}",
+                    i + 2
+                ));
             }
         }
         Ok(())
     }
 
+    // Checks the number of arguments of a command calling a function with a known arity.
+    fn check_arity(
+        &self,
+        cmd: &CommandNode,
+        piped: bool,
+        line: usize,
+        col: usize,
+    ) -> Result<(), ParseError> {
+        let ident = match cmd.args.first() {
+            Some(Nodes::Identifier(ref ident)) => ident,
+            _ => return Ok(()),
+        };
+        let name = &ident.ident;
+        let (min, max) = match self.arities.get(name) {
+            Some(&arity) => arity,
+            None => return Ok(()),
+        };
+        let got = cmd.args.len() - 1 + usize::from(piped);
+        let want = match max {
+            Some(max) if got > max && max == min => format!("{}", max),
+            Some(max) if got > max => format!("at most {}", max),
+            _ if got < min && max == Some(min) => format!("{}", min),
+            _ if got < min => format!("at least {}", min),
+            _ => return Ok(()),
+        };
+        let tree = self.tree.as_ref().map_or(&self.name, |t| &t.name);
+        let msg = format!(
+            "wrong number of args for {}: want {} got {}",
+            name, want, got
+        );
+        Err(ParseError::with_context(
+            tree,
+            &self.name,
+            line,
+            col,
+            ident.span(),
+            msg,
+        ))
+    }
+
     fn command(&mut self) -> Result<CommandNode, ParseError> {
-        let mut cmd = CommandNode::new(self.tree_id, self.peek_non_space_must("command")?.pos);
+        let pos = self.peek_non_space_must("command")?.pos;
+        let mut cmd = CommandNode::new(self.tree_id, Span::new(pos, pos));
         loop {
             self.peek_non_space_must("operand")?;
             if let Some(operand) = self.operand()? {
@@ -615,101 +1072,156 @@ impl Parser {
     }
 
     fn operand(&mut self) -> Result<Option<Nodes>, ParseError> {
-        let node = self.term()?;
-        match node {
-            None => Ok(None),
-            Some(n) => {
-                let next = self.next_must("operand")?;
-                if next.typ == ItemType::ItemField {
-                    let typ = n.typ().clone();
-                    match typ {
-                        NodeType::Bool
-                        | NodeType::String
-                        | NodeType::Number
-                        | NodeType::Nil
-                        | NodeType::Dot => {
-                            return self
-                                .error(&format!("unexpected . after term {}", n.to_string()));
-                        }
-                        _ => {}
-                    };
-                    let mut chain = ChainNode::new(self.tree_id, next.pos, n);
-                    chain.add(&next.val);
-                    while self
-                        .peek()
-                        .map(|p| p.typ == ItemType::ItemField)
-                        .unwrap_or(false)
-                    {
-                        let field = self.next().unwrap();
-                        chain.add(&field.val);
-                    }
-                    let n = match typ {
-                        NodeType::Field => Nodes::Field(FieldNode::new(
-                            self.tree_id,
-                            chain.pos(),
-                            &chain.to_string(),
-                        )),
-                        NodeType::Variable => Nodes::Variable(VariableNode::new(
-                            self.tree_id,
-                            chain.pos(),
-                            &chain.to_string(),
-                        )),
-                        _ => Nodes::Chain(chain),
-                    };
-                    Ok(Some(n))
-                } else {
+        let mut node = match self.term()? {
+            None => return Ok(None),
+            Some(n) => n,
+        };
+        loop {
+            let next = self.next_must("operand")?;
+            node = match next.typ {
+                ItemType::ItemField => self.field_chain(node, next)?,
+                ItemType::ItemChar if self.index_syntax && next.val == "[" => {
+                    self.index_expression(node)?
+                }
+                _ => {
                     self.backup(next);
-                    Ok(Some(n))
+                    return Ok(Some(node));
                 }
+            };
+        }
+    }
+
+    fn field_chain(&mut self, n: Nodes, next: Item) -> Result<Nodes, ParseError> {
+        let typ = n.typ().clone();
+        match typ {
+            NodeType::Bool
+            | NodeType::String
+            | NodeType::Number
+            | NodeType::Nil
+            | NodeType::Dot => {
+                return self.error(&format!("unexpected . after term {}", n));
             }
+            _ => {}
+        };
+        let start = n.span().start;
+        let mut chain = ChainNode::new(self.tree_id, Span::new(start, start), n);
+        chain.add(&next.val, next.span().end);
+        while self
+            .peek()
+            .map(|p| p.typ == ItemType::ItemField)
+            .unwrap_or(false)
+        {
+            let field = self.next().unwrap();
+            chain.add(&field.val, field.span().end);
+        }
+        Ok(match typ {
+            NodeType::Field => Nodes::Field(FieldNode::new(
+                self.tree_id,
+                chain.span(),
+                &chain.to_string(),
+            )),
+            NodeType::Variable => Nodes::Variable(VariableNode::new(
+                self.tree_id,
+                chain.span(),
+                &chain.to_string(),
+            )),
+            _ => Nodes::Chain(chain),
+        })
+    }
+
+    // Desugars `n[key]` into the parenthesized pipeline `(index n key)`.
+    fn index_expression(&mut self, n: Nodes) -> Result<Nodes, ParseError> {
+        let context = "index expression";
+        if !self.has_func("index") {
+            return self.error("function index not defined");
         }
+        let key = match self.operand()? {
+            Some(key) => key,
+            None => return self.error(&format!("missing key in {}", context)),
+        };
+        let close = self.next_non_space_must(context)?;
+        if close.typ != ItemType::ItemChar || close.val != "]" {
+            return Err(self.unexpected(&close, context));
+        }
+        let span = Span::new(n.span().start, close.span().end);
+        let mut index = IdentifierNode::new("index".to_owned());
+        index.set_span(Span::new(span.start, span.start));
+        index.set_tree(self.tree_id);
+        let mut cmd = CommandNode::new(self.tree_id, span);
+        cmd.append(Nodes::Identifier(index));
+        cmd.append(n);
+        cmd.append(key);
+        cmd.set_span(span);
+        let mut pipe = PipeNode::new(self.tree_id, span, vec![]);
+        pipe.append(cmd);
+        Ok(Nodes::Pipe(pipe))
     }
 
     fn term(&mut self) -> Result<Option<Nodes>, ParseError> {
+        ensure_stack(|| self.term_inner())
+    }
+
+    fn term_inner(&mut self) -> Result<Option<Nodes>, ParseError> {
         let token = self.next_non_space_must("token")?;
         let node = match token.typ {
             ItemType::ItemError => return self.error(&token.val),
             ItemType::ItemIdentifier => {
-                if !self.has_func(&token.val) {
-                    return self.error(&format!("function {} not defined", token.val));
+                let (name, span) = self.namespaced_identifier(&token);
+                if !self.has_func(&name) && !self.allow_undefined_functions {
+                    return self.error(&format!("function {} not defined", name));
                 }
-                let mut node = IdentifierNode::new(token.val);
-                node.set_pos(token.pos);
+                let mut node = IdentifierNode::new(name);
+                node.set_span(span);
                 node.set_tree(self.tree_id);
                 Nodes::Identifier(node)
             }
-            ItemType::ItemDot => Nodes::Dot(DotNode::new(self.tree_id, token.pos)),
-            ItemType::ItemNil => Nodes::Nil(NilNode::new(self.tree_id, token.pos)),
+            ItemType::ItemDot => Nodes::Dot(DotNode::new(self.tree_id, token.span())),
+            ItemType::ItemNil => Nodes::Nil(NilNode::new(self.tree_id, token.span())),
             ItemType::ItemVariable => {
-                Nodes::Variable(self.use_var(self.tree_id, token.pos, &token.val)?)
+                Nodes::Variable(self.use_var(self.tree_id, token.span(), &token.val)?)
             }
             ItemType::ItemField => {
-                Nodes::Field(FieldNode::new(self.tree_id, token.pos, &token.val))
-            }
-            ItemType::ItemBool => {
-                Nodes::Bool(BoolNode::new(self.tree_id, token.pos, token.val == "true"))
+                Nodes::Field(FieldNode::new(self.tree_id, token.span(), &token.val))
             }
+            ItemType::ItemBool => Nodes::Bool(BoolNode::new(
+                self.tree_id,
+                token.span(),
+                token.val == "true",
+            )),
             ItemType::ItemCharConstant | ItemType::ItemNumber => {
-                match NumberNode::new(self.tree_id, token.pos, token.val, &token.typ) {
+                match NumberNode::new(
+                    self.tree_id,
+                    token.span(),
+                    token.val.to_string(),
+                    &token.typ,
+                ) {
                     Ok(n) => Nodes::Number(n),
                     Err(e) => return self.error(&e.to_string()),
                 }
             }
             ItemType::ItemLeftParen => {
-                let pipe = self.pipeline("parenthesized pipeline")?;
-                let next = self.next_must("parenthesized pipeline")?;
-                if next.typ != ItemType::ItemRightParen {
-                    return self.error(&format!("unclosed right paren: unexpected {}", next));
+                if self.paren_depth >= MAX_PAREN_DEPTH {
+                    return self.error("max expression depth exceeded");
                 }
+                self.paren_depth += 1;
+                let pipe = self.pipeline("parenthesized pipeline", ItemType::ItemRightParen);
+                self.paren_depth -= 1;
+                let mut pipe = pipe?;
+                // The span of the closing paren.
+                pipe.set_span(Span::new(token.pos, self.span.end));
                 Nodes::Pipe(pipe)
             }
-            ItemType::ItemString | ItemType::ItemRawString => {
-                if let Some(s) = unquote_str(&token.val) {
-                    Nodes::String(StringNode::new(self.tree_id, token.pos, token.val, s))
-                } else {
-                    return self.error(&format!("unable to unqote string: {}", token.val));
+            ItemType::ItemString | ItemType::ItemRawString => match unquote_str(&token.val) {
+                Ok(s) => Nodes::String(StringNode::new(
+                    self.tree_id,
+                    token.span(),
+                    token.val.to_string(),
+                    s,
+                )),
+                Err(e) => {
+                    return self.error(&format!("unable to unquote string {}: {}", token.val, e));
                 }
-            }
+            },
 
             _ => {
                 self.backup(token);
@@ -719,9 +1231,9 @@ impl Parser {
         Ok(Some(node))
     }
 
-    fn use_var(&self, tree_id: TreeId, pos: Pos, name: &str) -> Result<VariableNode, ParseError> {
-        if name == "$" {
-            return Ok(VariableNode::new(tree_id, pos, name));
+    fn use_var(&self, tree_id: TreeId, span: Span, name: &str) -> Result<VariableNode, ParseError> {
+        if name == "$" || (self.template_args && name == "$args") {
+            return Ok(VariableNode::new(tree_id, span, name));
         }
         self.tree
             .as_ref()
@@ -729,7 +1241,7 @@ impl Parser {
                 t.vars
                     .iter()
                     .find(|&v| v == name)
-                    .map(|_| VariableNode::new(tree_id, pos, name))
+                    .map(|_| VariableNode::new(tree_id, span, name))
             })
             .ok_or_else(|| self.error_msg(&format!("undefined variable {}", name)))
     }
@@ -737,7 +1249,7 @@ impl Parser {
     fn parse_template_name(&self, token: &Item, context: &str) -> Result<String, ParseError> {
         match token.typ {
             ItemType::ItemString | ItemType::ItemRawString => unquote_str(&token.val)
-                .ok_or_else(|| ParseError::UnableToParseString(token.val.clone())),
+                .map_err(|e| ParseError::UnableToParseString(token.val.to_string(), e)),
             _ => Err(self.unexpected(token, context)),
         }
     }
@@ -755,6 +1267,8 @@ impl Iterator for Parser {
         match item {
             Some(item) => {
                 self.line = item.line;
+                self.col = item.col;
+                self.span = item.span();
                 Some(item)
             }
             _ => None,
@@ -793,13 +1307,16 @@ mod tests_mocked {
         make_parser_with_funcs(s, &[])
     }
 
-    fn make_parser_with_funcs<'a>(s: &str, funcs: &[&'a str]) -> Parser {
-        let lex = Lexer::new(s.to_owned());
+    fn make_parser_with_funcs(s: &str, funcs: &[&str]) -> Parser {
+        let source: Arc<str> = Arc::from(s);
         Parser {
             name: String::from("foo"),
             funcs: funcs.iter().map(|&k| k.to_owned()).collect(),
-            lex: Some(lex),
+            lex: Some(Lexer::new(source.clone())),
+            source,
             line: 0,
+            col: 0,
+            span: Span::default(),
             token: VecDeque::new(),
             peek_count: 0,
             tree_set: HashMap::new(),
@@ -807,6 +1324,17 @@ mod tests_mocked {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            index_syntax: false,
+            template_args: false,
+            allow_undefined_functions: false,
+            go_version: GoVersion::default(),
+            range_depth: 0,
+            paren_depth: 0,
+            errors: None,
+            resumed_at: None,
+            lex_failed: false,
+            arities: HashMap::new(),
+            extensions: HashSet::new(),
         }
     }
 
@@ -876,7 +1404,13 @@ mod tests_mocked {
     #[test]
     fn test_display() {
         let raw = r#"{{if .}}2000{{else}} 3000 {{end}}"#;
-        let mut ts = parse(String::default(), String::from(raw), HashSet::default()).unwrap();
+        let mut ts = parse(
+            String::default(),
+            String::from(raw),
+            HashSet::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         let tree = ts.get_mut("").unwrap();
         if let Some(ref root) = tree.root {
             assert_eq!(raw, format!("{}", root))
@@ -891,7 +1425,7 @@ mod tests_mocked {
         let r = p.parse_tree();
         assert_eq!(
             r.err().unwrap().to_string(),
-            "template: foo:2:function eq not defined"
+            "template: foo:1:function eq not defined"
         );
         let funcs = &["eq"];
         let mut p = make_parser_with_funcs(r#"{{ if eq .foo "bar" }} 2000 {{ end }}"#, funcs);
@@ -903,10 +1437,350 @@ mod tests_mocked {
         assert!(r.is_ok());
     }
 
+    #[test]
+    fn test_index_syntax() {
+        let src = r#"{{ .a[0].b[$.k]["x"] }}"#;
+        let mut p = make_parser_with_funcs(src, &["index"]);
+        p.index_syntax = true;
+        p.parse_tree().unwrap();
+        let root = p.tree_set["foo"].root.as_ref().unwrap();
+        assert_eq!(
+            root.to_string(),
+            r#"{{(index (index (index .a 0).b $.k) "x")}}"#
+        );
+        let node = root.node_at(src.find(".b").unwrap() + 1).unwrap();
+        assert_eq!(&src[node.span().start..node.span().end], ".a[0].b");
+
+        let mut p = make_parser_with_funcs(src, &["index"]);
+        assert_eq!(
+            p.parse_tree().unwrap_err().to_string(),
+            "template: foo:1:unexpected [ in operand"
+        );
+        let mut p = make_parser_with("{{ .a[0 }}");
+        p.index_syntax = true;
+        assert!(p.parse_tree().is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let funcs = &["index", "printf"];
+        for src in &[
+            "{{(.a).b}}",
+            "{{index (.a) 0}}",
+            "{{$x := .m}}{{$x.n}}",
+            r#"{{(.a | printf "%v").b}}"#,
+            r#"{{(printf "%v" 1).x}}"#,
+            "{{($).a}}",
+            "{{(.).a}}",
+            "{{$.a.b}}",
+        ] {
+            let mut p = make_parser_with_funcs(src, funcs);
+            p.parse_tree().unwrap();
+            let printed = p.tree_set["foo"].root.as_ref().unwrap().to_string();
+            assert_eq!(&printed, src);
+            let mut p = make_parser_with_funcs(&printed, funcs);
+            assert!(p.parse_tree().is_ok(), "{}", printed);
+        }
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let src = r#"a{{ if $x := .x }}{{ $x.y | printf "%s" (len .z) }}{{ else }}b{{ end }}"#;
+        let mut p = make_parser_with_funcs(src, &["printf", "len"]);
+        p.parse_tree().unwrap();
+        let text = |span: Span| &src[span.start..span.end];
+        let root = match p.tree_set["foo"].root {
+            Some(Nodes::List(ref l)) => l,
+            _ => panic!("no root"),
+        };
+        assert_eq!(text(root.span()), src);
+        let if_node = match root.nodes[1] {
+            Nodes::If(ref n) => n,
+            _ => panic!("no if"),
+        };
+        assert_eq!(text(if_node.span()), &src[1..]);
+        assert_eq!(text(if_node.pipe.span()), "$x := .x");
+        assert_eq!(text(if_node.pipe.decl[0].span()), "$x");
+        assert_eq!(text(if_node.else_list.as_ref().unwrap().span()), "b");
+        let action = match if_node.list.nodes[0] {
+            Nodes::Action(ref n) => n,
+            _ => panic!("no action"),
+        };
+        assert_eq!(text(action.span()), r#"{{ $x.y | printf "%s" (len .z) }}"#);
+        let cmds = &action.pipe.cmds;
+        assert_eq!(text(cmds[0].span()), "$x.y");
+        assert_eq!(text(cmds[1].span()), r#"printf "%s" (len .z)"#);
+        assert_eq!(text(cmds[1].args[1].span()), r#""%s""#);
+        assert_eq!(text(cmds[1].args[2].span()), "(len .z)");
+    }
+
+    #[test]
+    fn test_error_context() {
+        let mut p = make_parser_with("a\n  {{ ü }}");
+        match p.parse_tree() {
+            Err(ParseError::WithContext(ctx, msg)) => {
+                assert_eq!(msg, "function ü not defined");
+                assert_eq!((ctx.line, ctx.col), (2, 6));
+                assert_eq!(ctx.span, Span::new(7, 9));
+            }
+            r => panic!("unexpected {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn test_unclosed_action() {
+        let mut src = "{{ 1 }}\n".repeat(20);
+        src.push_str("é {{ range .\n}}{{ end }}");
+        let mut p = make_parser_with(&src);
+        match p.parse_tree() {
+            Err(ParseError::WithContext(ctx, msg)) => {
+                assert_eq!(msg, "unclosed action started at 21:3");
+                assert_eq!(ctx.line, 21);
+            }
+            r => panic!("unexpected {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn test_max_paren_depth() {
+        // Found by the `parse` fuzz target, unclosed parens used to recurse until the stack
+        // overflowed.
+        let src = format!("{{{{{}", "(".repeat(MAX_PAREN_DEPTH * 2));
+        let mut p = make_parser_with(&src);
+        match p.parse_tree() {
+            Err(ParseError::WithContext(ctx, msg)) => {
+                assert_eq!(msg, "max expression depth exceeded");
+                assert_eq!(ctx.col, MAX_PAREN_DEPTH + 3);
+            }
+            r => panic!("unexpected {:?}", r.err()),
+        }
+
+        let parens = |n| format!("{{{{ {}1{} }}}}", "(".repeat(n), ")".repeat(n));
+        let mut p = make_parser_with(&parens(MAX_PAREN_DEPTH));
+        assert!(p.parse_tree().is_ok());
+        let mut p = make_parser_with(&parens(MAX_PAREN_DEPTH + 1));
+        assert!(p.parse_tree().is_err());
+    }
+
+    #[test]
+    fn test_recovering() {
+        let parse = |src: &str| {
+            let mut funcs = HashSet::new();
+            funcs.insert("len".to_owned());
+            let errors =
+                parse_recovering("foo".into(), src.to_owned(), funcs, ParseOptions::default());
+            errors
+                .err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|e| {
+                    let span = e.span().unwrap();
+                    format!("{} @ {}", e, &src[span.start..span.end])
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(parse("{{ if .a }}{{ len .b }}{{ end }}").is_empty());
+        assert_eq!(
+            parse("{{ if .a }}{{ x }}{{ else }}{{ .b | 3 }}{{ end }}\n{{ y }}a"),
+            vec![
+                "template: foo:1:function x not defined @ x",
+                "template: foo:1:non executable command in pipeline stage 2 @ }}",
+                "template: foo:2:function y not defined @ y",
+            ]
+        );
+        // Follow-up errors.
+        assert_eq!(
+            parse("{{ end }}{{ define }}{{ end }}{{ .a }}"),
+            vec![
+                "template: foo:1:unexpected {{end}} @ }}",
+                "template: foo:1:unexpected }} in define clause @ }}",
+                "template: foo:1:unexpected {{end}} @ }}",
+            ]
+        );
+        assert_eq!(
+            parse("{{ x }}{{ if .a }}{{ 'ab' }}{{ y }}"),
+            vec![
+                "template: foo:1:function x not defined @ x",
+                "template: foo:1:unable to unquote: 'ab' is not a single character @ 'ab'",
+                "template: foo:1:function y not defined @ y",
+                "template: foo:1:unexpected EOF @ ",
+            ]
+        );
+        // Lexer errors end parsing.
+        assert_eq!(
+            parse("{{ x }}{{ if .a }}{{ .b \n"),
+            vec![
+                "template: foo:1:function x not defined @ x",
+                "template: foo:2:unclosed action started at 1:19 @ ",
+            ]
+        );
+        assert_eq!(
+            parse(r#"{{ define "a" }}{{ x }}{{ end }}{{ define "b" }}{{ y }}{{ end }}{{ z }}"#),
+            vec![
+                "template: foo:1:function x not defined @ x",
+                "template: foo:1:function y not defined @ y",
+                "template: foo:1:function z not defined @ z",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_define() {
+        let src = "{{ define \"a\" }}a{{ end }}\n{{ define \"a\" }}{{ end }}\n  {{ define \"a\" }}b{{ end }}";
+        let mut p = make_parser_with(src);
+        match p.parse_tree() {
+            Err(ParseError::WithContext(ctx, msg)) => {
+                assert_eq!(
+                    msg,
+                    "template multiple definitions of template a, first defined at foo:1:11"
+                );
+                assert_eq!((ctx.name.as_str(), ctx.line, ctx.col), ("foo", 3, 13));
+                assert_eq!(&src[ctx.span.start..ctx.span.end], "\"a\"");
+            }
+            r => panic!("unexpected {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn test_namespaced_funcs() {
+        let funcs = &["strings.upper", "a.b.c", "len"];
+        let mut p = make_parser_with_funcs(r#"{{ strings.upper "x" | a.b.c }}"#, funcs);
+        assert!(p.parse_tree().is_ok());
+        let tree = p.tree_set.get("foo").unwrap();
+        assert_eq!(
+            tree.root.as_ref().unwrap().to_string(),
+            r#"{{strings.upper "x" | a.b.c}}"#
+        );
+
+        let mut p = make_parser_with_funcs(r#"{{ strings.lower "x" }}"#, funcs);
+        assert_eq!(
+            p.parse_tree().err().unwrap().to_string(),
+            "template: foo:1:function strings not defined"
+        );
+
+        let mut p = make_parser_with_funcs(r#"{{ a.b }}"#, funcs);
+        assert_eq!(
+            p.parse_tree().err().unwrap().to_string(),
+            "template: foo:1:function a.b not defined"
+        );
+    }
+
+    #[test]
+    fn test_check_pipeline() {
+        let funcs = &["printf"];
+        let mut p = make_parser_with_funcs(r#"{{ . | printf | "x" }}"#, funcs);
+        assert_eq!(
+            p.parse_tree().err().unwrap().to_string(),
+            "template: foo:1:non executable command in pipeline stage 3"
+        );
+        let mut p = make_parser_with_funcs(r#"{{ $ | printf | (printf "%s") | $.x }}"#, funcs);
+        assert!(p.parse_tree().is_ok());
+    }
+
+    #[test]
+    fn test_parenthesized() {
+        let src = r#"{{ index (split (print "a," .x) ",") ((len .y)) }}"#;
+        let mut p = make_parser_with_funcs(src, &["index", "split", "print", "len"]);
+        p.parse_tree().unwrap();
+        let text = |span: Span| &src[span.start..span.end];
+        let action = match p.tree_set["foo"].root {
+            Some(Nodes::List(ref l)) => match l.nodes[0] {
+                Nodes::Action(ref a) => a,
+                _ => panic!("no action"),
+            },
+            _ => panic!("no root"),
+        };
+        let args = &action.pipe.cmds[0].args;
+        assert_eq!(args.len(), 3);
+        assert_eq!(text(args[1].span()), r#"(split (print "a," .x) ",")"#);
+        assert_eq!(text(args[2].span()), "((len .y))");
+        match args[2] {
+            Nodes::Pipe(ref outer) => {
+                assert_eq!(text(outer.cmds[0].args[0].span()), "(len .y)");
+            }
+            _ => panic!("no pipe"),
+        }
+
+        let error = |src: &str| {
+            let mut p = make_parser_with_funcs(src, &["print"]);
+            p.parse_tree().err().unwrap().to_string()
+        };
+        assert_eq!(error("{{ 1) }}"), "template: foo:1:unexpected ) in command");
+        assert_eq!(error("{{ (1 }}"), "template: foo:1:unclosed left paren");
+        assert_eq!(
+            error("{{ print ( ) }}"),
+            "template: foo:1:missing value for parenthesized pipeline"
+        );
+        assert_eq!(
+            error("{{ print (1)(2) }}"),
+            "template: foo:1:unexpected ( in operand"
+        );
+    }
+
+    #[test]
+    fn test_declarations() {
+        let decl = |src: &str| {
+            let mut p = make_parser_with_funcs(src, &["f"]);
+            p.parse_tree().unwrap();
+            let action = match p.tree_set["foo"].root {
+                Some(Nodes::List(ref l)) => match l.nodes[0] {
+                    Nodes::Action(ref a) => a.pipe.clone(),
+                    Nodes::Range(ref r) => r.pipe.clone(),
+                    _ => panic!("no action"),
+                },
+                _ => panic!("no root"),
+            };
+            let vars: Vec<_> = action.decl.iter().map(|v| v.to_string()).collect();
+            (vars.join(","), action.cmds.len())
+        };
+        assert_eq!(decl("{{ $x := 1 }}"), ("$x".to_owned(), 1));
+        assert_eq!(decl("{{ $x:=1 }}"), ("$x".to_owned(), 1));
+        assert_eq!(decl("{{ $ }}"), (String::new(), 1));
+        assert_eq!(decl("{{ $x := 1 }}{{ $x | f }}"), ("$x".to_owned(), 1));
+        assert_eq!(
+            decl("{{ range $i, $e := . }}{{ end }}"),
+            ("$i,$e".to_owned(), 1)
+        );
+        assert_eq!(
+            decl("{{ range $i ,$e:= . }}{{ end }}"),
+            ("$i,$e".to_owned(), 1)
+        );
+
+        let error = |src: &str| {
+            let mut p = make_parser_with_funcs(src, &["f"]);
+            p.parse_tree().err().unwrap().to_string()
+        };
+        assert_eq!(
+            error("{{ $x, $y := 1 }}"),
+            "template: foo:1:too many declarations in command"
+        );
+        assert_eq!(
+            error("{{ range $a, $b, $c := . }}{{ end }}"),
+            "template: foo:1:too many declarations in range"
+        );
+        assert_eq!(
+            error("{{ range $a, 1 }}{{ end }}"),
+            "template: foo:1:range can only initialize variables"
+        );
+        assert_eq!(
+            error("{{ range $a, }}{{ end }}"),
+            "template: foo:1:missing value for range"
+        );
+        assert_eq!(
+            error("{{ f 1, 2 }}"),
+            "template: foo:1:unexpected , in operand"
+        );
+        assert_eq!(
+            error("{{ $x := 1, 2 }}"),
+            "template: foo:1:unexpected , in operand"
+        );
+        assert_eq!(error("{{ , }}"), "template: foo:1:unexpected , in command");
+    }
+
     #[test]
     fn test_pipeline_simple() {
         let mut p = make_parser_with(r#" $foo, $bar := yay | blub "2000" }}"#);
-        let pipe = p.pipeline("range");
+        let pipe = p.pipeline("range", ItemType::ItemRightDelim);
         // broken for now
         assert!(pipe.is_err());
     }
@@ -951,4 +1825,64 @@ mod tests_mocked {
             panic!()
         }
     }
+
+    #[test]
+    fn test_check_arity() {
+        let check = |src: &str| {
+            let mut p = make_parser_with_funcs(src, &["not", "ne", "and", "f"]);
+            p.arities.insert("not".to_owned(), (1, Some(1)));
+            p.arities.insert("ne".to_owned(), (2, Some(2)));
+            p.arities.insert("and".to_owned(), (1, None));
+            p.parse_tree().err().map(|e| e.to_string())
+        };
+        assert_eq!(check("{{ not 1 }}{{ 1 | not }}{{ ne 1 2 }}{{ f }}"), None);
+        assert_eq!(check("{{ and 1 2 3 }}{{ 1 | ne 2 }}{{ (not 1) }}"), None);
+        assert_eq!(
+            check("{{ not }}").unwrap(),
+            "template: foo:1:wrong number of args for not: want 1 got 0"
+        );
+        assert_eq!(
+            check("{{ if 1 }}\n{{ 1 | ne 2 3 }}{{ end }}").unwrap(),
+            "template: foo:2:wrong number of args for ne: want 2 got 3"
+        );
+        assert_eq!(
+            check("{{ f (and) }}").unwrap(),
+            "template: foo:1:wrong number of args for and: want at least 1 got 0"
+        );
+    }
+
+    #[test]
+    fn test_extension() {
+        let parse = |src: &str| {
+            let mut p = make_parser_with_funcs(src, &["f"]);
+            p.extensions.insert("md".to_owned());
+            p.parse_tree()
+                .map(|_| p.tree_set["foo"].root.as_ref().unwrap().to_string())
+        };
+        assert_eq!(
+            parse("{{ md }}# {{ .a }}{{ end }}{{ md $x := f | f }}{{ $x }}{{ end }}").unwrap(),
+            "{{md}}# {{.a}}{{end}}{{md $x := f | f}}{{$x}}{{end}}"
+        );
+        assert_eq!(
+            parse("{{ md }}{{ md . }}{{ end }}{{ end }}").unwrap(),
+            "{{md}}{{md .}}{{end}}{{end}}"
+        );
+        assert_eq!(
+            parse("{{ md }}a{{ else }}b{{ end }}")
+                .unwrap_err()
+                .to_string(),
+            "template: foo:1:unexpected {{else}} in md clause"
+        );
+        assert_eq!(
+            parse("{{ md $x := 1 }}{{ end }}{{ $x }}")
+                .unwrap_err()
+                .to_string(),
+            "template: foo:1:undefined variable $x"
+        );
+        assert!(parse("{{ md }}a").is_err());
+        assert_eq!(
+            parse("{{ x | md }}").unwrap_err().to_string(),
+            "template: foo:1:function x not defined"
+        );
+    }
 }
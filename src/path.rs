@@ -0,0 +1,63 @@
+use gtmpl_value::Value;
+
+/// Resolves a single field of `value` like the executor does for `.name`.
+///
+/// Objects and maps are looked up by key. Additionally arrays can be indexed by a decimal
+/// number, which templates can only do via `index`.
+pub fn lookup_field<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    match *value {
+        Value::Object(ref o) | Value::Map(ref o) => o.get(name),
+        Value::Array(ref a) => name.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    }
+}
+
+/// Resolves a dotted path like `a.b.2.c` (a leading `.` is optional) field by field.
+///
+/// Functions stored in fields are returned as they are and not called.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{lookup_path, Value};
+/// use std::collections::HashMap;
+///
+/// let mut user = HashMap::new();
+/// user.insert("tags".to_owned(), Value::from(vec!["a", "b"]));
+/// let mut data = HashMap::new();
+/// data.insert("user".to_owned(), Value::Object(user));
+/// let data = Value::Object(data);
+///
+/// assert_eq!(lookup_path(&data, ".user.tags.1"), Some(&Value::from("b")));
+/// assert_eq!(lookup_path(&data, "user.name"), None);
+/// ```
+pub fn lookup_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|s| !s.is_empty())
+        .try_fold(value, lookup_field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_lookup_path() {
+        let mut inner = HashMap::new();
+        inner.insert("c".to_owned(), Value::from(42));
+        let mut map = HashMap::new();
+        map.insert(
+            "b".to_owned(),
+            Value::Array(vec![Value::Nil, Value::Nil, Value::Map(inner)]),
+        );
+        let mut obj = HashMap::new();
+        obj.insert("a".to_owned(), Value::Map(map));
+        let v = Value::Object(obj);
+        assert_eq!(lookup_path(&v, "a.b.2.c"), Some(&Value::from(42)));
+        assert_eq!(lookup_path(&v, ""), Some(&v));
+        assert_eq!(lookup_path(&v, "a.b.3"), None);
+        assert_eq!(lookup_path(&v, "a.b.x"), None);
+        assert_eq!(lookup_path(&v, "a.b.2.c.d"), None);
+    }
+}
@@ -1,10 +1,12 @@
 use std::char;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 
 use crate::error::PrintError;
 use crate::printf::{params_to_chars, FormatParams};
 
-use gtmpl_value::Value;
+use gtmpl_value::{Function, Value};
 
 /// Print a verb like golang's printf.
 pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, PrintError> {
@@ -25,15 +27,17 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, PrintEr
                 }
                 'x' => printf_x(p, u),
                 'X' => printf_xx(p, u),
-                'U' => printf_generic(p, format!("U+{:X}", u)),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
+                'U' => printf_unicode(p, u),
+                // Integral floats are stored as integers.
+                _ => print_float(p, typ, u as f64)
+                    .ok_or_else(|| PrintError::UnableToFormat(val.clone(), typ))?,
             })
         }
         Value::Number(ref n) if n.as_i64().is_some() => {
             let i = n.as_i64().unwrap();
             Ok(match typ {
                 'b' => printf_b(p, i),
-                'd' => printf_generic(p, i),
+                'd' | 'v' => printf_generic(p, i),
                 'o' => printf_o(p, i),
                 'c' => {
                     let c = char::from_u32(i as u32).ok_or(PrintError::NotAValidChar(i as i128))?;
@@ -45,25 +49,26 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, PrintEr
                 }
                 'x' => printf_x(p, i),
                 'X' => printf_xx(p, i),
-                'U' => printf_generic(p, format!("U+{:X}", i)),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-            })
-        }
-        Value::Number(ref n) if n.as_f64().is_some() => {
-            let f = n.as_f64().unwrap();
-            Ok(match typ {
-                'e' => printf_e(p, f),
-                'E' => printf_ee(p, f),
-                'f' | 'F' => printf_generic(p, f),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
+                'U' => printf_unicode(p, i as u64),
+                // Integral floats are stored as integers.
+                _ => print_float(p, typ, i as f64)
+                    .ok_or_else(|| PrintError::UnableToFormat(val.clone(), typ))?,
             })
         }
+        Value::Number(ref n) if n.as_f64().is_some() => print_float(p, typ, n.as_f64().unwrap())
+            .ok_or_else(|| PrintError::UnableToFormat(val.clone(), typ)),
         Value::Bool(ref b) => Ok(match typ {
             'v' | 't' => printf_generic(p, b),
             _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
         }),
         Value::String(ref s) => Ok(match typ {
             's' | 'v' => printf_generic(p, s),
+            // Single characters are accepted as runes.
+            'c' | 'U' => match single_char(s) {
+                Some(c) if typ == 'c' => printf_generic(p, c),
+                Some(c) => printf_unicode(p, c as u64),
+                None => return Err(PrintError::UnableToFormat(val.clone(), typ)),
+            },
             'x' => printf_x(p, Hexer::from(s.as_str())),
             'X' => printf_xx(p, Hexer::from(s.as_str())),
             'q' => {
@@ -75,32 +80,205 @@ pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, PrintEr
             }
             _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
         }),
-        Value::Array(ref a) => Ok(match typ {
+        Value::Array(_) | Value::Map(_) | Value::Object(_) => Ok(match typ {
             'v' => {
-                let values: Vec<String> = a.iter().map(|v| printf_generic(p, v)).collect();
-                let res = format!("[{}]", values.join(" "));
-                printf_generic(p, res)
+                let mut out = String::new();
+                // The plus flag selects field names, like golang's `%+v`.
+                let leaf_params = FormatParams {
+                    plus: false,
+                    sharp: p.sharp,
+                    zero: p.zero,
+                    minus: p.minus,
+                    space: p.space,
+                    width: p.width,
+                    precision: p.precision,
+                };
+                write_go(&mut out, val, p.plus, &|v| {
+                    print(&leaf_params, 'v', v).unwrap_or_else(|_| printf_generic(p, v))
+                });
+                out
             }
             _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
         }),
-        Value::Map(ref m) => Ok(match typ {
-            'v' => {
-                let values: Vec<String> = m
-                    .iter()
-                    .map(|(k, v)| {
-                        let v_str = printf_generic(p, v);
-                        format!("{}:{}", k, v_str)
-                    })
-                    .collect();
-                let res = format!("map[{}]", values.join(" "));
-                printf_generic(p, res)
-            }
+        Value::Function(ref func) => Ok(match typ {
+            'v' => printf_generic(p, func_string(func)),
+            // The address as an opaque id, without the `0x` prefix for `%#p` like golang.
+            'p' if p.sharp => printf_generic(p, func_addr(func).trim_start_matches("0x")),
+            'p' => printf_generic(p, func_addr(func)),
             _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
         }),
         _ => Err(PrintError::UnableToFormat(val.clone(), typ)),
     }
 }
 
+// Functions have no name, they are identified by their address like `func(0x55d0c3a0)`.
+fn func_string(func: &Function) -> String {
+    format!("func({})", func_addr(func))
+}
+
+fn func_addr(func: &Function) -> String {
+    format!("{:p}", func.f)
+}
+
+/// Formats a value like golang's `%v`. Arrays are printed as `[a b]`, maps as `map[k:v]` and
+/// objects as `{a b}`, both ordered by key, so the output is deterministic.
+pub(crate) fn go_string(val: &Value) -> String {
+    match *val {
+        Value::Array(_) | Value::Map(_) | Value::Object(_) => {
+            let mut out = String::new();
+            write_go(&mut out, val, false, &go_scalar);
+            out
+        }
+        ref v => go_scalar(v),
+    }
+}
+
+fn go_scalar(val: &Value) -> String {
+    match val {
+        Value::Number(n) => match n.as_f64() {
+            Some(f) => format_g(f, None),
+            None => n.to_string(),
+        },
+        Value::Nil => "<nil>".to_owned(),
+        Value::Function(f) => func_string(f),
+        v => v.to_string(),
+    }
+}
+
+/// Formats a float like golang's `%g`, which is also used for `%v`. Without a precision the
+/// shortest representation is used, switching to the exponent form like `1.5e+06` for
+/// exponents below -4 or from 6 on. With a precision the float is rounded to that many
+/// significant digits, switching to the exponent form from the precision on. Trailing zeros
+/// are removed in both cases.
+pub(crate) fn format_g(f: f64, precision: Option<usize>) -> String {
+    if f.is_nan() {
+        return "NaN".to_owned();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    let (sci, eprec) = match precision {
+        None => (format!("{:e}", f), 6),
+        Some(pr) => (format!("{:.*e}", pr.max(1) - 1, f), pr.max(1) as i32),
+    };
+    let (mantissa, exp) = sci.split_at(sci.find('e').unwrap_or(sci.len()));
+    let exp: i32 = exp[1..].parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+    let digits = match digits.trim_end_matches('0') {
+        "" => "0",
+        d => d,
+    };
+    if exp < -4 || exp >= eprec {
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        format!(
+            "{}{}{}{}e{}{:02}",
+            sign,
+            first,
+            point,
+            rest,
+            exp_sign,
+            exp.abs()
+        )
+    } else if exp < 0 {
+        let zeros = "0".repeat((-exp - 1) as usize);
+        format!("{}0.{}{}", sign, zeros, digits)
+    } else {
+        let int_len = exp as usize + 1;
+        if digits.len() <= int_len {
+            format!("{}{:0<width$}", sign, digits, width = int_len)
+        } else {
+            let (int, frac) = digits.split_at(int_len);
+            format!("{}{}.{}", sign, int, frac)
+        }
+    }
+}
+
+// Pads a formatted float to the width like golang, zeros are inserted after the sign.
+fn pad_float(p: &FormatParams, s: String) -> String {
+    let special = s.ends_with("Inf") || s.ends_with("NaN");
+    let (sign, num) = match s.strip_prefix('-') {
+        Some(num) => ("-", num),
+        None if p.plus => ("+", s.trim_start_matches('+')),
+        None if p.space => (" ", s.trim_start_matches('+')),
+        None => ("", s.as_str()),
+    };
+    let fill = p.width.saturating_sub(sign.len() + num.len());
+    if p.minus {
+        format!("{}{}{}", sign, num, " ".repeat(fill))
+    } else if p.zero && !special {
+        format!("{}{}{}", sign, "0".repeat(fill), num)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), sign, num)
+    }
+}
+
+// Writes arrays, maps and objects with their elements formatted by `leaf`. Objects are
+// written with field names like `%+v` if `fields` is set.
+fn write_go(out: &mut String, val: &Value, fields: bool, leaf: &dyn Fn(&Value) -> String) {
+    match *val {
+        Value::Array(ref a) => {
+            out.push('[');
+            for (i, v) in a.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_go(out, v, fields, leaf);
+            }
+            out.push(']');
+        }
+        Value::Map(ref m) => {
+            out.push_str("map[");
+            write_entries(out, m, true, fields, leaf);
+            out.push(']');
+        }
+        Value::Object(ref o) => {
+            out.push('{');
+            write_entries(out, o, fields, fields, leaf);
+            out.push('}');
+        }
+        Value::Nil => out.push_str(&leaf(&Value::from("<nil>"))),
+        ref v => out.push_str(&leaf(v)),
+    }
+}
+
+fn write_entries(
+    out: &mut String,
+    m: &HashMap<String, Value>,
+    keys: bool,
+    fields: bool,
+    leaf: &dyn Fn(&Value) -> String,
+) {
+    let mut entries: Vec<_> = m.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (i, (k, v)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        if keys {
+            out.push_str(k);
+            out.push(':');
+        }
+        write_go(out, v, fields, leaf);
+    }
+}
+
+fn print_float(p: &FormatParams, typ: char, f: f64) -> Option<String> {
+    Some(match typ {
+        'e' => printf_e(p, f),
+        'E' => printf_ee(p, f),
+        'f' | 'F' => printf_generic(p, f),
+        'g' | 'v' => pad_float(p, format_g(f, p.precision)),
+        'G' => pad_float(p, format_g(f, p.precision).replace('e', "E")),
+        _ => return None,
+    })
+}
+
 fn printf_b<B: fmt::Binary>(p: &FormatParams, u: B) -> String {
     match params_to_chars(p) {
         ('#', '_', '+', '_', _) => format!("{:+#width$b}", u, width = p.width),
@@ -281,6 +459,27 @@ fn printf_ee<E: fmt::UpperExp>(p: &FormatParams, f: E) -> String {
     }
 }
 
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+// Formats a code point like golang's `%U` with at least four digits, `%#U` appends the
+// character if it is printable, like `U+0078 'x'`.
+fn printf_unicode(p: &FormatParams, u: u64) -> String {
+    let mut s = format!("U+{:04X}", u);
+    if p.sharp {
+        let c = u32::try_from(u).ok().and_then(char::from_u32);
+        if let Some(c) = c.filter(|c| !c.is_control()) {
+            s.push_str(&format!(" '{}'", c));
+        }
+    }
+    printf_generic(p, s)
+}
+
 fn escape_char(c: char) -> String {
     let mut s = c.escape_default().to_string();
     if s.starts_with(r"\u") {
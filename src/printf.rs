@@ -1,34 +1,58 @@
 use std::char;
+use std::convert::TryFrom;
 
 use gtmpl_value::{FromValue, Value};
 
 use crate::error::PrintError;
 use crate::print_verb::print;
 
-pub fn sprintf(s: &str, args: &[Value]) -> Result<String, PrintError> {
+// Go's limit for widths and precisions given as arguments.
+const MAX_ARG_WIDTH: u64 = 1_000_000;
+
+/// Formats `args` like Go's `fmt.Sprintf`.
+///
+/// Mismatches between verbs and arguments are reported inline like in Go, e.g. `%!d(MISSING)`,
+/// `%!d(string=x)` or `%!(EXTRA int=1)`. Only malformed format strings are an error. Missing
+/// values are printed as `no_value` by `%v` and `%s` if it is given, see `ExecOptions::no_value`.
+pub fn sprintf(s: &str, args: &[Value], no_value: Option<&str>) -> Result<String, PrintError> {
     let tokens = tokenize(s)?;
     let mut fmt = String::new();
     let mut i = 0;
-    let mut index = 0;
+    let mut state = ArgState::default();
     for t in tokens {
         fmt.push_str(&s[i..t.start]);
-        let (s, idx) = process_verb(&s[t.start + 1..t.end], t.typ, args, index)?;
-        fmt.push_str(&s);
-        index = idx;
-        i = t.end + 1;
+        match t.typ {
+            Some(typ) => {
+                fmt.push_str(&process_verb(
+                    &s[t.start + 1..t.end],
+                    typ,
+                    args,
+                    &mut state,
+                    no_value,
+                )?);
+                i = t.end + typ.len_utf8();
+            }
+            None => {
+                fmt.push_str("%!(NOVERB)");
+                i = t.end;
+            }
+        }
     }
     fmt.push_str(&s[i..]);
+    if !state.reordered && state.index < args.len() {
+        let extra: Vec<String> = args[state.index..].iter().map(describe).collect();
+        fmt.push_str(&format!("%!(EXTRA {})", extra.join(", ")));
+    }
     Ok(fmt)
 }
 
 struct FormatArg {
     pub start: usize,
+    // The position of the verb or the end of the format if it is missing.
     pub end: usize,
-    pub typ: char,
+    pub typ: Option<char>,
 }
 
-static TYPS: &str = "vVtTbcdoqxXUeEfFgGsp";
-
 #[derive(Default)]
 pub struct FormatParams {
     pub sharp: bool,
@@ -40,15 +64,29 @@ pub struct FormatParams {
     pub precision: Option<usize>,
 }
 
+#[derive(Default)]
+struct ArgState {
+    // The next argument to format.
+    index: usize,
+    // Whether an explicit index like `%[2]d` was used, which disables the check for extra
+    // arguments.
+    reordered: bool,
+    // Whether the explicit index of the current verb was in range.
+    bad_index: bool,
+}
+
 fn process_verb(
     s: &str,
     typ: char,
     args: &[Value],
-    mut index: usize,
-) -> Result<(String, usize), PrintError> {
+    state: &mut ArgState,
+    no_value: Option<&str>,
+) -> Result<String, PrintError> {
+    let mut out = String::new();
     let mut params = FormatParams::default();
     let mut complex = false;
     let mut pos = 0;
+    state.bad_index = false;
     for (i, c) in s.chars().enumerate() {
         match c {
             '#' => params.sharp = true,
@@ -68,27 +106,21 @@ fn process_verb(
         }
     }
     if complex {
-        let mut after_index = false;
-        let arg_num = parse_index(&s[pos..])?.map(|(i, till)| {
-            pos += till;
-            after_index = true;
-            index = i;
-            i
-        });
+        let till = arg_index(&s[pos..], args.len(), state)?;
+        let mut after_index = till > 0;
+        pos += till;
         if s[pos..].starts_with('*') {
             pos += 1;
-            let arg_num = arg_num.unwrap_or_else(|| {
-                let i = index;
-                index += 1;
-                i
-            });
-            if let Some(width) = args.get(arg_num).and_then(|v| i64::from_value(v)) {
-                if width < 0 {
-                    params.minus = true;
-                    // Golang does not pad with zeros to the right.
-                    params.zero = false;
+            match int_arg(args, state) {
+                Some(width) => {
+                    if width < 0 {
+                        params.minus = true;
+                        // Golang does not pad with zeros to the right.
+                        params.zero = false;
+                    }
+                    params.width = width.unsigned_abs() as usize;
                 }
-                params.width = width.abs() as usize;
+                None => out.push_str("%!(BADWIDTH)"),
             }
             after_index = false;
         } else if let Some((width, till)) = parse_num(&s[pos..])? {
@@ -105,24 +137,15 @@ fn process_verb(
                 return Err(PrintError::PrecisionAfterIndex);
             }
 
-            let arg_num = parse_index(&s[pos..])?.map(|(i, till)| {
-                pos += till;
-                after_index = true;
-                index = i;
-                i
-            });
+            let till = arg_index(&s[pos..], args.len(), state)?;
+            after_index = till > 0;
+            pos += till;
             if s[pos..].starts_with('*') {
                 pos += 1;
-                let arg_num = arg_num.unwrap_or_else(|| {
-                    let i = index;
-                    index += 1;
-                    i
-                });
-                if let Some(prec) = args.get(arg_num).and_then(|v| i64::from_value(v)) {
-                    if prec < 0 {
-                        params.precision = None;
-                    }
-                    params.precision = Some(prec.abs() as usize);
+                match int_arg(args, state) {
+                    // A negative precision is treated as none.
+                    Some(prec) => params.precision = usize::try_from(prec).ok(),
+                    None => out.push_str("%!(BADPREC)"),
                 }
             } else if let Some((prec, till)) = parse_num(&s[pos..])? {
                 if after_index {
@@ -134,19 +157,79 @@ fn process_verb(
         }
     }
 
-    let arg_num = if let Some((i, _)) = parse_index(&s[pos..])? {
-        index = i;
-        i
-    } else {
-        let i = index;
-        index += 1;
-        i
-    };
+    arg_index(&s[pos..], args.len(), state)?;
+    if state.bad_index {
+        out.push_str(&format!("%!{}(BADINDEX)", typ));
+        return Ok(out);
+    }
+    match args.get(state.index) {
+        None => out.push_str(&format!("%!{}(MISSING)", typ)),
+        Some(val) => {
+            state.index += 1;
+            let res = match (val, no_value) {
+                (Value::NoValue, Some(text)) if typ == 'v' || typ == 's' => {
+                    print(&params, 's', &Value::from(text))
+                }
+                _ => print(&params, typ, val),
+            };
+            match res {
+                Ok(s) => out.push_str(&s),
+                Err(PrintError::UnableToFormat(..)) => {
+                    out.push_str(&format!("%!{}({})", typ, describe(val)))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Moves to the argument of an explicit index like `[2]` and returns the length of the index.
+fn arg_index(s: &str, len: usize, state: &mut ArgState) -> Result<usize, PrintError> {
+    match parse_index(s)? {
+        Some((i, till)) => {
+            if i < len {
+                state.index = i;
+                state.reordered = true;
+            } else {
+                state.bad_index = true;
+            }
+            Ok(till)
+        }
+        None => Ok(0),
+    }
+}
+
+// The next argument as width or precision.
+fn int_arg(args: &[Value], state: &mut ArgState) -> Option<i64> {
+    let arg = args.get(state.index)?;
+    state.index += 1;
+    i64::from_value(arg).filter(|n| n.unsigned_abs() <= MAX_ARG_WIDTH)
+}
 
-    if arg_num < args.len() {
-        return print(&params, typ, &args[arg_num]).map(|s| (s, index));
+// The name of the Go type corresponding to `val`, used in errors.
+pub(crate) fn type_name(val: &Value) -> &'static str {
+    match val {
+        Value::NoValue | Value::Nil => "<nil>",
+        Value::Number(n) if n.as_f64().is_some() => "float64",
+        Value::Number(n) if n.as_i64().is_some() => "int",
+        Value::Number(_) => "uint64",
+        Value::Bool(_) => "bool",
+        Value::String(_) => "string",
+        Value::Array(_) => "[]interface {}",
+        Value::Map(_) | Value::Object(_) => "map[string]interface {}",
+        Value::Function(_) => "func",
     }
-    Err(PrintError::UnableToProcessVerb(s.to_string()))
+}
+
+// Describes an argument in errors, e.g. `int=1`.
+fn describe(val: &Value) -> String {
+    let typ = match val {
+        Value::NoValue | Value::Nil => return "<nil>".to_owned(),
+        val => type_name(val),
+    };
+    let val = print(&FormatParams::default(), 'v', val).unwrap_or_else(|_| val.to_string());
+    format!("{}={}", typ, val)
 }
 
 fn parse_index(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
@@ -154,9 +237,10 @@ fn parse_index(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
         let till = s
             .find(']')
             .ok_or_else(|| PrintError::MissingClosingBracket(s.to_string()))?;
+        // Go starts with 1, `[0]` is out of range.
         s[1..till]
             .parse::<usize>()
-            .map(|u| Some((u - 1, till + 1)))
+            .map(|u| Some((u.wrapping_sub(1), till + 1)))
             .map_err(PrintError::UnableToParseIndex)
     } else {
         Ok(None)
@@ -164,7 +248,7 @@ fn parse_index(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
 }
 
 fn parse_num(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
-    let till = s.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| s.len());
+    let till = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
     if till > 0 {
         s[..till]
             .parse()
@@ -178,36 +262,49 @@ fn parse_num(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
 fn tokenize(s: &str) -> Result<Vec<FormatArg>, PrintError> {
     let mut iter = s.char_indices().peekable();
     let mut args = Vec::new();
-    loop {
-        let from = match iter.next() {
-            None => break,
-            Some((i, '%')) => i,
-            _ => continue,
-        };
-
+    while let Some((from, c)) = iter.next() {
+        if c != '%' {
+            continue;
+        }
         if let Some(&(_, '%')) = iter.peek() {
             iter.next();
             continue;
         }
 
-        loop {
+        // Flags come first, then width and precision, each possibly with an index. The next
+        // character is the verb.
+        let mut in_flags = true;
+        let mut in_precision = false;
+        let verb = loop {
             match iter.next() {
-                None => {
-                    return Err(PrintError::UnableToTerminateFormatArg(
-                        s[from..].to_string(),
-                    ))
+                None => break None,
+                Some((_, c)) if in_flags && "#0+- ".contains(c) => {}
+                Some((_, '[')) => {
+                    if !iter.any(|(_, c)| c == ']') {
+                        return Err(PrintError::MissingClosingBracket(s[from..].to_string()));
+                    }
+                    in_flags = false;
                 }
-                Some((i, t)) if TYPS.contains(t) => {
-                    args.push(FormatArg {
-                        start: from,
-                        end: i,
-                        typ: t,
-                    });
-                    break;
+                Some((_, c)) if c.is_ascii_digit() || c == '*' => in_flags = false,
+                Some((_, '.')) if !in_precision => {
+                    in_flags = false;
+                    in_precision = true;
                 }
-                _ => continue,
-            };
-        }
+                Some(verb) => break Some(verb),
+            }
+        };
+        args.push(match verb {
+            Some((i, t)) => FormatArg {
+                start: from,
+                end: i,
+                typ: Some(t),
+            },
+            None => FormatArg {
+                start: from,
+                end: s.len(),
+                typ: None,
+            },
+        });
     }
     Ok(args)
 }
@@ -230,12 +327,12 @@ mod test {
 
     #[test]
     fn test_sprinttf_to_format() {
-        let s = sprintf("foo%v2000", &["bar".into()]);
+        let s = sprintf("foo%v2000", &["bar".into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foobar2000");
 
-        let s = sprintf("%+0v", &[1.into()]);
+        let s = sprintf("%+0v", &[1.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"+1");
@@ -243,12 +340,12 @@ mod test {
 
     #[test]
     fn test_sprintf_fancy() {
-        let s = sprintf("%+-#10c", &[10000.into()]);
+        let s = sprintf("%+-#10c", &[10000.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"✐         ");
 
-        let s = sprintf("%+-#10q", &[10000.into()]);
+        let s = sprintf("%+-#10q", &[10000.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"'\u2710'  ");
@@ -256,12 +353,12 @@ mod test {
 
     #[test]
     fn test_sprintf_string_to_hex() {
-        let s = sprintf("%x", &["foobar2000".into()]);
+        let s = sprintf("%x", &["foobar2000".into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"666f6f62617232303030");
 
-        let s = sprintf("%X", &["foobar2000".into()]);
+        let s = sprintf("%X", &["foobar2000".into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"666F6F62617232303030");
@@ -269,7 +366,7 @@ mod test {
 
     #[test]
     fn test_sprintf_string_prec() {
-        let s = sprintf("%.6s", &["foobar2000".into()]);
+        let s = sprintf("%.6s", &["foobar2000".into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foobar");
@@ -277,7 +374,7 @@ mod test {
 
     #[test]
     fn test_sprintf_index() {
-        let s = sprintf("%[1]v %v", &["foo".into(), "bar".into(), 2000.into()]);
+        let s = sprintf("%[1]v %v", &["foo".into(), "bar".into(), 2000.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foo bar");
@@ -285,6 +382,7 @@ mod test {
         let s = sprintf(
             "%[2]v %v%[1]v %v%[1]v",
             &["!".into(), "wtf".into(), "golang".into()],
+            None,
         );
         assert!(s.is_ok());
         let s = s.unwrap();
@@ -293,32 +391,79 @@ mod test {
 
     #[test]
     fn test_sprintf_number() {
-        let s = sprintf("foobar%d", &[2000.into()]);
+        let s = sprintf("foobar%d", &[2000.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foobar2000");
 
-        let s = sprintf("%+0d", &[1.into()]);
+        let s = sprintf("%+0d", &[1.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"+1");
 
-        let s = sprintf("%+0b", &[5.into()]);
+        let s = sprintf("%+0b", &[5.into()], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"+101");
+
+        let s = sprintf("%v %.2f %e", &[(-1).into(), 3.into(), 1.5.into()], None);
+        assert_eq!(s.unwrap(), "-1 3.00 1.5e0");
+    }
+
+    #[test]
+    fn test_sprintf_float() {
+        let s = sprintf(
+            "%v %v %v %v %v",
+            &[
+                1234567.5.into(),
+                0.000012345.into(),
+                0.0001.into(),
+                100000.5.into(),
+                1e-7.into(),
+            ],
+            None,
+        );
+        assert_eq!(s.unwrap(), "1.2345675e+06 1.2345e-05 0.0001 100000.5 1e-07");
+
+        let s = sprintf(
+            "%g %G %g %.3v %.2g",
+            &[
+                1e6.into(),
+                2.5e-7.into(),
+                3.5.into(),
+                1.23456.into(),
+                1234.5.into(),
+            ],
+            None,
+        );
+        assert_eq!(s.unwrap(), "1e+06 2.5E-07 3.5 1.23 1.2e+03");
+
+        let s = sprintf(
+            "%8v|%-8v|%08v|%+v|% v",
+            &[1.5.into(), 1.5.into(), 1.5.into(), 1.5.into(), 1.5.into()],
+            None,
+        );
+        assert_eq!(s.unwrap(), "     1.5|1.5     |000001.5|+1.5| 1.5");
+
+        let inf = Value::from(f64::INFINITY);
+        let s = sprintf("%v %05v %v", &[inf.clone(), inf, f64::NAN.into()], None);
+        assert_eq!(s.unwrap(), "+Inf  +Inf NaN");
+
+        let list = Value::Array(vec![1.5e-9.into(), 2.into()]);
+        let s = sprintf("%v %6v", &[list.clone(), list], None);
+        assert_eq!(s.unwrap(), "[1.5e-09 2] [1.5e-09      2]");
     }
 
     #[test]
     fn test_sprintf_array() {
         let values: Vec<Value> = vec!["hello".into(), "world".into()];
-        let s = sprintf("foo %v", &[Value::Array(values)]);
+        let s = sprintf("foo %v", &[Value::Array(values)], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foo [hello world]");
 
         let values: Vec<Value> = vec![42.into(), 100.into()];
-        let s = sprintf("foo %v", &[Value::Array(values)]);
+        let s = sprintf("foo %v", &[Value::Array(values)], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"foo [42 100]");
@@ -329,21 +474,34 @@ mod test {
         let mut values: HashMap<String, Value> = HashMap::new();
         values.insert("hello".into(), "world".into());
         values.insert("number".into(), 42.into());
-        let s = sprintf("foo %v", &[Value::Map(values)]);
+        let s = sprintf("foo %v", &[Value::Map(values)], None);
         assert!(s.is_ok());
-        let s = s.unwrap();
-        // The print order is unpredictable, we can't write
-        // a straight comparison
-        assert!(s == "foo map[number:42 hello:world]" || s == "foo map[hello:world number:42]");
+        assert_eq!(s.unwrap(), "foo map[hello:world number:42]");
 
         let mut values: HashMap<String, Value> = HashMap::new();
         values.insert("float".into(), 4.2.into());
-        let s = sprintf("%v", &[Value::Map(values)]);
+        let s = sprintf("%v", &[Value::Map(values)], None);
         assert!(s.is_ok());
         let s = s.unwrap();
         assert_eq!(s, r"map[float:4.2]");
     }
 
+    #[test]
+    fn test_sprintf_object() {
+        let mut inner: HashMap<String, Value> = HashMap::new();
+        inner.insert("b".into(), Value::Nil);
+        inner.insert("a".into(), Value::Array(vec![1.into(), "x".into()]));
+        let mut values: HashMap<String, Value> = HashMap::new();
+        values.insert("name".into(), "foo".into());
+        values.insert("inner".into(), Value::Object(inner));
+        let object = Value::Object(values);
+        let s = sprintf("%v %+v", &[object.clone(), object], None);
+        assert_eq!(
+            s.unwrap(),
+            "{{[1 x] <nil>} foo} {inner:{a:[1 x] b:<nil>} name:foo}"
+        );
+    }
+
     #[test]
     fn test_tokenize() {
         let t = tokenize("foobar%6.2ffoobar");
@@ -353,15 +511,91 @@ mod test {
         let a = &t[0];
         assert_eq!(a.start, 6);
         assert_eq!(a.end, 10);
-        assert_eq!(a.typ, 'f');
+        assert_eq!(a.typ, Some('f'));
     }
 
     #[test]
     fn test_tokenize_err() {
-        let t = tokenize(" %6.2 ");
+        let t = tokenize(" %[1d");
         assert!(t.is_err());
     }
 
+    #[test]
+    fn test_tokenize_verb() {
+        let t = tokenize("%-08.[2]*[1]x%6.2 %z%").unwrap();
+        let typs: Vec<_> = t.iter().map(|t| (t.start, t.end, t.typ)).collect();
+        assert_eq!(
+            typs,
+            vec![
+                (0, 12, Some('x')),
+                (13, 17, Some(' ')),
+                (18, 19, Some('z')),
+                (20, 21, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sprintf_mismatch() {
+        let s = |format: &str, args: &[Value]| sprintf(format, args, None).unwrap();
+        assert_eq!(s("%d %s", &[1.into()]), "1 %!s(MISSING)");
+        assert_eq!(
+            s("%d", &[1.into(), "a".into(), 1.5.into()]),
+            "1%!(EXTRA string=a, float64=1.5)"
+        );
+        assert_eq!(s("%[2]d %[1]d", &[1.into(), 2.into(), 3.into()]), "2 1");
+        assert_eq!(
+            s("%[3]d %[0]d", &[1.into()]),
+            "%!d(BADINDEX) %!d(BADINDEX)%!(EXTRA int=1)"
+        );
+        assert_eq!(s("%d", &["x".into()]), "%!d(string=x)");
+        assert_eq!(s("%s", &[Value::Nil]), "%!s(<nil>)");
+        assert_eq!(s("%z", &[(-1).into()]), "%!z(int=-1)");
+        assert_eq!(s("%6.2 ", &[1.into()]), "%! (int=1)");
+        assert_eq!(s("a%", &[]), "a%!(NOVERB)");
+        assert_eq!(s("%*d", &["x".into(), 1.into()]), "%!(BADWIDTH)1");
+        assert_eq!(s("%.*s", &[(-1).into(), "ab".into()]), "ab");
+        assert_eq!(s("%.*d", &[]), "%!(BADPREC)%!d(MISSING)");
+        assert_eq!(s("%*d", &[10_000_000.into(), 1.into()]), "%!(BADWIDTH)1");
+    }
+
+    #[test]
+    fn test_sprintf_rune() {
+        let s = |format: &str, args: &[Value]| sprintf(format, args, None).unwrap();
+        assert_eq!(s("%c%c", &["ä".into(), 98.into()]), "äb");
+        assert_eq!(s("%U %U", &["x".into(), 0x1F600.into()]), "U+0078 U+1F600");
+        assert_eq!(s("%#U %U", &["x".into(), 97.into()]), "U+0078 'x' U+0061");
+        assert_eq!(s("%#U", &["\n".into()]), "U+000A");
+        assert_eq!(s("%q", &["x".into()]), "x");
+        assert_eq!(s("%c", &["ab".into()]), "%!c(string=ab)");
+        assert_eq!(s("%U", &["".into()]), "%!U(string=)");
+    }
+
+    #[test]
+    fn test_sprintf_function() {
+        fn f(_: &[Value]) -> Result<Value, gtmpl_value::FuncError> {
+            Ok(Value::Nil)
+        }
+        let func = Value::Function(gtmpl_value::Function { f });
+        let addr = format!("{:p}", f as gtmpl_value::Func);
+        let s = |format: &str, args: &[Value]| sprintf(format, args, None).unwrap();
+        assert_eq!(
+            s("%v", std::slice::from_ref(&func)),
+            format!("func({})", addr)
+        );
+        assert_eq!(s("%p", std::slice::from_ref(&func)), addr);
+        assert_eq!(
+            s("%#p", std::slice::from_ref(&func)),
+            addr.trim_start_matches("0x")
+        );
+        assert_eq!(
+            s("%v", &[Value::Array(vec![1.into(), func.clone()])]),
+            format!("[1 func({})]", addr)
+        );
+        assert_eq!(s("%d", &[func]), format!("%!d(func=func({}))", addr));
+        assert_eq!(s("%p", &[1.into()]), "%!p(int=1)");
+    }
+
     #[test]
     fn test_tokenize_none() {
         let t = tokenize(" foo %% bar ");
@@ -0,0 +1,219 @@
+//! Instrumentation of the execution, e.g. to find slow templates.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::node::{Nodes, Span};
+
+/// Instrumentation of the execution, set via
+/// [`ExecOptions::hook`](crate::ExecOptions::hook).
+///
+/// Methods are called synchronously from the executing thread, so they should be cheap.
+pub trait ExecHook: Send + Sync {
+    /// Called before `node` of the template `template` is executed.
+    fn on_node_enter(&self, _template: &str, _node: &Nodes) {}
+
+    /// Called after `node` was executed, even if it failed.
+    fn on_node_exit(&self, _template: &str, _node: &Nodes) {}
+
+    /// Called after the function `name` returned, with the time it took without evaluating its
+    /// arguments.
+    fn on_func_call(&self, _name: &str, _elapsed: Duration) {}
+}
+
+/// Number of executions and their total duration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub count: u64,
+    pub total: Duration,
+}
+
+impl Stats {
+    fn add(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+    }
+}
+
+/// An [`ExecHook`] aggregating the time spent per template, node and function.
+///
+/// Times are inclusive, i.e. the time of a node contains the time of its children and of the
+/// templates it invokes. As it uses [`Instant`] it doesn't work on `wasm32-unknown-unknown`.
+///
+/// ## Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use gtmpl::{Context, ExecOptions, Profiler, Template};
+///
+/// let profiler = Arc::new(Profiler::default());
+/// let mut tmpl = Template::default();
+/// tmpl.set_exec_options(ExecOptions {
+///     hook: Some(profiler.clone()),
+///     ..Default::default()
+/// });
+/// tmpl.parse(r#"{{ define "item" }}{{ len . }}{{ end }}{{ range . }}{{ template "item" . }}{{ end }}"#)
+///     .unwrap();
+/// tmpl.render(&Context::from(vec!["a", "bc"])).unwrap();
+///
+/// let templates = profiler.templates();
+/// assert_eq!(templates.iter().find(|(name, _)| name == "item").unwrap().1.count, 2);
+/// assert_eq!(profiler.functions()[0].0, "len");
+/// println!("{}", profiler);
+/// ```
+#[derive(Default)]
+pub struct Profiler {
+    data: Mutex<ProfileData>,
+}
+
+#[derive(Default)]
+struct ProfileData {
+    templates: HashMap<String, Stats>,
+    nodes: HashMap<(String, Span), Stats>,
+    funcs: HashMap<String, Stats>,
+    // Nodes being executed per thread.
+    stacks: HashMap<ThreadId, Vec<Frame>>,
+}
+
+struct Frame {
+    template: String,
+    start: Instant,
+    // The first node executed of an invoked template.
+    entry: bool,
+}
+
+impl Profiler {
+    /// Time spent per template, slowest first.
+    pub fn templates(&self) -> Vec<(String, Stats)> {
+        sorted(self.data().templates.clone().into_iter())
+    }
+
+    /// Time spent per node, identified by the name of its template and its span, slowest first.
+    pub fn nodes(&self) -> Vec<((String, Span), Stats)> {
+        sorted(self.data().nodes.clone().into_iter())
+    }
+
+    /// Time spent per function, slowest first.
+    pub fn functions(&self) -> Vec<(String, Stats)> {
+        sorted(self.data().funcs.clone().into_iter())
+    }
+
+    /// Discards the collected timings.
+    pub fn reset(&self) {
+        let mut data = self.data();
+        data.templates.clear();
+        data.nodes.clear();
+        data.funcs.clear();
+    }
+
+    fn data(&self) -> std::sync::MutexGuard<'_, ProfileData> {
+        // The data stays consistent even if a thread panicked while holding the lock.
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn sorted<K>(stats: impl Iterator<Item = (K, Stats)>) -> Vec<(K, Stats)> {
+    let mut stats: Vec<_> = stats.collect();
+    stats.sort_by_key(|s| std::cmp::Reverse(s.1.total));
+    stats
+}
+
+impl ExecHook for Profiler {
+    fn on_node_enter(&self, template: &str, _node: &Nodes) {
+        let mut data = self.data();
+        let stack = data.stacks.entry(thread::current().id()).or_default();
+        let entry = stack.last().is_none_or(|f| f.template != template);
+        stack.push(Frame {
+            template: template.to_owned(),
+            start: Instant::now(),
+            entry,
+        });
+    }
+
+    fn on_node_exit(&self, template: &str, node: &Nodes) {
+        let mut data = self.data();
+        let id = thread::current().id();
+        let frame = match data.stacks.get_mut(&id).and_then(Vec::pop) {
+            Some(frame) => frame,
+            None => return,
+        };
+        if data.stacks.get(&id).is_some_and(Vec::is_empty) {
+            data.stacks.remove(&id);
+        }
+        let elapsed = frame.start.elapsed();
+        let key = (template.to_owned(), node.span());
+        data.nodes.entry(key).or_default().add(elapsed);
+        if frame.entry {
+            data.templates
+                .entry(frame.template)
+                .or_default()
+                .add(elapsed);
+        }
+    }
+
+    fn on_func_call(&self, name: &str, elapsed: Duration) {
+        self.data()
+            .funcs
+            .entry(name.to_owned())
+            .or_default()
+            .add(elapsed);
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "templates:")?;
+        for (name, stats) in self.templates() {
+            writeln!(f, "  {:?} {} calls {:?}", name, stats.count, stats.total)?;
+        }
+        writeln!(f, "functions:")?;
+        for (name, stats) in self.functions() {
+            writeln!(f, "  {} {} calls {:?}", name, stats.count, stats.total)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, ExecOptions, Template};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_profiler() {
+        let profiler = Arc::new(Profiler::default());
+        let mut t = Template::default();
+        t.set_exec_options(ExecOptions {
+            hook: Some(profiler.clone()),
+            ..Default::default()
+        });
+        let src = r#"{{ define "x" }}<{{ . }}>{{ end }}a{{ template "x" 1 }}{{ template "x" 2 }}{{ len "ab" }}{{ tpl "{{ 2 }}" . }}"#;
+        t.parse(src).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "a<1><2>22");
+
+        let templates: HashMap<_, _> = profiler.templates().into_iter().collect();
+        assert_eq!(templates[""].count, 1);
+        assert_eq!(templates["x"].count, 2);
+        assert_eq!(templates["tpl"].count, 1);
+        assert!(templates["x"].total <= templates[""].total);
+        let funcs: Vec<_> = profiler.functions().into_iter().map(|f| f.0).collect();
+        assert_eq!(funcs, vec!["len"]);
+        let nodes: HashMap<_, _> = profiler.nodes().into_iter().collect();
+        let text = nodes
+            .keys()
+            .find(|(name, span)| name.is_empty() && &src[span.start..span.end] == "a");
+        assert!(text.is_some());
+        assert!(profiler.data().stacks.is_empty());
+
+        t.parse(r#"{{ if true }}{{ template "missing" }}{{ end }}"#)
+            .unwrap();
+        t.render(&Context::empty()).unwrap_err();
+        assert!(profiler.data().stacks.is_empty());
+
+        profiler.reset();
+        assert!(profiler.templates().is_empty());
+    }
+}
@@ -1,17 +1,88 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::error::{ParseError, TemplateError};
-use crate::funcs::BUILTINS;
-use crate::parse::{parse, Tree};
+use crate::builder::TemplateBuilder;
+use crate::error::{snippet, ExecError, ParseError, TemplateError};
+use crate::escape::OutputMode;
+use crate::exec::{ExecContext, ExecOptions};
+use crate::funcs::{ContextFunc, FuncRegistry, IterFunc};
+use crate::parse::{parse, parse_recovering, ParseOptions, Tree};
 
-use gtmpl_value::Func;
+use gtmpl_value::{Func, FuncError, Value};
+
+/// Custom stringifier for values printed by an action like `{{ . }}`.
+///
+/// Returning `None` falls back to the default formatting.
+pub type Formatter = fn(&Value) -> Option<String>;
+
+/// Renders an extension block from the value of its pipeline and its rendered body, see
+/// [`Template::add_extension`].
+pub type ExtensionFunc = Arc<dyn Fn(&Value, &str) -> Result<String, FuncError> + Send + Sync>;
+
+/// Post-processes the complete output of the template, see [`Template::add_post_filter`].
+pub type PostFilter = Arc<dyn Fn(String) -> String + Send + Sync>;
+
+/// A template added by [`Template::parse`] or [`Template::add_template`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Defined {
+    pub name: String,
+    /// `true` if an existing template with the same name was replaced.
+    pub overridden: bool,
+}
+
+/// What [`Template::extend`] does with a template or function defined by both templates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collision {
+    /// Takes the definition of the other template.
+    #[default]
+    Override,
+    /// Keeps the own definition.
+    Keep,
+    /// Fails without changing anything.
+    Error,
+}
+
+/// The Golang release whose template language is emulated, see [`Template::set_go_version`].
+///
+/// Later versions include the additions of earlier ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GoVersion {
+    /// Before Go 1.18: `and` and `or` evaluate all their arguments.
+    Go1_17,
+    /// Go 1.18 added `{{ break }}` and `{{ continue }}` in `range` and made `and` and `or`
+    /// stop evaluating their arguments once the result is known.
+    Go1_18,
+    /// Go 1.23 added `{{ else with }}`, like `{{ else if }}`.
+    #[default]
+    Go1_23,
+}
 
 /// The main template structure.
+///
+/// `Template::default()` provides Golang's builtin functions, see [`FuncRegistry::builtins`],
+/// and `include`, `tpl`, `raw` and `data`. [`Template::empty`] provides none.
 pub struct Template {
     pub name: String,
+    /// The text last passed to [`Template::parse`] or [`Template::parse_recovering`].
     pub text: String,
-    pub funcs: HashMap<String, Func>,
-    pub tree_set: HashMap<String, Tree>,
+    pub funcs: FuncRegistry,
+    pub(crate) tree_set: HashMap<String, Tree>,
+    // The text last parsed for every name, see `Template::source`.
+    pub(crate) sources: HashMap<String, Arc<str>>,
+    pub(crate) formatter: Option<Formatter>,
+    pub(crate) field_fallback: bool,
+    pub(crate) parse_options: ParseOptions,
+    pub(crate) strict_parse: bool,
+    pub(crate) strict: bool,
+    pub(crate) exec_options: ExecOptions,
+    pub(crate) output_mode: OutputMode,
+    pub(crate) data: HashMap<String, Value>,
+    pub(crate) extensions: HashMap<String, ExtensionFunc>,
+    pub(crate) post_filters: Vec<PostFilter>,
+    pub(crate) layout: Option<String>,
+    pub(crate) pages: HashMap<String, HashMap<String, Tree>>,
+    #[cfg(feature = "gtmpl_os")]
+    pub(crate) os_policy: Option<std::sync::Arc<crate::funcs::os::OsPolicy>>,
 }
 
 impl Default for Template {
@@ -19,13 +90,33 @@ impl Default for Template {
         Template {
             name: String::default(),
             text: String::from(""),
-            funcs: BUILTINS.iter().map(|&(k, v)| (k.to_owned(), v)).collect(),
+            funcs: FuncRegistry::builtins(),
             tree_set: HashMap::default(),
+            sources: HashMap::default(),
+            formatter: None,
+            field_fallback: false,
+            parse_options: ParseOptions::default(),
+            strict_parse: false,
+            strict: false,
+            exec_options: ExecOptions::default(),
+            output_mode: OutputMode::default(),
+            data: HashMap::default(),
+            extensions: HashMap::default(),
+            post_filters: vec![],
+            layout: None,
+            pages: HashMap::default(),
+            #[cfg(feature = "gtmpl_os")]
+            os_policy: None,
         }
     }
 }
 
 impl Template {
+    /// Returns a [`TemplateBuilder`] to configure a new template in one go.
+    pub fn builder() -> TemplateBuilder {
+        TemplateBuilder::default()
+    }
+
     /// Creates a new empty template with a given `name`.
     pub fn with_name<T: Into<String>>(name: T) -> Template {
         Template {
@@ -34,6 +125,29 @@ impl Template {
         }
     }
 
+    /// Creates a new template without any functions, e.g. for sandboxed templates. Unlike
+    /// `Template::default()`, which provides Golang's builtins (see
+    /// [`FuncRegistry::builtins`]) as well as `include`, `tpl`, `raw` and `data`, only the
+    /// functions added explicitly are available.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::empty();
+    /// assert!(tmpl.parse(r#"{{ printf "%d" 1 }}"#).is_err());
+    /// assert!(tmpl.parse(r#"{{ include "x" . }}"#).is_err());
+    /// tmpl.parse("{{ if . }}{{ . }}{{ end }}").unwrap();
+    /// assert_eq!(&tmpl.render(&Context::from(1)).unwrap(), "1");
+    /// ```
+    pub fn empty() -> Template {
+        Template {
+            funcs: FuncRegistry::default().without_exec_funcs(),
+            ..Default::default()
+        }
+    }
+
     /// Adds a single custom function to the template.
     ///
     /// ## Example
@@ -52,7 +166,32 @@ impl Template {
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
     pub fn add_func(&mut self, name: &str, func: Func) {
-        self.funcs.insert(name.to_owned(), func);
+        self.funcs.register(name, func);
+    }
+
+    /// Adds a single custom function to the template unless a function with the same name
+    /// already exists, be it a builtin, `include`, `tpl`, `raw`, `data`, a custom function, an
+    /// iterator or a context function.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Func, FuncError, Template, Value};
+    ///
+    /// fn len(_args: &[Value]) -> Result<Value, FuncError> {
+    ///   Ok(Value::from(42))
+    /// }
+    ///
+    /// let mut tmpl = Template::default();
+    /// assert!(tmpl.try_add_func("len", len).is_err());
+    /// assert!(tmpl.try_add_func("myLen", len).is_ok());
+    /// ```
+    pub fn try_add_func(&mut self, name: &str, func: Func) -> Result<(), TemplateError> {
+        if self.funcs.name_set().contains(name) {
+            return Err(TemplateError::FuncAlreadyDefined(name.to_owned()));
+        }
+        self.funcs.register(name, func);
+        Ok(())
     }
 
     /// Adds custom functions to the template.
@@ -76,26 +215,599 @@ impl Template {
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
     pub fn add_funcs<T: Into<String> + Clone>(&mut self, funcs: &[(T, Func)]) {
-        self.funcs
-            .extend(funcs.iter().cloned().map(|(k, v)| (k.into(), v)));
+        self.funcs.extend(funcs);
     }
 
-    /// Parse the given `text` as template body.
+    /// Adds the constant `value` as `name`, e.g. a lookup table. Any template can read it via
+    /// the builtin `data`, like `{{ index (data "colors") .level }}`. Adding `name` again
+    /// replaces the value.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use gtmpl::{Context, Template, Value};
+    ///
+    /// let mut colors = HashMap::new();
+    /// colors.insert("warn".to_owned(), "yellow");
+    /// colors.insert("error".to_owned(), "red");
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_data("colors", Value::from(colors));
+    /// tmpl.add_template("level", r#"{{ index (data "colors") . }}"#).unwrap();
+    /// tmpl.parse(r#"{{ template "level" . }}"#).unwrap();
+    /// let output = tmpl.render(&Context::from("error"));
+    /// assert_eq!(&output.unwrap(), "red");
+    /// ```
+    pub fn add_data<T: Into<String>>(&mut self, name: T, value: Value) {
+        self.data.insert(name.into(), value);
+    }
+
+    /// Adds the block keyword `name` for subsequently parsed text, like `{{ name }}...{{ end }}`
+    /// or with a pipeline `{{ name pipeline }}...{{ end }}`. When executed the body is rendered
+    /// with the current dot and variables and passed to `handler` along with the value of the
+    /// pipeline, [`Value::NoValue`] if there is none. The returned text is printed as it is,
+    /// without applying the [output mode](Template::set_output_mode).
+    ///
+    /// At the start of an action `name` always opens a block, elsewhere it is still a function
+    /// if one of that name exists. Adding `name` again replaces the handler.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, FuncError, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_extension("indent", |width: &Value, body: &str| {
+    ///     let width = match width {
+    ///         Value::Number(n) => n.as_u64().unwrap_or(0) as usize,
+    ///         _ => return Err(FuncError::Generic("indent needs a width".to_owned())),
+    ///     };
+    ///     let lines: Vec<String> = body
+    ///         .lines()
+    ///         .map(|l| format!("{:width$}{}", "", l, width = width))
+    ///         .collect();
+    ///     Ok(lines.join("\n"))
+    /// });
+    /// tmpl.parse("spec:\n{{ indent 2 }}name: {{ . }}\nimage: {{ . }}:latest{{ end }}").unwrap();
+    /// let output = tmpl.render(&Context::from("app"));
+    /// assert_eq!(&output.unwrap(), "spec:\n  name: app\n  image: app:latest");
+    /// ```
+    pub fn add_extension<T, F>(&mut self, name: T, handler: F)
+    where
+        T: Into<String>,
+        F: Fn(&Value, &str) -> Result<String, FuncError> + Send + Sync + 'static,
+    {
+        self.extensions.insert(name.into(), Arc::new(handler));
+    }
+
+    /// Adds the iterator `name`, which creates a sequence of values from its arguments.
+    ///
+    /// Called directly by `range`, e.g. `{{ range rows 1000 }}`, the values are created one at a
+    /// time while executing the body, so large sequences render in constant memory when using
+    /// [`Template::execute`]. Called anywhere else the values are collected into an array.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, FuncError, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_iterator("upTo", |args: &[Value]| {
+    ///     let n = match args {
+    ///         [Value::Number(n)] => n.as_u64().unwrap_or(0),
+    ///         _ => return Err(FuncError::ExactlyXArgs("upTo".into(), 1)),
+    ///     };
+    ///     Ok(Box::new((1..=n).map(Value::from)) as Box<dyn Iterator<Item = Value>>)
+    /// });
+    /// tmpl.parse("{{ range $i, $n := upTo 3 }}{{ $i }}:{{ $n }} {{ end }}{{ len (upTo 2) }}").unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "0:1 1:2 2:3 2");
+    /// ```
+    pub fn add_iterator<T, F>(&mut self, name: T, iter: F)
+    where
+        T: Into<String>,
+        F: Fn(&[Value]) -> Result<Box<dyn Iterator<Item = Value>>, FuncError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.funcs.register_iterator(name, Arc::new(iter));
+    }
+
+    /// Adds the function `name`, which gets the state of the execution at the call besides its
+    /// arguments: dot, the variables in scope and the name of the executed template. This
+    /// allows helpers like a debugging `dump` or a `required` reporting where it failed.
+    /// Functions added via [`Template::add_func`] of the same name take precedence.
     ///
     /// ## Example
     ///
     /// ```rust
+    /// use gtmpl::{Context, ExecContext, FuncError, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_context_func("dump", |_: &[Value], ctx: &ExecContext| {
+    ///     let vars: Vec<String> = ctx
+    ///         .vars()
+    ///         .skip(1)
+    ///         .map(|(name, value)| format!("{}={}", name, value))
+    ///         .collect();
+    ///     Ok(Value::from(format!("{}: {} {}", ctx.template_name(), ctx.dot(), vars.join(" "))))
+    /// });
+    /// tmpl.parse(concat!(
+    ///     r#"{{ define "t" }}{{ $x := 1 }}{{ range $i, $e := . }}{{ dump }}|{{ end }}{{ end }}"#,
+    ///     r#"{{ template "t" . }}"#,
+    /// ))
+    /// .unwrap();
+    /// let output = tmpl.render(&Context::from(vec!["a", "b"]));
+    /// assert_eq!(&output.unwrap(), "t: a $x=1 $i=0 $e=a|t: b $x=1 $i=1 $e=b|");
+    /// ```
+    pub fn add_context_func<T, F>(&mut self, name: T, func: F)
+    where
+        T: Into<String>,
+        F: Fn(&[Value], &ExecContext<'_>) -> Result<Value, FuncError> + Send + Sync + 'static,
+    {
+        self.funcs.register_context_func(name, Arc::new(func));
+    }
+
+    /// Adds custom functions under a `namespace`. They are called as `namespace.name`, which
+    /// keeps function packs from colliding with each other. Namespaces can be nested by using
+    /// dots in `namespace`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Func, FuncError, Value};
+    ///
+    /// fn upper(args: &[Value]) -> Result<Value, FuncError> {
+    ///     match args {
+    ///         [Value::String(s)] => Ok(Value::from(s.to_uppercase())),
+    ///         _ => Err(FuncError::UnableToConvertFromValue),
+    ///     }
+    /// }
+    ///
     /// let mut tmpl = gtmpl::Template::default();
-    /// tmpl.parse("Hello World!").unwrap();
+    /// tmpl.add_namespaced_funcs("strings", &[("upper", upper as Func)]);
+    /// tmpl.parse(r#"{{ strings.upper "hello" }} {{ "world" | strings.upper }}"#).unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "HELLO WORLD");
     /// ```
-    pub fn parse<T: Into<String>>(&mut self, text: T) -> Result<(), ParseError> {
-        let tree_set = parse(
-            self.name.clone(),
-            text.into(),
-            self.funcs.keys().cloned().collect(),
-        )?;
-        self.tree_set.extend(tree_set);
-        Ok(())
+    pub fn add_namespaced_funcs<T: Into<String> + Clone>(
+        &mut self,
+        namespace: &str,
+        funcs: &[(T, Func)],
+    ) {
+        for (name, func) in funcs.iter().cloned() {
+            self.funcs
+                .register(format!("{}.{}", namespace, name.into()), func);
+        }
+    }
+
+    /// Adds the regular expression functions from [`funcs::regex`](crate::funcs::regex).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.add_regex_funcs();
+    /// tmpl.parse(r#"{{ regexReplaceAll "a(x*)b" "-ab-axxb-" "${1}W" }}"#).unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "-W-xxW-");
+    /// ```
+    #[cfg(feature = "gtmpl_regex")]
+    pub fn add_regex_funcs(&mut self) {
+        self.add_funcs(crate::funcs::regex::REGEX_FUNCS);
+    }
+
+    /// Adds the environment and file system functions from [`funcs::os`](crate::funcs::os).
+    /// Every access is checked against `policy`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{funcs::os::OsPolicy, Context};
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.enable_os_funcs(OsPolicy::default().allow_path("Cargo.toml"));
+    /// tmpl.parse(r#"{{ fileExists "Cargo.toml" }}"#).unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "true");
+    /// ```
+    #[cfg(feature = "gtmpl_os")]
+    pub fn enable_os_funcs(&mut self, policy: crate::funcs::os::OsPolicy) {
+        self.add_funcs(crate::funcs::os::OS_FUNCS);
+        self.os_policy = Some(std::sync::Arc::new(policy));
+    }
+
+    /// Sets a `formatter` that is consulted whenever a value is printed. If it returns `None`
+    /// the value is printed as usual.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Value};
+    ///
+    /// fn shout(value: &Value) -> Option<String> {
+    ///     match value {
+    ///         Value::String(s) => Some(s.to_uppercase()),
+    ///         _ => None,
+    ///     }
+    /// }
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.set_formatter(shout);
+    /// tmpl.parse("{{ . }} {{ 1 }}").unwrap();
+    /// let output = tmpl.render(&Context::from("hello"));
+    /// assert_eq!(&output.unwrap(), "HELLO 1");
+    /// ```
+    pub fn set_formatter(&mut self, formatter: Formatter) {
+        self.formatter = Some(formatter);
+    }
+
+    /// Enables looking up fields under alternative names. If `.UserName` is not found the
+    /// lowerCamelCase (`userName`) and snake_case (`user_name`) variants are tried, which lets
+    /// templates written for Go structs work with derived Rust structs.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    /// use gtmpl_derive::Gtmpl;
+    ///
+    /// #[derive(Gtmpl)]
+    /// struct User {
+    ///     user_name: String,
+    /// }
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_field_fallback(true);
+    /// tmpl.parse("Hello {{ .UserName }}!").unwrap();
+    /// let user = User { user_name: "gopher".to_owned() };
+    /// let output = tmpl.render(&Context::from(user));
+    /// assert_eq!(&output.unwrap(), "Hello gopher!");
+    /// ```
+    pub fn set_field_fallback(&mut self, enabled: bool) {
+        self.field_fallback = enabled;
+    }
+
+    /// Enables strict mode: accessing a missing key of a map and printing a pipeline which
+    /// yields no value fail with [`ExecError::NoValue`](crate::error::ExecError::NoValue) instead
+    /// of producing `<no value>`. Like Golang's `missingkey=error`, but also covering printed
+    /// output.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, Value};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut data = HashMap::new();
+    /// data.insert("name".to_owned(), Value::from("gopher"));
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_strict(true);
+    /// tmpl.parse("{{ .name }} {{ .nmae }}").unwrap();
+    /// let err = tmpl.render(&Context::from(Value::Map(data))).unwrap_err();
+    /// assert_eq!(err.to_string(), "no value for .nmae");
+    /// assert_eq!(err.span().map(|s| s.start), Some(15));
+    /// ```
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+    }
+
+    /// Sets the options for executing the template, e.g. to memoize invoked templates.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, ExecOptions, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_exec_options(ExecOptions {
+    ///     memoize: true,
+    ///     ..Default::default()
+    /// });
+    /// tmpl.parse(r#"{{ define "item" }}<{{ . }}>{{ end }}{{ range . }}{{ template "item" . }}{{ end }}"#)
+    ///     .unwrap();
+    /// let output = tmpl.render(&Context::from(vec![1, 2, 1]));
+    /// assert_eq!(&output.unwrap(), "<1><2><1>");
+    /// ```
+    pub fn set_exec_options(&mut self, options: ExecOptions) {
+        self.exec_options = options;
+    }
+
+    /// Sets the escaping of the output of actions, e.g. quoting for YAML files. An action
+    /// ending with `raw`, like `{{ .port | raw }}`, is printed as it is.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::escape::OutputMode;
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_output_mode(OutputMode::Shell);
+    /// tmpl.parse("echo {{ . }} {{ `$HOME` | raw }}").unwrap();
+    /// let output = tmpl.render(&Context::from("it's"));
+    /// assert_eq!(&output.unwrap(), r"echo 'it'\''s' $HOME");
+    /// ```
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    /// Adds a filter post-processing the complete output of [`Template::render`] and
+    /// [`Template::execute`], e.g. one of the [`filter`](crate::filter) module. Filters are
+    /// applied in the order they were added. The output is buffered if there are any.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{filter, Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_post_filter(filter::normalize_newlines);
+    /// tmpl.add_post_filter(|out: String| out.replace("\t", "  "));
+    /// tmpl.parse("a:\r\n\t{{ . }}").unwrap();
+    /// let mut out = vec![];
+    /// tmpl.execute(&mut out, &Context::from("b: 1")).unwrap();
+    /// assert_eq!(out, b"a:\n  b: 1");
+    /// ```
+    pub fn add_post_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(String) -> String + Send + Sync + 'static,
+    {
+        self.post_filters.push(Arc::new(filter));
+    }
+
+    /// Enables trimming around block actions (`if`, `else`, `range`, `with`, `define`, `block`
+    /// and `end`) for subsequently parsed text. If such an action starts its line, the
+    /// indentation before and a newline directly after it are removed, as if it were written
+    /// with `{{-` and `-}}` restricted to that line. Actions following text on their line are
+    /// left as they are. Disabled by default like in Golang.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_trim_blocks(true);
+    /// tmpl.parse("items:\n  {{ range . }}\n  - {{ . }}\n  {{ end }}\ndone").unwrap();
+    /// let output = tmpl.render(&Context::from(vec![1, 2]));
+    /// assert_eq!(&output.unwrap(), "items:\n  - 1\n  - 2\ndone");
+    /// ```
+    pub fn set_trim_blocks(&mut self, enabled: bool) {
+        self.parse_options.trim_blocks = enabled;
+    }
+
+    /// Enables bracket index syntax for subsequently parsed text. `{{ .list[0].name }}` is
+    /// parsed as `{{ (index .list 0).name }}`, keys can be any operand like `.map["key"]` or
+    /// `.list[$i]`. Disabled by default like in Golang.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_index_syntax(true);
+    /// tmpl.parse("{{ .[1] }} {{ $i := 0 }}{{ .[$i] }}").unwrap();
+    /// let output = tmpl.render(&Context::from(vec!["a", "b"]));
+    /// assert_eq!(&output.unwrap(), "b a");
+    /// ```
+    pub fn set_index_syntax(&mut self, enabled: bool) {
+        self.parse_options.index_syntax = enabled;
+    }
+
+    /// Enables numeric fields on arrays for subsequently parsed text. `{{ .pair.0 }}` is the
+    /// first element of the array `pair`, like `{{ index .pair 0 }}`, which is handy for
+    /// contexts derived from tuples or `Vec`s. As `.5` is a field then, floats have to be
+    /// written with a leading zero like `0.5`. Disabled by default like in Golang, where `.0`
+    /// is a number.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{gtmpl_list, gtmpl_map, Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_numeric_fields(true);
+    /// tmpl.parse("{{ .point.0 }},{{ $.point.1 }} {{ range .pairs }}{{ .1 }}{{ end }}")
+    ///     .unwrap();
+    /// let data = gtmpl_map! {
+    ///     "point" => gtmpl_list![3, 4],
+    ///     "pairs" => gtmpl_list![gtmpl_list!["a", 1], gtmpl_list!["b", 2]],
+    /// };
+    /// let output = tmpl.render(&Context::from(data));
+    /// assert_eq!(&output.unwrap(), "3,4 12");
+    /// ```
+    pub fn set_numeric_fields(&mut self, enabled: bool) {
+        self.parse_options.numeric_fields = enabled;
+    }
+
+    /// Enables arguments for the `template` action. In subsequently parsed text the operands
+    /// after dot in `{{ template "x" . arg1 arg2 }}` are passed to `x` as the array `$args`,
+    /// which is empty in templates executed without arguments. This is not supported by
+    /// Golang, where only dot is passed and a call like `{{ template "x" .f 1 }}` calls the
+    /// method `f` instead. Function calls like `{{ template "x" printf "%d" 1 }}` and longer
+    /// pipelines still yield dot.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{gtmpl_map, Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_template_args(true);
+    /// tmpl.parse(concat!(
+    ///     r#"{{ define "field" }}{{ index $args 0 }}: {{ index . (index $args 1) }}{{ end }}"#,
+    ///     r#"{{ template "field" . "Name" "name" }}"#,
+    /// ))
+    /// .unwrap();
+    /// let output = tmpl.render(&Context::from(gtmpl_map! { "name" => "Ferris" }));
+    /// assert_eq!(&output.unwrap(), "Name: Ferris");
+    /// ```
+    pub fn set_template_args(&mut self, enabled: bool) {
+        self.parse_options.template_args = enabled;
+    }
+
+    /// Allows calls of functions which are not defined yet in subsequently parsed text, like
+    /// Golang's `SkipFuncCheck` mode. Instead of parsing, executing fails if a called function
+    /// is still not defined, so functions can be added after parsing. Namespaced functions like
+    /// `strings.upper` still have to be defined when parsing, or at least one function of their
+    /// namespace.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, Value};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_allow_undefined_functions(true);
+    /// tmpl.parse("{{ greet . }}").unwrap();
+    /// assert!(tmpl.render(&Context::from("gopher")).is_err());
+    /// tmpl.add_func("greet", |args| Ok(Value::from(format!("Hello {}!", args[0]))));
+    /// let output = tmpl.render(&Context::from("gopher"));
+    /// assert_eq!(&output.unwrap(), "Hello gopher!");
+    /// ```
+    pub fn set_allow_undefined_functions(&mut self, enabled: bool) {
+        self.parse_options.allow_undefined_functions = enabled;
+    }
+
+    /// Emulates the template language of the given Golang release. Syntax added later is
+    /// rejected for subsequently parsed text like by the Golang parser of that release, e.g.
+    /// `{{ break }}` before [`GoVersion::Go1_18`] is a call of the function `break`. Semantics
+    /// like the short-circuit evaluation of `and` and `or` follow the version when executing.
+    /// Defaults to the latest supported release.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, GoVersion, Template};
+    ///
+    /// let src = "{{ range . }}{{ if eq . 3 }}{{ break }}{{ end }}{{ . }}{{ end }}";
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(src).unwrap();
+    /// assert_eq!(&tmpl.render(&Context::from(vec![1, 2, 3, 4])).unwrap(), "12");
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_go_version(GoVersion::Go1_17);
+    /// assert!(tmpl.parse(src).is_err());
+    /// ```
+    pub fn set_go_version(&mut self, version: GoVersion) {
+        self.parse_options.go_version = version;
+    }
+
+    /// Sets the action delimiters for subsequently parsed text. An empty delimiter stands for
+    /// the default, `{{` or `}}` respectively.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_delims("<%", "%>");
+    /// tmpl.parse("{{ . }} <%- . %>").unwrap();
+    /// let output = tmpl.render(&Context::from(1));
+    /// assert_eq!(&output.unwrap(), "{{ . }}1");
+    /// ```
+    pub fn set_delims<L: Into<String>, R: Into<String>>(&mut self, left: L, right: R) {
+        self.parse_options.left_delim = left.into();
+        self.parse_options.right_delim = right.into();
+    }
+
+    /// Enables strict parsing for subsequently parsed text: calls of builtin functions with the
+    /// wrong number of arguments, like `{{ not .a .b }}` or `{{ .a | ne .b .c }}`, fail to
+    /// parse instead of failing when executed. Functions overridden by own ones are not
+    /// checked.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Template;
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.set_strict_parse(true);
+    /// let err = tmpl.parse("{{ if .a }}\n{{ len .a .b }}{{ end }}").unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "template: :2:wrong number of args for len: want 1 got 2"
+    /// );
+    /// assert_eq!(err.span().map(|s| s.start), Some(15));
+    /// ```
+    pub fn set_strict_parse(&mut self, enabled: bool) {
+        self.strict_parse = enabled;
+    }
+
+    /// Parse the given `text` as template body.
+    ///
+    /// Templates defined by `text`, via `{{ define }}` or `{{ block }}`, are added to the
+    /// template. A definition replaces an earlier one with the same name unless it is empty, so
+    /// the last non-empty definition wins. This lets a later parse override the default body of
+    /// a `block`, and parsing a text with only definitions keeps the main template.
+    ///
+    /// Returns the added templates in the order of their definition.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"<h1>{{ block "title" . }}Default{{ end }}</h1>"#).unwrap();
+    /// let defined = tmpl.parse(r#"{{ define "title" }}{{ . }}{{ end }}"#).unwrap();
+    /// assert_eq!(defined[0].name, "title");
+    /// assert!(defined[0].overridden);
+    /// assert_eq!(&tmpl.render(&Context::from("Hello")).unwrap(), "<h1>Hello</h1>");
+    /// ```
+    pub fn parse<T: Into<String>>(&mut self, text: T) -> Result<Vec<Defined>, ParseError> {
+        let name = self.name.clone();
+        let text = text.into();
+        let source = self.add_source(&name, text.as_str());
+        self.text = text;
+        let tree_set = parse(name, source, self.funcs.name_set(), self.parse_options())?;
+        Ok(self.add_trees(tree_set))
+    }
+
+    /// Like [`Template::parse`], but reports all errors instead of only the first, e.g. for
+    /// editors and CI. After an error parsing continues with the next action, so one mistake
+    /// may cause follow-up errors like an unexpected `{{ end }}`. Nothing is added to the
+    /// template if there are errors.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Template;
+    ///
+    /// let mut tmpl = Template::default();
+    /// let errors = tmpl
+    ///     .parse_recovering("{{ .a }}\n{{ nope }}\n{{ .b }}\n{{ if }}{{ end }}")
+    ///     .unwrap_err();
+    /// let lines: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+    /// assert_eq!(
+    ///     lines,
+    ///     vec![
+    ///         "template: :2:function nope not defined",
+    ///         "template: :4:missing value for if",
+    ///         // Follow-up error of the failed if.
+    ///         "template: :4:unexpected {{end}}",
+    ///     ]
+    /// );
+    /// assert_eq!(errors[0].span().map(|s| s.start), Some(12));
+    /// ```
+    pub fn parse_recovering<T: Into<String>>(
+        &mut self,
+        text: T,
+    ) -> Result<Vec<Defined>, Vec<ParseError>> {
+        let name = self.name.clone();
+        let text = text.into();
+        let source = self.add_source(&name, text.as_str());
+        self.text = text;
+        let tree_set = parse_recovering(name, source, self.funcs.name_set(), self.parse_options())?;
+        Ok(self.add_trees(tree_set))
     }
 
     /// Add the given `text` as a template with a `name`.
@@ -115,15 +827,327 @@ impl Template {
         &mut self,
         name: N,
         text: T,
+    ) -> Result<Vec<Defined>, TemplateError> {
+        let name = name.into();
+        let source = self.add_source(&name, text.into());
+        let tree_set = parse(name, source, self.funcs.name_set(), self.parse_options())?;
+        Ok(self.add_trees(tree_set))
+    }
+
+    /// Sets the template executed by [`Template::render_page`], which includes the content of
+    /// the page via `{{ block "content" . }}` or `{{ template "content" . }}`.
+    pub fn set_layout<T: Into<String>>(&mut self, name: T) {
+        self.layout = Some(name.into());
+    }
+
+    /// Adds the page `name` for [`Template::render_page`]. The text outside of definitions is
+    /// the `content` of the page, unless it defines `content` itself. Unlike with
+    /// [`Template::add_template`] the definitions of a page, e.g. of a `title` block, are only
+    /// visible while rendering this page, so every page can override the blocks of the layout.
+    pub fn add_page<N: Into<String>, T: Into<String>>(
+        &mut self,
+        name: N,
+        text: T,
     ) -> Result<(), TemplateError> {
-        let tree_set = parse(
-            name.into(),
-            text.into(),
-            self.funcs.keys().cloned().collect(),
+        let name = name.into();
+        let source = self.add_source(&name, text.into());
+        let mut tree_set = parse(
+            name.clone(),
+            source,
+            self.funcs.name_set(),
+            self.parse_options(),
         )?;
-        self.tree_set.extend(tree_set);
+        let defines_content = tree_set
+            .get("content")
+            .is_some_and(|tree| tree.root.is_some() && tree.name() != name);
+        if !defines_content {
+            if let Some(tree) = tree_set.get(&name).cloned() {
+                tree_set.insert("content".to_owned(), tree);
+            }
+        }
+        self.pages.insert(name, tree_set);
         Ok(())
     }
+
+    // The options for parsing a text, with the extension keywords and the argument counts to
+    // check if parsing strictly.
+    pub(crate) fn parse_options(&self) -> ParseOptions {
+        let mut options = self.parse_options.clone();
+        options.extensions = self.extensions.keys().cloned().collect();
+        if self.strict_parse {
+            options.arities = self.funcs.arities();
+        }
+        options
+    }
+
+    /// Returns the parsed template `name`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Template;
+    ///
+    /// let mut tmpl = Template::with_name("main");
+    /// tmpl.parse(r#"{{ $x := 1 }}{{ define "item" }}{{ . }}{{ end }}"#).unwrap();
+    /// assert_eq!(tmpl.tree("main").unwrap().vars(), ["$x"]);
+    /// let item = tmpl.tree("item").unwrap();
+    /// assert_eq!(item.root().unwrap().to_string(), "{{.}}");
+    /// assert_eq!(&item.source()[item.defined_at().start..item.defined_at().end], r#""item""#);
+    /// assert!(tmpl.tree("missing").is_none());
+    /// ```
+    pub fn tree(&self, name: &str) -> Option<&Tree> {
+        self.tree_set.get(name)
+    }
+
+    /// Returns the text the template `name` was parsed from: the text last passed as `name` to
+    /// [`Template::parse`], [`Template::add_template`] or [`Template::add_page`], even if
+    /// parsing failed, or else the text a template defined via `define` or `block` is part
+    /// of.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Template;
+    ///
+    /// let mut tmpl = Template::with_name("main");
+    /// tmpl.add_template("item", r#"{{ define "price" }}{{ . }}${{ end }}"#).unwrap();
+    /// tmpl.parse("{{ if }}").unwrap_err();
+    /// assert_eq!(tmpl.source("main"), Some("{{ if }}"));
+    /// assert_eq!(tmpl.source("price"), tmpl.source("item"));
+    /// assert_eq!(tmpl.source("missing"), None);
+    /// ```
+    pub fn source(&self, name: &str) -> Option<&str> {
+        match self.sources.get(name) {
+            Some(source) => Some(source),
+            None => self.tree_set.get(name).map(Tree::source),
+        }
+    }
+
+    /// Renders the line of the template source an error occurred at, with the failing action
+    /// underlined. Works for [`ParseError`]s, [`ExecError`]s and [`TemplateError`]s which know
+    /// their template and span, otherwise returns `None`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("a:\n  {{ .a.b }}").unwrap();
+    /// let err = tmpl.render(&Context::from(1)).unwrap_err();
+    /// assert_eq!(
+    ///     tmpl.error_snippet(&err).unwrap(),
+    ///     "2 |   {{ .a.b }}\n  |   ^^^^^^^^^^"
+    /// );
+    /// ```
+    pub fn error_snippet(&self, err: &(dyn std::error::Error + 'static)) -> Option<String> {
+        let (ctx, source) = if let Some(e) = err.downcast_ref::<ParseError>() {
+            let ctx = e.context()?;
+            (ctx, self.sources.get(&ctx.origin).map(|s| &**s))
+        } else if let Some(e) = err.downcast_ref::<ExecError>() {
+            let ctx = e.context()?;
+            // Execution errors refer to the parsed trees.
+            let tree = self.tree_set.get(&ctx.name);
+            (
+                ctx,
+                tree.filter(|t| t.origin() == ctx.origin).map(Tree::source),
+            )
+        } else {
+            return match err.downcast_ref::<TemplateError>()? {
+                TemplateError::ParseError(e) => self.error_snippet(e),
+                TemplateError::ExecError(e) => self.error_snippet(e),
+                _ => None,
+            };
+        };
+        snippet(source?, ctx.span)
+    }
+
+    /// Returns all parsed templates with their names in no particular order.
+    pub fn trees(&self) -> impl Iterator<Item = (&str, &Tree)> {
+        self.tree_set
+            .iter()
+            .map(|(name, tree)| (name.as_str(), tree))
+    }
+
+    /// Imports the templates and functions of `other`, e.g. a pack of partials and helpers
+    /// built separately.
+    ///
+    /// Only the functions registered on `other` are imported, its builtins are left out so
+    /// builtins overridden here stay overridden. Data added via [`Template::add_data`] and
+    /// extensions added via [`Template::add_extension`] are imported as well.
+    ///
+    /// `collision` decides about templates, functions, data and extensions defined by both.
+    /// Templates only clash if both have a body, so an empty main template or `define` never
+    /// replaces a definition, data, iterators, context functions and extensions only clash if
+    /// they differ. With [`Collision::Error`] nothing is imported if there is a clash.
+    ///
+    /// Returns the imported templates, sorted by name.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Collision, Context, FuncError, Template, Value};
+    ///
+    /// fn shout(args: &[Value]) -> Result<Value, FuncError> {
+    ///     Ok(Value::from(format!("{}!", args[0])))
+    /// }
+    ///
+    /// let mut pack = Template::default();
+    /// pack.add_func("shout", shout);
+    /// pack.parse(r#"{{ define "greet" }}{{ shout . }}{{ end }}"#).unwrap();
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"Hello {{ template "greet" . }}"#).unwrap();
+    /// let defined = tmpl.extend(&pack, Collision::Error).unwrap();
+    /// assert_eq!(defined[0].name, "greet");
+    /// assert_eq!(&tmpl.render(&Context::from("World")).unwrap(), "Hello World!");
+    ///
+    /// let err = tmpl.extend(&pack, Collision::Error).unwrap_err();
+    /// assert_eq!(err.to_string(), "template greet is already defined");
+    /// ```
+    pub fn extend(
+        &mut self,
+        other: &Template,
+        collision: Collision,
+    ) -> Result<Vec<Defined>, TemplateError> {
+        // The builtins of `other` are left out, only the functions it registered are imported.
+        let clashing_func = |name: &str| !other.funcs.is_builtin(name) && self.funcs.contains(name);
+        let clashing_iter = |name: &str| match (
+            self.funcs.get_iterator(name),
+            other.funcs.get_iterator(name),
+        ) {
+            (Some(own), Some(theirs)) => !Arc::ptr_eq(&own, &theirs),
+            _ => false,
+        };
+        let clashing_context_func = |name: &str| match (
+            self.funcs.get_context_func(name),
+            other.funcs.get_context_func(name),
+        ) {
+            (Some(own), Some(theirs)) => !Arc::ptr_eq(&own, &theirs),
+            _ => false,
+        };
+        let mut trees: Vec<(&String, &Tree)> = other.tree_set.iter().collect();
+        trees.sort_by_key(|(name, _)| name.as_str());
+
+        if collision == Collision::Error {
+            if let Some((name, _)) = trees
+                .iter()
+                .find(|(name, tree)| tree.clashes(self.tree_set.get(*name)))
+            {
+                return Err(TemplateError::TemplateAlreadyDefined(name.to_string()));
+            }
+            let mut names: Vec<&str> = other.funcs.names().collect();
+            names.sort_unstable();
+            if let Some(name) = names.into_iter().find(|name| clashing_func(name)) {
+                return Err(TemplateError::FuncAlreadyDefined(name.to_owned()));
+            }
+            let mut names: Vec<&str> = other.funcs.iterator_names().collect();
+            names.sort_unstable();
+            if let Some(name) = names.into_iter().find(|name| clashing_iter(name)) {
+                return Err(TemplateError::FuncAlreadyDefined(name.to_owned()));
+            }
+            let mut names: Vec<&str> = other.funcs.context_func_names().collect();
+            names.sort_unstable();
+            if let Some(name) = names.into_iter().find(|name| clashing_context_func(name)) {
+                return Err(TemplateError::FuncAlreadyDefined(name.to_owned()));
+            }
+            let mut names: Vec<&String> = other.data.keys().collect();
+            names.sort_unstable();
+            if let Some(name) = names.into_iter().find(|name| {
+                self.data
+                    .get(*name)
+                    .is_some_and(|own| own != &other.data[*name])
+            }) {
+                return Err(TemplateError::DataAlreadyDefined(name.clone()));
+            }
+            let mut names: Vec<&String> = other.extensions.keys().collect();
+            names.sort_unstable();
+            if let Some(name) = names.into_iter().find(|name| {
+                self.extensions
+                    .get(*name)
+                    .is_some_and(|own| !Arc::ptr_eq(own, &other.extensions[*name]))
+            }) {
+                return Err(TemplateError::ExtensionAlreadyDefined(name.clone()));
+            }
+        }
+
+        let keep = collision == Collision::Keep;
+        let funcs: Vec<&str> = other
+            .funcs
+            .names()
+            .filter(|name| !other.funcs.is_builtin(name))
+            .filter(|name| !(keep && self.funcs.contains(name)))
+            .collect();
+        let iters: Vec<(String, IterFunc)> = other
+            .funcs
+            .iterator_names()
+            .filter(|name| !(keep && self.funcs.contains_iterator(name)))
+            .filter_map(|name| Some((name.to_owned(), other.funcs.get_iterator(name)?)))
+            .collect();
+        let context_funcs: Vec<(String, ContextFunc)> = other
+            .funcs
+            .context_func_names()
+            .filter(|name| !(keep && self.funcs.contains_context_func(name)))
+            .filter_map(|name| Some((name.to_owned(), other.funcs.get_context_func(name)?)))
+            .collect();
+        for name in funcs {
+            self.funcs.import(&other.funcs, name);
+        }
+        for (name, iter) in iters {
+            self.funcs.register_iterator(name, iter);
+        }
+        for (name, func) in context_funcs {
+            self.funcs.register_context_func(name, func);
+        }
+        for (name, value) in &other.data {
+            if !(keep && self.data.contains_key(name)) {
+                self.data.insert(name.clone(), value.clone());
+            }
+        }
+        for (name, handler) in &other.extensions {
+            if !(keep && self.extensions.contains_key(name)) {
+                self.extensions.insert(name.clone(), handler.clone());
+            }
+        }
+
+        let mut defined = vec![];
+        for (name, tree) in trees {
+            let old = self.tree_set.get(name);
+            if !tree.replaces(old) || (keep && tree.clashes(old)) {
+                continue;
+            }
+            defined.push(Defined {
+                name: name.clone(),
+                overridden: old.is_some(),
+            });
+            self.tree_set.insert(name.clone(), tree.clone());
+        }
+        Ok(defined)
+    }
+
+    // Remembers `text` as the source of the template `name`.
+    pub(crate) fn add_source(&mut self, name: &str, text: impl Into<Arc<str>>) -> Arc<str> {
+        let source = text.into();
+        self.sources.insert(name.to_owned(), source.clone());
+        source
+    }
+
+    fn add_trees(&mut self, tree_set: HashMap<String, Tree>) -> Vec<Defined> {
+        let mut trees: Vec<(String, Tree)> = tree_set.into_iter().collect();
+        trees.sort_by_key(|(_, tree)| tree.id());
+        let mut defined = vec![];
+        for (name, tree) in trees {
+            let old = self.tree_set.get(&name);
+            if tree.replaces(old) {
+                defined.push(Defined {
+                    name: name.clone(),
+                    overridden: old.is_some(),
+                });
+                self.tree_set.insert(name, tree);
+            }
+        }
+        defined
+    }
 }
 
 #[cfg(test)]
@@ -136,4 +1160,215 @@ mod tests_mocked {
         assert!(t.parse(r#"{{ if eq "bar" "bar" }} 2000 {{ end }}"#).is_ok());
         assert!(t.tree_set.contains_key("foo"));
     }
+
+    #[test]
+    fn test_defined() {
+        let mut t = Template::with_name("main");
+        let defined = t
+            .parse(
+                r#"{{ define "z" }}z{{ end }}{{ block "a" . }}a{{ end }}{{ define "e" }}{{ end }}"#,
+            )
+            .unwrap();
+        let names: Vec<_> = defined.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "z", "a", "e"]);
+        assert!(defined.iter().all(|d| !d.overridden));
+        let defined = t.add_template("z", "Z").unwrap();
+        assert_eq!(
+            defined,
+            vec![Defined {
+                name: "z".to_owned(),
+                overridden: true
+            }]
+        );
+        assert!(t.parse(r#"{{ define "a" }}{{ end }}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_override_order() {
+        use crate::Context;
+        let mut t = Template::with_name("layout");
+        t.parse(r#"[{{ block "a" . }}A{{ end }}{{ block "b" . }}B{{ end }}]"#)
+            .unwrap();
+        t.parse(r#"{{ define "a" }}a{{ end }}"#).unwrap();
+        t.add_template("b", "b").unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "[ab]");
+        t.parse(r#"{{ define "a" }}{{ end }}{{ define "b" }}{{ end }}"#)
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "[ab]");
+        t.parse(r#"[{{ block "a" . }}A{{ end }}]"#).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "[A]");
+    }
+
+    #[test]
+    fn test_extend() {
+        use crate::Context;
+        fn one(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(1))
+        }
+        fn two(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(2))
+        }
+
+        let mut pack = Template::default();
+        pack.add_func("num", two);
+        pack.add_func("extra", two);
+        pack.parse(
+            r#"{{ define "a" }}A2{{ end }}{{ define "b" }}B2{{ end }}{{ define "c" }}{{ end }}"#,
+        )
+        .unwrap();
+        let base = |t: &mut Template| {
+            t.add_func("num", one);
+            t.parse(r#"{{ define "a" }}A1{{ end }}{{ define "c" }}C1{{ end }}"#)
+                .unwrap();
+            t.parse(r#"{{ template "a" }}{{ template "b" }}{{ template "c" }}{{ num }}"#)
+                .unwrap();
+        };
+
+        let mut t = Template::default();
+        base(&mut t);
+        let err = t.extend(&pack, Collision::Error).unwrap_err();
+        assert_eq!(err.to_string(), "template a is already defined");
+        assert!(!t.tree_set.contains_key("b"));
+        assert!(!t.funcs.contains("extra"));
+
+        let defined = t.extend(&pack, Collision::Keep).unwrap();
+        let names: Vec<_> = defined.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, vec!["b"]);
+        assert!(t.funcs.contains("extra"));
+        assert_eq!(t.render(&Context::empty()).unwrap(), "A1B2C11");
+
+        let mut t = Template::default();
+        base(&mut t);
+        let defined = t.extend(&pack, Collision::Override).unwrap();
+        assert_eq!(
+            defined,
+            vec![
+                Defined {
+                    name: "a".to_owned(),
+                    overridden: true
+                },
+                Defined {
+                    name: "b".to_owned(),
+                    overridden: false
+                },
+            ]
+        );
+        assert_eq!(t.render(&Context::empty()).unwrap(), "A2B2C12");
+
+        // The builtins of the pack and empty templates don't clash.
+        let mut t = Template::default();
+        t.add_func("num", two);
+        t.parse(r#"{{ define "c" }}C1{{ end }}"#).unwrap();
+        let mut pack = Template::default();
+        pack.parse(r#"{{ define "c" }}{{ end }}"#).unwrap();
+        assert!(t.extend(&pack, Collision::Error).unwrap().is_empty());
+        pack.add_func("num", one);
+        let err = t.extend(&pack, Collision::Error).unwrap_err();
+        assert_eq!(err.to_string(), "function num is already defined");
+
+        // Builtins overridden by the host stay overridden.
+        let mut t = Template::default();
+        t.add_func("len", two);
+        t.parse(r#"{{ len "abc" }}"#).unwrap();
+        t.extend(&Template::default(), Collision::Error).unwrap();
+        t.extend(&Template::default(), Collision::Override).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "2");
+        assert!(!t.funcs.is_builtin("len"));
+        let mut pack = Template::default();
+        pack.add_func("len", one);
+        let err = t.extend(&pack, Collision::Error).unwrap_err();
+        assert_eq!(err.to_string(), "function len is already defined");
+        t.extend(&pack, Collision::Override).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "1");
+
+        let mut t = Template::default();
+        t.add_data("a", Value::from(1));
+        t.add_data("b", Value::from(1));
+        let mut pack = Template::default();
+        pack.add_data("a", Value::from(1));
+        pack.add_data("b", Value::from(2));
+        pack.add_data("c", Value::from(3));
+        let err = t.extend(&pack, Collision::Error).unwrap_err();
+        assert_eq!(err.to_string(), "data b is already defined");
+        assert!(!t.data.contains_key("c"));
+        t.extend(&pack, Collision::Keep).unwrap();
+        assert_eq!(t.data["b"], Value::from(1));
+        assert_eq!(t.data["c"], Value::from(3));
+        t.extend(&pack, Collision::Override).unwrap();
+        assert_eq!(t.data["b"], Value::from(2));
+
+        let mut t = Template::default();
+        t.add_extension("x", |_: &Value, body: &str| Ok(body.to_owned()));
+        let mut pack = Template::default();
+        pack.add_extension("x", |_: &Value, _: &str| Ok(String::new()));
+        pack.add_extension("y", |_: &Value, _: &str| Ok(String::new()));
+        let err = t.extend(&pack, Collision::Error).unwrap_err();
+        assert_eq!(err.to_string(), "extension x is already defined");
+        assert!(!t.extensions.contains_key("y"));
+        t.extend(&pack, Collision::Keep).unwrap();
+        t.parse("{{ x }}a{{ end }}{{ y }}b{{ end }}").unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_source() {
+        let mut t = Template::with_name("main");
+        t.parse(r#"{{ define "a" }}a{{ end }}{{ template "a" }}"#)
+            .unwrap();
+        assert_eq!(t.text, r#"{{ define "a" }}a{{ end }}{{ template "a" }}"#);
+        assert_eq!(t.source("a"), Some(t.text.as_str()));
+        t.add_template("b", "{{ if }}").unwrap_err();
+        assert_eq!(t.source("b"), Some("{{ if }}"));
+        assert_eq!(t.source("e"), None);
+    }
+
+    #[test]
+    fn test_origin() {
+        use crate::Context;
+        let mut t = Template::with_name("main");
+        t.add_template("helpers", "\n{{ define \"price\" }}{{ .a.b }}{{ end }}")
+            .unwrap();
+        t.parse(r#"{{ template "price" . }}"#).unwrap();
+        assert_eq!(t.tree("price").unwrap().origin(), "helpers");
+        assert_eq!(t.tree("main").unwrap().origin(), "main");
+        let err = t.render(&Context::from(1)).unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!(
+            (ctx.name.as_str(), ctx.to_string(), ctx.col),
+            ("price", "helpers:2".to_owned(), 21)
+        );
+        let err = t
+            .add_template("more", "{{ define \"tax\" }}\n{{ if }}{{ end }}")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "template: more:2:missing value for if");
+        assert_eq!(
+            t.error_snippet(&err).unwrap(),
+            "2 | {{ if }}{{ end }}\n  |       ^^"
+        );
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        use crate::Context;
+        let mut t = Template::with_name("main");
+        t.add_template("inner", "\u{feff}a\r\n\t b {{ .x.y }}\r\n")
+            .unwrap();
+        t.parse(r#"{{ template "inner" . }}"#).unwrap();
+        let err = t.render(&Context::from(1)).unwrap_err();
+        assert_eq!(err.template(), Some("inner"));
+        assert_eq!(
+            t.error_snippet(&err).unwrap(),
+            "2 | \t b {{ .x.y }}\n  | \t   ^^^^^^^^^^"
+        );
+        // A failed parse doesn't replace the tree executed above.
+        let err = TemplateError::from(t.parse("é\n{{ .a }}{{ end }}").unwrap_err());
+        assert_eq!(
+            t.error_snippet(&err).unwrap(),
+            "2 | {{ .a }}{{ end }}\n  |                ^^"
+        );
+        let err = t.render(&Context::from(1)).unwrap_err();
+        assert!(t.error_snippet(&err).is_some());
+        let err = TemplateError::TemplateAlreadyDefined("x".to_owned());
+        assert_eq!(t.error_snippet(&err), None);
+    }
 }
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::error::{ExecError, TemplateError};
+use crate::escape::OutputMode;
+use crate::exec::{Context, ExecContext, ExecOptions};
+use crate::parse::parse;
+use crate::template::{Formatter, GoVersion, Template};
+
+use gtmpl_value::{Func, FuncError, Value};
+
+/// A registry of named templates sharing one set of functions.
+///
+/// Every template inserted into the set can call every other template via
+/// `{{ template "name" . }}`, including the ones introduced by `define`. A `TemplateSet` is meant
+/// to be built once and then shared between render threads, e.g. behind an `Arc`.
+///
+/// ## Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use gtmpl::{Context, TemplateSet};
+///
+/// let mut set = TemplateSet::default();
+/// set.insert("greeting", r#"Hello {{ template "name" . }}!"#).unwrap();
+/// set.insert("name", "{{ .name }}").unwrap();
+///
+/// let set = Arc::new(set);
+/// let mut ctx = std::collections::HashMap::new();
+/// ctx.insert("name".to_owned(), "World");
+/// let output = set.render("greeting", &Context::from(ctx));
+/// assert_eq!(&output.unwrap(), "Hello World!");
+/// ```
+#[derive(Default)]
+pub struct TemplateSet {
+    template: Template,
+    // Maps every tree to the name of the template it was inserted with.
+    owners: HashMap<String, String>,
+}
+
+impl TemplateSet {
+    /// Adds a single custom function to the set.
+    ///
+    /// Functions have to be added before inserting the templates using them.
+    pub fn add_func(&mut self, name: &str, func: Func) {
+        self.template.add_func(name, func);
+    }
+
+    /// Adds custom functions to the set.
+    ///
+    /// Functions have to be added before inserting the templates using them.
+    pub fn add_funcs<T: Into<String> + Clone>(&mut self, funcs: &[(T, Func)]) {
+        self.template.add_funcs(funcs);
+    }
+
+    /// Adds a constant readable by all templates of the set via `data`, see
+    /// [`Template::add_data`](crate::Template::add_data).
+    pub fn add_data<T: Into<String>>(&mut self, name: T, value: Value) {
+        self.template.add_data(name, value);
+    }
+
+    /// Adds an iterator to the set, see
+    /// [`Template::add_iterator`](crate::Template::add_iterator).
+    pub fn add_iterator<T, F>(&mut self, name: T, iter: F)
+    where
+        T: Into<String>,
+        F: Fn(&[Value]) -> Result<Box<dyn Iterator<Item = Value>>, FuncError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.template.add_iterator(name, iter);
+    }
+
+    /// Adds a context function to the set, see
+    /// [`Template::add_context_func`](crate::Template::add_context_func).
+    pub fn add_context_func<T, F>(&mut self, name: T, func: F)
+    where
+        T: Into<String>,
+        F: Fn(&[Value], &ExecContext<'_>) -> Result<Value, FuncError> + Send + Sync + 'static,
+    {
+        self.template.add_context_func(name, func);
+    }
+
+    /// Adds an extension block keyword for templates inserted afterwards, see
+    /// [`Template::add_extension`](crate::Template::add_extension).
+    pub fn add_extension<T, F>(&mut self, name: T, handler: F)
+    where
+        T: Into<String>,
+        F: Fn(&Value, &str) -> Result<String, FuncError> + Send + Sync + 'static,
+    {
+        self.template.add_extension(name, handler);
+    }
+
+    /// Adds custom functions under a `namespace`, see
+    /// [`Template::add_namespaced_funcs`](crate::Template::add_namespaced_funcs).
+    pub fn add_namespaced_funcs<T: Into<String> + Clone>(
+        &mut self,
+        namespace: &str,
+        funcs: &[(T, Func)],
+    ) {
+        self.template.add_namespaced_funcs(namespace, funcs);
+    }
+
+    /// Sets a `formatter` used by all templates of the set, see
+    /// [`Template::set_formatter`](crate::Template::set_formatter).
+    pub fn set_formatter(&mut self, formatter: Formatter) {
+        self.template.set_formatter(formatter);
+    }
+
+    /// Sets the escaping of the output of all templates of the set, see
+    /// [`Template::set_output_mode`](crate::Template::set_output_mode).
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.template.set_output_mode(mode);
+    }
+
+    /// Adds a filter post-processing the output of all templates of the set, see
+    /// [`Template::add_post_filter`](crate::Template::add_post_filter).
+    pub fn add_post_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(String) -> String + Send + Sync + 'static,
+    {
+        self.template.add_post_filter(filter);
+    }
+
+    /// Enables the field name fallback for all templates of the set, see
+    /// [`Template::set_field_fallback`](crate::Template::set_field_fallback).
+    pub fn set_field_fallback(&mut self, enabled: bool) {
+        self.template.set_field_fallback(enabled);
+    }
+
+    /// Enables strict mode for all templates of the set, see
+    /// [`Template::set_strict`](crate::Template::set_strict).
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.template.set_strict(enabled);
+    }
+
+    /// Sets the execution options for all templates of the set, see
+    /// [`Template::set_exec_options`](crate::Template::set_exec_options).
+    pub fn set_exec_options(&mut self, options: ExecOptions) {
+        self.template.set_exec_options(options);
+    }
+
+    /// Enables bracket index syntax for templates inserted afterwards, see
+    /// [`Template::set_index_syntax`](crate::Template::set_index_syntax).
+    pub fn set_index_syntax(&mut self, enabled: bool) {
+        self.template.set_index_syntax(enabled);
+    }
+
+    /// Enables numeric fields on arrays for templates inserted afterwards, see
+    /// [`Template::set_numeric_fields`](crate::Template::set_numeric_fields).
+    pub fn set_numeric_fields(&mut self, enabled: bool) {
+        self.template.set_numeric_fields(enabled);
+    }
+
+    /// Enables arguments for the `template` action in templates inserted afterwards, see
+    /// [`Template::set_template_args`](crate::Template::set_template_args).
+    pub fn set_template_args(&mut self, enabled: bool) {
+        self.template.set_template_args(enabled);
+    }
+
+    /// Allows calls of undefined functions in templates inserted afterwards, see
+    /// [`Template::set_allow_undefined_functions`](crate::Template::set_allow_undefined_functions).
+    pub fn set_allow_undefined_functions(&mut self, enabled: bool) {
+        self.template.set_allow_undefined_functions(enabled);
+    }
+
+    /// Emulates a Golang release for templates inserted afterwards and all executions, see
+    /// [`Template::set_go_version`](crate::Template::set_go_version).
+    pub fn set_go_version(&mut self, version: GoVersion) {
+        self.template.set_go_version(version);
+    }
+
+    /// Sets the action delimiters for templates inserted afterwards, see
+    /// [`Template::set_delims`](crate::Template::set_delims).
+    pub fn set_delims<L: Into<String>, R: Into<String>>(&mut self, left: L, right: R) {
+        self.template.set_delims(left, right);
+    }
+
+    /// Enables trimming around block actions for templates inserted afterwards, see
+    /// [`Template::set_trim_blocks`](crate::Template::set_trim_blocks).
+    pub fn set_trim_blocks(&mut self, enabled: bool) {
+        self.template.set_trim_blocks(enabled);
+    }
+
+    /// Enables checking the number of arguments of builtin functions for templates inserted
+    /// afterwards, see [`Template::set_strict_parse`](crate::Template::set_strict_parse).
+    pub fn set_strict_parse(&mut self, enabled: bool) {
+        self.template.set_strict_parse(enabled);
+    }
+
+    /// Enables the environment and file system functions for all templates of the set, see
+    /// [`Template::enable_os_funcs`](crate::Template::enable_os_funcs).
+    #[cfg(feature = "gtmpl_os")]
+    pub fn enable_os_funcs(&mut self, policy: crate::funcs::os::OsPolicy) {
+        self.template.enable_os_funcs(policy);
+    }
+
+    /// Parses `text` and inserts it as template `name`.
+    ///
+    /// An existing template with the same `name` is replaced along with all templates it
+    /// defined. Defining a template which another inserted template already defines fails with
+    /// [`TemplateError::TemplateAlreadyDefined`]. If inserting fails the templates of the set
+    /// are left unchanged, only the text is kept for [`TemplateSet::error_snippet`].
+    pub fn insert<N: Into<String>, T: Into<String>>(
+        &mut self,
+        name: N,
+        text: T,
+    ) -> Result<(), TemplateError> {
+        let name = name.into();
+        let source = self.template.add_source(&name, text.into());
+        let tree_set = parse(
+            name.clone(),
+            source.clone(),
+            self.template.funcs.name_set(),
+            self.template.parse_options(),
+        )?;
+        for (tree_name, tree) in &tree_set {
+            let owner = self.owners.get(tree_name);
+            if owner.is_some_and(|owner| *owner != name)
+                && tree.replaces(self.template.tree_set.get(tree_name))
+            {
+                return Err(TemplateError::TemplateAlreadyDefined(tree_name.clone()));
+            }
+        }
+        self.remove(&name);
+        self.template.add_source(&name, source);
+        for (tree_name, tree) in tree_set {
+            if tree.replaces(self.template.tree_set.get(&tree_name)) {
+                self.owners.insert(tree_name.clone(), name.clone());
+                self.template.tree_set.insert(tree_name, tree);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the template `name` and all templates it defined.
+    ///
+    /// Returns `false` if no template was inserted as `name`.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let owned: Vec<String> = self
+            .owners
+            .iter()
+            .filter(|&(_, owner)| owner == name)
+            .map(|(tree_name, _)| tree_name.clone())
+            .collect();
+        for tree_name in &owned {
+            self.owners.remove(tree_name);
+            self.template.tree_set.remove(tree_name);
+        }
+        self.template.sources.remove(name);
+        !owned.is_empty()
+    }
+
+    /// Returns `true` if a template (inserted or defined) called `name` exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.template.tree_set.contains_key(name)
+    }
+
+    /// Returns the names of all templates in the set, including defined ones.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.template.tree_set.keys().map(String::as_str)
+    }
+
+    /// Returns the text the template `name` was inserted or defined with, see
+    /// [`Template::source`].
+    pub fn source(&self, name: &str) -> Option<&str> {
+        self.template.source(name)
+    }
+
+    /// Renders the line of the template source an error occurred at, see
+    /// [`Template::error_snippet`].
+    pub fn error_snippet(&self, err: &(dyn std::error::Error + 'static)) -> Option<String> {
+        self.template.error_snippet(err)
+    }
+
+    /// Executes the template `name` writing to `writer`.
+    pub fn execute<T: Write>(
+        &self,
+        name: &str,
+        writer: &mut T,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        self.check_defined(name)?;
+        self.template.execute_template(name, writer, data)
+    }
+
+    /// Renders the template `name` to a `String`.
+    pub fn render(&self, name: &str, data: &Context) -> Result<String, ExecError> {
+        self.check_defined(name)?;
+        self.template.render_template(name, data)
+    }
+
+    fn check_defined(&self, name: &str) -> Result<(), ExecError> {
+        if self.contains(name) {
+            Ok(())
+        } else {
+            Err(ExecError::TemplateNotDefined(name.to_owned()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use gtmpl_value::{FuncError, Value};
+
+    #[test]
+    fn test_cross_template() {
+        let mut set = TemplateSet::default();
+        set.insert("a", r#"a{{ template "b" . }}"#).unwrap();
+        set.insert("b", r#"b{{ template "c" . }}"#).unwrap();
+        set.insert(
+            "c",
+            r#"{{ define "d" }}d{{ . }}{{ end }}c{{ template "d" . }}"#,
+        )
+        .unwrap();
+        assert_eq!(set.render("a", &Context::from(1)).unwrap(), "abcd1");
+        assert_eq!(set.render("d", &Context::from(2)).unwrap(), "d2");
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut set = TemplateSet::default();
+        set.insert("c", r#"{{ define "d" }}d{{ end }}c"#).unwrap();
+        assert!(set.contains("d"));
+        assert!(set.remove("c"));
+        assert!(!set.contains("c"));
+        assert!(!set.contains("d"));
+        assert!(!set.remove("c"));
+        assert!(matches!(
+            set.render("c", &Context::empty()),
+            Err(ExecError::TemplateNotDefined(_))
+        ));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut set = TemplateSet::default();
+        set.insert("a", r#"{{ define "old" }}{{ end }}1"#).unwrap();
+        set.insert("a", "2").unwrap();
+        assert!(!set.contains("old"));
+        assert_eq!(set.render("a", &Context::empty()).unwrap(), "2");
+        let err = set.insert("a", "{{ if }}").unwrap_err();
+        assert_eq!(set.render("a", &Context::empty()).unwrap(), "2");
+        assert_eq!(set.source("a"), Some("{{ if }}"));
+        assert_eq!(
+            set.error_snippet(&err).unwrap(),
+            "1 | {{ if }}\n  |       ^^"
+        );
+    }
+
+    #[test]
+    fn test_define_collision() {
+        let mut set = TemplateSet::default();
+        set.insert("a", r#"{{ define "d" }}a{{ end }}"#).unwrap();
+        let err = set
+            .insert("b", r#"{{ define "d" }}b{{ end }}"#)
+            .unwrap_err();
+        assert!(matches!(err, TemplateError::TemplateAlreadyDefined(ref name) if name == "d"));
+        assert!(!set.contains("b"));
+        assert_eq!(set.render("d", &Context::empty()).unwrap(), "a");
+        assert!(set.remove("a"));
+        assert!(!set.contains("d"));
+
+        set.insert("b", r#"{{ define "d" }}b{{ end }}"#).unwrap();
+        assert!(set.insert("a", r#"{{ define "d" }}a{{ end }}"#).is_err());
+        assert!(set.insert("d", "d").is_err());
+        set.insert("a", r#"{{ define "d" }}{{ end }}a"#).unwrap();
+        assert!(set.remove("a"));
+        assert_eq!(set.render("d", &Context::empty()).unwrap(), "b");
+
+        set.insert("b", r#"{{ define "e" }}b{{ end }}"#).unwrap();
+        set.insert("a", r#"{{ define "d" }}a{{ end }}"#).unwrap();
+        assert!(set.remove("b"));
+        assert_eq!(set.render("d", &Context::empty()).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_shared_funcs() {
+        fn foo(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from("foo"))
+        }
+        let mut set = TemplateSet::default();
+        set.add_func("foo", foo);
+        set.insert("a", "{{ foo }}").unwrap();
+        set.insert("b", r#"{{ template "a" }}{{ foo }}"#).unwrap();
+        assert_eq!(set.render("b", &Context::empty()).unwrap(), "foofoo");
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<TemplateSet>();
+    }
+}
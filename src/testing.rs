@@ -0,0 +1,191 @@
+//! Helpers for golden tests of rendered output.
+//!
+//! [`assert_template_eq!`](crate::assert_template_eq) renders a template and on a mismatch
+//! panics with a line diff, in which whitespace is made visible: spaces as `·`, tabs as `→`,
+//! carriage returns as `␍` and line ends as `⏎`. A missing or extra trailing newline or
+//! indentation off by one space is easy to spot that way.
+//!
+//! ```rust
+//! use gtmpl::assert_template_eq;
+//!
+//! assert_template_eq!("a: {{ . }}\n", 1, "a: 1\n");
+//! ```
+//!
+//! A failing assertion like `assert_template_eq!("a:  {{ . }}", 1, "a: 1\n")` panics with:
+//!
+//! ```text
+//! rendered output differs (- expected, + actual):
+//! - a:·1⏎
+//! + a:··1
+//! ```
+use std::fmt::Write;
+
+use crate::exec::Context;
+use crate::template::Template;
+
+/// Something rendering to a string for [`assert_template_eq`], i.e. a parsed [`Template`] or
+/// the text of one.
+pub trait Render {
+    /// Renders `self` with `context`, the error is rendered as message.
+    fn render_to_string(&self, context: &Context) -> Result<String, String>;
+}
+
+impl Render for Template {
+    fn render_to_string(&self, context: &Context) -> Result<String, String> {
+        self.render(context).map_err(|e| e.to_string())
+    }
+}
+
+impl Render for str {
+    fn render_to_string(&self, context: &Context) -> Result<String, String> {
+        let mut tmpl = Template::default();
+        tmpl.parse(self).map_err(|e| e.to_string())?;
+        tmpl.render_to_string(context)
+    }
+}
+
+impl Render for String {
+    fn render_to_string(&self, context: &Context) -> Result<String, String> {
+        self.as_str().render_to_string(context)
+    }
+}
+
+impl<T: Render + ?Sized> Render for &T {
+    fn render_to_string(&self, context: &Context) -> Result<String, String> {
+        (**self).render_to_string(context)
+    }
+}
+
+/// Asserts that a template, given as [`Template`] or text, renders to `expected` with the given
+/// context. Panics with a diff of the output, see the [module](crate::testing) documentation.
+///
+/// The context is anything convertible into a [`Value`](crate::Value). Use
+/// [`testing::assert_template_eq`](crate::testing::assert_template_eq) for a [`Context`].
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{assert_template_eq, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.parse("{{ range . }}- {{ . }}\n{{ end }}").unwrap();
+/// assert_template_eq!(tmpl, vec!["a", "b"], "- a\n- b\n");
+/// ```
+#[macro_export]
+macro_rules! assert_template_eq {
+    ($template:expr, $context:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_template_eq(&$template, &$crate::Context::from($context), $expected)
+    };
+}
+
+/// Asserts that `template` renders to `expected` with `context`, see
+/// [`assert_template_eq!`](crate::assert_template_eq).
+#[track_caller]
+pub fn assert_template_eq<T, E>(template: &T, context: &Context, expected: E)
+where
+    T: Render + ?Sized,
+    E: AsRef<str>,
+{
+    let expected = expected.as_ref();
+    match template.render_to_string(context) {
+        Ok(actual) if actual == expected => {}
+        Ok(actual) => panic!(
+            "rendered output differs (- expected, + actual):\n{}",
+            diff(expected, &actual)
+        ),
+        Err(e) => panic!("unable to render template: {}", e),
+    }
+}
+
+/// Returns a line diff of `expected` and `actual` with visible whitespace. Lines only in
+/// `expected` start with `- `, lines only in `actual` with `+ ` and common lines with two
+/// spaces.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::testing::diff;
+///
+/// assert_eq!(diff("a\nb\n", "a\n b\n"), "  a⏎\n- b⏎\n+ ·b⏎\n");
+/// ```
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+    // Lengths of the longest common subsequences of the suffixes.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push_line(&mut out, "  ", old[i]);
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            push_line(&mut out, "- ", old[i]);
+            i += 1;
+        } else {
+            push_line(&mut out, "+ ", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+fn push_line(out: &mut String, prefix: &str, line: &str) {
+    out.push_str(prefix);
+    for c in line.chars() {
+        match c {
+            ' ' => out.push('·'),
+            '\t' => out.push('→'),
+            '\r' => out.push('␍'),
+            '\n' => out.push('⏎'),
+            c => out.push(c),
+        }
+    }
+    let _ = writeln!(out);
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::Value;
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("", ""), "");
+        assert_eq!(diff("a", "a\n"), "- a\n+ a⏎\n");
+        assert_eq!(
+            diff("x:\n  a: 1\n  b: 2\n", "x:\n  a: 1\n\tb: 2\n  c: 3\n"),
+            "  x:⏎\n  ··a:·1⏎\n- ··b:·2⏎\n+ →b:·2⏎\n+ ··c:·3⏎\n"
+        );
+        assert_eq!(diff("a\r\n", ""), "- a␍⏎\n");
+    }
+
+    #[test]
+    fn test_assert_template_eq() {
+        assert_template_eq!("{{ . }}", "x", "x");
+        assert_template_eq!(String::from("{{ . }}"), 1, String::from("1"));
+        assert_template_eq("{{ . }}", &Context::from(Value::from(2)), "2");
+    }
+
+    #[test]
+    #[should_panic(expected = "rendered output differs (- expected, + actual):\n- a:·1⏎\n+ a:··1")]
+    fn test_mismatch() {
+        assert_template_eq!("a:  {{ . }}", 1, "a: 1\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "unable to render template: template: :1:function nope not defined")]
+    fn test_render_error() {
+        assert_template_eq!("{{ nope }}", 1, "");
+    }
+}
@@ -1,114 +1,134 @@
+use crate::error::UnquoteError;
 use gtmpl_value::Value;
-use std::char;
+use std::str::Chars;
 
-pub fn unquote_char(s: &str, quote: char) -> Option<char> {
-    if s.len() < 2 || !s.starts_with(quote) || !s.ends_with(quote) {
-        return None;
-    }
-    let raw = &s[1..s.len() - 1];
-    match unqote(raw) {
-        Some((c, l)) => {
-            if l == raw.len() {
-                c.chars().next()
-            } else {
-                None
-            }
-        }
-        _ => None,
-    }
+// Deeply nested templates are parsed and executed recursively. Before the remaining stack drops
+// below the red zone, recursion continues on a new segment allocated on the heap.
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// Runs `f`, growing the stack first if it is about to run out.
+#[inline]
+pub fn ensure_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
 }
 
-pub fn unquote_str(s: &str) -> Option<String> {
-    if s.len() < 2 {
-        return None;
+/// Unquotes a character constant like `'a'` or `'\u263a'`.
+pub fn unquote_char(s: &str, quote: char) -> Result<char, UnquoteError> {
+    let raw = strip_quotes(s, quote)?;
+    let unquoted = unescape(raw)?;
+    let mut chars = unquoted.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(UnquoteError::NotASingleChar(s.to_owned())),
     }
-    let quote = &s[0..1];
-    if !s.ends_with(quote) {
-        return None;
-    }
-    let mut r = String::new();
-    let raw = &s[1..s.len() - 1];
-    let mut i = 0;
-    while i < raw.len() {
-        match unqote(&raw[i..]) {
-            Some((c, len)) => {
-                r += &c;
-                i += len;
-            }
-            None => return None,
-        }
-    }
-    Some(r)
 }
 
-fn unqote(raw: &str) -> Option<(String, usize)> {
-    if raw.starts_with('\\') {
-        match &raw[..2] {
-            r"\x" => extract_bytes_x(raw),
-            r"\U" => extract_bytes_u32(raw),
-            r"\u" => extract_bytes_u16(raw),
-            r"\b" => Some(('\u{0008}'.to_string(), 2)),
-            r"\f" => Some(('\u{000C}'.to_string(), 2)),
-            r"\n" => Some(('\n'.to_string(), 2)),
-            r"\r" => Some(('\r'.to_string(), 2)),
-            r"\t" => Some(('\t'.to_string(), 2)),
-            r"\'" => Some(('\''.to_string(), 2)),
-            r#"\""# => Some(('\"'.to_string(), 2)),
-            r#"\\"# => Some(('\\'.to_string(), 2)),
-            _ => None,
-        }
-    } else {
-        get_char(raw)
+/// Unquotes an interpreted (`"…"`) or raw (`` `…` ``) string literal.
+pub fn unquote_str(s: &str) -> Result<String, UnquoteError> {
+    let quote = s.chars().next().ok_or(UnquoteError::MissingQuotes)?;
+    let raw = strip_quotes(s, quote)?;
+    if quote == '`' {
+        return Ok(raw.to_owned());
     }
+    unescape(raw)
 }
 
-fn get_char(s: &str) -> Option<(String, usize)> {
-    s.char_indices()
-        .next()
-        .map(|(i, c)| (c.to_string(), i + c.len_utf8()))
+fn strip_quotes(s: &str, quote: char) -> Result<&str, UnquoteError> {
+    s.strip_prefix(quote)
+        .and_then(|s| s.strip_suffix(quote))
+        .ok_or(UnquoteError::MissingQuotes)
 }
 
-fn extract_bytes_u32(s: &str) -> Option<(String, usize)> {
-    if s.len() != 10 {
-        return None;
+/// Resolves Go escape sequences. `\x` and octal escapes produce raw bytes which have to add up
+/// to valid UTF-8, consecutive `\u` escapes may form a UTF-16 surrogate pair.
+fn unescape(raw: &str) -> Result<String, UnquoteError> {
+    let mut out: Vec<u8> = Vec::with_capacity(raw.len());
+    let mut high_surrogate: Option<u16> = None;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if high_surrogate.is_some() {
+                return Err(UnquoteError::UnpairedSurrogate);
+            }
+            push_char(&mut out, c);
+            continue;
+        }
+        let e = chars.next().ok_or(UnquoteError::UnterminatedEscape)?;
+        if e != 'u' && high_surrogate.is_some() {
+            return Err(UnquoteError::UnpairedSurrogate);
+        }
+        match e {
+            'a' => out.push(0x07),
+            'b' => out.push(0x08),
+            'f' => out.push(0x0C),
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'v' => out.push(0x0B),
+            '\\' | '\'' | '"' => push_char(&mut out, e),
+            'x' => out.push(hex_digits(&mut chars, e, 2)? as u8),
+            '0'..='7' => {
+                let mut n = e.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    let d = chars.next().ok_or(UnquoteError::UnterminatedEscape)?;
+                    n = n * 8 + d.to_digit(8).ok_or(UnquoteError::InvalidEscape(d))?;
+                }
+                if n > 0xFF {
+                    return Err(UnquoteError::InvalidCodePoint(n));
+                }
+                out.push(n as u8);
+            }
+            'u' => {
+                let unit = hex_digits(&mut chars, e, 4)? as u16;
+                match (high_surrogate.take(), unit) {
+                    (None, 0xD800..=0xDBFF) => high_surrogate = Some(unit),
+                    (Some(high), 0xDC00..=0xDFFF) => {
+                        let c = char::decode_utf16([high, unit])
+                            .next()
+                            .and_then(|r| r.ok())
+                            .ok_or(UnquoteError::UnpairedSurrogate)?;
+                        push_char(&mut out, c);
+                    }
+                    (None, _) => push_char(&mut out, code_point(u32::from(unit))?),
+                    (Some(_), _) => return Err(UnquoteError::UnpairedSurrogate),
+                }
+            }
+            'U' => {
+                let c = code_point(hex_digits(&mut chars, e, 8)?)?;
+                push_char(&mut out, c);
+            }
+            _ => return Err(UnquoteError::InvalidEscape(e)),
+        }
     }
-    u32::from_str_radix(&s[2..10], 16)
-        .ok()
-        .and_then(char::from_u32)
-        .map(|c| (c.to_string(), 10))
+    if high_surrogate.is_some() {
+        return Err(UnquoteError::UnpairedSurrogate);
+    }
+    String::from_utf8(out).map_err(|_| UnquoteError::InvalidUtf8)
 }
 
-fn extract_bytes_u16(s: &str) -> Option<(String, usize)> {
-    let mut bytes = vec![];
-    let mut i = 0;
-    while s.len() > i && s.starts_with(r"\u") && s[i..].len() >= 6 {
-        match u16::from_str_radix(&s[(i + 2)..(i + 6)], 16) {
-            Ok(x) => bytes.push(x),
-            _ => {
-                return None;
-            }
-        };
-        i += 6;
-    }
-    String::from_utf16(&bytes).ok().map(|s| (s, i))
+fn push_char(out: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
 }
 
-fn extract_bytes_x(s: &str) -> Option<(String, usize)> {
-    let mut bytes = vec![];
-    let mut i = 0;
-    while s.len() > i && s.starts_with(r"\x") && s[i..].len() >= 4 {
-        match u8::from_str_radix(&s[(i + 2)..(i + 4)], 16) {
-            Ok(x) => bytes.push(x),
-            _ => {
-                return None;
-            }
-        };
-        i += 4;
+fn hex_digits(chars: &mut Chars<'_>, escape: char, n: usize) -> Result<u32, UnquoteError> {
+    let mut v = 0u32;
+    for _ in 0..n {
+        let c = chars.next().ok_or(UnquoteError::UnterminatedEscape)?;
+        let d = c
+            .to_digit(16)
+            .ok_or(UnquoteError::InvalidHexDigit(escape, c))?;
+        v = (v << 4) | d;
     }
-    String::from_utf8(bytes).ok().map(|s| (s, i))
+    Ok(v)
 }
 
-/// Returns
+fn code_point(n: u32) -> Result<char, UnquoteError> {
+    char::from_u32(n).ok_or(UnquoteError::InvalidCodePoint(n))
+}
+
+/// Returns whether `val` is true like in Go, i.e. not empty, zero, false or nil.
 pub fn is_true(val: &Value) -> bool {
     match *val {
         Value::Bool(ref b) => *b,
@@ -118,62 +138,158 @@ pub fn is_true(val: &Value) -> bool {
         Value::Map(ref m) => !m.is_empty(),
         Value::Function(_) => true,
         Value::NoValue | Value::Nil => false,
-        Value::Number(ref n) => n.as_u64().map(|u| u != 0).unwrap_or_else(|| true),
+        Value::Number(ref n) => n
+            .as_f64()
+            .map(|f| f != 0.0)
+            .or_else(|| n.as_i64().map(|i| i != 0))
+            .or_else(|| n.as_u64().map(|u| u != 0))
+            .unwrap_or(true),
+    }
+}
+
+/// Converts a Go style field name like `HTTPServer` to lowerCamelCase (`httpServer`).
+pub fn lower_camel(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len());
+    let mut leading = true;
+    for (i, &c) in chars.iter().enumerate() {
+        // Lowercase the leading run of capitals but keep the one starting the next word.
+        let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+        if leading && c.is_uppercase() && !(i > 0 && next_lower) {
+            out.extend(c.to_lowercase());
+        } else {
+            leading = false;
+            out.push(c);
+        }
     }
+    out
+}
+
+/// Converts a Go style field name like `HTTPServer` to snake_case (`http_server`).
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = if i > 0 { chars.get(i - 1) } else { None };
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            let boundary = prev.is_some_and(|p| {
+                p.is_lowercase() || p.is_numeric() || (p.is_uppercase() && next_lower)
+            });
+            if boundary {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_field_name_conversion() {
+        assert_eq!(lower_camel("UserName"), "userName");
+        assert_eq!(lower_camel("ID"), "id");
+        assert_eq!(lower_camel("HTTPServer"), "httpServer");
+        assert_eq!(lower_camel("name"), "name");
+        assert_eq!(snake_case("UserName"), "user_name");
+        assert_eq!(snake_case("UserID"), "user_id");
+        assert_eq!(snake_case("HTTPServer"), "http_server");
+        assert_eq!(snake_case("Page2Title"), "page2_title");
+        assert_eq!(snake_case("name"), "name");
+    }
+
     #[test]
     fn test_unquote_char() {
         let s = "'→'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, Some('→'));
+        assert_eq!(c, Ok('→'));
         let s = "'→←'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\xf0\x9f\x92\xa9'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, Some('💩'));
+        assert_eq!(c, Ok('💩'));
         let s = r"'\xf0\x9f\x92\xa'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\xf0\x9f\x92\xa99'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\u263a'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, Some('☺'));
+        assert_eq!(c, Ok('☺'));
         let s = r"'\uD83D\uDCA9'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, Some('💩'));
+        assert_eq!(c, Ok('💩'));
         let s = r"'\uD83\uDCA9'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\uD83D\uDCA9B'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\U0001F4A9'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, Some('💩'));
+        assert_eq!(c, Ok('💩'));
         let s = r"'\U0001F4A'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
         let s = r"'\U0001F4A99'";
         let c = unquote_char(s, '\'');
-        assert_eq!(c, None);
+        assert!(c.is_err());
     }
 
     #[test]
     fn test_unquote_str() {
         let s = r#""Fran & Freddie's Diner""#;
         let u = unquote_str(s);
-        assert_eq!(u, Some("Fran & Freddie's Diner".to_owned()));
+        assert_eq!(u, Ok("Fran & Freddie's Diner".to_owned()));
         let s = r#""Fran & Freddie's Diner\t\u263a""#;
         let u = unquote_str(s);
-        assert_eq!(u, Some("Fran & Freddie's Diner\t☺".to_owned()));
+        assert_eq!(u, Ok("Fran & Freddie's Diner\t☺".to_owned()));
+    }
+
+    #[test]
+    fn test_unquote_malformed() {
+        use UnquoteError::*;
+        assert_eq!(unquote_char("'", '\''), Err(MissingQuotes));
+        assert_eq!(
+            unquote_char("''", '\''),
+            Err(NotASingleChar("''".to_owned()))
+        );
+        assert_eq!(unquote_char(r"'\'", '\''), Err(UnterminatedEscape));
+        assert_eq!(unquote_char(r"'\é'", '\''), Err(InvalidEscape('é')));
+        assert_eq!(unquote_char(r"'\xé'", '\''), Err(InvalidHexDigit('x', 'é')));
+        assert_eq!(
+            unquote_char(r"'\u00é'", '\''),
+            Err(InvalidHexDigit('u', 'é'))
+        );
+        assert_eq!(
+            unquote_char(r"'\U0001F4é'", '\''),
+            Err(InvalidHexDigit('U', 'é'))
+        );
+        assert_eq!(
+            unquote_char(r"'\U00110000'", '\''),
+            Err(InvalidCodePoint(0x110000))
+        );
+        assert_eq!(unquote_char(r"'\uD83D'", '\''), Err(UnpairedSurrogate));
+        assert_eq!(
+            unquote_char(r"'\uDCA9'", '\''),
+            Err(InvalidCodePoint(0xDCA9))
+        );
+        assert_eq!(unquote_char(r"'\xff'", '\''), Err(InvalidUtf8));
+        assert_eq!(unquote_char(r"'\400'", '\''), Err(InvalidCodePoint(0o400)));
+        assert_eq!(unquote_str("é"), Err(MissingQuotes));
+        assert_eq!(unquote_str(r#""\"#), Err(MissingQuotes));
+        assert_eq!(unquote_str(r#""\""#), Err(UnterminatedEscape));
+        assert_eq!(unquote_str(r#""\u263a→""#), Ok("☺→".to_owned()));
+        assert_eq!(unquote_str(r#""\101\x42""#), Ok("AB".to_owned()));
+        assert_eq!(unquote_str(r"`C:\path\x`"), Ok(r"C:\path\x".to_owned()));
     }
 
     #[test]
@@ -182,5 +298,11 @@ mod tests {
         assert!(is_true(&t));
         let t = Value::from(0u32);
         assert!(!is_true(&t));
+        assert!(is_true(&Value::from(-1i64)));
+        assert!(is_true(&Value::from(i64::MIN)));
+        assert!(is_true(&Value::from(u64::MAX)));
+        assert!(is_true(&Value::from(0.5)));
+        assert!(!is_true(&Value::from(0.0)));
+        assert!(!is_true(&Value::from(-0.0)));
     }
 }
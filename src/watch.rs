@@ -0,0 +1,254 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::error::WatchError;
+use crate::template_set::TemplateSet;
+
+use gtmpl_value::Func;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Keeps a [`TemplateSet`](crate::TemplateSet) in sync with template files on disk.
+///
+/// Every file is inserted into the set with its file name as template name. Directories add
+/// all files they contain (not recursively). Whenever one of the watched files changes, all
+/// files are parsed again and the new set replaces the old one atomically. If re-parsing fails
+/// the previous set is kept and the error is available via `take_error`.
+///
+/// Requires the `notify` feature.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, TemplateWatcher};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// std::fs::write(dir.path().join("hello.tmpl"), "Hello {{ . }}!").unwrap();
+///
+/// let watcher = TemplateWatcher::new(&[dir.path()]).unwrap();
+/// let output = watcher.get().render("hello.tmpl", &Context::from("World"));
+/// assert_eq!(&output.unwrap(), "Hello World!");
+/// ```
+pub struct TemplateWatcher {
+    shared: Arc<Shared>,
+    _watcher: RecommendedWatcher,
+}
+
+struct Shared {
+    paths: Vec<PathBuf>,
+    funcs: Vec<(String, Func)>,
+    current: RwLock<Arc<TemplateSet>>,
+    error: Mutex<Option<WatchError>>,
+}
+
+impl TemplateWatcher {
+    /// Parses and watches the given files and directories.
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Result<TemplateWatcher, WatchError> {
+        TemplateWatcher::with_funcs::<P, String>(paths, &[])
+    }
+
+    /// Parses and watches the given files and directories using additional custom `funcs`.
+    pub fn with_funcs<P: AsRef<Path>, T: Into<String> + Clone>(
+        paths: &[P],
+        funcs: &[(T, Func)],
+    ) -> Result<TemplateWatcher, WatchError> {
+        // Canonical like the paths of notify's events, so relative paths match them too.
+        let paths = paths
+            .iter()
+            .map(|p| fs::canonicalize(p).map_err(|e| WatchError::Read(p.as_ref().to_path_buf(), e)))
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        let funcs: Vec<(String, Func)> =
+            funcs.iter().cloned().map(|(k, v)| (k.into(), v)).collect();
+        let set = load(&paths, &funcs)?;
+        let shared = Arc::new(Shared {
+            paths,
+            funcs,
+            current: RwLock::new(Arc::new(set)),
+            error: Mutex::new(None),
+        });
+
+        let handler = Arc::clone(&shared);
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+                Ok(event) => {
+                    if !matches!(event.kind, EventKind::Access(_)) && handler.affected_by(&event) {
+                        handler.reload_or_store_error();
+                    }
+                }
+                Err(e) => handler.store_error(e.into()),
+            })?;
+        // Watching the parent directory instead of the file itself keeps the watch alive when
+        // editors replace files on save.
+        for path in &shared.paths {
+            let dir = if path.is_dir() {
+                path.as_path()
+            } else {
+                path.parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+            };
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(TemplateWatcher {
+            shared,
+            _watcher: watcher,
+        })
+    }
+
+    /// Returns the most recently parsed template set.
+    ///
+    /// The returned set stays valid even if it is replaced by a reload in the meantime.
+    pub fn get(&self) -> Arc<TemplateSet> {
+        self.shared.get()
+    }
+
+    /// Re-parses all watched paths immediately.
+    pub fn reload(&self) -> Result<(), WatchError> {
+        self.shared.reload()
+    }
+
+    /// Returns the error of the last failed automatic reload, if any.
+    pub fn take_error(&self) -> Option<WatchError> {
+        self.shared
+            .error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+}
+
+impl Shared {
+    fn get(&self) -> Arc<TemplateSet> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn reload(&self) -> Result<(), WatchError> {
+        let set = load(&self.paths, &self.funcs)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(set);
+        Ok(())
+    }
+
+    fn reload_or_store_error(&self) {
+        if let Err(e) = self.reload() {
+            self.store_error(e);
+        }
+    }
+
+    fn store_error(&self, e: WatchError) {
+        *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+    }
+
+    fn affected_by(&self, event: &Event) -> bool {
+        event.paths.iter().any(|changed| {
+            self.paths.iter().any(|path| {
+                changed == path || (path.is_dir() && changed.parent() == Some(path.as_path()))
+            })
+        })
+    }
+}
+
+fn load(paths: &[PathBuf], funcs: &[(String, Func)]) -> Result<TemplateSet, WatchError> {
+    let mut set = TemplateSet::default();
+    set.add_funcs(funcs);
+    for path in paths {
+        if path.is_dir() {
+            let entries = fs::read_dir(path).map_err(|e| WatchError::Read(path.clone(), e))?;
+            let mut files = vec![];
+            for entry in entries {
+                let entry = entry.map_err(|e| WatchError::Read(path.clone(), e))?;
+                if entry.path().is_file() {
+                    files.push(entry.path());
+                }
+            }
+            // Sorted so every load inserts the files, and reports a template defined by several
+            // of them, in the same order.
+            files.sort();
+            for file in &files {
+                load_file(&mut set, file)?;
+            }
+        } else {
+            load_file(&mut set, path)?;
+        }
+    }
+    Ok(set)
+}
+
+fn load_file(set: &mut TemplateSet, path: &Path) -> Result<(), WatchError> {
+    let text = fs::read_to_string(path).map_err(|e| WatchError::Read(path.to_path_buf(), e))?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    set.insert(name, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::Context;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, "1").unwrap();
+        let paths = vec![file.clone()];
+        let shared = Shared {
+            current: RwLock::new(Arc::new(load(&paths, &[]).unwrap())),
+            paths,
+            funcs: vec![],
+            error: Mutex::new(None),
+        };
+        let before = shared.get();
+        fs::write(&file, "2").unwrap();
+        assert!(shared.reload().is_ok());
+        assert_eq!(before.render("a", &Context::empty()).unwrap(), "1");
+        assert_eq!(shared.get().render("a", &Context::empty()).unwrap(), "2");
+
+        fs::write(&file, "{{ if }}").unwrap();
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.get().render("a", &Context::empty()).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), r#"{{ template "b" }}"#).unwrap();
+        fs::write(dir.path().join("b"), "1").unwrap();
+        let watcher = TemplateWatcher::new(&[dir.path()]).unwrap();
+        assert_eq!(watcher.get().render("a", &Context::empty()).unwrap(), "1");
+        fs::write(dir.path().join("b"), "2").unwrap();
+        let start = Instant::now();
+        while watcher.get().render("a", &Context::empty()).unwrap() != "2" {
+            assert!(start.elapsed() < Duration::from_secs(10), "no reload");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_relative_dir() {
+        let tmp = tempfile::tempdir_in(".").unwrap();
+        let dir = tmp
+            .path()
+            .strip_prefix(std::env::current_dir().unwrap())
+            .unwrap();
+        fs::write(dir.join("a"), "1").unwrap();
+        let watcher = TemplateWatcher::new(&[dir]).unwrap();
+        let event = |path: PathBuf| Event::new(EventKind::Any).add_path(path);
+        let file = fs::canonicalize(dir.join("a")).unwrap();
+        assert!(watcher.shared.affected_by(&event(file)));
+        let elsewhere = Path::new("/elsewhere").join(dir).join("a");
+        assert!(!watcher.shared.affected_by(&event(elsewhere)));
+
+        fs::write(dir.join("a"), "2").unwrap();
+        let start = Instant::now();
+        while watcher.get().render("a", &Context::empty()).unwrap() != "2" {
+            assert!(start.elapsed() < Duration::from_secs(10), "no reload");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "cli")]
+use std::fs;
+use std::process::Command;
+
+fn gtmpl(args: &[&str]) -> (bool, String, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_gtmpl"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        out.status.success(),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn render_with_context_and_defines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+    fs::write(
+        path("main.tmpl"),
+        r#"{{ template "greet" .user }} {{ .env }} {{ add 1 2 }}"#,
+    )
+    .unwrap();
+    fs::write(
+        path("helpers.tmpl"),
+        r#"{{ define "greet" }}Hello {{ .name }}!{{ end }}"#,
+    )
+    .unwrap();
+    fs::write(
+        path("ctx.json"),
+        r#"{"user": {"name": "json"}, "env": "dev"}"#,
+    )
+    .unwrap();
+    fs::write(path("ctx.yaml"), "user:\n  name: yaml\nenv: dev\n").unwrap();
+
+    let main = path("main.tmpl");
+    let helpers = path("helpers.tmpl");
+    let json = path("ctx.json");
+    let (ok, out, _) = gtmpl(&[&main, "-t", &helpers, "-c", &json]);
+    assert!(ok);
+    assert_eq!(out, "Hello json! dev 3");
+
+    let yaml = path("ctx.yaml");
+    let (ok, out, _) = gtmpl(&[&main, "-t", &helpers, "-c", &yaml, "--set", "env=prod"]);
+    assert!(ok);
+    assert_eq!(out, "Hello yaml! prod 3");
+
+    let out_file = path("out.txt");
+    let (ok, _, _) = gtmpl(&[
+        &main,
+        "-t",
+        &helpers,
+        "-s",
+        "user.name=set",
+        "-o",
+        &out_file,
+    ]);
+    assert!(ok);
+    assert_eq!(
+        fs::read_to_string(&out_file).unwrap(),
+        "Hello set! <no value> 3"
+    );
+}
+
+#[test]
+fn errors() {
+    let (ok, _, err) = gtmpl(&[]);
+    assert!(!ok);
+    assert!(err.starts_with("gtmpl: missing TEMPLATE"));
+
+    let (ok, out, _) = gtmpl(&["--help"]);
+    assert!(ok);
+    assert!(out.starts_with("Usage: gtmpl"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let main = dir.path().join("main.tmpl");
+    fs::write(&main, "{{ .missing }}").unwrap();
+    let main = main.to_string_lossy();
+    let (ok, _, err) = gtmpl(&[&main, "--strict", "-c", "-"]);
+    assert!(!ok);
+    assert!(err.starts_with("gtmpl: unable to parse -"), "{}", err);
+    let (ok, _, err) = gtmpl(&[&main, "--strict", "-s", "x=1"]);
+    assert!(!ok);
+    assert_eq!(
+        err,
+        "gtmpl: main.tmpl:1: no value for .missing\n1 | {{ .missing }}\n  |    ^^^^^^^^\n"
+    );
+}
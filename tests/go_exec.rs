@@ -0,0 +1,898 @@
+//! Conformance tests ported from Go's `text/template/exec_test.go`.
+//!
+//! Every case is rendered with gtmpl and compared to Go's output. Cases where gtmpl knowingly
+//! behaves differently are marked as a divergence with the reason. A divergent case that starts
+//! to match Go fails the test as well, so the marker can be removed.
+use std::collections::HashMap;
+
+use gtmpl::{Context, FuncError, Template, Value};
+
+struct ExecTest {
+    name: &'static str,
+    input: &'static str,
+    output: &'static str,
+    data: Value,
+    ok: bool,
+    divergence: Option<&'static str>,
+}
+
+fn t<T: Into<Value>>(
+    name: &'static str,
+    input: &'static str,
+    output: &'static str,
+    data: T,
+    ok: bool,
+) -> ExecTest {
+    ExecTest {
+        name,
+        input,
+        output,
+        data: data.into(),
+        ok,
+        divergence: None,
+    }
+}
+
+impl ExecTest {
+    fn diverges(mut self, reason: &'static str) -> ExecTest {
+        self.divergence = Some(reason);
+        self
+    }
+}
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn map(fields: Vec<(&str, i64)>) -> Value {
+    Value::Map(
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), Value::from(v)))
+            .collect(),
+    )
+}
+
+// The subset of Go's `tVal` that can be expressed without methods and pointers.
+fn t_val() -> Value {
+    object(vec![
+        ("True", Value::from(true)),
+        ("I", Value::from(17)),
+        ("U16", Value::from(16u64)),
+        ("X", Value::from("x")),
+        ("S", Value::from("xyz")),
+        ("FloatZero", Value::from(0.0)),
+        ("U", object(vec![("V", Value::from("v"))])),
+        ("SI", Value::from(vec![3, 4, 5])),
+        ("SIEmpty", Value::Array(vec![])),
+        ("SB", Value::from(vec![true, false])),
+        ("MSI", map(vec![("one", 1), ("two", 2), ("three", 3)])),
+        ("MSIone", map(vec![("one", 1)])),
+        ("MSIEmpty", map(vec![])),
+        ("MXI", map(vec![("one", 1)])),
+        (
+            "SMSI",
+            Value::Array(vec![
+                map(vec![("one", 1), ("two", 2)]),
+                map(vec![("eleven", 11), ("twelve", 12)]),
+            ]),
+        ),
+        ("Empty0", Value::Nil),
+        ("Empty3", Value::from(vec![7, 8])),
+        ("Empty4", object(vec![("V", Value::from("UinEmpty"))])),
+        ("PSI", Value::from(vec![21, 22, 23])),
+        ("NIL", Value::Nil),
+        ("PS", Value::from("a string")),
+    ])
+}
+
+fn echo(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(args.first().cloned().unwrap_or(Value::Nil))
+}
+
+fn die(_: &[Value]) -> Result<Value, FuncError> {
+    Err(FuncError::Generic("die".to_owned()))
+}
+
+fn add(args: &[Value]) -> Result<Value, FuncError> {
+    let mut sum = 0;
+    for arg in args {
+        match arg {
+            Value::Number(n) if n.as_i64().is_some() => sum += n.as_i64().unwrap(),
+            _ => return Err(FuncError::Generic(format!("not an int: {}", arg))),
+        }
+    }
+    Ok(Value::from(sum))
+}
+
+fn makemap(args: &[Value]) -> Result<Value, FuncError> {
+    let mut m = HashMap::new();
+    for pair in args.chunks(2) {
+        if let [Value::String(k), v] = pair {
+            m.insert(k.clone(), v.clone());
+        }
+    }
+    Ok(Value::Map(m))
+}
+
+fn map_of_three(_: &[Value]) -> Result<Value, FuncError> {
+    Ok(map(vec![("three", 3)]))
+}
+
+fn exec_tests() -> Vec<ExecTest> {
+    let nil = Value::Nil;
+    vec![
+        // Trivial cases.
+        t("empty", "", "", nil.clone(), true),
+        t("text", "some text", "some text", nil.clone(), true),
+        t("nil action", "{{nil}}", "", nil.clone(), false),
+        // Fields of structs.
+        t(".X", "-{{.X}}-", "-x-", t_val(), true),
+        t(".U.V", "-{{.U.V}}-", "-v-", t_val(), true),
+        t(".unexported", "{{.unexported}}", "", t_val(), false),
+        // Fields on maps.
+        t("map .one", "{{.MSI.one}}", "1", t_val(), true),
+        t("map .two", "{{.MSI.two}}", "2", t_val(), true),
+        t("map .NO", "{{.MSI.NO}}", "<no value>", t_val(), true),
+        t("map .one interface", "{{.MXI.one}}", "1", t_val(), true),
+        t("map .WRONG args", "{{.MSI.one 1}}", "", t_val(), false),
+        // Dots of all kinds to test basic evaluation.
+        t("dot int", "<{{.}}>", "<13>", 13, true),
+        t("dot uint", "<{{.}}>", "<14>", 14u64, true),
+        t("dot float", "<{{.}}>", "<15.1>", 15.1, true),
+        t("dot bool", "<{{.}}>", "<true>", true, true),
+        t("dot string", "<{{.}}>", "<hello>", "hello", true),
+        t(
+            "dot slice",
+            "<{{.}}>",
+            "<[-1 -2 -3]>",
+            vec![-1, -2, -3],
+            true,
+        ),
+        t(
+            "dot map",
+            "<{{.}}>",
+            "<map[two:22]>",
+            map(vec![("two", 22)]),
+            true,
+        ),
+        // Variables.
+        t("$ int", "{{$}}", "123", 123, true),
+        t("$.I", "{{$.I}}", "17", t_val(), true),
+        t("$.U.V", "{{$.U.V}}", "v", t_val(), true),
+        t(
+            "declare in action",
+            "{{$x := $.U.V}}{{$x}}",
+            "v",
+            t_val(),
+            true,
+        ),
+        t(
+            "simple assignment",
+            "{{$x := 2}}{{$x = 3}}{{$x}}",
+            "3",
+            t_val(),
+            true,
+        )
+        .diverges("assignments with = are not supported"),
+        // Parenthesized expressions.
+        t(
+            "parens in pipeline",
+            "{{printf `%d %d %d` (1) (2 | add 3) (add 4 (add 5 6))}}",
+            "1 5 15",
+            t_val(),
+            true,
+        ),
+        t("parens: $ in paren", "{{($).X}}", "x", t_val(), true),
+        t("parens: $.U in paren", "{{($.U).V}}", "v", t_val(), true),
+        t(
+            "parens: $ in paren in pipe",
+            "{{($ | echo).X}}",
+            "x",
+            t_val(),
+            true,
+        ),
+        t(
+            "parens: spaces and args",
+            r#"{{(makemap "up" "down" "left" "right").left}}"#,
+            "right",
+            t_val(),
+            true,
+        ),
+        // If.
+        t("if true", "{{if true}}TRUE{{end}}", "TRUE", t_val(), true),
+        t(
+            "if false",
+            "{{if false}}TRUE{{else}}FALSE{{end}}",
+            "FALSE",
+            t_val(),
+            true,
+        ),
+        t("if nil", "{{if nil}}TRUE{{end}}", "", t_val(), false),
+        t(
+            "if 1",
+            "{{if 1}}NON-ZERO{{else}}ZERO{{end}}",
+            "NON-ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if 0",
+            "{{if 0}}NON-ZERO{{else}}ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if 1.5",
+            "{{if 1.5}}NON-ZERO{{else}}ZERO{{end}}",
+            "NON-ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if 0.0",
+            "{{if .FloatZero}}NON-ZERO{{else}}ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if emptystring",
+            "{{if ``}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if string",
+            "{{if `notempty`}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "NON-EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if emptyslice",
+            "{{if .SIEmpty}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if slice",
+            "{{if .SI}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "NON-EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if emptymap",
+            "{{if .MSIEmpty}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if map",
+            "{{if .MSI}}NON-EMPTY{{else}}EMPTY{{end}}",
+            "NON-EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "if map unset",
+            "{{if .MXI.none}}NON-ZERO{{else}}ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if map not unset",
+            "{{if not .MXI.none}}ZERO{{else}}NON-ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "if $x with $y int",
+            "{{if $x := true}}{{with $y := .I}}{{$x}},{{$y}}{{end}}{{end}}",
+            "true,17",
+            t_val(),
+            true,
+        ),
+        t(
+            "if $x with $x int",
+            "{{if $x := true}}{{with $x := .I}}{{$x}},{{end}}{{$x}}{{end}}",
+            "17,true",
+            t_val(),
+            true,
+        ),
+        t(
+            "if else if",
+            "{{if false}}FALSE{{else if true}}TRUE{{end}}",
+            "TRUE",
+            t_val(),
+            true,
+        ),
+        t(
+            "if else chain",
+            "{{if eq 1 3}}1{{else if eq 2 3}}2{{else if eq 3 3}}3{{end}}",
+            "3",
+            t_val(),
+            true,
+        ),
+        // Print etc.
+        t(
+            "print",
+            r#"{{print "hello, print"}}"#,
+            "hello, print",
+            t_val(),
+            true,
+        ),
+        t("print 123", "{{print 1 2 3}}", "1 2 3", t_val(), true),
+        t("print nil", "{{print nil}}", "<nil>", t_val(), true)
+            .diverges("nil is not allowed as an argument"),
+        t("println", "{{println 1 2 3}}", "1 2 3\n", t_val(), true),
+        t(
+            "print mixed",
+            "{{print .I .U16 .X .S 1.5 .SI .MSIone}}",
+            "17 16xxyz1.5 [3 4 5] map[one:1]",
+            t_val(),
+            true,
+        ),
+        t(
+            "print strings and nil",
+            r#"{{print "a" 1 2 "b" true .NIL}}"#,
+            "a1 2btrue <nil>",
+            t_val(),
+            true,
+        ),
+        t(
+            "print empty strings",
+            r#"{{print "" 1 "" 2}}"#,
+            "12",
+            t_val(),
+            true,
+        ),
+        t("print nothing", "{{print}}", "", t_val(), true),
+        t(
+            "print bools",
+            "{{print .SB true}}",
+            "[true false] true",
+            t_val(),
+            true,
+        ),
+        t(
+            "println mixed",
+            "{{println .X .I .NIL}}",
+            "x 17 <nil>\n",
+            t_val(),
+            true,
+        ),
+        t(
+            "printf int",
+            r#"{{printf "%04x" 127}}"#,
+            "007f",
+            t_val(),
+            true,
+        )
+        .diverges("printf ignores the 0 flag"),
+        t(
+            "printf float",
+            r#"{{printf "%g" 3.5}}"#,
+            "3.5",
+            t_val(),
+            true,
+        ),
+        t(
+            "printf string",
+            r#"{{printf "%s" "hello"}}"#,
+            "hello",
+            t_val(),
+            true,
+        ),
+        t(
+            "printf field",
+            r#"{{printf "%s" .U.V}}"#,
+            "v",
+            t_val(),
+            true,
+        ),
+        t(
+            "printf dot",
+            r#"{{with .I}}{{printf "%d" .}}{{end}}"#,
+            "17",
+            t_val(),
+            true,
+        ),
+        t(
+            "printf var",
+            r#"{{with $x := .I}}{{printf "%d" $x}}{{end}}"#,
+            "17",
+            t_val(),
+            true,
+        ),
+        // URL query.
+        t(
+            "urlquery",
+            r#"{{"http://www.example.org/"|urlquery}}"#,
+            "http%3A%2F%2Fwww.example.org%2F",
+            nil.clone(),
+            true,
+        ),
+        // Booleans.
+        t(
+            "not",
+            "{{not true}} {{not false}}",
+            "false true",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "and",
+            "{{and false 0}} {{and 1 0}} {{and 0 true}} {{and 1 1}}",
+            "false 0 0 1",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "or",
+            "{{or 0 0}} {{or 1 0}} {{or 0 true}} {{or 1 1}}",
+            "0 1 true 1",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "or short-circuit",
+            "{{or 0 1 (die)}}",
+            "1",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "and short-circuit",
+            "{{and 1 0 (die)}}",
+            "0",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "or short-circuit2",
+            "{{or 0 0 (die)}}",
+            "",
+            nil.clone(),
+            false,
+        ),
+        t(
+            "and short-circuit2",
+            "{{and 1 1 (die)}}",
+            "",
+            nil.clone(),
+            false,
+        ),
+        t("and pipe-true", "{{1 | and 1}}", "1", nil.clone(), true),
+        t("and pipe-false", "{{0 | and 1}}", "0", nil.clone(), true),
+        t("or pipe-true", "{{1 | or 0}}", "1", nil.clone(), true),
+        t("or pipe-false", "{{0 | or 0}}", "0", nil.clone(), true),
+        t(
+            "boolean if",
+            "{{if and true 1 `hi`}}TRUE{{else}}FALSE{{end}}",
+            "TRUE",
+            t_val(),
+            true,
+        ),
+        t(
+            "boolean if not",
+            "{{if and true 1 `hi` | not}}TRUE{{else}}FALSE{{end}}",
+            "FALSE",
+            nil.clone(),
+            true,
+        ),
+        t(
+            "boolean if pipe",
+            "{{if true | not | and 1}}TRUE{{else}}FALSE{{end}}",
+            "FALSE",
+            nil.clone(),
+            true,
+        ),
+        // Indexing.
+        t("slice[0]", "{{index .SI 0}}", "3", t_val(), true),
+        t("slice[1]", "{{index .SI 1}}", "4", t_val(), true),
+        t("slice[HUGE]", "{{index .SI 10}}", "", t_val(), false),
+        t("slice[WRONG]", "{{index .SI `hello`}}", "", t_val(), false),
+        t("slice[nil]", "{{index .SI nil}}", "", t_val(), false),
+        t("map[one]", "{{index .MSI `one`}}", "1", t_val(), true),
+        t("map[two]", "{{index .MSI `two`}}", "2", t_val(), true),
+        t("map[NO]", "{{index .MSI `XXX`}}", "0", t_val(), true)
+            .diverges("maps are untyped, so missing keys have no zero value"),
+        t("map[nil]", "{{index .MSI nil}}", "", t_val(), false),
+        t("map[WRONG]", "{{index .MSI 10}}", "", t_val(), false)
+            .diverges("maps are untyped, so keys are not type checked"),
+        t(
+            "double index",
+            "{{index .SMSI 1 `eleven`}}",
+            "11",
+            t_val(),
+            true,
+        ),
+        t("nil[1]", "{{index nil 1}}", "", t_val(), false),
+        // Len.
+        t("slice", "{{len .SI}}", "3", t_val(), true),
+        t("map", "{{len .MSI }}", "3", t_val(), true),
+        t("len of int", "{{len 3}}", "", t_val(), false),
+        t("len of nothing", "{{len .Empty0}}", "", t_val(), false),
+        t(
+            "len of an interface field",
+            "{{len .Empty3}}",
+            "2",
+            t_val(),
+            true,
+        ),
+        // With.
+        t(
+            "with true",
+            "{{with true}}{{.}}{{end}}",
+            "true",
+            t_val(),
+            true,
+        ),
+        t(
+            "with false",
+            "{{with false}}{{.}}{{else}}FALSE{{end}}",
+            "FALSE",
+            t_val(),
+            true,
+        ),
+        t(
+            "with 1",
+            "{{with 1}}{{.}}{{else}}ZERO{{end}}",
+            "1",
+            t_val(),
+            true,
+        ),
+        t(
+            "with 0",
+            "{{with 0}}{{.}}{{else}}ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "with 1.5",
+            "{{with 1.5}}{{.}}{{else}}ZERO{{end}}",
+            "1.5",
+            t_val(),
+            true,
+        ),
+        t(
+            "with 0.0",
+            "{{with .FloatZero}}{{.}}{{else}}ZERO{{end}}",
+            "ZERO",
+            t_val(),
+            true,
+        ),
+        t(
+            "with emptystring",
+            "{{with ``}}{{.}}{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "with string",
+            "{{with `notempty`}}{{.}}{{else}}EMPTY{{end}}",
+            "notempty",
+            t_val(),
+            true,
+        ),
+        t(
+            "with emptyslice",
+            "{{with .SIEmpty}}{{.}}{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "with slice",
+            "{{with .SI}}{{.}}{{else}}EMPTY{{end}}",
+            "[3 4 5]",
+            t_val(),
+            true,
+        ),
+        t(
+            "with emptymap",
+            "{{with .MSIEmpty}}{{.}}{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "with map",
+            "{{with .MSIone}}{{.}}{{else}}EMPTY{{end}}",
+            "map[one:1]",
+            t_val(),
+            true,
+        ),
+        t(
+            "with empty interface, struct field",
+            "{{with .Empty4}}{{.V}}{{end}}",
+            "UinEmpty",
+            t_val(),
+            true,
+        ),
+        t(
+            "with $x int",
+            "{{with $x := .I}}{{$x}}{{end}}",
+            "17",
+            t_val(),
+            true,
+        ),
+        t(
+            "with $x struct.U.V",
+            "{{with $x := $}}{{$x.U.V}}{{end}}",
+            "v",
+            t_val(),
+            true,
+        ),
+        t(
+            "with variable and action",
+            "{{with $x := $}}{{$y := $.U.V}}{{$y}}{{end}}",
+            "v",
+            t_val(),
+            true,
+        ),
+        t(
+            "with else with",
+            "{{with 0}}{{.}}{{else with true}}{{.}}{{end}}",
+            "true",
+            t_val(),
+            true,
+        ),
+        t(
+            "with else with chain",
+            "{{with 0}}{{.}}{{else with false}}{{.}}{{else with `notempty`}}{{.}}{{end}}",
+            "notempty",
+            t_val(),
+            true,
+        ),
+        // Range.
+        t(
+            "range []int",
+            "{{range .SI}}-{{.}}-{{end}}",
+            "-3--4--5-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty no else",
+            "{{range .SIEmpty}}-{{.}}-{{end}}",
+            "",
+            t_val(),
+            true,
+        ),
+        t(
+            "range []int else",
+            "{{range .SI}}-{{.}}-{{else}}EMPTY{{end}}",
+            "-3--4--5-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty else",
+            "{{range .SIEmpty}}-{{.}}-{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "range []bool",
+            "{{range .SB}}-{{.}}-{{end}}",
+            "-true--false-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range map",
+            "{{range .MSI}}-{{.}}-{{end}}",
+            "-1--3--2-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty map no else",
+            "{{range .MSIEmpty}}-{{.}}-{{end}}",
+            "",
+            t_val(),
+            true,
+        ),
+        t(
+            "range map else",
+            "{{range .MSI}}-{{.}}-{{else}}EMPTY{{end}}",
+            "-1--3--2-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty map else",
+            "{{range .MSIEmpty}}-{{.}}-{{else}}EMPTY{{end}}",
+            "EMPTY",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty interface",
+            "{{range .Empty3}}-{{.}}-{{else}}EMPTY{{end}}",
+            "-7--8-",
+            t_val(),
+            true,
+        ),
+        t(
+            "range empty nil",
+            "{{range .Empty0}}-{{.}}-{{end}}",
+            "",
+            t_val(),
+            true,
+        )
+        .diverges("ranging over nil is an error"),
+        t(
+            "range $x SI",
+            "{{range $x := .SI}}<{{$x}}>{{end}}",
+            "<3><4><5>",
+            t_val(),
+            true,
+        ),
+        t(
+            "range $x $y SI",
+            "{{range $x, $y := .SI}}<{{$x}}={{$y}}>{{end}}",
+            "<0=3><1=4><2=5>",
+            t_val(),
+            true,
+        ),
+        t(
+            "range $x MSIone",
+            "{{range $x := .MSIone}}<{{$x}}>{{end}}",
+            "<1>",
+            t_val(),
+            true,
+        ),
+        t(
+            "range $x $y MSIone",
+            "{{range $x, $y := .MSIone}}<{{$x}}={{$y}}>{{end}}",
+            "<one=1>",
+            t_val(),
+            true,
+        ),
+        t(
+            "range quick break",
+            "{{range .SI}}{{break}}{{.}}{{end}}",
+            "",
+            t_val(),
+            true,
+        ),
+        t(
+            "range break after two",
+            "{{range $i, $x := .SI}}{{if ge $i 2}}{{break}}{{end}}{{.}}{{end}}",
+            "34",
+            t_val(),
+            true,
+        ),
+        t(
+            "range continue",
+            "{{range .SI}}{{continue}}{{.}}{{end}}",
+            "",
+            t_val(),
+            true,
+        ),
+        t(
+            "range continue condition",
+            "{{range .SI}}{{if eq . 3 }}{{continue}}{{end}}{{.}}{{end}}",
+            "45",
+            t_val(),
+            true,
+        ),
+        t(
+            "range $x PSI",
+            "{{range $x := .PSI}}<{{$x}}>{{end}}",
+            "<21><22><23>",
+            t_val(),
+            true,
+        ),
+        t(
+            "declare in range",
+            "{{range $x := .PSI}}<{{$foo:=$x}}{{$x}}>{{end}}",
+            "<21><22><23>",
+            t_val(),
+            true,
+        ),
+        // Cute examples.
+        t(
+            "or as if true",
+            r#"{{or .SI "slice is empty"}}"#,
+            "[3 4 5]",
+            t_val(),
+            true,
+        ),
+        t(
+            "or as if false",
+            r#"{{or .SIEmpty "slice is empty"}}"#,
+            "slice is empty",
+            t_val(),
+            true,
+        ),
+        // Fixed bugs.
+        t(
+            "bug9",
+            "{{.cause}}",
+            "neglect",
+            Value::Map(
+                vec![("cause".to_owned(), Value::from("neglect"))]
+                    .into_iter()
+                    .collect(),
+            ),
+            true,
+        ),
+        t(
+            "bug10",
+            "{{mapOfThree.three}}-{{(mapOfThree).three}}",
+            "3-3",
+            0,
+            true,
+        ),
+        // Chained nodes did not work as arguments, with a field instead of the method Copy.
+        t("bug13", "{{print (.U).V}}", "v", t_val(), true),
+        // Didn't protect against nil or literal values in field chains.
+        t("bug14a", "{{(nil).True}}", "", t_val(), false),
+        t("bug14b", "{{$x := nil}}{{$x.anything}}", "", t_val(), false),
+        t(
+            "bug14c",
+            r#"{{$x := (1.0)}}{{$y := ("hello")}}{{$x.anything}}{{$y.true}}"#,
+            "",
+            t_val(),
+            false,
+        ),
+        t("bug18a", "{{eq . '.'}}", "true", '.' as i64, true),
+        t("bug18b", "{{eq . 'e'}}", "true", 'e' as i64, true),
+        t("bug18c", "{{eq . 'P'}}", "true", 'P' as i64, true),
+    ]
+}
+
+#[test]
+fn go_exec_conformance() {
+    let mut failures = vec![];
+    for test in exec_tests() {
+        let mut tmpl = Template::default();
+        tmpl.add_funcs(&[
+            ("echo", echo as gtmpl::Func),
+            ("die", die as gtmpl::Func),
+            ("add", add as gtmpl::Func),
+            ("makemap", makemap as gtmpl::Func),
+            ("mapOfThree", map_of_three as gtmpl::Func),
+        ]);
+        let result = tmpl
+            .parse(test.input)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                tmpl.render(&Context::from(test.data.clone()))
+                    .map_err(|e| e.to_string())
+            });
+        let matches = match result {
+            Ok(ref output) => test.ok && output == test.output,
+            Err(_) => !test.ok,
+        };
+        match (matches, test.divergence) {
+            (false, None) => failures.push(format!(
+                "{}: {:?} expected {}, got {:?}",
+                test.name,
+                test.input,
+                if test.ok { test.output } else { "an error" },
+                result
+            )),
+            (true, Some(reason)) => failures.push(format!(
+                "{}: matches Go now, remove the divergence ({})",
+                test.name, reason
+            )),
+            _ => {}
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
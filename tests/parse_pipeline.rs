@@ -0,0 +1,287 @@
+//! Pipeline cases from Go's `text/template/parse/parse_test.go`.
+//!
+//! Cases for features gtmpl does not support (complex numbers, assignments with `=` and
+//! newlines within actions) are left out.
+use gtmpl::{GoVersion, Template};
+
+const NO_ERROR: bool = false;
+const HAS_ERROR: bool = true;
+
+struct ParseTest {
+    name: &'static str,
+    input: &'static str,
+    has_error: bool,
+}
+
+const fn t(name: &'static str, input: &'static str, has_error: bool) -> ParseTest {
+    ParseTest {
+        name,
+        input,
+        has_error,
+    }
+}
+
+const PARSE_TESTS: &[ParseTest] = &[
+    t("simple command", "{{printf}}", NO_ERROR),
+    t("$ invocation", "{{$}}", NO_ERROR),
+    t(
+        "variable invocation",
+        "{{with $x := 3}}{{$x 23}}{{end}}",
+        NO_ERROR,
+    ),
+    t("variable with fields", "{{$.I}}", NO_ERROR),
+    t("multi-word command", "{{printf `%d` 23}}", NO_ERROR),
+    t("pipeline", "{{.X|.Y}}", NO_ERROR),
+    t("pipeline with decl", "{{$x := .X|.Y}}", NO_ERROR),
+    t(
+        "nested pipeline",
+        "{{.X (.Y .Z) (.A | .B .C) (.E)}}",
+        NO_ERROR,
+    ),
+    t(
+        "field applied to parentheses",
+        "{{(.Y .Z).Field}}",
+        NO_ERROR,
+    ),
+    t("simple if", "{{if .X}}hello{{end}}", NO_ERROR),
+    t(
+        "if with else",
+        "{{if .X}}true{{else}}false{{end}}",
+        NO_ERROR,
+    ),
+    t("simple range", "{{range .X}}hello{{end}}", NO_ERROR),
+    t(
+        "chained field range",
+        "{{range .X.Y.Z}}hello{{end}}",
+        NO_ERROR,
+    ),
+    t(
+        "nested range",
+        "{{range .X}}hello{{range .Y}}goodbye{{end}}{{end}}",
+        NO_ERROR,
+    ),
+    t("range []int", "{{range .SI}}{{.}}{{end}}", NO_ERROR),
+    t("range 1 var", "{{range $x := .SI}}{{.}}{{end}}", NO_ERROR),
+    t(
+        "range 2 vars",
+        "{{range $x, $y := .SI}}{{.}}{{end}}",
+        NO_ERROR,
+    ),
+    t("template", "{{template `x`}}", NO_ERROR),
+    t("template with arg", "{{template `x` .Y}}", NO_ERROR),
+    t("with", "{{with .X}}hello{{end}}", NO_ERROR),
+    t(
+        "with with else",
+        "{{with .X}}hello{{else}}goodbye{{end}}",
+        NO_ERROR,
+    ),
+    t(
+        "with with else with",
+        "{{with .X}}hello{{else with .Y}}goodbye{{end}}",
+        NO_ERROR,
+    ),
+    t(
+        "with else chain",
+        "{{with .X}}X{{else with .Y}}Y{{else with .Z}}Z{{end}}",
+        NO_ERROR,
+    ),
+    t(
+        "range with break",
+        "{{range .SI}}{{.}}{{break}}{{end}}",
+        NO_ERROR,
+    ),
+    t(
+        "range with continue",
+        "{{range .SI}}{{.}}{{continue}}{{end}}",
+        NO_ERROR,
+    ),
+    t("trim left", "x \r\n\t{{- 3}}", NO_ERROR),
+    t("trim right", "{{3 -}}\n\n\ty", NO_ERROR),
+    t("trim left and right", "x \r\n\t{{- 3 -}}\n\n\ty", NO_ERROR),
+    t(
+        "block definition",
+        "{{block \"foo\" .}}hello{{end}}",
+        NO_ERROR,
+    ),
+    t("newline in assignment", "{{ $x \n := \n 1 \n }}", NO_ERROR),
+    t("newline in empty action", "{{\n}}", HAS_ERROR),
+    t("newline in comment", "{{/*\nhello\n*/}}", NO_ERROR),
+    // Errors.
+    t("unclosed action", "hello{{range", HAS_ERROR),
+    t("unmatched end", "{{end}}", HAS_ERROR),
+    t("unmatched else", "{{else}}", HAS_ERROR),
+    t(
+        "unmatched else after if",
+        "{{if .X}}hello{{end}}{{else}}",
+        HAS_ERROR,
+    ),
+    t(
+        "multiple else",
+        "{{if .X}}1{{else}}2{{else}}3{{end}}",
+        HAS_ERROR,
+    ),
+    t("missing end", "hello{{range .x}}", HAS_ERROR),
+    t(
+        "missing end after else",
+        "hello{{range .x}}{{else}}",
+        HAS_ERROR,
+    ),
+    t("undefined function", "hello{{undefined}}", HAS_ERROR),
+    t("undefined variable", "{{$x}}", HAS_ERROR),
+    t(
+        "variable undefined after end",
+        "{{with $x := 4}}{{end}}{{$x}}",
+        HAS_ERROR,
+    ),
+    t(
+        "variable undefined in template",
+        "{{template $v}}",
+        HAS_ERROR,
+    ),
+    t("declare with field", "{{with $x.Y := 4}}{{end}}", HAS_ERROR),
+    t("template with field ref", "{{template .X}}", HAS_ERROR),
+    t("template with var", "{{template $v}}", HAS_ERROR),
+    t("invalid punctuation", "{{printf 3, 4}}", HAS_ERROR),
+    t(
+        "multidecl outside range",
+        "{{with $v, $u := 3}}{{end}}",
+        HAS_ERROR,
+    ),
+    t(
+        "too many decls in range",
+        "{{range $u, $v, $w := 3}}{{end}}",
+        HAS_ERROR,
+    ),
+    t(
+        "dot applied to parentheses",
+        "{{printf (printf .).}}",
+        HAS_ERROR,
+    ),
+    t("adjacent args", "{{printf 3`x`}}", HAS_ERROR),
+    t("adjacent args with .", "{{printf `x`.}}", HAS_ERROR),
+    t(
+        "extra end after if",
+        "{{if .X}}a{{else if .Y}}b{{end}}{{end}}",
+        HAS_ERROR,
+    ),
+    t(
+        "break outside range",
+        "{{range .}}{{end}} {{break}}",
+        HAS_ERROR,
+    ),
+    t(
+        "continue outside range",
+        "{{range .}}{{end}} {{continue}}",
+        HAS_ERROR,
+    ),
+    t(
+        "break in range else",
+        "{{range .}}{{else}}{{break}}{{end}}",
+        HAS_ERROR,
+    ),
+    t(
+        "continue in range else",
+        "{{range .}}{{else}}{{continue}}{{end}}",
+        HAS_ERROR,
+    ),
+    // Other kinds of assignments and operators aren't available yet.
+    t("bug0a", "{{$x := 0}}{{$x}}", NO_ERROR),
+    t("bug0b", "{{$x += 1}}{{$x}}", HAS_ERROR),
+    t("bug0c", "{{$x ! 2}}{{$x}}", HAS_ERROR),
+    t("bug0d", "{{$x % 3}}{{$x}}", HAS_ERROR),
+    // Check the parse fails for := rather than comma.
+    t("bug0e", "{{range $x := $y := 3}}{{end}}", HAS_ERROR),
+    // Another bug: variable read must ignore following punctuation.
+    t("bug1a", "{{$x:=.}}{{$x!2}}", HAS_ERROR),
+    t("bug1b", "{{$x:=.}}{{$x+2}}", HAS_ERROR),
+    t("bug1c", "{{$x:=.}}{{$x +2}}", NO_ERROR),
+    // Check the range handles assignment vs. declaration properly.
+    t("bug2a", "{{range $x := 0}}{{$x}}{{end}}", NO_ERROR),
+    // dot following a literal value
+    t("dot after float", "{{0.1.E}}", HAS_ERROR),
+    t("dot after boolean", "{{true.E}}", HAS_ERROR),
+    t("dot after char", "{{'a'.any}}", HAS_ERROR),
+    t("dot after string", "{{\"hello\".guys}}", HAS_ERROR),
+    t("dot after dot", "{{..E}}", HAS_ERROR),
+    t("dot after nil", "{{nil.E}}", HAS_ERROR),
+    // Wrong pipeline
+    t("wrong pipeline dot", "{{12|.}}", HAS_ERROR),
+    t("wrong pipeline number", "{{.|12|printf}}", HAS_ERROR),
+    t("wrong pipeline string", "{{.|printf|\"error\"}}", HAS_ERROR),
+    t("wrong pipeline char", "{{12|printf|'e'}}", HAS_ERROR),
+    t("wrong pipeline boolean", "{{.|true}}", HAS_ERROR),
+    t("wrong pipeline nil", "{{'c'|nil}}", HAS_ERROR),
+    t("empty pipeline", "{{printf \"%d\" ( ) }}", HAS_ERROR),
+    // Missing pipeline in block
+    t(
+        "block definition",
+        "{{block \"foo\"}}hello{{end}}",
+        HAS_ERROR,
+    ),
+];
+
+#[test]
+fn parse_pipelines() {
+    let mut failures = vec![];
+    for test in PARSE_TESTS {
+        let mut tmpl = Template::default();
+        let res = tmpl.parse(test.input);
+        if res.is_err() != test.has_error {
+            failures.push(format!(
+                "{}: {:?} -> {:?}",
+                test.name,
+                test.input,
+                res.err()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn parse_pipelines_go_version() {
+    let cases = [
+        (
+            "{{range .SI}}{{break}}{{end}}",
+            GoVersion::Go1_17,
+            HAS_ERROR,
+        ),
+        (
+            "{{range .SI}}{{continue}}{{end}}",
+            GoVersion::Go1_17,
+            HAS_ERROR,
+        ),
+        ("{{range .SI}}{{break}}{{end}}", GoVersion::Go1_18, NO_ERROR),
+        (
+            "{{range .SI}}{{continue}}{{end}}",
+            GoVersion::Go1_18,
+            NO_ERROR,
+        ),
+        (
+            "{{with .X}}hello{{else with .Y}}goodbye{{end}}",
+            GoVersion::Go1_18,
+            HAS_ERROR,
+        ),
+        (
+            "{{with .X}}hello{{else with .Y}}goodbye{{end}}",
+            GoVersion::Go1_23,
+            NO_ERROR,
+        ),
+        (
+            "{{with .X}}X{{else with .Y}}Y{{else with .Z}}Z{{end}}",
+            GoVersion::Go1_23,
+            NO_ERROR,
+        ),
+        (
+            "{{with .X}}X{{else with .Y}}Y{{else}}{{break}}{{end}}",
+            GoVersion::Go1_23,
+            HAS_ERROR,
+        ),
+    ];
+    for (input, version, has_error) in cases {
+        let mut tmpl = Template::default();
+        tmpl.set_go_version(version);
+        let res = tmpl.parse(input);
+        assert_eq!(res.is_err(), has_error, "{:?} with {:?}", input, version);
+    }
+}
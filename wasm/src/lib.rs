@@ -0,0 +1,56 @@
+//! Renders gtmpl templates from JavaScript, e.g. for a live preview in the browser.
+//!
+//! Build it with `wasm-pack build --target web` and open `index.html`.
+use std::collections::HashMap;
+
+use gtmpl::{Context, Template, Value};
+use wasm_bindgen::prelude::*;
+
+/// Renders `template` with the JSON document `data` as dot.
+#[wasm_bindgen]
+pub fn render(template: &str, data: &str) -> Result<String, JsError> {
+    render_json(template, data).map_err(|e| JsError::new(&e))
+}
+
+pub fn render_json(template: &str, data: &str) -> Result<String, String> {
+    let data: serde_json::Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+    let mut tmpl = Template::default();
+    tmpl.parse(template).map_err(|e| e.to_string())?;
+    tmpl.render(&Context::from(from_json(data)))
+        .map_err(|e| e.to_string())
+}
+
+fn from_json(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::from(b),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Value::from(i),
+            (_, Some(u)) => Value::from(u),
+            _ => Value::from(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::from(s),
+        serde_json::Value::Array(a) => Value::Array(a.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(o) => Value::Map(
+            o.into_iter()
+                .map(|(k, v)| (k, from_json(v)))
+                .collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_json() {
+        let out = render_json(
+            "{{ range .items }}{{ .name }} {{ end }}",
+            r#"{"items": [{"name": "a"}, {"name": "b"}]}"#,
+        );
+        assert_eq!(out.unwrap(), "a b ");
+        assert!(render_json("{{ if }}", "null").is_err());
+        assert!(render_json("{{ . }}", "{").is_err());
+    }
+}
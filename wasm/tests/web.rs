@@ -0,0 +1,8 @@
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_render() {
+    let out = gtmpl_wasm::render(r#"Hello {{ .name }}!"#, r#"{"name": "wasm"}"#);
+    assert_eq!(out.unwrap(), "Hello wasm!");
+    assert!(gtmpl_wasm::render("{{ .name", "{}").is_err());
+}